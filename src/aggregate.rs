@@ -0,0 +1,167 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::expr::{self, Path};
+
+/// A value computed over the members of a group, stored under `name` in the group's result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    name: String,
+    function: AggregateFunction,
+    path: Path<'static>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+impl Aggregation {
+    fn new(name: &str, function: AggregateFunction, path: &str) -> Aggregation {
+        Aggregation {
+            name: name.to_string(),
+            function,
+            path: Path::parse(path).into_owned(),
+        }
+    }
+
+    /// The number of members of the group.
+    pub fn count(name: &str) -> Aggregation {
+        Aggregation::new(name, AggregateFunction::Count, "")
+    }
+
+    /// The sum of the numbers at `path` in the members of the group.
+    pub fn sum(name: &str, path: &str) -> Aggregation {
+        Aggregation::new(name, AggregateFunction::Sum, path)
+    }
+
+    /// The smallest number at `path` in the members of the group.
+    pub fn min(name: &str, path: &str) -> Aggregation {
+        Aggregation::new(name, AggregateFunction::Min, path)
+    }
+
+    /// The largest number at `path` in the members of the group.
+    pub fn max(name: &str, path: &str) -> Aggregation {
+        Aggregation::new(name, AggregateFunction::Max, path)
+    }
+
+    /// The mean of the numbers at `path` in the members of the group.
+    pub fn avg(name: &str, path: &str) -> Aggregation {
+        Aggregation::new(name, AggregateFunction::Avg, path)
+    }
+
+    /// Computes the aggregation over the members of a group. Members whose value at the path isn't
+    /// a number are skipped; if none is left, the result is null, except for sums, which are 0.
+    fn compute(&self, members: &[&Value]) -> Value {
+        let resolved: Vec<_> = members
+            .iter()
+            .filter_map(|v| self.path.resolve(v))
+            .collect();
+        let numbers: Vec<&Value> = resolved
+            .iter()
+            .map(|v| v.as_ref())
+            .filter(|v| v.is_number())
+            .collect();
+        let result = match self.function {
+            AggregateFunction::Count => Some(Value::from(members.len())),
+            AggregateFunction::Sum => expr::sum(numbers.iter().copied()).map(Value::Number),
+            AggregateFunction::Min => extreme(&numbers, Ordering::Less),
+            AggregateFunction::Max => extreme(&numbers, Ordering::Greater),
+            AggregateFunction::Avg if numbers.is_empty() => None,
+            AggregateFunction::Avg => expr::sum(numbers.iter().copied())
+                .and_then(|sum| sum.as_f64())
+                .and_then(|sum| expr::float_to_number(sum / numbers.len() as f64))
+                .map(Value::Number),
+        };
+        result.unwrap_or(Value::Null)
+    }
+}
+
+/// Picks the number that compares as `wanted` against all others.
+fn extreme(numbers: &[&Value], wanted: Ordering) -> Option<Value> {
+    numbers
+        .iter()
+        .copied()
+        .reduce(|best, n| match (n.as_number(), best.as_number()) {
+            (Some(a), Some(b)) if expr::compare_numbers(a, b) == Some(wanted) => n,
+            _ => best,
+        })
+        .cloned()
+}
+
+/// Groups Values by the value at `key` and computes the aggregations for every group.
+///
+/// Returns an array with an object per group, in the order the groups were first seen. Each object
+/// has the group's key under `"key"` and the result of each aggregation under its name. Keys are
+/// compared as JSON, and Values without the key are grouped under null.
+pub(crate) fn group_by<'v>(
+    values: impl IntoIterator<Item = &'v Value>,
+    key: &str,
+    aggregations: &[Aggregation],
+) -> Value {
+    let key_path = Path::parse(key);
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<(Value, Vec<&Value>)> = Vec::new();
+    for v in values {
+        let key = key_path
+            .resolve(v)
+            .map_or(Value::Null, |key| key.into_owned());
+        let i = *index.entry(key.to_string()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[i].1.push(v);
+    }
+    let results = groups.into_iter().map(|(key, members)| {
+        let mut result = Map::new();
+        result.insert("key".to_string(), key);
+        for aggregation in aggregations {
+            result.insert(aggregation.name.clone(), aggregation.compute(&members));
+        }
+        Value::Object(result)
+    });
+    Value::Array(results.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_group_by() {
+        let values = [
+            json!({ "status": "paid", "total": 10 }),
+            json!({ "status": "open", "total": 2.5 }),
+            json!({ "status": "paid", "total": 30 }),
+            json!({ "status": "open", "total": "n/a" }),
+            json!({ "total": 1 }),
+        ];
+        let aggregations = [
+            Aggregation::count("orders"),
+            Aggregation::sum("revenue", ".total"),
+            Aggregation::min("smallest", ".total"),
+            Aggregation::max("largest", ".total"),
+            Aggregation::avg("average", ".total"),
+        ];
+        assert_eq!(
+            group_by(&values, ".status", &aggregations),
+            json!([
+                { "key": "paid", "orders": 2, "revenue": 40, "smallest": 10, "largest": 30, "average": 20 },
+                { "key": "open", "orders": 2, "revenue": 2.5, "smallest": 2.5, "largest": 2.5, "average": 2.5 },
+                { "key": null, "orders": 1, "revenue": 1, "smallest": 1, "largest": 1, "average": 1 },
+            ])
+        );
+        let values = [json!({ "status": "open" })];
+        assert_eq!(
+            group_by(&values, ".status", &aggregations[1..]),
+            json!([{ "key": "open", "revenue": 0, "smallest": null, "largest": null, "average": null }])
+        );
+    }
+}
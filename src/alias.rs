@@ -0,0 +1,116 @@
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::case::KeyCase;
+use crate::expr::{self, Path, Segment};
+
+/// Other paths to read fields from when a Value doesn't have them, set in
+/// [`crate::ApplyOptions::aliases`], so one filter works across documents of different shapes. With
+/// `user -> customer.profile`, `.user.name` reads `.customer.profile.name` from Values without a `user`.
+///
+/// Aliases apply to every path a filter reads, including the paths of the clauses of `ANY` and `ALL`,
+/// which start at the elements of the array. A path is rewritten once, by the first alias it starts
+/// with, so aliases whose targets start with other aliases don't chain.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AliasMap {
+    aliases: Vec<(Path<'static>, Path<'static>)>,
+}
+
+impl AliasMap {
+    /// Creates a map without aliases.
+    pub fn new() -> AliasMap {
+        AliasMap::default()
+    }
+
+    /// Reads paths starting with `alias`, such as `.user`, from `target`, such as `.customer.profile`,
+    /// in Values without `alias`. Wildcards aren't allowed in either, and an alias given again
+    /// replaces the earlier one.
+    pub fn with_alias(mut self, alias: &str, target: &str) -> AliasMap {
+        let alias = Path::parse(alias).into_owned();
+        let target = Path::parse(target).into_owned();
+        assert!(
+            !alias.segments.is_empty()
+                && !alias.segments.contains(&Segment::Wildcard)
+                && !target.segments.contains(&Segment::Wildcard),
+            "an alias and its target must be paths of keys"
+        );
+        self.aliases
+            .retain(|(known, _)| !same_keys(&known.segments, &alias.segments));
+        self.aliases.push((alias, target));
+        self
+    }
+
+    /// Returns whether the map has no aliases.
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    /// The path to read instead of `path` from the Value `v`, if it starts with an alias `v` doesn't
+    /// have with keys matched as `case` allows.
+    pub(crate) fn rewrite<'p>(
+        &self,
+        path: &Path<'p>,
+        v: &Value,
+        case: KeyCase,
+    ) -> Option<Path<'p>> {
+        let (alias, target) = self.aliases.iter().find(|(alias, _)| {
+            let len = alias.segments.len();
+            path.segments.len() >= len && same_keys(&path.segments[..len], &alias.segments)
+        })?;
+        if expr::lookup(&alias.segments, v, case).is_some() {
+            return None;
+        }
+        let rest = &path.segments[alias.segments.len()..];
+        Some(Path {
+            segments: target.segments.iter().chain(rest).cloned().collect(),
+        })
+    }
+}
+
+/// Returns whether two lists of segments have the same keys, whether they are null-safe or not.
+fn same_keys(a: &[Segment], b: &[Segment]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (Segment::Key { name: a, .. }, Segment::Key { name: b, .. }) => a == b,
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_with, parse, ApplyOptions};
+    use serde_json::json;
+
+    #[test]
+    fn test_aliases() {
+        let options = ApplyOptions {
+            aliases: AliasMap::new()
+                .with_alias("user", "customer.profile")
+                .with_alias(".sku", ".product.code"),
+            ..Default::default()
+        };
+        let passes = |filter_string, v: &Value| {
+            let passes = apply_with(v, &parse(filter_string).unwrap(), &options);
+            #[cfg(feature = "std")]
+            assert_eq!(
+                crate::apply_str(&v.to_string(), &parse(filter_string).unwrap(), &options).unwrap(),
+                passes
+            );
+            passes
+        };
+        let renamed = json!({ "customer": { "profile": { "name": "Ann", "age": 40 } }, "product": { "code": "A1" } });
+        let original = json!({ "user": { "name": "Bob", "age": 20 }, "sku": "B2" });
+        assert!(passes(
+            ".user.name = 'Ann' AND .user.age > 30 AND .sku = 'A1'",
+            &renamed
+        ));
+        assert!(passes(".user.name = 'Bob' AND .sku = 'B2'", &original));
+        assert!(!passes(".user.name = 'Ann'", &original));
+        assert!(passes(".user.address?.city = null", &renamed));
+        let orders = json!({ "orders": [{ "sku": "x" }, { "product": { "code": "A1" } }] });
+        assert!(passes("ANY .orders WHERE .sku = 'A1'", &orders));
+        assert!(!passes("ALL .orders WHERE .sku = 'A1'", &orders));
+    }
+}
@@ -0,0 +1,242 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::eval::{self, Context};
+use crate::expr::{Path, Segment};
+use crate::{ApplyOptions, EvalObserver, Filter, PathLimits};
+
+/// Receives an [`AuditEntry`] for every evaluation done with [`crate::apply_audited`] or
+/// [`crate::CompiledFilter::matches_audited`], so pipelines can record why each document was included
+/// or excluded, such as in an append-only compliance log.
+///
+/// The method takes `&self` so one log can be shared across threads.
+pub trait AuditLog {
+    /// Called once for every Value evaluated, after its evaluation.
+    fn record(&self, entry: &AuditEntry);
+}
+
+/// The outcome of evaluating a filter on one document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry<'a> {
+    /// The id of the filter given to the [`Auditor`], such as its name in a [`crate::FilterSet`].
+    pub filter: &'a str,
+    /// The document's id, or its hash if it has none.
+    pub document: DocumentId<'a>,
+    /// Whether the document passed every clause.
+    pub passed: bool,
+    /// The index of the top-level clause that rejected the document, if it didn't pass.
+    pub rejected_by: Option<usize>,
+    /// How long the evaluation took.
+    pub duration: Duration,
+}
+
+/// How an [`AuditEntry`] identifies its document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocumentId<'a> {
+    /// The value at the [`Auditor`]'s id path.
+    Field(&'a Value),
+    /// A 64-bit FNV-1a hash of the document, for documents without an id. Equal documents have equal
+    /// hashes whatever the order of their keys, in every run.
+    Hash(u64),
+}
+
+/// An [`AuditLog`] with how to identify the filter and documents of its entries.
+#[derive(Clone, Copy)]
+pub struct Auditor<'l> {
+    filter: &'l str,
+    id_path: Option<&'l str>,
+    log: &'l dyn AuditLog,
+}
+
+impl<'l> Auditor<'l> {
+    /// Creates an auditor recording the evaluations of the filter with the id `filter` to `log`,
+    /// identifying documents by their hash.
+    pub fn new(filter: &'l str, log: &'l dyn AuditLog) -> Auditor<'l> {
+        Auditor {
+            filter,
+            id_path: None,
+            log,
+        }
+    }
+
+    /// Identifies documents by the value at a path such as `.id` or `.meta.request_id`, falling back
+    /// to their hash for documents without one.
+    pub fn with_id_path(mut self, path: &'l str) -> Auditor<'l> {
+        self.id_path = Some(path);
+        self
+    }
+}
+
+/// The index of the clause that rejected the Value, told by [`eval::apply`].
+#[derive(Default)]
+struct Rejection(Cell<Option<usize>>);
+
+impl EvalObserver for Rejection {
+    fn rejected(&self, index: usize, _: &Filter) {
+        self.0.set(Some(index));
+    }
+}
+
+/// Returns whether the Value `v` passes every filter, recording the evaluation to the auditor's log.
+pub(crate) fn apply(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    limits: PathLimits,
+    auditor: &Auditor,
+) -> bool {
+    let rejection = Rejection::default();
+    let start = Instant::now();
+    let passed = eval::apply(
+        v,
+        filters,
+        &Context::with_limits(options, limits).observed(&rejection),
+    );
+    let duration = start.elapsed();
+    let id = auditor
+        .id_path
+        .and_then(|path| lookup(v, &Path::parse(path)));
+    auditor.log.record(&AuditEntry {
+        filter: auditor.filter,
+        document: match id {
+            Some(id) => DocumentId::Field(id),
+            None => DocumentId::Hash(hash(v)),
+        },
+        passed,
+        rejected_by: rejection.0.get(),
+        duration,
+    });
+    passed
+}
+
+/// The value at a path without wildcards, borrowed from the document.
+fn lookup<'v>(v: &'v Value, path: &Path) -> Option<&'v Value> {
+    path.segments
+        .iter()
+        .try_fold(v, |v, segment| match segment {
+            Segment::Key { name, .. } => v.get(name.as_ref()),
+            Segment::Wildcard => None,
+        })
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn hash(v: &Value) -> u64 {
+    let mut state = FNV_OFFSET;
+    feed(&mut state, v);
+    state
+}
+
+/// Hashes a tag byte for the type of `v`, then its contents, with the keys of objects in order.
+fn feed(state: &mut u64, v: &Value) {
+    match v {
+        Value::Null => write(state, b"n"),
+        Value::Bool(b) => write(state, if *b { b"t" } else { b"f" }),
+        Value::Number(n) => {
+            write(state, b"#");
+            write(state, n.to_string().as_bytes());
+            write(state, b";");
+        }
+        Value::String(s) => feed_str(state, s),
+        Value::Array(items) => {
+            write(state, b"[");
+            write(state, &(items.len() as u64).to_le_bytes());
+            for item in items {
+                feed(state, item);
+            }
+        }
+        Value::Object(map) => {
+            write(state, b"{");
+            write(state, &(map.len() as u64).to_le_bytes());
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            for (key, value) in entries {
+                feed_str(state, key);
+                feed(state, value);
+            }
+        }
+    }
+}
+
+fn feed_str(state: &mut u64, s: &str) {
+    write(state, b"s");
+    write(state, &(s.len() as u64).to_le_bytes());
+    write(state, s.as_bytes());
+}
+
+fn write(state: &mut u64, bytes: &[u8]) {
+    for &byte in bytes {
+        *state = (*state ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_audited, compile, parse};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+
+    impl AuditLog for Log {
+        fn record(&self, entry: &AuditEntry) {
+            let document = match &entry.document {
+                DocumentId::Field(id) => id.to_string(),
+                DocumentId::Hash(hash) => format!("{hash:x}"),
+            };
+            self.0.lock().unwrap().push(format!(
+                "{} {document} {} {:?}",
+                entry.filter, entry.passed, entry.rejected_by
+            ));
+        }
+    }
+
+    #[test]
+    fn test_apply_audited() {
+        let log = Log::default();
+        let auditor = Auditor::new("adults", &log).with_id_path(".meta.id");
+        let filters = parse(".age >= 18 AND .country = 'NZ'").unwrap();
+        let options = ApplyOptions::default();
+        let anonymous = json!({ "age": 30, "country": "AU" });
+        assert!(apply_audited(
+            &json!({ "meta": { "id": 7 }, "age": 30, "country": "NZ" }),
+            &filters,
+            &options,
+            &auditor
+        ));
+        assert!(!apply_audited(
+            &json!({ "meta": { "id": "x" }, "age": 12 }),
+            &filters,
+            &options,
+            &auditor
+        ));
+        assert!(!compile(filters).matches_audited(&anonymous, &options, &auditor));
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                "adults 7 true None".to_string(),
+                r#"adults "x" false Some(0)"#.to_string(),
+                format!("adults {:x} false Some(1)", hash(&anonymous)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        let mut reordered = serde_json::Map::new();
+        reordered.insert("b".to_string(), json!([1, "2"]));
+        reordered.insert("a".to_string(), json!(null));
+        assert_eq!(
+            hash(&json!({ "a": null, "b": [1, "2"] })),
+            hash(&Value::Object(reordered))
+        );
+        assert_eq!(hash(&json!(null)), 0xaf63_e34c_8601_f871);
+        assert_ne!(hash(&json!(["ab"])), hash(&json!(["a", "b"])));
+        assert_ne!(hash(&json!(1)), hash(&json!("1")));
+    }
+}
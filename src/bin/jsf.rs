@@ -0,0 +1,586 @@
+//! `jsf`, filtering newline-delimited JSON:
+//!
+//! ```text
+//! jsf [-f | -w] FILTER [FILE...]
+//! jsf check FILTER [--sample FILE]
+//! ```
+//!
+//! Prints the lines of the files, or of the standard input, that are JSON values passing the filter,
+//! and the rows of `.parquet` files passing it with the `arrow` feature.
+//! With `-f`, the lines appended to the file are filtered as they are written, like `tail -F`, and
+//! with `-w`, the files are filtered
+//! again whenever they change. `jsf check` reports what is wrong with a filter without reading any
+//! data.
+
+use std::fs::{File, Metadata};
+use std::io::{self, BufRead, BufReader, IsTerminal, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+#[cfg(feature = "arrow")]
+use simple_json_filter::ApplyOptions;
+use simple_json_filter::{
+    compile, explain, format, lint, parse_with_limits, validate, CompiledFilter, ParseLimits, Shape,
+};
+
+/// How long to wait for a followed or watched file to change before checking it again.
+const POLL: Duration = Duration::from_millis(250);
+
+const USAGE: &str = "\
+usage: jsf [-f | -w] FILTER [FILE...]
+       jsf check FILTER [--sample FILE]
+
+Prints the lines of the files, or of the standard input, that are JSON values passing FILTER.
+The rows of FILEs ending in `.parquet` are printed as JSON.
+
+`jsf check` parses and lints FILTER, checks it against the sample document if one is given, and
+prints its normalized form and its plan, failing if it found any problem.
+
+options:
+  -f, --follow     filter the lines appended to the file, reopening it when rotated, like `tail -F`
+  -w, --watch      filter the files again, and print the lines again, whenever they change
+  -s, --sample     the JSON document `jsf check` checks FILTER against
+  -h, --help       print this help";
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    filter: String,
+    files: Vec<PathBuf>,
+    follow: bool,
+    watch: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut filter = None;
+    let mut files = Vec::new();
+    let mut follow = false;
+    let mut watch = false;
+    let mut options = true;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" if options => return Ok(None),
+            "-f" | "--follow" if options => follow = true,
+            "-w" | "--watch" if options => watch = true,
+            "--" if options => options = false,
+            _ if options && arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option `{arg}`"))
+            }
+            _ if filter.is_none() => filter = Some(arg),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    let filter = filter.ok_or("missing FILTER")?;
+    if follow && watch {
+        return Err("`--follow` and `--watch` can't be used together".to_string());
+    }
+    if follow && files.len() != 1 {
+        return Err("`--follow` needs exactly one FILE".to_string());
+    }
+    if follow && is_parquet(&files[0]) {
+        return Err("`--follow` reads newline-delimited JSON, not Parquet".to_string());
+    }
+    if watch && files.is_empty() {
+        return Err("`--watch` needs a FILE".to_string());
+    }
+    Ok(Some(Args {
+        filter,
+        files,
+        follow,
+        watch,
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+struct CheckArgs {
+    filter: String,
+    sample: Option<PathBuf>,
+}
+
+fn parse_check_args(args: impl IntoIterator<Item = String>) -> Result<Option<CheckArgs>, String> {
+    let mut filter = None;
+    let mut sample = None;
+    let mut options = true;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" if options => return Ok(None),
+            "-s" | "--sample" if options => {
+                let path = args.next().ok_or("`--sample` needs a FILE")?;
+                sample = Some(PathBuf::from(path));
+            }
+            "--" if options => options = false,
+            _ if options && arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option `{arg}`"))
+            }
+            _ if filter.is_none() => filter = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+    Ok(Some(CheckArgs {
+        filter: filter.ok_or("missing FILTER")?,
+        sample,
+    }))
+}
+
+/// Describes a filter: its normalized form, its lint warnings, the problems found checking it against
+/// the sample, whether the sample passes it, and its plan. Returns the report and whether it found
+/// any problem.
+fn check(filter_string: &str, sample: Option<&Value>) -> Result<(String, bool), String> {
+    let invalid = |error| format!("invalid filter: {error}");
+    let filters = parse_with_limits(filter_string, &ParseLimits::default()).map_err(invalid)?;
+    let mut report = format!("normalized: {}\n", format(filter_string).map_err(invalid)?);
+    let mut problems = false;
+    for warning in lint(&filters) {
+        report += &format!("warning: {warning}\n");
+        problems = true;
+    }
+    let compiled = compile(filters);
+    if let Some(sample) = sample {
+        for issue in validate(compiled.filters(), Shape::Sample(sample)) {
+            report += &format!("sample: {issue}\n");
+            problems = true;
+        }
+        report += match compiled.matches(sample) {
+            true => "sample: passes\n",
+            false => "sample: doesn't pass\n",
+        };
+    }
+    report += &format!("plan:\n{}\n", explain(&compiled));
+    Ok((report, problems))
+}
+
+fn run_check(args: CheckArgs) -> Result<bool, String> {
+    let sample = match &args.sample {
+        Some(path) => {
+            let name = path.display().to_string();
+            let text = std::fs::read_to_string(path).map_err(|error| format!("{name}: {error}"))?;
+            Some(serde_json::from_str(&text).map_err(|error| format!("{name}: {error}"))?)
+        }
+        None => None,
+    };
+    let (report, problems) = check(&args.filter, sample.as_ref())?;
+    print!("{report}");
+    Ok(!problems)
+}
+
+/// Filters lines of NDJSON, writing those passing the filter and warning about those that aren't
+/// JSON.
+struct Lines<'f, W> {
+    filter: &'f CompiledFilter<'f>,
+    out: W,
+}
+
+impl<W: Write> Lines<'_, W> {
+    fn line(&mut self, name: &str, number: usize, line: &str) -> io::Result<()> {
+        let json = line.trim_end_matches(['\n', '\r']);
+        if json.trim().is_empty() {
+            return Ok(());
+        }
+        match self.filter.matches_str(json) {
+            Ok(true) => writeln!(self.out, "{json}"),
+            Ok(false) => Ok(()),
+            Err(error) => {
+                eprintln!("jsf: {name}:{number}: {error}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the rows of a Parquet file passing the filter, each as a line of JSON.
+    #[cfg(feature = "arrow")]
+    fn parquet(&mut self, file: File) -> Result<(), String> {
+        let rows = self
+            .filter
+            .filter_parquet(file, &ApplyOptions::default())
+            .map_err(|error| error.to_string())?;
+        for row in rows {
+            writeln!(self.out, "{row}").map_err(|error| error.to_string())?;
+        }
+        self.out.flush().map_err(|error| error.to_string())
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    fn parquet(&mut self, _: File) -> Result<(), String> {
+        Err("reading Parquet files needs the `arrow` feature".to_string())
+    }
+
+    fn read(&mut self, name: &str, reader: impl BufRead) -> io::Result<()> {
+        for (i, line) in reader.lines().enumerate() {
+            self.line(name, i + 1, &line?)?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Reads the lines appended to a file since it was last read.
+struct Follower {
+    path: PathBuf,
+    reader: BufReader<File>,
+    /// The file being read, to notice when another file replaces it at the path.
+    id: Option<(u64, u64)>,
+    /// Where the complete lines read so far end.
+    position: u64,
+    /// The start of a line whose end hasn't been written yet.
+    partial: Vec<u8>,
+    number: usize,
+}
+
+impl Follower {
+    /// Opens a file to read the lines written to it from now on.
+    fn open(path: &Path) -> io::Result<Follower> {
+        let mut file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        let position = file.seek(SeekFrom::End(0))?;
+        Ok(Follower {
+            path: path.to_path_buf(),
+            reader: BufReader::new(file),
+            id,
+            position,
+            partial: Vec::new(),
+            number: 0,
+        })
+    }
+
+    /// Filters the complete lines written since the last poll. A file that got shorter was
+    /// truncated, as logs are when rotated in place, and is read again from its start. When
+    /// another file replaced it at its path, as logs are when rotated by renaming, the rest of the
+    /// file is read before the new one is, from its start.
+    fn poll<W: Write>(&mut self, name: &str, lines: &mut Lines<'_, W>) -> io::Result<()> {
+        let len = self.reader.get_ref().metadata()?.len();
+        if len < self.position + self.partial.len() as u64 {
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.restart();
+        }
+        self.read(name, lines)?;
+        // The path is missing for a moment while a file is rotated, so the old file is kept until
+        // a new one is there.
+        if let Ok(metadata) = std::fs::metadata(&self.path) {
+            if file_id(&metadata) != self.id {
+                self.reader = BufReader::new(File::open(&self.path)?);
+                self.id = file_id(&metadata);
+                self.restart();
+                self.read(name, lines)?;
+            }
+        }
+        lines.out.flush()
+    }
+
+    /// Forgets the lines read, to read the file again from its start.
+    fn restart(&mut self) {
+        self.position = 0;
+        self.partial.clear();
+        self.number = 0;
+    }
+
+    /// Filters the complete lines after the position, warning about those that aren't UTF-8.
+    fn read<W: Write>(&mut self, name: &str, lines: &mut Lines<'_, W>) -> io::Result<()> {
+        loop {
+            if self.reader.read_until(b'\n', &mut self.partial)? == 0
+                || !self.partial.ends_with(b"\n")
+            {
+                return Ok(());
+            }
+            self.number += 1;
+            self.position += self.partial.len() as u64;
+            match std::str::from_utf8(&self.partial) {
+                Ok(line) => lines.line(name, self.number, line)?,
+                Err(error) => eprintln!("jsf: {name}:{}: {error}", self.number),
+            }
+            self.partial.clear();
+        }
+    }
+}
+
+/// The device and inode of a file, which stay the same while a file is written to, and differ for
+/// the file replacing it when it is rotated.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let filters = parse_with_limits(&args.filter, &ParseLimits::default())
+        .map_err(|error| format!("invalid filter: {error}"))?;
+    let filter = compile(filters);
+    let mut lines = Lines {
+        filter: &filter,
+        out: io::stdout().lock(),
+    };
+    let io_error = |path: &Path| {
+        let name = path.display().to_string();
+        move |error: io::Error| format!("{name}: {error}")
+    };
+    if args.follow {
+        let path = &args.files[0];
+        let name = path.display().to_string();
+        let mut follower = Follower::open(path).map_err(io_error(path))?;
+        loop {
+            follower.poll(&name, &mut lines).map_err(io_error(path))?;
+            std::thread::sleep(POLL);
+        }
+    }
+    if args.watch {
+        let clear = io::stdout().is_terminal();
+        let mut seen = None;
+        loop {
+            let stamps = stamps(&args.files);
+            if seen.as_ref() != Some(&stamps) {
+                if clear && seen.is_some() {
+                    // Clears the terminal, so only the latest results are shown.
+                    write!(lines.out, "\x1b[2J\x1b[H").map_err(io_error(Path::new("<stdout>")))?;
+                }
+                // A file being replaced may be missing for a moment, so errors don't stop watching.
+                if let Err(error) = filter_files(&args.files, &mut lines) {
+                    eprintln!("jsf: {error}");
+                }
+                seen = Some(stamps);
+            }
+            std::thread::sleep(POLL);
+        }
+    }
+    if args.files.is_empty() {
+        return lines
+            .read("<stdin>", io::stdin().lock())
+            .map_err(io_error(Path::new("<stdin>")));
+    }
+    filter_files(&args.files, &mut lines)
+}
+
+fn filter_files<W: Write>(files: &[PathBuf], lines: &mut Lines<'_, W>) -> Result<(), String> {
+    for path in files {
+        let name = path.display().to_string();
+        let file = File::open(path).map_err(|error| format!("{name}: {error}"))?;
+        if is_parquet(path) {
+            lines
+                .parquet(file)
+                .map_err(|error| format!("{name}: {error}"))?;
+            continue;
+        }
+        lines
+            .read(&name, BufReader::new(file))
+            .map_err(|error| format!("{name}: {error}"))?;
+    }
+    Ok(())
+}
+
+fn is_parquet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "parquet")
+}
+
+/// The length and modification time of each file, which change when it is written.
+fn stamps(files: &[PathBuf]) -> Vec<Option<(u64, SystemTime)>> {
+    files
+        .iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(path).ok()?;
+            Some((metadata.len(), metadata.modified().ok()?))
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "check") {
+        return main_check(args.skip(1));
+    }
+    let args = match parse_args(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("jsf: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("jsf: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main_check(args: impl IntoIterator<Item = String>) -> ExitCode {
+    let args = match parse_check_args(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("jsf: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run_check(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("jsf: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["-f", ".level = 'error'", "app.log.json"]),
+            Ok(Some(Args {
+                filter: ".level = 'error'".to_string(),
+                files: vec![PathBuf::from("app.log.json")],
+                follow: true,
+                watch: false,
+            }))
+        );
+        assert!(
+            args(&["-w", ".a = 1", "a.json", "b.json"])
+                .unwrap()
+                .unwrap()
+                .watch
+        );
+        assert!(args(&["-w", ".a = 1"]).is_err());
+        assert!(args(&["-w", "-f", ".a = 1", "a.json"]).is_err());
+        assert_eq!(
+            args(&[".a = 1"]).unwrap().unwrap().files,
+            Vec::<PathBuf>::new()
+        );
+        assert_eq!(args(&["--help", ".a = 1"]), Ok(None));
+        assert_eq!(args(&["--", "-f"]).unwrap().unwrap().filter, "-f");
+        assert_eq!(args(&[]), Err("missing FILTER".to_string()));
+        assert!(args(&["-x", ".a = 1"]).is_err());
+        assert!(args(&["-f", ".a = 1"]).is_err());
+        assert!(args(&["-f", ".a = 1", "a.parquet"]).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let check_args = |args: &[&str]| parse_check_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(
+            check_args(&[".a = 1", "--sample", "user.json"]),
+            Ok(Some(CheckArgs {
+                filter: ".a = 1".to_string(),
+                sample: Some(PathBuf::from("user.json")),
+            }))
+        );
+        assert!(check_args(&[".a = 1", "--sample"]).is_err());
+        assert!(check_args(&[".a = 1", "b.json"]).is_err());
+        let sample = serde_json::json!({ "age": 30, "name": "Ann" });
+        let (report, problems) = check(".age>=18 AND  .nmae = 'Ann'", Some(&sample)).unwrap();
+        assert!(problems);
+        assert_eq!(
+            report,
+            "normalized: .age >= 18 AND .nmae = 'Ann'
+sample: .nmae = 'Ann': unknown field `.nmae` (did you mean `.name`?)
+sample: doesn't pass
+plan:
+1. .age >= 18 (cost 2, index on .age)
+2. .nmae = 'Ann' (cost 2, index on .nmae)
+Total cost: 4
+"
+        );
+        assert!(!check(".age >= 18", Some(&sample)).unwrap().1);
+        // Whether a quoted number is a mistake depends on the field, so only the sample tells.
+        assert!(!check(".code = '200'", None).unwrap().1);
+        assert!(check(".age = '18'", Some(&sample)).unwrap().1);
+        assert!(check(".age >=", None)
+            .unwrap_err()
+            .starts_with("invalid filter"));
+    }
+
+    #[test]
+    fn test_follow() {
+        let path = std::env::temp_dir().join(format!("jsf-{}.log.json", std::process::id()));
+        let write = |text: &str, append: bool| {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(&path)
+                .unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        let filter = compile(simple_json_filter::parse(".n > 1").unwrap());
+        let mut lines = Lines {
+            filter: &filter,
+            out: Vec::new(),
+        };
+        // Only the lines written after the file is opened are read.
+        write("{\"n\": 5}\n", false);
+        let mut follower = Follower::open(&path).unwrap();
+        follower.poll("log", &mut lines).unwrap();
+        assert!(lines.out.is_empty());
+        write("{\"n\": 1}\n{\"n\": 2}\n{\"n\"", true);
+        follower.poll("log", &mut lines).unwrap();
+        assert_eq!(String::from_utf8_lossy(&lines.out), "{\"n\": 2}\n");
+        // The last line is read once it is complete.
+        write(": 3}\n\n{\"n\": 0}\n", true);
+        follower.poll("log", &mut lines).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&lines.out),
+            "{\"n\": 2}\n{\"n\": 3}\n"
+        );
+        // A line that isn't UTF-8 is skipped.
+        lines.out.clear();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"n\": \"\xff\"}\n{\"n\": 6}\n")
+            .unwrap();
+        follower.poll("log", &mut lines).unwrap();
+        assert_eq!(String::from_utf8_lossy(&lines.out), "{\"n\": 6}\n");
+        lines.out.clear();
+        write("{\"n\": 4}\n", false);
+        follower.poll("log", &mut lines).unwrap();
+        assert_eq!(String::from_utf8_lossy(&lines.out), "{\"n\": 4}\n");
+        // A file rotated by renaming is read to its end, then the new file from its start.
+        lines.out.clear();
+        write("{\"n\": 8}\n", true);
+        let rotated = path.with_extension("1");
+        std::fs::rename(&path, &rotated).unwrap();
+        write("{\"n\": 7}\n", false);
+        follower.poll("log", &mut lines).unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            String::from_utf8_lossy(&lines.out),
+            "{\"n\": 8}\n{\"n\": 7}\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+
+    #[test]
+    fn test_stamps() {
+        let path = std::env::temp_dir().join(format!("jsf-{}-stamps.json", std::process::id()));
+        let files = [path.clone()];
+        assert_eq!(stamps(&files), [None]);
+        std::fs::write(&path, "{}\n").unwrap();
+        let written = stamps(&files);
+        assert!(written[0].is_some());
+        std::fs::write(&path, "{}\n{}\n").unwrap();
+        assert_ne!(stamps(&files), written);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::parser::{self, ParseError, ParseLimits};
+use crate::{CompiledFilter, Filter};
+
+/// A cache of compiled filters keyed by their filter string, so a service that receives the same
+/// filter strings again and again parses and compiles each one only once.
+///
+/// The cache holds at most `capacity` filters. When it is full, the least recently used filter is
+/// evicted to make room for a new one. Filter strings that don't parse aren't cached.
+///
+/// Looking a filter up takes `&mut self`; to share a cache between threads, put it in a `Mutex`.
+#[derive(Debug)]
+pub struct FilterCache {
+    capacity: usize,
+    limits: ParseLimits,
+    entries: HashMap<String, Entry>,
+    /// The filter strings by when they were last used, oldest first.
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+#[derive(Debug)]
+struct Entry {
+    filter: Arc<CompiledFilter<'static>>,
+    used: u64,
+}
+
+/// How often a [`FilterCache`] found the filters it was asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The lookups that found the filter in the cache.
+    pub hits: u64,
+    /// The lookups that had to parse the filter string, including those that failed to parse.
+    pub misses: u64,
+    /// The filters removed to make room for others.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// The share of lookups that were hits, between 0 and 1, or 0 before any lookup.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+impl FilterCache {
+    /// Creates an empty cache holding at most `capacity` filters. Filter strings are parsed with
+    /// `ParseLimits::default()`.
+    pub fn new(capacity: usize) -> FilterCache {
+        FilterCache {
+            capacity,
+            limits: ParseLimits::default(),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Parses filter strings with `limits` instead of `ParseLimits::default()`.
+    pub fn with_parse_limits(mut self, limits: ParseLimits) -> FilterCache {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the compiled filter for a filter string, parsing and compiling it if it isn't cached.
+    pub fn get(&mut self, filter_string: &str) -> Result<Arc<CompiledFilter<'static>>, ParseError> {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(filter_string) {
+            self.stats.hits += 1;
+            let key = self
+                .recency
+                .remove(&entry.used)
+                .expect("every entry has a recency");
+            entry.used = self.clock;
+            self.recency.insert(self.clock, key);
+            return Ok(entry.filter.clone());
+        }
+        self.stats.misses += 1;
+        let filters = parser::parse_with_limits(filter_string, &self.limits)?;
+        let filter = Arc::new(CompiledFilter::new(Filter::into_owned_all(filters)));
+        if self.capacity == 0 {
+            return Ok(filter);
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+                self.stats.evictions += 1;
+            }
+        }
+        self.recency.insert(self.clock, filter_string.to_string());
+        self.entries.insert(
+            filter_string.to_string(),
+            Entry {
+                filter: filter.clone(),
+                used: self.clock,
+            },
+        );
+        Ok(filter)
+    }
+
+    /// The number of filters in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache holds no filters.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The most filters the cache holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The hits, misses and evictions since the cache was created or its stats last reset.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Sets the hits, misses and evictions back to 0.
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Removes every filter from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_get() {
+        let mut cache = FilterCache::new(2);
+        let filter = cache.get(".a = 1").unwrap();
+        assert!(filter.matches(&json!({ "a": 1 })));
+        assert!(Arc::ptr_eq(&filter, &cache.get(".a = 1").unwrap()));
+        assert!(cache.get(".a =").is_err());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                evictions: 0
+            }
+        );
+        assert!((cache.stats().hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_get_evicts_least_recently_used() {
+        let mut cache = FilterCache::new(2);
+        let a = cache.get(".a = 1").unwrap();
+        cache.get(".b = 1").unwrap();
+        // Using `.a = 1` again makes `.b = 1` the least recently used.
+        cache.get(".a = 1").unwrap();
+        cache.get(".c = 1").unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(Arc::ptr_eq(&a, &cache.get(".a = 1").unwrap()));
+        cache.get(".b = 1").unwrap();
+        assert_eq!(cache.stats().misses, 4);
+    }
+
+    #[test]
+    fn test_get_with_parse_limits() {
+        let mut cache = FilterCache::new(2).with_parse_limits(ParseLimits {
+            max_clauses: 1,
+            ..ParseLimits::default()
+        });
+        assert!(cache.get(".a = 1").is_ok());
+        assert!(cache.get(".a = 1 AND .b = 2").is_err());
+        let mut cache = FilterCache::new(0);
+        assert!(cache.get(".a = 1").is_ok());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::string::String;
+
+use serde_json::Value;
+
+/// How keys written in filters match the keys of objects that don't have them as written, set in
+/// [`crate::ApplyOptions::key_case`], for data sources that disagree on the casing of their keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyCase {
+    /// Keys only match as written.
+    #[default]
+    Exact,
+    /// A missing key is looked up in snake_case, so `.createdAt` reads `created_at`.
+    Snake,
+    /// A missing key is looked up in camelCase, so `.created_at` reads `createdAt`.
+    Camel,
+    /// A missing key matches any key of the same words in any case or separators, so `.created_at`
+    /// reads `createdAt`, `CreatedAt` or `created-at`. The first such key of the object is read.
+    Any,
+}
+
+/// Looks a key up in an object, trying the other forms `case` allows when it isn't there as written.
+pub(crate) fn get<'v>(v: &'v Value, key: &str, case: KeyCase) -> Option<&'v Value> {
+    let found = v.get(key);
+    if found.is_some() || case == KeyCase::Exact {
+        return found;
+    }
+    let object = v.as_object()?;
+    match case {
+        KeyCase::Snake => object.get(&snake_case(key)),
+        KeyCase::Camel => object.get(&camel_case(key)),
+        _ => {
+            let words = snake_case(key);
+            object
+                .iter()
+                .find(|(other, _)| snake_case(other) == words)
+                .map(|(_, value)| value)
+        }
+    }
+}
+
+/// Writes a key in snake_case, starting a word at each uppercase letter after a lowercase letter or
+/// digit, and at `-`.
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    let mut previous = None;
+    for c in key.chars() {
+        if c == '-' {
+            snake.push('_');
+        } else if c.is_uppercase() {
+            if previous.is_some_and(|p: char| p.is_lowercase() || p.is_ascii_digit()) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+        previous = Some(c);
+    }
+    snake
+}
+
+/// Writes a key in camelCase, joining the words separated by `_` or `-` and capitalizing each after
+/// the first. Separators before the first word are kept, as in `_id`.
+fn camel_case(key: &str) -> String {
+    let mut camel = String::with_capacity(key.len());
+    let mut capitalize = false;
+    for c in key.chars() {
+        if (c == '_' || c == '-') && camel.chars().any(|c| c != '_' && c != '-') {
+            capitalize = true;
+        } else if capitalize {
+            camel.extend(c.to_uppercase());
+            capitalize = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_with, parse, ApplyOptions};
+    use serde_json::json;
+
+    #[test]
+    fn test_case_conversions() {
+        assert_eq!(snake_case("createdAt"), "created_at");
+        assert_eq!(snake_case("HTTPStatus2Code"), "httpstatus2_code");
+        assert_eq!(snake_case("sent-at"), "sent_at");
+        assert_eq!(camel_case("created_at"), "createdAt");
+        assert_eq!(camel_case("_id"), "_id");
+        assert_eq!(camel_case("sent-at_utc"), "sentAtUtc");
+    }
+
+    #[test]
+    fn test_key_case() {
+        let v = json!({ "createdAt": 1, "updated_at": 2, "Deleted-At": 3, "user_info": { "firstName": "Ann" } });
+        let passes = |filter_string, key_case| {
+            let options = ApplyOptions {
+                key_case,
+                ..Default::default()
+            };
+            apply_with(&v, &parse(filter_string).unwrap(), &options)
+        };
+        assert!(!passes(".created_at = 1", KeyCase::Exact));
+        assert!(passes(
+            ".created_at = 1 AND .updated_at = 2",
+            KeyCase::Camel
+        ));
+        assert!(!passes(".updatedAt = 2", KeyCase::Camel));
+        assert!(passes(".updatedAt = 2 AND .createdAt = 1", KeyCase::Snake));
+        assert!(passes(".userInfo.first_name = 'Ann'", KeyCase::Any));
+        assert!(passes(".deleted_at = 3 AND .DeletedAt = 3", KeyCase::Any));
+        assert!(!passes(".deleted = 3", KeyCase::Any));
+    }
+}
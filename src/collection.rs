@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::expr::Path;
+
+/// Keeps the first of the Values that have the same value at `path`.
+///
+/// Values are the same when they serialize to the same JSON, so `1` and `1.0` are different, but
+/// objects with the same keys in a different order are the same. Values without the path count as
+/// having null there.
+pub(crate) fn distinct_by(values: Vec<Value>, path: &str) -> Vec<Value> {
+    let path = Path::parse(path);
+    let mut seen = HashSet::new();
+    values
+        .into_iter()
+        .filter(|v| {
+            let key = path
+                .resolve(v)
+                .map_or_else(|| "null".to_string(), |key| key.to_string());
+            seen.insert(key)
+        })
+        .collect()
+}
+
+/// Which Values of the left side a join keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only left Values that have a matching right Value.
+    Inner,
+    /// Every left Value, merged with the matching right Values if there are any.
+    Left,
+}
+
+/// Joins two lists of Values where the value at `on_left` in a left Value equals the value at
+/// `on_right` in a right Value.
+///
+/// A left Value with several matches yields one merged Value per match. Keys are compared as JSON,
+/// and missing or null keys never match.
+pub(crate) fn join(
+    left: &[Value],
+    right: &[Value],
+    on_left: &str,
+    on_right: &str,
+    kind: JoinKind,
+) -> Vec<Value> {
+    let on_left = Path::parse(on_left);
+    let on_right = Path::parse(on_right);
+    let key = |path: &Path, v: &Value| {
+        path.resolve(v)
+            .filter(|key| !key.is_null())
+            .map(|key| key.to_string())
+    };
+
+    let mut index: HashMap<String, Vec<&Value>> = HashMap::new();
+    for r in right {
+        if let Some(key) = key(&on_right, r) {
+            index.entry(key).or_default().push(r);
+        }
+    }
+
+    let mut joined = Vec::new();
+    for l in left {
+        match key(&on_left, l).and_then(|key| index.get(&key)) {
+            Some(matches) => joined.extend(matches.iter().map(|r| merge(l, r))),
+            None if kind == JoinKind::Left => joined.push(l.clone()),
+            None => {}
+        }
+    }
+    joined
+}
+
+/// Adds the fields of the object `right` to a copy of the object `left`. Fields `left` already has
+/// are kept. If either is not an object, `left` is returned as it is.
+fn merge(left: &Value, right: &Value) -> Value {
+    let mut merged = left.clone();
+    if let (Value::Object(merged), Value::Object(right)) = (&mut merged, right) {
+        for (key, value) in right {
+            merged.entry(key.as_str()).or_insert_with(|| value.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_distinct_by() {
+        let values = vec![
+            json!({ "id": 1, "user": { "email": "a@example.com" } }),
+            json!({ "id": 2, "user": { "email": "b@example.com" } }),
+            json!({ "id": 3, "user": { "email": "a@example.com" } }),
+            json!({ "id": 4, "user": {} }),
+            json!({ "id": 5, "user": { "email": null } }),
+        ];
+        let ids: Vec<Value> = distinct_by(values, ".user.email")
+            .into_iter()
+            .map(|v| v["id"].clone())
+            .collect();
+        assert_eq!(ids, [json!(1), json!(2), json!(4)]);
+    }
+
+    #[test]
+    fn test_join() {
+        let orders = [
+            json!({ "id": 1, "customer_id": 10 }),
+            json!({ "id": 2, "customer_id": 20 }),
+            json!({ "id": 3 }),
+        ];
+        let customers = [
+            json!({ "id": 10, "name": "ada" }),
+            json!({ "id": 10, "name": "ada (duplicate)" }),
+            json!({ "id": 30, "name": "bob" }),
+        ];
+        assert_eq!(
+            join(&orders, &customers, ".customer_id", ".id", JoinKind::Inner),
+            [
+                json!({ "id": 1, "customer_id": 10, "name": "ada" }),
+                json!({ "id": 1, "customer_id": 10, "name": "ada (duplicate)" }),
+            ]
+        );
+        assert_eq!(
+            join(
+                &orders,
+                &customers[2..],
+                ".customer_id",
+                ".id",
+                JoinKind::Left
+            ),
+            orders
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::fs::File;
+
+use arrow_json::writer::LineDelimited;
+use arrow_json::WriterBuilder;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{ConvertedType, LogicalType, Type as PhysicalType};
+use parquet::file::metadata::{ColumnChunkMetaData, RowGroupMetaData};
+use parquet::file::statistics::Statistics;
+use serde_json::Value;
+
+use crate::translate::is_plain_key;
+use crate::{ApplyOptions, CompiledFilter, ZoneMap};
+
+/// An error reading a Parquet file in [`CompiledFilter::filter_parquet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParquetError {
+    message: String,
+}
+
+impl ParquetError {
+    fn new(message: impl Into<String>) -> ParquetError {
+        ParquetError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParquetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParquetError {}
+
+/// Reads the rows of a Parquet file passing a filter, as JSON objects, skipping the row groups whose
+/// column statistics show none of their rows can pass it. Returns the rows and how many row groups
+/// were skipped.
+pub(crate) fn scan(
+    file: File,
+    filter: &CompiledFilter,
+    options: &ApplyOptions,
+) -> Result<(Vec<Value>, usize), ParquetError> {
+    let invalid = |error: &dyn fmt::Display| ParquetError::new(format!("invalid Parquet: {error}"));
+    let builder =
+        ParquetRecordBatchReaderBuilder::try_new(file).map_err(|error| invalid(&error))?;
+    let groups = builder.metadata().row_groups();
+    let read: Vec<usize> = (0..groups.len())
+        .filter(|&i| filter.may_match(&zone_map(&groups[i]), options))
+        .collect();
+    let skipped = groups.len() - read.len();
+    let reader = builder
+        .with_row_groups(read)
+        .build()
+        .map_err(|error| invalid(&error))?;
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch = batch.map_err(|error| invalid(&error))?;
+        // Null columns are written as null fields, rather than left out, as `.field = null` tells
+        // the two apart.
+        let mut writer = WriterBuilder::new()
+            .with_explicit_nulls(true)
+            .build::<_, LineDelimited>(Vec::new());
+        writer.write(&batch).map_err(|error| invalid(&error))?;
+        writer.finish().map_err(|error| invalid(&error))?;
+        for line in writer.into_inner().split(|&b| b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let row: Value = serde_json::from_slice(line).map_err(|error| invalid(&error))?;
+            if filter.matches_with(&row, options) {
+                rows.push(row);
+            }
+        }
+    }
+    Ok((rows, skipped))
+}
+
+/// The ranges of the numbers at the paths of a row group's columns, from their statistics.
+fn zone_map(group: &RowGroupMetaData) -> ZoneMap {
+    let mut zone = ZoneMap::new();
+    for column in group.columns() {
+        let Some(path) = column_path(column) else {
+            continue;
+        };
+        let Some(statistics) = column.statistics() else {
+            continue;
+        };
+        if statistics.null_count_opt() == Some(group.num_rows() as u64) {
+            zone = zone.without_numbers(&path);
+            continue;
+        }
+        let range = match statistics {
+            _ if !signed_number(column) => None,
+            Statistics::Int32(s) => s
+                .min_opt()
+                .zip(s.max_opt())
+                .map(|(&min, &max)| (min as f64, max as f64)),
+            Statistics::Int64(s) => s
+                .min_opt()
+                .zip(s.max_opt())
+                .map(|(&min, &max)| (min as f64, max as f64)),
+            Statistics::Float(s) => s
+                .min_opt()
+                .zip(s.max_opt())
+                .map(|(&min, &max)| (min as f64, max as f64)),
+            Statistics::Double(s) => s.min_opt().zip(s.max_opt()).map(|(&min, &max)| (min, max)),
+            Statistics::Boolean(_) => {
+                zone = zone.without_numbers(&path);
+                None
+            }
+            _ => None,
+        };
+        if let Some((min, max)) = range {
+            zone = zone.with_range(&path, min, max);
+        }
+    }
+    zone
+}
+
+/// The path of the field a column holds, such as `.user.age`, unless the column is of a list, or a
+/// part of its path isn't a plain name.
+fn column_path(column: &ColumnChunkMetaData) -> Option<String> {
+    let descr = column.column_descr();
+    if descr.max_rep_level() > 0 {
+        return None;
+    }
+    let mut path = String::new();
+    for part in descr.path().parts() {
+        if !is_plain_key(part) {
+            return None;
+        }
+        path.push('.');
+        path.push_str(part);
+    }
+    Some(path)
+}
+
+/// Returns whether a column's values are written to JSON as the numbers its statistics give,
+/// rather than as strings such as dates, or as unsigned numbers the statistics order as signed.
+fn signed_number(column: &ColumnChunkMetaData) -> bool {
+    let descr = column.column_descr();
+    match descr.physical_type() {
+        PhysicalType::INT32 | PhysicalType::INT64 => {
+            let logical = match descr.logical_type_ref() {
+                None => true,
+                Some(LogicalType::Integer(int)) => int.is_signed,
+                Some(_) => false,
+            };
+            logical
+                && matches!(
+                    descr.converted_type(),
+                    ConvertedType::NONE
+                        | ConvertedType::INT_8
+                        | ConvertedType::INT_16
+                        | ConvertedType::INT_32
+                        | ConvertedType::INT_64
+                )
+        }
+        PhysicalType::FLOAT | PhysicalType::DOUBLE => descr.logical_type_ref().is_none(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+    use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_scan() {
+        let path = std::env::temp_dir().join(format!("filter-{}.parquet", std::process::id()));
+        let batch = RecordBatch::try_from_iter([
+            (
+                "id",
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])) as ArrayRef,
+            ),
+            (
+                "name",
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d", "e"])) as ArrayRef,
+            ),
+            (
+                "score",
+                Arc::new(Float64Array::from(vec![
+                    Some(0.5),
+                    None,
+                    Some(2.5),
+                    None,
+                    None,
+                ])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let properties = WriterProperties::builder()
+            .set_max_row_group_row_count(Some(2))
+            .build();
+        let mut writer = ArrowWriter::try_new(
+            File::create(&path).unwrap(),
+            batch.schema(),
+            Some(properties),
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let read = |filter_string| {
+            let filter = compile(crate::parse(filter_string).unwrap());
+            scan(
+                File::open(&path).unwrap(),
+                &filter,
+                &ApplyOptions::default(),
+            )
+            .unwrap()
+        };
+        let (rows, skipped) = read(".id > 2 AND .name != 'd'");
+        assert_eq!(
+            rows,
+            [
+                json!({ "id": 3, "name": "c", "score": 2.5 }),
+                json!({ "id": 5, "name": "e", "score": null })
+            ]
+        );
+        assert_eq!(skipped, 1);
+        // The last row group has no scores, and the second no scores above 2.5.
+        let (rows, skipped) = read(".score > 1");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(skipped, 2);
+        assert_eq!(read(".score = null").0.len(), 3);
+        std::fs::remove_file(&path).unwrap();
+
+        let invalid = std::env::temp_dir().join(format!("invalid-{}.parquet", std::process::id()));
+        std::fs::write(&invalid, "not parquet").unwrap();
+        let filter = compile(crate::parse(".id > 2").unwrap());
+        let error = scan(
+            File::open(&invalid).unwrap(),
+            &filter,
+            &ApplyOptions::default(),
+        );
+        std::fs::remove_file(&invalid).unwrap();
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid Parquet"));
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::expr::{self, Path};
+use crate::version;
+
+/// Orders the values of a path against the values they are compared with, in place of the usual
+/// comparison, for clauses comparing the path with `=`, `!=`, `<`, `<=`, `>` or `>=`.
+///
+/// Closures taking two Values and returning an `Option<Ordering>` are comparators.
+pub trait Comparator: Send + Sync {
+    /// Orders the value at the path, on the left of the clause, against the value it is compared
+    /// with, or returns `None` if they can't be compared, which fails the clause, even with `!=`.
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering>;
+}
+
+impl<F> Comparator for F
+where
+    F: Fn(&Value, &Value) -> Option<Ordering> + Send + Sync,
+{
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering> {
+        self(left, right)
+    }
+}
+
+/// Compares strings ignoring case, so `.name = 'alice'` passes `Alice`. Other values can't be compared.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaseInsensitive;
+
+impl Comparator for CaseInsensitive {
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering> {
+        Some(
+            left.as_str()?
+                .to_lowercase()
+                .cmp(&right.as_str()?.to_lowercase()),
+        )
+    }
+}
+
+/// Compares strings as semantic versions, as the `SEMVER` operators do, so `.version >= '1.10.0'`
+/// passes `1.10.2` but not `1.9.0`. Other values can't be compared.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SemVer;
+
+impl Comparator for SemVer {
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering> {
+        version::compare_versions(left.as_str()?, right.as_str()?)
+    }
+}
+
+/// The comparators of paths, set in [`crate::ApplyOptions::comparators`]. A comparator applies to the
+/// clauses whose left side is its path, such as `.name = 'alice'`, but not to expressions over it,
+/// such as `coalesce(.name, 'x') = 'alice'`.
+#[derive(Clone, Default)]
+pub struct Comparators {
+    comparators: Vec<(Path<'static>, Arc<dyn Comparator>)>,
+}
+
+impl Comparators {
+    /// Creates a set without comparators.
+    pub fn new() -> Comparators {
+        Comparators::default()
+    }
+
+    /// Compares the values of a path such as `.version` with `comparator`. A path given again
+    /// replaces its earlier comparator.
+    pub fn with_comparator(
+        mut self,
+        path: &str,
+        comparator: impl Comparator + 'static,
+    ) -> Comparators {
+        let path = Path::parse(path).into_owned();
+        self.comparators
+            .retain(|(known, _)| !expr::same_path(known, &path));
+        self.comparators.push((path, Arc::new(comparator)));
+        self
+    }
+
+    /// Returns whether there are no comparators.
+    pub fn is_empty(&self) -> bool {
+        self.comparators.is_empty()
+    }
+
+    /// The comparator of a path, if it has one.
+    pub(crate) fn get(&self, path: &Path) -> Option<&dyn Comparator> {
+        self.comparators
+            .iter()
+            .find(|(known, _)| expr::same_path(known, path))
+            .map(|(_, comparator)| comparator.as_ref())
+    }
+}
+
+impl fmt::Debug for Comparators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.comparators.iter().map(|(path, _)| path.to_string()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ApplyOptions, CompiledFilter};
+    #[cfg(feature = "std")]
+    use crate::{IndexKind, IndexedCollection};
+    use serde_json::json;
+
+    #[test]
+    fn test_comparators() {
+        let options = ApplyOptions {
+            comparators: Comparators::new()
+                .with_comparator(".name", CaseInsensitive)
+                .with_comparator(".app.version", SemVer)
+                .with_comparator(".size", |l: &Value, r: &Value| {
+                    Some(l.as_f64()?.abs().total_cmp(&r.as_f64()?.abs()))
+                }),
+            ..Default::default()
+        };
+        let values = [
+            json!({ "name": "Alice", "app": { "version": "1.10.2" }, "size": -7 }),
+            json!({ "name": "bob", "app": { "version": "1.9.0" }, "size": 2 }),
+            json!({ "name": 1, "app": {}, "size": "big" }),
+        ];
+        let passing = |filter_string| {
+            let filters = CompiledFilter::new(parse(filter_string).unwrap());
+            let passed: Vec<bool> = values
+                .iter()
+                .map(|v| filters.matches_with(v, &options))
+                .collect();
+            assert_eq!(
+                filters.matches_batch(&values, &options),
+                passed,
+                "{filter_string}"
+            );
+            passed
+        };
+        assert_eq!(passing(".name = 'alice'"), [true, false, false]);
+        assert_eq!(passing(".name != 'ALICE'"), [false, true, false]);
+        assert_eq!(passing(".app.version >= '1.10.0'"), [true, false, false]);
+        assert_eq!(passing(".size > 5"), [true, false, false]);
+        // Other operators compare as usual.
+        assert_eq!(passing(".name DEEP_EQ 'alice'"), [false, false, false]);
+        #[cfg(feature = "std")]
+        {
+            let collection =
+                IndexedCollection::new(values.to_vec()).with_index(".name", IndexKind::Hash);
+            let filters = parse(".name = 'BOB'").unwrap();
+            assert_eq!(collection.query_with(&filters, &options), [&values[1]]);
+        }
+        assert_eq!(
+            format!("{:?}", options.comparators),
+            r#"{".name", ".app.version", ".size"}"#
+        );
+    }
+}
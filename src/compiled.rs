@@ -0,0 +1,580 @@
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+#[cfg(feature = "std")]
+use crate::audit;
+use crate::batch::{self, BitVec};
+#[cfg(feature = "arrow")]
+use crate::columnar::{self, ParquetError};
+use crate::encoding;
+use crate::eval::{self, Context};
+use crate::expr::{Arena, Expr, Segment};
+use crate::parser::{self, ParseError};
+#[cfg(feature = "std")]
+use crate::raw;
+use crate::zone;
+#[cfg(feature = "std")]
+use crate::Auditor;
+use crate::{
+    ApplyOptions, Compound, DecodeError, EvalError, EvalObserver, Filter, PluginHost, ZoneMap,
+};
+
+/// Reads an encoded filter, parsing its clauses with `parse`.
+fn decode<'t>(
+    bytes: &'t [u8],
+    parse: impl FnOnce(&'t str) -> Result<Vec<Filter<'t>>, ParseError>,
+) -> Result<CompiledFilter<'static>, DecodeError> {
+    let (text, limits) = encoding::decode(bytes)?;
+    let filters = parse(text)
+        .map_err(|error| DecodeError::new(format!("invalid encoded filter: {error}")))?;
+    Ok(CompiledFilter {
+        filters: Filter::into_owned_all(filters),
+        limits,
+    })
+}
+
+/// The number of elements an array is assumed to have when estimating the cost of `ANY` and `ALL`.
+const ASSUMED_ELEMENTS: u32 = 4;
+
+/// Filters prepared for being applied to many Values, with their clauses reordered so the cheapest
+/// are evaluated first.
+///
+/// Clauses are joined by `AND` and have no side effects, so their order never changes which Values
+/// pass, only how soon a failing Value is rejected.
+///
+/// Compiled filters are `Send` and `Sync`, and evaluating one only reads it, so worker threads can
+/// share one behind an `Arc`. Use [`crate::Filter::into_owned`] on the clauses, or a
+/// [`crate::FilterCache`], to get a `CompiledFilter<'static>` that doesn't borrow the filter string.
+#[derive(Debug)]
+pub struct CompiledFilter<'a> {
+    filters: Vec<Filter<'a>>,
+    limits: PathLimits,
+}
+
+/// Bounds on how much of a Value paths may explore, set with [`CompiledFilter::with_path_limits`] so
+/// evaluating filters stays cheap on adversarial documents, such as deeply nested arrays of arrays.
+///
+/// A path that exceeds a limit is treated as missing, so its clause doesn't match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLimits {
+    /// The most steps a path may take, counting each key and each `[*]`.
+    pub max_depth: usize,
+    /// The most array elements `[*]` wildcards may visit while evaluating the filters against one
+    /// Value, across every path.
+    pub max_nodes: usize,
+}
+
+impl PathLimits {
+    pub(crate) const UNLIMITED: PathLimits = PathLimits {
+        max_depth: usize::MAX,
+        max_nodes: usize::MAX,
+    };
+}
+
+impl Default for PathLimits {
+    /// Limits that ordinary paths stay well within: 32 steps and 10,000 elements per Value.
+    fn default() -> PathLimits {
+        PathLimits {
+            max_depth: 32,
+            max_nodes: 10_000,
+        }
+    }
+}
+
+impl<'a> CompiledFilter<'a> {
+    pub(crate) fn new(mut filters: Vec<Filter<'a>>) -> CompiledFilter<'a> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("compile", clauses = filters.len()).entered();
+        optimize(&mut filters);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            cost = filters.iter().map(cost).sum::<u32>(),
+            order = %crate::format::Clauses(&filters),
+            "compiled filter"
+        );
+        CompiledFilter {
+            filters,
+            limits: PathLimits::UNLIMITED,
+        }
+    }
+
+    /// Bounds how deep paths may go and how many array elements wildcards may visit in each Value.
+    /// Without limits, paths may explore the whole Value.
+    pub fn with_path_limits(mut self, limits: PathLimits) -> CompiledFilter<'a> {
+        self.limits = limits;
+        self
+    }
+
+    /// Encodes the filter as bytes that [`CompiledFilter::from_bytes`] reads back, so a filter can be
+    /// compiled and validated once and sent to the processes that evaluate it. The encoding starts
+    /// with a version number, and bytes written by one version of the crate can be read by later ones.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encoding::encode(&self.filters, self.limits)
+    }
+
+    /// Reads a filter encoded with [`CompiledFilter::to_bytes`], with its clauses in the same order and
+    /// the same path limits. Fails if the bytes aren't an encoded filter, or use a feature, such as
+    /// `WITHIN` with the `geo` feature, that this build of the crate doesn't have.
+    ///
+    /// Filters calling plugin functions are read with [`CompiledFilter::from_bytes_with_plugins`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompiledFilter<'static>, DecodeError> {
+        decode(bytes, parser::parse_filters)
+    }
+
+    /// Reads a filter encoded with [`CompiledFilter::to_bytes`] like [`CompiledFilter::from_bytes`],
+    /// accepting calls to the functions of `plugins`. Fails if the filter calls a function that
+    /// `plugins` doesn't have.
+    pub fn from_bytes_with_plugins(
+        bytes: &[u8],
+        plugins: &PluginHost,
+    ) -> Result<CompiledFilter<'static>, DecodeError> {
+        decode(bytes, |text| parser::parse_with_plugins(text, plugins))
+    }
+
+    /// The clauses, in the order they are evaluated.
+    pub fn filters(&self) -> &[Filter<'a>] {
+        &self.filters
+    }
+
+    /// The paths the clauses read, sorted and without duplicates, such as `.user.name`. Paths read by the
+    /// clauses of `ANY` and `ALL` are given under their array, as in `.items[*].qty`, and `.` means
+    /// the filter reads the Value as a whole.
+    #[cfg(feature = "std")]
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = raw::referenced_paths(&self.filters)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Returns whether the Value `v` passes every clause, like [`crate::apply`].
+    pub fn matches(&self, v: &Value) -> bool {
+        self.matches_with(v, &ApplyOptions::default())
+    }
+
+    /// Returns whether the Value `v` passes every clause, like [`crate::apply_with`].
+    pub fn matches_with(&self, v: &Value, options: &ApplyOptions) -> bool {
+        eval::apply(
+            v,
+            &self.filters,
+            &Context::with_limits(options, self.limits),
+        )
+    }
+
+    /// Returns whether the Value `v` passes every clause within a budget of fuel, like
+    /// [`crate::apply_with_budget`].
+    pub fn matches_with_budget(
+        &self,
+        v: &Value,
+        options: &ApplyOptions,
+        budget: u64,
+    ) -> Result<bool, EvalError> {
+        eval::apply_budgeted(
+            v,
+            &self.filters,
+            &Context::with_limits(options, self.limits).with_budget(budget),
+        )
+    }
+
+    /// Returns whether a JSON document given as text passes every clause, building only the values the
+    /// clauses read. The top-level fields of an object are read one at a time, skipping the values of
+    /// those no clause reads, and each clause is evaluated as soon as its fields are read. The result
+    /// is the same as parsing the whole document: a repeated field has its last value.
+    ///
+    /// Returns an error if the document isn't valid JSON.
+    #[cfg(feature = "std")]
+    pub fn matches_str(&self, json: &str) -> Result<bool, serde_json::Error> {
+        raw::matches(
+            json,
+            &self.filters,
+            &Context::with_limits(&ApplyOptions::default(), self.limits),
+        )
+    }
+
+    /// Returns whether each Value of a batch passes every clause, like calling
+    /// [`CompiledFilter::matches_with`] for each of them. The batch is evaluated clause by clause, so
+    /// batches of Values with the same shape are evaluated faster: each clause only visits the Values
+    /// the earlier clauses passed, and clauses comparing a field with a number compare a column of
+    /// the field's numbers at once.
+    pub fn matches_batch(&self, values: &[Value], options: &ApplyOptions) -> Vec<bool> {
+        let mask = batch::matches(values, &self.filters, options, self.limits);
+        (0..mask.len()).map(|i| mask.get(i)).collect()
+    }
+
+    /// Evaluates a batch of Values like [`CompiledFilter::matches_batch`] with the default options,
+    /// returning a mask with the bits of the passing Values set. Masks from several filters over the
+    /// same batch can be combined with [`BitVec::union`] and [`BitVec::intersection`].
+    pub fn eval_mask(&self, values: &[Value]) -> BitVec {
+        batch::matches(values, &self.filters, &ApplyOptions::default(), self.limits)
+    }
+
+    /// Returns whether any Value of a chunk, described by the ranges of its numbers in `zone`, may pass
+    /// every clause. When it returns `false`, the chunk can be skipped without reading it; when it
+    /// returns `true`, its Values still have to be evaluated.
+    pub fn may_match(&self, zone: &ZoneMap, options: &ApplyOptions) -> bool {
+        zone::may_match(&self.filters, zone, options)
+    }
+
+    /// Reads the rows of a Parquet file that pass every clause, as JSON objects. Row groups whose
+    /// column statistics show that none of their rows can pass, as [`CompiledFilter::may_match`]
+    /// decides, aren't read.
+    #[cfg(feature = "arrow")]
+    pub fn filter_parquet(
+        &self,
+        file: std::fs::File,
+        options: &ApplyOptions,
+    ) -> Result<Vec<Value>, ParquetError> {
+        columnar::scan(file, self, options).map(|(rows, _)| rows)
+    }
+
+    /// Returns whether the Value `v` passes every clause like [`CompiledFilter::matches_with`], reporting
+    /// the outcome to `observer`. Rejecting clauses are identified by their index in
+    /// [`CompiledFilter::filters`].
+    pub fn matches_observed(
+        &self,
+        v: &Value,
+        options: &ApplyOptions,
+        observer: &dyn EvalObserver,
+    ) -> bool {
+        eval::apply(
+            v,
+            &self.filters,
+            &Context::with_limits(options, self.limits).observed(observer),
+        )
+    }
+
+    /// Returns whether the Value `v` passes every clause like [`CompiledFilter::matches_with`], recording
+    /// the evaluation to the auditor's log. Rejecting clauses are identified by their index in
+    /// [`CompiledFilter::filters`].
+    #[cfg(feature = "std")]
+    pub fn matches_audited(&self, v: &Value, options: &ApplyOptions, auditor: &Auditor) -> bool {
+        audit::apply(v, &self.filters, options, self.limits, auditor)
+    }
+}
+
+/// Orders clauses by their estimated cost, cheapest first, including the clauses nested in
+/// conditionals and quantifiers. Clauses of equal cost keep their order.
+fn optimize(filters: &mut [Filter]) {
+    for filter in filters.iter_mut() {
+        match &mut filter.compound {
+            Some(Compound::Quantified { filters, .. } | Compound::Match { filters, .. }) => {
+                optimize(filters)
+            }
+            Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                optimize(condition);
+                optimize(then);
+                optimize(otherwise);
+            }
+            _ => {}
+        }
+    }
+    filters.sort_by_cached_key(cost);
+}
+
+/// Estimates the cost of evaluating a clause against one Value, in arbitrary units where looking up
+/// a field and comparing it with a literal costs 2.
+pub(crate) fn cost(filter: &Filter) -> u32 {
+    let arena = &filter.arena;
+    match &filter.compound {
+        Some(Compound::Quantified { array, filters, .. }) => {
+            expr_cost(arena, array) + ASSUMED_ELEMENTS * filters.iter().map(cost).sum::<u32>()
+        }
+        Some(Compound::Fuzzy { input, target, .. }) => {
+            8 + expr_cost(arena, input) + expr_cost(arena, target)
+        }
+        #[cfg(feature = "geo")]
+        Some(Compound::Within { location, .. }) => 5 + expr_cost(arena, location),
+        Some(Compound::If {
+            condition,
+            then,
+            otherwise,
+        }) => {
+            let branch = |filters: &[Filter]| filters.iter().map(cost).sum::<u32>();
+            branch(condition) + branch(then).max(branch(otherwise))
+        }
+        // Every clause is evaluated to compute the score.
+        Some(Compound::Match { filters, .. }) => filters.iter().map(cost).sum(),
+        None => {
+            let left = match &filter.lhs {
+                Some(expr) => expr_cost(arena, expr),
+                None => 1,
+            };
+            let right = match (&filter.rhs, &filter.value_field) {
+                (Some(expr), _) => expr_cost(arena, expr),
+                (None, Some(_)) => 1,
+                (None, None) => 0,
+            };
+            operator_cost(&filter.operator) + left + right
+        }
+    }
+}
+
+fn operator_cost(operator: &str) -> u32 {
+    match operator {
+        "=" | "!=" | ">=" | ">" | "<=" | "<" => 1,
+        "IS UUID" | "IS NOT UUID" | "CONTAINS" | "IN" => 2,
+        "IN_CIDR" | "DEEP_EQ" | "MATCHES_OBJECT" => 3,
+        "HAS_ALL" | "HAS_ANY" | "SUBSET_OF" => 4,
+        "MATCHES_TEXT" => 6,
+        _ => 3,
+    }
+}
+
+fn expr_cost(arena: &Arena, expr: &Expr) -> u32 {
+    let cost = |expr| expr_cost(arena, expr);
+    match expr {
+        Expr::Path(path) if path.segments.contains(&Segment::Wildcard) => 4,
+        Expr::Path(_) => 1,
+        // Anchored paths also walk up to the enclosing Value.
+        Expr::Anchored(_, path) => 1 + cost(&Expr::Path(path.clone())),
+        Expr::Literal(_) => 0,
+        Expr::Mul(lhs, rhs) | Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            1 + cost(&arena[*lhs]) + cost(&arena[*rhs])
+        }
+        Expr::Shift(base, _) => 1 + cost(&arena[*base]),
+        #[cfg(feature = "chrono")]
+        Expr::Now => 2,
+        Expr::Call(_, args) => 1 + arena[*args].iter().map(cost).sum::<u32>(),
+        // The cost of a plugin's function is unknown, so it is taken to be that of a regex match.
+        Expr::Plugin(_, args) => 8 + arena[*args].iter().map(cost).sum::<u32>(),
+        Expr::Coalesce(exprs) | Expr::List(exprs) => arena[*exprs].iter().map(cost).sum(),
+        Expr::Capture(input, ..) => 8 + cost(&arena[*input]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_compile_orders_by_cost() {
+        let compiled = CompiledFilter::new(
+            parse(".bio MATCHES_TEXT 'rust' AND .tags HAS_ANY ('a', 'b') AND .age > 30 AND .name = 'x'")
+                .unwrap(),
+        );
+        let operators: Vec<_> = compiled
+            .filters()
+            .iter()
+            .map(|f| f.operator.as_ref())
+            .collect();
+        assert_eq!(operators, [">", "=", "HAS_ANY", "MATCHES_TEXT"]);
+        let v = json!({ "bio": "I write Rust", "tags": ["b"], "age": 40, "name": "x" });
+        assert!(compiled.matches(&v));
+        assert!(!compiled
+            .matches(&json!({ "bio": "I write Go", "tags": ["b"], "age": 40, "name": "x" })));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_paths() {
+        let compiled = CompiledFilter::new(
+            parse(".user?.name = 'ada' AND .n > .m AND ANY .items WHERE .qty > 1 AND abs(.n) < 9")
+                .unwrap(),
+        );
+        assert_eq!(
+            compiled.paths(),
+            [".items", ".items[*].qty", ".m", ".n", ".user.name"]
+        );
+        assert_eq!(CompiledFilter::new(parse(". = 1").unwrap()).paths(), ["."]);
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let compiled = CompiledFilter::new(
+            parse("ANY .items WHERE .qty > 1 AND .name = 'ann' AND .score > 2*.min").unwrap(),
+        )
+        .with_path_limits(PathLimits::default());
+        let decoded = CompiledFilter::from_bytes(&compiled.to_bytes()).unwrap();
+        assert_eq!(
+            crate::format::Clauses(decoded.filters()).to_string(),
+            crate::format::Clauses(compiled.filters()).to_string()
+        );
+        assert_eq!(decoded.limits, compiled.limits);
+        let v = json!({ "name": "ann", "score": 5, "min": 2, "items": [{ "qty": 2 }] });
+        assert!(decoded.matches(&v) && compiled.matches(&v));
+        assert_eq!(decoded.to_bytes(), compiled.to_bytes());
+
+        let mut bytes = compiled.to_bytes();
+        bytes.truncate(bytes.len() - 1);
+        assert!(CompiledFilter::from_bytes(&bytes).is_err());
+
+        // Escaped strings and names decode to the same clauses.
+        let compiled = CompiledFilter::new(
+            parse(
+                ".s = 'it\\'s' AND .t = \"a\\\\b\" AND .x-y = '' AND capture(.u, '(?P<n>x)', 'it\\'s') = 'x'",
+            )
+            .unwrap(),
+        );
+        let decoded = CompiledFilter::from_bytes(&compiled.to_bytes()).unwrap();
+        assert_eq!(
+            format!("{:?}", decoded.filters()),
+            format!("{:?}", compiled.filters())
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_with_plugins() {
+        struct Double;
+
+        impl crate::FunctionPlugin for Double {
+            fn name(&self) -> &str {
+                "double"
+            }
+
+            fn arity(&self) -> (usize, usize) {
+                (1, 1)
+            }
+
+            fn call(&self, args: &[Value]) -> Option<Value> {
+                Some(Value::from(args[0].as_f64()? * 2.0))
+            }
+        }
+
+        let host = PluginHost::new().with_function(Double);
+        let compiled = CompiledFilter::new(
+            crate::parse_with_plugins("double(.n) = 4 AND .m > 1", &host)
+                .unwrap()
+                .into_iter()
+                .map(Filter::into_owned)
+                .collect(),
+        );
+        let bytes = compiled.to_bytes();
+        assert!(CompiledFilter::from_bytes(&bytes).is_err());
+        let decoded = CompiledFilter::from_bytes_with_plugins(&bytes, &host).unwrap();
+        let options = ApplyOptions {
+            plugins: host.clone(),
+            ..Default::default()
+        };
+        assert!(decoded.matches_with(&json!({ "n": 2, "m": 2 }), &options));
+        assert!(!decoded.matches_with(&json!({ "n": 3, "m": 2 }), &options));
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_matches_str() {
+        let compiled = CompiledFilter::new(parse(".a = 1 AND .b.c = 'x'").unwrap());
+        assert!(compiled
+            .matches_str(r#"{"skip": [1, {"a": 2}], "b": {"c": "x"}, "a": 1}"#)
+            .unwrap());
+        assert!(!compiled.matches_str(r#"{"a": 1}"#).unwrap());
+        assert!(compiled
+            .matches_str(r#"{"a": 1, "b": {"c": "x"}, "rest": [}"#)
+            .is_err());
+        let error = compiled.matches_str(r#"{"a": 1, "rest": [}"#).unwrap_err();
+        assert_eq!((error.line(), error.column()), (1, 19));
+        assert!(compiled
+            .matches_str(r#"{"a": 1, "b": {"c": "x"}} x"#)
+            .is_err());
+        assert!(!compiled.matches_str("[1]").unwrap());
+        // A repeated field has its last value, as with the full parser.
+        let compiled = CompiledFilter::new(parse(".a > 3").unwrap());
+        for json in [r#"{"a": 1, "a": 5}"#, r#"{"a": 5, "a": 1}"#] {
+            let full: Value = serde_json::from_str(json).unwrap();
+            assert_eq!(compiled.matches_str(json).unwrap(), compiled.matches(&full));
+        }
+        assert!(compiled.matches_str(r#"{"a": 1, "a": 5}"#).unwrap());
+        assert!(CompiledFilter::new(parse(". = 1").unwrap())
+            .matches_str(" 1 ")
+            .unwrap());
+    }
+
+    #[test]
+    fn test_matches_batch() {
+        let compiled = CompiledFilter::new(parse(".age > 30 AND .name != 'x'").unwrap())
+            .with_path_limits(PathLimits::default());
+        let values = [
+            json!({ "age": 40, "name": "a" }),
+            json!({ "age": 20, "name": "b" }),
+            json!({ "age": "40", "name": "c" }),
+            json!({ "age": 50, "name": "x" }),
+            json!([1]),
+        ];
+        assert_eq!(
+            compiled.matches_batch(&values, &ApplyOptions::default()),
+            [true, false, false, false, false]
+        );
+        assert!(compiled
+            .matches_batch(&[], &ApplyOptions::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_compiled_filter_is_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CompiledFilter<'static>>();
+        assert_send_sync::<Filter<'static>>();
+
+        let compiled = Arc::new(CompiledFilter::new(
+            parse(".n > 2 AND capture(.name, '^(a)') = 'a'")
+                .unwrap()
+                .into_iter()
+                .map(Filter::into_owned)
+                .collect(),
+        ));
+        let workers: Vec<_> = (0..4)
+            .map(|n| {
+                let compiled = Arc::clone(&compiled);
+                std::thread::spawn(move || compiled.matches(&json!({ "n": n, "name": "ada" })))
+            })
+            .collect();
+        let matched: Vec<bool> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(matched, [false, false, false, true]);
+    }
+
+    #[test]
+    fn test_path_limits() {
+        let compiled = CompiledFilter::new(parse(".rows[*][*] CONTAINS 9").unwrap());
+        let v = json!({ "rows": [[1, 2, 3], [4, 5, 6], [7, 8, 9]] });
+        assert!(compiled.matches(&v));
+        let limited = |max_depth, max_nodes| {
+            CompiledFilter::new(parse(".rows[*][*] CONTAINS 9").unwrap()).with_path_limits(
+                PathLimits {
+                    max_depth,
+                    max_nodes,
+                },
+            )
+        };
+        // The outer wildcard visits 3 rows and the inner one 9 numbers.
+        assert!(limited(3, 12).matches(&v));
+        assert!(!limited(3, 11).matches(&v));
+        assert!(!limited(2, 12).matches(&v));
+    }
+
+    #[test]
+    fn test_path_limits_are_per_value() {
+        let compiled = CompiledFilter::new(parse(".a[*] CONTAINS 1 AND .b[*] CONTAINS 2").unwrap())
+            .with_path_limits(PathLimits {
+                max_depth: 2,
+                max_nodes: 4,
+            });
+        assert!(compiled.matches(&json!({ "a": [1, 0], "b": [0, 2] })));
+        assert!(!compiled.matches(&json!({ "a": [1, 0, 0], "b": [0, 2] })));
+        assert!(compiled.matches(&json!({ "a": [0, 1], "b": [2] })));
+    }
+
+    #[test]
+    fn test_compile_orders_nested_clauses() {
+        let compiled = CompiledFilter::new(
+            parse("ANY .items WHERE (.name FUZZY('bolt', 1) AND .qty > 1)").unwrap(),
+        );
+        match &compiled.filters()[0].compound {
+            Some(Compound::Quantified { filters, .. }) => assert_eq!(filters[0].operator, ">"),
+            other => panic!("unexpected compound {other:?}"),
+        }
+    }
+}
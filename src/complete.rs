@@ -0,0 +1,382 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use serde_json::Value;
+
+use crate::expr::{self, Path};
+use crate::lexer::{self, is_word_char, Token, TokenKind};
+use crate::parser::OPERATORS;
+
+/// A completion for the word being typed in a filter string, returned by [`crate::complete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// What the suggestion is.
+    pub kind: SuggestionKind,
+    /// The text to insert.
+    pub text: String,
+    /// The byte range of the filter string the text replaces: the part of the word typed before
+    /// the cursor, which is empty when the cursor isn't in a word.
+    pub replace: Range<usize>,
+}
+
+/// The kinds of [`Suggestion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    /// A field path such as `.user.name`.
+    Field,
+    /// A comparison operator such as `>=` or `CONTAINS`.
+    Operator,
+    /// A keyword such as `AND`, `if` or `ANY`.
+    Keyword,
+    /// A literal such as `'paid'` or `42`, taken from the sample document.
+    Value,
+}
+
+/// What the parser expects at the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expecting {
+    /// The left side of a comparison, or the start of a clause.
+    Field,
+    /// An operator, after the left side of a comparison.
+    Operator,
+    /// The right side of a comparison.
+    Value,
+    /// `AND` or another keyword ending a clause, after the right side of a comparison.
+    Connective,
+}
+
+/// The state of the input before the cursor.
+pub(crate) struct Context {
+    pub(crate) expecting: Expecting,
+    /// The path on the left side of the current comparison, if it is a plain path.
+    lhs: Option<String>,
+    /// The paths of the `ANY` and `ALL` quantifiers the cursor is inside, outermost first.
+    scopes: Vec<String>,
+    /// Whether an `if` is waiting for its `then`.
+    pub(crate) in_condition: bool,
+    /// The number of open parentheses.
+    pub(crate) depth: usize,
+    /// The start of operator words already typed, such as `IS` in `IS NOT UUID`.
+    operator_start: Option<usize>,
+}
+
+impl Context {
+    pub(crate) fn read(tokens: &[Token]) -> Context {
+        let mut ctx = Context {
+            expecting: Expecting::Field,
+            lhs: None,
+            scopes: Vec::new(),
+            in_condition: false,
+            depth: 0,
+            operator_start: None,
+        };
+        let mut depth = 0;
+        // Each open quantifier: its path, the depth of its `WHERE`, and whether it is grouped.
+        let mut quantifiers: Vec<(String, usize, bool)> = Vec::new();
+        let mut quantified: Option<String> = None;
+        for (i, token) in tokens.iter().enumerate() {
+            let mut operand = false;
+            match &token.kind {
+                TokenKind::Ident("AND") => {
+                    // An ungrouped `WHERE` clause ends at the next `AND` at its depth.
+                    while quantifiers
+                        .last()
+                        .is_some_and(|&(_, d, grouped)| !grouped && d == depth)
+                    {
+                        quantifiers.pop();
+                    }
+                    ctx.expecting = Expecting::Field;
+                }
+                TokenKind::Ident("if") => {
+                    ctx.in_condition = true;
+                    ctx.expecting = Expecting::Field;
+                }
+                TokenKind::Ident("then") => {
+                    ctx.in_condition = false;
+                    ctx.expecting = Expecting::Field;
+                }
+                TokenKind::Ident("else") => ctx.expecting = Expecting::Field,
+                TokenKind::Ident("ANY" | "ALL") if ctx.expecting == Expecting::Field => {
+                    quantified = Some(String::new());
+                }
+                TokenKind::Ident("WHERE") => {
+                    let path = quantified.take().unwrap_or_default();
+                    let grouped = tokens.get(i + 1).map(|t| &t.kind) == Some(&TokenKind::LParen);
+                    quantifiers.push((path, depth, grouped));
+                    ctx.expecting = Expecting::Field;
+                }
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => {
+                    depth -= 1.min(depth);
+                    while quantifiers
+                        .last()
+                        .is_some_and(|&(_, d, grouped)| grouped && d == depth)
+                    {
+                        quantifiers.pop();
+                    }
+                    operand = true;
+                }
+                TokenKind::Comma
+                | TokenKind::Star
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Alternative => {
+                    ctx.expecting = match ctx.expecting {
+                        Expecting::Operator | Expecting::Field => Expecting::Field,
+                        Expecting::Connective | Expecting::Value => Expecting::Value,
+                    }
+                }
+                TokenKind::Op(_) => ctx.expecting = Expecting::Value,
+                TokenKind::Ident("IS" | "NOT") if ctx.expecting == Expecting::Operator => {
+                    ctx.operator_start.get_or_insert(token.span.start);
+                    continue;
+                }
+                TokenKind::Ident("UUID") if ctx.expecting == Expecting::Operator => {
+                    ctx.expecting = Expecting::Connective;
+                }
+                TokenKind::Ident(_) if ctx.expecting == Expecting::Operator => {
+                    ctx.expecting = Expecting::Value;
+                }
+                TokenKind::Path(path) => {
+                    if let Some(quantified) = quantified.as_mut() {
+                        *quantified = path.to_string();
+                    }
+                    if ctx.expecting == Expecting::Field {
+                        ctx.lhs = Some(path.to_string());
+                    }
+                    operand = true;
+                }
+                TokenKind::Ident(word) if ctx.expecting == Expecting::Field => {
+                    let is_call = tokens.get(i + 1).map(|t| &t.kind) == Some(&TokenKind::LParen);
+                    ctx.lhs = (!is_call).then(|| format!(".{word}"));
+                    operand = !is_call;
+                }
+                _ => operand = true,
+            }
+            ctx.operator_start = None;
+            if operand {
+                ctx.expecting = match ctx.expecting {
+                    Expecting::Field | Expecting::Operator => Expecting::Operator,
+                    Expecting::Value | Expecting::Connective => Expecting::Connective,
+                };
+            }
+        }
+        ctx.depth = depth;
+        ctx.scopes = quantifiers.into_iter().map(|(path, ..)| path).collect();
+        ctx
+    }
+}
+
+/// Writes a Value as a literal in filter string syntax.
+struct Literal<'v>(&'v Value);
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        expr::write_literal(f, self.0)
+    }
+}
+
+pub(crate) fn complete(input: &str, cursor: usize, sample: &Value) -> Vec<Suggestion> {
+    let cursor = cursor.min(input.len());
+    if !input.is_char_boundary(cursor) {
+        return Vec::new();
+    }
+    let before = &input[..cursor];
+    // The word being typed is either an unterminated string or the last token, if it touches the cursor.
+    let (tokens, mut word_start) = match lexer::tokenize(before) {
+        Ok(mut tokens) => match tokens.last() {
+            Some(token)
+                if token.span.end == cursor
+                    && matches!(token.kind, TokenKind::Path(_) | TokenKind::Ident(_)) =>
+            {
+                let start = token.span.start;
+                tokens.pop();
+                (tokens, start)
+            }
+            _ if before.ends_with(|c: char| is_word_char(c) || c == '.') => return Vec::new(),
+            _ => (tokens, cursor),
+        },
+        Err(error) if error.message == "unterminated string" => {
+            match lexer::tokenize(&before[..error.span.start]) {
+                Ok(tokens) => (tokens, error.span.start),
+                Err(_) => return Vec::new(),
+            }
+        }
+        Err(_) => return Vec::new(),
+    };
+    let ctx = Context::read(&tokens);
+    if let Some(start) = ctx.operator_start {
+        word_start = start;
+    }
+    let typed = &input[word_start..cursor];
+    let scope = scope_values(sample, &ctx.scopes);
+    let mut suggestions = Vec::new();
+    let mut suggest = |kind: SuggestionKind, text: String| {
+        let matches = text.to_lowercase().starts_with(&typed.to_lowercase());
+        if matches && !suggestions.iter().any(|s: &Suggestion| s.text == text) {
+            suggestions.push(Suggestion {
+                kind,
+                text,
+                replace: word_start..cursor,
+            });
+        }
+    };
+    match ctx.expecting {
+        Expecting::Field => {
+            let (parent, _) = typed.rsplit_once('.').unwrap_or(("", typed));
+            for v in &scope {
+                for key in keys(v, parent) {
+                    suggest(SuggestionKind::Field, format!("{parent}.{key}"));
+                }
+            }
+            for keyword in ["if", "ANY", "ALL"] {
+                suggest(SuggestionKind::Keyword, keyword.to_string());
+            }
+        }
+        Expecting::Operator => {
+            for operator in OPERATORS {
+                suggest(SuggestionKind::Operator, operator.to_string());
+            }
+            suggest(SuggestionKind::Operator, "FUZZY".to_string());
+            #[cfg(feature = "geo")]
+            suggest(SuggestionKind::Operator, "WITHIN".to_string());
+        }
+        Expecting::Value => {
+            let lhs = ctx.lhs.as_deref().map(Path::parse);
+            for v in &scope {
+                let Some(found) = lhs.as_ref().and_then(|lhs| lhs.resolve(v)) else {
+                    continue;
+                };
+                let candidates = match found.as_ref() {
+                    Value::Array(elements) => elements.iter().collect(),
+                    value => vec![value],
+                };
+                for candidate in candidates {
+                    if !candidate.is_array() && !candidate.is_object() {
+                        suggest(SuggestionKind::Value, Literal(candidate).to_string());
+                    }
+                }
+            }
+        }
+        Expecting::Connective => {
+            suggest(SuggestionKind::Keyword, "AND".to_string());
+            if ctx.in_condition {
+                suggest(SuggestionKind::Keyword, "then".to_string());
+            }
+        }
+    }
+    suggestions
+}
+
+/// The Values fields are looked up in inside the given quantifiers: the elements of the arrays at
+/// their paths.
+fn scope_values(sample: &Value, scopes: &[String]) -> Vec<Value> {
+    let mut values = vec![sample.clone()];
+    for scope in scopes {
+        let path = Path::parse(scope);
+        values = values
+            .iter()
+            .filter_map(|v| path.resolve(v))
+            .filter_map(|v| v.as_array().cloned())
+            .flatten()
+            .collect();
+    }
+    values
+}
+
+/// The keys that can follow a parent path in a Value, skipping keys a path can't spell.
+fn keys(v: &Value, parent: &str) -> Vec<String> {
+    let Some(found) = Path::parse(parent).resolve(v) else {
+        return Vec::new();
+    };
+    let objects: Vec<_> = match found.as_ref() {
+        Value::Object(object) => vec![object],
+        Value::Array(elements) if parent.ends_with("[*]") => {
+            elements.iter().filter_map(Value::as_object).collect()
+        }
+        _ => Vec::new(),
+    };
+    objects
+        .into_iter()
+        .flat_map(|object| object.keys())
+        .filter(|key| !key.is_empty() && key.chars().all(is_word_char))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn texts(input: &str, sample: &Value) -> Vec<String> {
+        complete(input, input.len(), sample)
+            .into_iter()
+            .map(|s| s.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_complete_fields() {
+        let sample = json!({
+            "status": "paid",
+            "user": { "name": "Ada", "age": 36, "odd key": 1 },
+            "items": [{ "sku": "a-1", "qty": 2 }, { "sku": "b-2", "price": 5 }],
+        });
+        assert_eq!(
+            texts("", &sample),
+            [".items", ".status", ".user", "if", "ANY", "ALL"]
+        );
+        assert_eq!(texts(".us", &sample), [".user"]);
+        assert_eq!(
+            texts(".status = 'paid' AND .user.", &sample),
+            [".user.age", ".user.name"]
+        );
+        assert_eq!(
+            texts(".items[*].", &sample),
+            [".items[*].qty", ".items[*].sku", ".items[*].price"]
+        );
+        assert_eq!(
+            texts("ANY .items WHERE (.qty > 1 AND .p", &sample),
+            [".price"]
+        );
+        assert_eq!(
+            texts("ANY .items WHERE (.qty > 1) AND .st", &sample),
+            [".status"]
+        );
+        let suggestion = &complete(".a = 1 AND .us", 14, &sample)[0];
+        assert_eq!(suggestion.replace, 11..14);
+        assert_eq!(suggestion.kind, SuggestionKind::Field);
+    }
+
+    #[test]
+    fn test_complete_operators_and_keywords() {
+        let sample = json!({});
+        assert_eq!(texts(".id IS", &sample), ["IS UUID", "IS NOT UUID"]);
+        assert_eq!(texts(".id IS N", &sample), ["IS NOT UUID"]);
+        assert_eq!(complete(".id IS N", 8, &sample)[0].replace, 4..8);
+        assert_eq!(texts(".tags CON", &sample), ["CONTAINS"]);
+        assert!(texts(".age ", &sample).contains(&">=".to_string()));
+        assert_eq!(texts(".age > 3 ", &sample), ["AND"]);
+        assert_eq!(texts("if .age > 3 ", &sample), ["AND", "then"]);
+        assert_eq!(texts(".id IS UUID ", &sample), ["AND"]);
+    }
+
+    #[test]
+    fn test_complete_values() {
+        let sample =
+            json!({ "status": "paid", "tags": ["rust", "json", 3], "user": { "name": "Ada" } });
+        assert_eq!(texts(".status = ", &sample), ["'paid'"]);
+        assert_eq!(texts(".tags CONTAINS 'r", &sample), ["'rust'"]);
+        assert_eq!(
+            texts(".tags HAS_ANY ('rust', ", &sample),
+            ["'rust'", "'json'", "3"]
+        );
+        assert_eq!(texts(".user.name != ", &sample), ["'Ada'"]);
+        assert!(texts(".missing = ", &sample).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::format::Clauses;
+use crate::{Filter, PathLimits};
+
+/// The bytes every encoded filter starts with.
+const MAGIC: &[u8; 4] = b"JSF\0";
+
+/// The version of the encoding, written after the magic bytes. Later versions of the crate keep
+/// reading every earlier version.
+const VERSION: u8 = 1;
+
+/// An error found while reading a filter encoded with [`crate::CompiledFilter::to_bytes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    message: String,
+}
+
+impl DecodeError {
+    pub(crate) fn new(message: impl Into<String>) -> DecodeError {
+        DecodeError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// Encodes clauses and their path limits. The clauses are stored as filter text, in the order they
+/// are evaluated, so that the encoding doesn't change when the way filters are represented in
+/// memory does.
+///
+/// The layout is the magic bytes, the version, the path limits as two little-endian `u64`s, then the
+/// length of the text as a little-endian `u32` followed by the text in UTF-8.
+pub(crate) fn encode(filters: &[Filter], limits: PathLimits) -> Vec<u8> {
+    let text = Clauses(filters).to_string();
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 21 + text.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    for limit in [limits.max_depth, limits.max_nodes] {
+        bytes.extend_from_slice(&u64::try_from(limit).unwrap_or(u64::MAX).to_le_bytes());
+    }
+    let len = u32::try_from(text.len()).expect("filter text longer than 4 GiB");
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+    bytes
+}
+
+/// Reads the filter text and path limits written by [`encode`].
+pub(crate) fn decode(bytes: &[u8]) -> Result<(&str, PathLimits), DecodeError> {
+    let rest = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| DecodeError::new("not an encoded filter"))?;
+    let (&version, mut rest) = rest
+        .split_first()
+        .ok_or_else(|| DecodeError::new("truncated encoded filter"))?;
+    if version != VERSION {
+        return Err(DecodeError::new(format!(
+            "unsupported encoding version {version}"
+        )));
+    }
+    let mut take = |len: usize| {
+        if rest.len() < len {
+            return Err(DecodeError::new("truncated encoded filter"));
+        }
+        let (taken, left) = rest.split_at(len);
+        rest = left;
+        Ok(taken)
+    };
+    let mut limit = || -> Result<usize, DecodeError> {
+        let n = u64::from_le_bytes(take(8)?.try_into().expect("took 8 bytes"));
+        Ok(usize::try_from(n).unwrap_or(usize::MAX))
+    };
+    let limits = PathLimits {
+        max_depth: limit()?,
+        max_nodes: limit()?,
+    };
+    let len = u32::from_le_bytes(take(4)?.try_into().expect("took 4 bytes"));
+    let text = take(len as usize)?;
+    if !rest.is_empty() {
+        return Err(DecodeError::new("trailing bytes after encoded filter"));
+    }
+    let text = core::str::from_utf8(text)
+        .map_err(|_| DecodeError::new("encoded filter text isn't UTF-8"))?;
+    Ok((text, limits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_decode() {
+        let filters = parse(".a > 1 AND ANY .b WHERE .c = 'x'").unwrap();
+        let limits = PathLimits::default();
+        let bytes = encode(&filters, limits);
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            (".a > 1 AND ANY .b WHERE (.c = 'x')", limits)
+        );
+        assert_eq!(
+            decode(&encode(&filters, PathLimits::UNLIMITED)).unwrap().1,
+            PathLimits::UNLIMITED
+        );
+
+        let error = |bytes: &[u8]| decode(bytes).unwrap_err().to_string();
+        assert_eq!(error(b"{}"), "not an encoded filter");
+        assert_eq!(error(b"JSF\0\x02"), "unsupported encoding version 2");
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "truncated encoded filter");
+        assert_eq!(
+            error(&[&bytes[..], b" "].concat()),
+            "trailing bytes after encoded filter"
+        );
+    }
+}
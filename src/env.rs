@@ -0,0 +1,110 @@
+use crate::lexer::Span;
+use crate::parser::ParseError;
+
+/// Replaces every `${NAME}` in a filter string with the value `lookup` gives for `NAME`, or the
+/// default in `${NAME:-default}` when there is none. `$${` is written as a literal `${`.
+///
+/// Values are inserted as written, so a string value needs quotes around the placeholder, as in
+/// `.region = '${REGION}'`.
+pub(crate) fn expand(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(input.len());
+    let mut pos = 0;
+    while let Some(i) = input[pos..].find('$') {
+        let start = pos + i;
+        out.push_str(&input[pos..start]);
+        let rest = &input[start..];
+        if rest.starts_with("$${") {
+            out.push_str("${");
+            pos = start + 3;
+            continue;
+        }
+        if !rest.starts_with("${") {
+            out.push('$');
+            pos = start + 1;
+            continue;
+        }
+        let Some(close) = rest.find('}') else {
+            return Err(ParseError::new(
+                "unterminated `${`",
+                Span {
+                    start,
+                    end: input.len(),
+                },
+            ));
+        };
+        let span = Span {
+            start,
+            end: start + close + 1,
+        };
+        let (name, default) = match rest[2..close].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&rest[2..close], None),
+        };
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            return Err(ParseError::new("invalid variable name", span));
+        }
+        match lookup(name).or_else(|| default.map(str::to_string)) {
+            Some(value) => out.push_str(&value),
+            None => {
+                return Err(ParseError::new(
+                    format!("environment variable `{name}` is not set"),
+                    span,
+                ))
+            }
+        }
+        pos = span.end;
+    }
+    out.push_str(&input[pos..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "SLO_MS" => Some("250".to_string()),
+            "REGION" => Some("eu-west".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand(".latency > ${SLO_MS} AND .region = '${REGION}'", lookup).unwrap(),
+            ".latency > 250 AND .region = 'eu-west'"
+        );
+        assert_eq!(
+            expand(
+                ".n < ${LIMIT:-10} AND .price = '$5' AND .s = '$${X}'",
+                lookup
+            )
+            .unwrap(),
+            ".n < 10 AND .price = '$5' AND .s = '${X}'"
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert_eq!(
+            expand(".n < ${LIMIT}", lookup).unwrap_err(),
+            ParseError::new(
+                "environment variable `LIMIT` is not set",
+                Span { start: 5, end: 13 }
+            )
+        );
+        assert_eq!(
+            expand(".n < ${SLO_MS", lookup).unwrap_err().message,
+            "unterminated `${`"
+        );
+        assert_eq!(
+            expand(".n < ${SLO MS}", lookup).unwrap_err().message,
+            "invalid variable name"
+        );
+    }
+}
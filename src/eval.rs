@@ -0,0 +1,806 @@
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::fmt;
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, FixedOffset, Offset, Utc};
+#[cfg(feature = "std")]
+use serde_json::Map;
+use serde_json::{Number, Value};
+
+use crate::comparator::Comparator;
+use crate::expr::{self, Arena, Expr};
+#[cfg(feature = "geo")]
+use crate::geo;
+use crate::intern::Name;
+use crate::net;
+#[cfg(feature = "std")]
+use crate::raw;
+use crate::text;
+#[cfg(feature = "chrono")]
+use crate::time;
+use crate::uuid;
+use crate::version;
+use crate::{ApplyOptions, Compound, EvalObserver, Filter, PathLimits};
+
+/// The state shared by every filter evaluated in one call to `apply_with`.
+pub(crate) struct Context<'o> {
+    pub(crate) options: &'o ApplyOptions,
+    pub(crate) limits: PathLimits,
+    /// The array elements wildcards have visited so far.
+    nodes: Cell<usize>,
+    /// The fuel spent so far, and how much may be spent, counted by [`Context::spend`].
+    spent: Cell<u64>,
+    budget: u64,
+    observer: Option<&'o dyn EvalObserver>,
+    /// The Values enclosing the one being evaluated, while evaluating the clauses of a quantifier.
+    enclosing: Option<&'o Enclosing<'o>>,
+}
+
+/// A Value whose array elements are being evaluated, and the Values enclosing it in turn.
+pub(crate) struct Enclosing<'e> {
+    value: &'e Value,
+    outer: Option<&'e Enclosing<'e>>,
+}
+
+impl<'o> Context<'o> {
+    pub(crate) fn new(options: &'o ApplyOptions) -> Context<'o> {
+        Context::with_limits(options, PathLimits::UNLIMITED)
+    }
+
+    pub(crate) fn with_limits(options: &'o ApplyOptions, limits: PathLimits) -> Context<'o> {
+        Context {
+            options,
+            limits,
+            nodes: Cell::new(0),
+            spent: Cell::new(0),
+            budget: u64::MAX,
+            observer: None,
+            enclosing: None,
+        }
+    }
+
+    /// A context for evaluating the elements of an array in `enclosing`, counting visited elements
+    /// and spent fuel from where this one is. The counts have to be taken back with
+    /// [`Context::resume`].
+    fn within<'c>(&'c self, enclosing: &'c Enclosing<'c>) -> Context<'c> {
+        Context {
+            options: self.options,
+            limits: self.limits,
+            nodes: Cell::new(self.nodes.get()),
+            spent: Cell::new(self.spent.get()),
+            budget: self.budget,
+            observer: None,
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Takes back the counts of visited elements and spent fuel from a context returned by
+    /// [`Context::within`].
+    fn resume(&self, inner: Context) {
+        self.nodes.set(inner.nodes.get());
+        self.spent.set(inner.spent.get());
+    }
+
+    /// Limits the fuel the evaluation may spend to `budget`.
+    pub(crate) fn with_budget(mut self, budget: u64) -> Context<'o> {
+        self.budget = budget;
+        self
+    }
+
+    /// Spends fuel for a step of the evaluation, returning whether it is within the budget. Once
+    /// the budget is exceeded, every step fails, so the evaluation ends quickly.
+    pub(crate) fn spend(&self, fuel: u64) -> bool {
+        let spent = self.spent.get().saturating_add(fuel);
+        self.spent.set(spent);
+        spent <= self.budget
+    }
+
+    /// Returns whether the evaluation has spent more fuel than its budget.
+    pub(crate) fn over_budget(&self) -> bool {
+        self.spent.get() > self.budget
+    }
+
+    /// The Value `$root` refers to, or `None` outside quantifiers, where it is the Value itself.
+    pub(crate) fn root(&self) -> Option<&Value> {
+        let mut enclosing = self.enclosing?;
+        while let Some(outer) = enclosing.outer {
+            enclosing = outer;
+        }
+        Some(enclosing.value)
+    }
+
+    /// The Value `$parent` refers to, or `None` outside quantifiers.
+    pub(crate) fn parent(&self) -> Option<&Value> {
+        self.enclosing.map(|enclosing| enclosing.value)
+    }
+
+    /// The value at `path` in the defaults of the options, read in place of a path of the Value being
+    /// filtered that it doesn't have. Paths in the clauses of quantifiers read the elements of
+    /// arrays, which have no defaults, unless `from_root` is set for a `$root` path.
+    pub(crate) fn default(
+        &self,
+        path: &expr::Path,
+        from_root: bool,
+    ) -> Option<Cow<'static, Value>> {
+        if self.enclosing.is_some() && !from_root {
+            return None;
+        }
+        let found = path.resolve(self.options.defaults.as_ref()?)?;
+        Some(Cow::Owned(found.into_owned()))
+    }
+
+    /// Reports the outcome of [`apply`] to `observer`.
+    pub(crate) fn observed(mut self, observer: &'o dyn EvalObserver) -> Context<'o> {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Counts a visit to an array element, returning whether it is within `limits.max_nodes` and
+    /// the budget.
+    pub(crate) fn visit(&self) -> bool {
+        let nodes = self.nodes.get().saturating_add(1);
+        self.nodes.set(nodes);
+        nodes <= self.limits.max_nodes && self.spend(1)
+    }
+
+    /// Returns whether wildcards have visited more elements than `limits.max_nodes` allows, or the
+    /// budget is exceeded.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.nodes.get() > self.limits.max_nodes || self.over_budget()
+    }
+
+    /// The instant `now()` evaluates to.
+    #[cfg(feature = "chrono")]
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.options.now.unwrap_or_else(Utc::now)
+    }
+
+    /// The offset of timestamps that don't specify one.
+    #[cfg(feature = "chrono")]
+    pub(crate) fn timezone(&self) -> FixedOffset {
+        self.options.timezone.unwrap_or(Utc.fix())
+    }
+}
+
+/// An error ending an evaluation before it decided whether a Value passes its filters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// The evaluation spent more fuel than its budget. Each clause, step of a path, array element a
+    /// wildcard visits and function call spends one unit, and matching text against a regex, a fuzzy
+    /// target or a text query spends more the longer the text is.
+    BudgetExceeded { budget: u64 },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::BudgetExceeded { budget } => {
+                write!(
+                    f,
+                    "the evaluation spent more than its budget of {budget} fuel"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for EvalError {}
+
+/// Returns whether the Value `v` passes every filter, like [`apply`], or an error if the evaluation
+/// spent more fuel than the context's budget.
+pub(crate) fn apply_budgeted(
+    v: &Value,
+    filters: &[Filter],
+    ctx: &Context,
+) -> Result<bool, EvalError> {
+    let passed = apply(v, filters, ctx);
+    match ctx.over_budget() {
+        true => Err(EvalError::BudgetExceeded { budget: ctx.budget }),
+        false => Ok(passed),
+    }
+}
+
+/// Returns whether the Value `v` passes every filter, like [`matches_all`], reporting the outcome to the
+/// context's observer. With the `tracing` feature, the evaluation is a trace-level `apply` span, and the
+/// clause that rejected the Value a debug event.
+pub(crate) fn apply(v: &Value, filters: &[Filter], ctx: &Context) -> bool {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("apply", clauses = filters.len()).entered();
+    for (index, filter) in filters.iter().enumerate() {
+        if !filter.matches(v, ctx) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(clause = %crate::format::Clause(filter), "clause rejected the value");
+            if let Some(observer) = ctx.observer {
+                observer.rejected(index, filter);
+                observer.evaluated(false);
+            }
+            return false;
+        }
+    }
+    if let Some(observer) = ctx.observer {
+        observer.evaluated(true);
+    }
+    true
+}
+
+/// Returns whether the Value `v` passes every filter.
+pub(crate) fn matches_all(v: &Value, filters: &[Filter], ctx: &Context) -> bool {
+    for filter in filters {
+        // If the comparison is false, we return false immediately.
+        if !filter.matches(v, ctx) {
+            return false;
+        }
+    }
+    // If none of the filters returned false, we return true.
+    true
+}
+
+/// The fraction of the filters the Value `v` passes, weighted by their weights, from 0 to 1. Filters
+/// of no weight in total score 1, as the Value passes all of them that count.
+pub(crate) fn score(v: &Value, filters: &[Filter], ctx: &Context) -> f64 {
+    let (mut passed, mut total) = (0.0, 0.0);
+    for filter in filters {
+        let weight = filter.weight.unwrap_or(1.0);
+        total += weight;
+        if weight > 0.0 && filter.holds(v, ctx) {
+            passed += weight;
+        }
+    }
+    if total > 0.0 {
+        passed / total
+    } else {
+        1.0
+    }
+}
+
+/// Returns whether an object passes every filter. Clauses comparing top-level fields look them up in
+/// the map, and the others are evaluated against an object holding only the fields they read, copied
+/// from the map the first time one is needed.
+#[cfg(feature = "std")]
+pub(crate) fn matches_map(map: &Map<String, Value>, filters: &[Filter], ctx: &Context) -> bool {
+    let mut object = None;
+    filters.iter().all(|filter| {
+        filter.matches_fields(map, ctx).unwrap_or_else(|| {
+            let object = object.get_or_insert_with(|| {
+                Value::Object(match raw::read_keys(filters, ctx.options) {
+                    Some(keys) => map
+                        .iter()
+                        .filter(|(key, _)| keys.contains(key.as_str()))
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect(),
+                    None => map.clone(),
+                })
+            });
+            filter.matches(object, ctx)
+        })
+    })
+}
+
+impl<'a> Filter<'a> {
+    /// Returns whether the Value `v` passes this filter, which a `SHOULD` clause always does.
+    pub(crate) fn matches(&self, v: &Value, ctx: &Context) -> bool {
+        self.should || self.holds(v, ctx)
+    }
+
+    /// Returns whether the Value `v` meets the condition of this filter, even if it is a `SHOULD`
+    /// clause.
+    fn holds(&self, v: &Value, ctx: &Context) -> bool {
+        if !ctx.spend(1) {
+            return false;
+        }
+        if let Some(compound) = &self.compound {
+            return compound.matches(&self.arena, v, ctx);
+        }
+        let left = self.left(v, ctx);
+        if left.is_none() && self.optional {
+            return true;
+        }
+        self.passes(left, || self.right(v, ctx), ctx)
+    }
+
+    /// Returns whether an object passes this filter by looking its fields up directly, or `None` if
+    /// the filter reads more than top-level fields and has to be evaluated against a Value.
+    #[cfg(feature = "std")]
+    fn matches_fields(&self, map: &Map<String, Value>, ctx: &Context) -> Option<bool> {
+        if self.should {
+            return Some(true);
+        }
+        if self.compound.is_some()
+            || self.lhs.is_some()
+            || self.rhs.is_some()
+            || ctx.options.fills_missing()
+        {
+            return None;
+        }
+        let left = map
+            .get(self.field.as_deref()?)
+            .and_then(|left| scale(Cow::Borrowed(left), self.multiplier_field));
+        if left.is_none() && self.optional {
+            return Some(true);
+        }
+        let right = || match (&self.value, &self.value_field) {
+            (Some(value), _) => scale(Cow::Borrowed(value), self.multiplier_value),
+            (None, Some(field)) => scale(Cow::Borrowed(map.get(field)?), self.multiplier_value),
+            (None, None) => None,
+        };
+        Some(self.passes(left, right, ctx))
+    }
+
+    /// Returns whether the operands of a comparison pass its operator.
+    fn passes<'x>(
+        &self,
+        left: Option<Cow<'x, Value>>,
+        right: impl FnOnce() -> Option<Cow<'x, Value>>,
+        ctx: &Context,
+    ) -> bool {
+        if let Some(negated) = match self.operator.as_ref() {
+            "IS UUID" => Some(false),
+            "IS NOT UUID" => Some(true),
+            _ => None,
+        } {
+            return left
+                .is_some_and(|left| left.as_str().and_then(uuid::parse_uuid).is_some() != negated);
+        }
+        // The value we're comparing is taken from the JSON value.
+        // The value to compare with is a literal, another field, or an expression over the JSON value.
+        // If either one is missing, the comparison fails.
+        match (left, right()) {
+            (Some(left), Some(right)) => {
+                if let Some(comparator) = self.comparator(ctx) {
+                    return comparator
+                        .compare(&left, &right)
+                        .is_some_and(|ordering| ordering_matches(&self.operator, ordering));
+                }
+                if let Some(plugin) = ctx.options.plugins.operator(&self.operator) {
+                    return plugin.matches(&left, &right);
+                }
+                compare(&left, &self.operator, &right, ctx)
+            }
+            _ => false,
+        }
+    }
+
+    /// The comparator of the options for the path on the left of a comparison, if it has one.
+    fn comparator<'c>(&self, ctx: &Context<'c>) -> Option<&'c dyn Comparator> {
+        let comparators = &ctx.options.comparators;
+        if comparators.is_empty()
+            || !matches!(self.operator.as_ref(), "=" | "!=" | "<" | "<=" | ">" | ">=")
+        {
+            return None;
+        }
+        match (&self.lhs, self.field.as_deref()) {
+            (Some(Expr::Path(path)), _) => comparators.get(path),
+            (None, Some(field)) => comparators.get(&expr::Path {
+                segments: vec![expr::Segment::Key {
+                    name: Name::Borrowed(field),
+                    null_safe: false,
+                }],
+            }),
+            _ => None,
+        }
+    }
+
+    /// The value being compared, taken from the field or computed by the left-hand expression.
+    fn left<'x>(&'x self, v: &'x Value, ctx: &Context) -> Option<Cow<'x, Value>> {
+        match &self.lhs {
+            Some(expr) => expr.eval(&self.arena, v, ctx),
+            None => scale(
+                field(v, self.field.as_deref()?, ctx)?,
+                self.multiplier_field,
+            ),
+        }
+    }
+
+    /// How the left side of a comparison is written, such as `.age` or `2*.price`, and its value in
+    /// `v`, `None` if `v` doesn't have it. `None` for clauses that aren't comparisons.
+    #[cfg(feature = "std")]
+    pub(crate) fn left_operand(&self, v: &Value, ctx: &Context) -> Option<(String, Option<Value>)> {
+        let written = match (&self.lhs, self.field.as_deref(), self.multiplier_field) {
+            (Some(expr), ..) => expr.display(&self.arena).to_string(),
+            (None, Some(field), Some(multiplier)) => format!("{multiplier}*.{field}"),
+            (None, Some(field), None) => format!(".{field}"),
+            (None, None, _) => return None,
+        };
+        Some((written, self.left(v, ctx).map(Cow::into_owned)))
+    }
+
+    /// The value to compare with, taken from the literal, the value field or the right-hand expression.
+    fn right<'x>(&'x self, v: &'x Value, ctx: &Context) -> Option<Cow<'x, Value>> {
+        if let Some(expr) = &self.rhs {
+            return expr.eval(&self.arena, v, ctx);
+        }
+        match (&self.value, &self.value_field) {
+            (Some(value), _) => scale(Cow::Borrowed(value), self.multiplier_value),
+            (None, Some(name)) => scale(field(v, name, ctx)?, self.multiplier_value),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Looks a top-level field up in a Value, through the aliases of the options.
+fn field<'x>(v: &'x Value, name: &str, ctx: &Context) -> Option<Cow<'x, Value>> {
+    if !ctx.options.fills_missing() {
+        return v.get(name).map(Cow::Borrowed);
+    }
+    let path = expr::Path {
+        segments: vec![expr::Segment::Key {
+            name: Name::Borrowed(name),
+            null_safe: false,
+        }],
+    };
+    path.resolve_limited(v, ctx)
+        .or_else(|| ctx.default(&path, false))
+}
+
+impl<'a> Compound<'a> {
+    fn matches(&self, arena: &Arena, v: &Value, ctx: &Context) -> bool {
+        match self {
+            Compound::Quantified {
+                all,
+                array,
+                filters,
+            } => {
+                let array = array.eval(arena, v, ctx);
+                let Some(Value::Array(elements)) = array.as_deref() else {
+                    return false;
+                };
+                let enclosing = Enclosing {
+                    value: v,
+                    outer: ctx.enclosing,
+                };
+                let inner = ctx.within(&enclosing);
+                let passed = if *all {
+                    elements.iter().all(|e| matches_all(e, filters, &inner))
+                } else {
+                    elements.iter().any(|e| matches_all(e, filters, &inner))
+                };
+                ctx.resume(inner);
+                passed
+            }
+            Compound::Fuzzy {
+                input,
+                target,
+                max_distance,
+            } => match (input.eval(arena, v, ctx), target.eval(arena, v, ctx)) {
+                (Some(input), Some(target)) => match (input.as_str(), target.as_str()) {
+                    (Some(input), Some(target)) => {
+                        // The edit distance takes time proportional to the product of the lengths.
+                        ctx.spend((input.len() as u64).saturating_mul(target.len() as u64))
+                            && text::fuzzy_matches(input, target, *max_distance)
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            #[cfg(feature = "geo")]
+            Compound::Within {
+                location,
+                radius_m,
+                center,
+            } => location
+                .eval(arena, v, ctx)
+                .and_then(|location| geo::location(&location))
+                .is_some_and(|point| geo::distance_m(point, *center) <= *radius_m),
+            Compound::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if matches_all(v, condition, ctx) {
+                    matches_all(v, then, ctx)
+                } else {
+                    matches_all(v, otherwise, ctx)
+                }
+            }
+            Compound::Match {
+                comparison,
+                threshold,
+                filters,
+            } => {
+                let score = score(v, filters, ctx);
+                match *comparison {
+                    "=" => score == *threshold,
+                    "!=" => score != *threshold,
+                    ">" => score > *threshold,
+                    ">=" => score >= *threshold,
+                    "<" => score < *threshold,
+                    _ => score <= *threshold,
+                }
+            }
+        }
+    }
+}
+
+/// Multiplies a number by an optional multiplier. Values that are not numbers are left as they are.
+fn scale(v: Cow<'_, Value>, multiplier: Option<i64>) -> Option<Cow<'_, Value>> {
+    match (multiplier, v.as_ref()) {
+        (Some(mult), Value::Number(n)) => {
+            expr::multiply(n, &Number::from(mult)).map(|n| Cow::Owned(Value::Number(n)))
+        }
+        _ => Some(v),
+    }
+}
+
+/// Compares two values with an operator.
+///
+/// Strings and booleans support `=` and `!=`, numbers support every operator.
+/// The `SEMVER` operators compare strings as semantic versions.
+/// `IN_CIDR` checks that an IP address string is inside a network.
+/// `DEEP_EQ` checks that two values are equal in their entirety, including objects and arrays.
+/// With `unordered_arrays` set in the options, arrays are equal if they have the same elements.
+/// `MATCHES_OBJECT` checks that an object has at least the keys and values of another.
+/// `CONTAINS` checks that an array has an element equal to a value, and `IN` that a value is equal to
+/// an element of an array.
+/// `HAS_ALL` and `HAS_ANY` check that an array has all or any of the elements of another, and
+/// `SUBSET_OF` that all of its elements are in another.
+/// `MATCHES_TEXT` checks that a string contains every word of a query, ignoring case and punctuation,
+/// and ignoring inflections when `stem_text` is set in the options.
+/// Strings that are both UUIDs are compared by value, whatever their case or hyphenation, and
+/// support every operator.
+/// With the `chrono` feature, strings that are both ISO-8601 timestamps are compared chronologically
+/// and support every operator. Timestamps without an offset are in the timezone of the options.
+/// With `coerce_epoch_millis` set in the options, a number and a timestamp string are compared as
+/// instants, reading the number as milliseconds since the Unix epoch.
+/// Null is only equal to null, and is unequal to every other value.
+/// Other values of different types never match.
+fn compare(left: &Value, operator: &str, right: &Value, ctx: &Context) -> bool {
+    // Comparing two numbers is the most common case, so it skips the checks for other operators.
+    if let (Value::Number(l), Value::Number(r)) = (left, right) {
+        let ordering = expr::compare_numbers(l, r);
+        return match operator {
+            "DEEP_EQ" | "MATCHES_OBJECT" => ordering == Some(Ordering::Equal),
+            _ => ordering.is_some_and(|ordering| ordering_matches(operator, ordering)),
+        };
+    }
+    if operator == "IN_CIDR" {
+        return match (left.as_str(), right.as_str()) {
+            (Some(addr), Some(cidr)) => net::in_cidr(addr, cidr).unwrap_or(false),
+            _ => false,
+        };
+    }
+    if operator == "DEEP_EQ" {
+        return deep_eq(left, right, ctx.options.unordered_arrays);
+    }
+    if operator == "MATCHES_OBJECT" {
+        return matches_object(left, right, ctx.options.unordered_arrays);
+    }
+    if operator == "CONTAINS" {
+        return match left {
+            Value::Array(elements) => elements.iter().any(|e| compare(e, "=", right, ctx)),
+            _ => false,
+        };
+    }
+    if operator == "IN" {
+        return match right {
+            Value::Array(elements) => elements.iter().any(|e| compare(left, "=", e, ctx)),
+            _ => false,
+        };
+    }
+    if matches!(operator, "HAS_ALL" | "HAS_ANY" | "SUBSET_OF") {
+        let (Value::Array(left), Value::Array(right)) = (left, right) else {
+            return false; // Only arrays are sets
+        };
+        let contains =
+            |values: &[Value], value: &Value| values.iter().any(|e| compare(e, "=", value, ctx));
+        return match operator {
+            "HAS_ALL" => right.iter().all(|r| contains(left, r)),
+            "HAS_ANY" => right.iter().any(|r| contains(left, r)),
+            _ => left.iter().all(|l| contains(right, l)),
+        };
+    }
+    if operator == "MATCHES_TEXT" {
+        return match (left.as_str(), right.as_str()) {
+            (Some(text), Some(query)) => {
+                ctx.spend((text.len() + query.len()) as u64)
+                    && text::matches_text(text, query, ctx.options.stem_text)
+            }
+            _ => false,
+        };
+    }
+    if let Some(operator) = operator.strip_prefix("SEMVER") {
+        return match (left.as_str(), right.as_str()) {
+            (Some(l), Some(r)) => version::compare_versions(l, r)
+                .is_some_and(|ordering| ordering_matches(operator, ordering)),
+            _ => false, // Only strings can be versions
+        };
+    }
+    match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => match operator {
+            "=" => left.is_null() && right.is_null(),
+            "!=" => !(left.is_null() && right.is_null()),
+            _ => false, // Null has no ordering
+        },
+        (Value::String(l), Value::String(r)) => {
+            if let (Some(l), Some(r)) = (uuid::parse_uuid(l), uuid::parse_uuid(r)) {
+                return ordering_matches(operator, l.cmp(&r));
+            }
+            #[cfg(feature = "chrono")]
+            if let Some(ordering) = time::compare_timestamps(l, r, ctx.timezone()) {
+                return ordering_matches(operator, ordering);
+            }
+            match operator {
+                "=" => l == r,
+                "!=" => l != r,
+                _ => false, // Unknown operator for string comparisons
+            }
+        }
+        (Value::Bool(l), Value::Bool(r)) => match operator {
+            "=" => l == r,
+            "!=" => l != r,
+            _ => false, // Booleans have no ordering
+        },
+        #[cfg(feature = "chrono")]
+        (Value::Number(n), Value::String(s)) if ctx.options.coerce_epoch_millis => {
+            match time::compare_epoch_millis(n, s, ctx.timezone()) {
+                Some(ordering) => ordering_matches(operator, ordering),
+                None => false,
+            }
+        }
+        #[cfg(feature = "chrono")]
+        (Value::String(s), Value::Number(n)) if ctx.options.coerce_epoch_millis => {
+            match time::compare_epoch_millis(n, s, ctx.timezone()) {
+                Some(ordering) => ordering_matches(operator, ordering.reverse()),
+                None => false,
+            }
+        }
+        _ => false, // In case there's a mismatch in type (one is number and the other is string)
+    }
+}
+
+/// Returns whether two values are structurally equal. Numbers are equal if they have the same value,
+/// so `1` equals `1.0`; objects are equal if they have the same keys with equal values, in any order.
+/// Arrays must have equal elements in the same order, or in any order if `unordered` is set.
+pub(crate) fn deep_eq(left: &Value, right: &Value, unordered: bool) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => {
+            expr::compare_numbers(l, r) == Some(Ordering::Equal)
+        }
+        (Value::Array(l), Value::Array(r)) if unordered => {
+            l.len() == r.len() && same_elements(l, r)
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r).all(|(l, r)| deep_eq(l, r, unordered))
+        }
+        (Value::Object(l), Value::Object(r)) => {
+            l.len() == r.len()
+                && l.iter()
+                    .all(|(key, l)| r.get(key).is_some_and(|r| deep_eq(l, r, unordered)))
+        }
+        _ => left == right,
+    }
+}
+
+/// Returns whether two arrays of the same length have the same elements, counting duplicates, in
+/// any order.
+fn same_elements(left: &[Value], right: &[Value]) -> bool {
+    let mut unmatched: Vec<&Value> = right.iter().collect();
+    left.iter().all(
+        |l| match unmatched.iter().position(|r| deep_eq(l, r, true)) {
+            Some(i) => {
+                unmatched.swap_remove(i);
+                true
+            }
+            None => false,
+        },
+    )
+}
+
+/// Returns whether the object `left` has every key of the object `pattern`, with a deeply equal
+/// value. Objects nested in the pattern are matched the same way, so they can also leave keys out.
+fn matches_object(left: &Value, pattern: &Value, unordered: bool) -> bool {
+    match (left, pattern) {
+        (Value::Object(left), Value::Object(pattern)) => pattern.iter().all(|(key, p)| {
+            left.get(key)
+                .is_some_and(|l| matches_object(l, p, unordered))
+        }),
+        _ => deep_eq(left, pattern, unordered),
+    }
+}
+
+/// Returns whether the ordering of two values satisfies an operator.
+fn ordering_matches(operator: &str, ordering: Ordering) -> bool {
+    match operator {
+        "=" => ordering == Ordering::Equal,
+        "!=" => ordering != Ordering::Equal,
+        ">=" => ordering != Ordering::Less,
+        ">" => ordering == Ordering::Greater,
+        "<=" => ordering != Ordering::Greater,
+        "<" => ordering == Ordering::Less,
+        _ => false, // Unknown operator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    #[test]
+    fn test_operands_are_borrowed() {
+        let options = ApplyOptions::default();
+        let ctx = Context::new(&options);
+        let v = json!({ "n": 5, "limit": 10 });
+        let filters = parse(".n < 10 AND .n < .limit AND 2*.n <= .limit").unwrap();
+        for filter in &filters[..2] {
+            assert!(matches!(filter.left(&v, &ctx), Some(Cow::Borrowed(_))));
+            assert!(matches!(filter.right(&v, &ctx), Some(Cow::Borrowed(_))));
+        }
+        // Only a multiplied operand is computed.
+        assert!(matches!(filters[2].left(&v, &ctx), Some(Cow::Owned(_))));
+        assert!(matches_all(&v, &filters, &ctx));
+    }
+
+    #[test]
+    fn test_compare_numbers() {
+        let options = ApplyOptions::default();
+        let ctx = Context::new(&options);
+        let compare = |l: Value, operator, r: Value| compare(&l, operator, &r, &ctx);
+        assert!(compare(json!(2), "<", json!(2.5)));
+        assert!(compare(json!(2), "=", json!(2.0)));
+        assert!(compare(json!(2), "DEEP_EQ", json!(2.0)));
+        assert!(compare(json!(2), "MATCHES_OBJECT", json!(2)));
+        assert!(!compare(json!(2), "CONTAINS", json!(2)));
+        assert!(!compare(json!(2), "SEMVER=", json!(2)));
+        assert!(!compare(json!(2), "IN_CIDR", json!(2)));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = ApplyOptions {
+            defaults: Some(json!({ "retries": 0, "limits": { "max": 5 }, "jobs": [] })),
+            ..Default::default()
+        };
+        let passes = |filter_string, v: &Value| {
+            let filters = parse(filter_string).unwrap();
+            #[cfg(feature = "std")]
+            assert_eq!(
+                crate::apply_str(&v.to_string(), &filters, &options).unwrap(),
+                crate::apply_with(v, &filters, &options)
+            );
+            crate::apply_with(v, &filters, &options)
+        };
+        let v = json!({ "name": "a", "jobs": [{ "retries": 4 }, {}] });
+        assert!(passes(".retries = 0 AND .limits.max > .retries", &v));
+        assert!(!passes(".retries >= 3", &v));
+        assert!(passes(".retries >= 3", &json!({ "retries": 3 })));
+        assert!(!passes(".missing = 0", &v));
+        // Elements of arrays have no defaults, except through `$root`.
+        assert!(!passes("ALL .jobs WHERE .retries >= 0", &v));
+        assert!(passes(
+            "ANY .jobs WHERE $root.limits.max > .retries",
+            &json!({ "jobs": [{ "retries": 4 }] })
+        ));
+        assert!(passes("count(.jobs) = 0", &json!({})));
+    }
+
+    #[test]
+    fn test_budget() {
+        let options = ApplyOptions::default();
+        let v = json!({ "items": (0..100).map(|n| json!({ "n": n })).collect::<Vec<_>>(), "s": "x".repeat(500) });
+        let run = |filter_string, budget| {
+            let filters = parse(filter_string).unwrap();
+            apply_budgeted(&v, &filters, &Context::new(&options).with_budget(budget))
+        };
+        assert_eq!(run(".s != 'y' AND count(.items) = 100", 10), Ok(true));
+        assert_eq!(run(".s = 'y'", 10), Ok(false));
+        let error = EvalError::BudgetExceeded { budget: 100 };
+        let exceeded = Err(error.clone());
+        assert_eq!(run("sum(.items[*].n) > 0", 100), exceeded);
+        assert_eq!(run("ANY .items WHERE .n = 99", 100), exceeded);
+        assert_eq!(run("capture(.s, 'x(x)') = 'x'", 100), exceeded);
+        assert_eq!(run("ANY .items WHERE .n = 99", 1000), Ok(true));
+        assert_eq!(
+            error.to_string(),
+            "the evaluation spent more than its budget of 100 fuel"
+        );
+    }
+}
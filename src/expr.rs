@@ -0,0 +1,981 @@
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::Index;
+
+use regex::{Regex, RegexBuilder};
+use serde_json::{Number, Value};
+
+use crate::case::{self, KeyCase};
+use crate::eval::Context;
+use crate::intern::{Interner, Name};
+#[cfg(feature = "chrono")]
+use crate::time;
+
+/// A single step of a field path.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment<'a> {
+    /// An object key. A null-safe key, written after `?.`, yields null when the value it is looked
+    /// up in is missing or null, instead of failing the whole path.
+    Key { name: Name<'a>, null_safe: bool },
+    /// `[*]`, every element of an array.
+    Wildcard,
+}
+
+/// A path into a JSON Value, such as `.user.name`. An empty path refers to the Value itself.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Path<'a> {
+    pub(crate) segments: Vec<Segment<'a>>,
+}
+
+static NULL: Value = Value::Null;
+
+impl<'a> Path<'a> {
+    /// Parses the text of a path token such as `.user.name` or `.user?.address`.
+    pub(crate) fn parse(text: &'a str) -> Path<'a> {
+        let mut segments = Vec::new();
+        let mut null_safe = false;
+        for part in text.strip_prefix('.').unwrap_or(text).split('.') {
+            let (part, next_null_safe) = match part.strip_suffix('?') {
+                Some(part) => (part, true),
+                None => (part, false),
+            };
+            let mut name = part;
+            let mut wildcards = 0;
+            while let Some(rest) = name.strip_suffix("[*]") {
+                name = rest;
+                wildcards += 1;
+            }
+            if !name.is_empty() {
+                segments.push(Segment::Key {
+                    name: Name::Borrowed(name),
+                    null_safe,
+                });
+            }
+            segments.extend(core::iter::repeat_n(Segment::Wildcard, wildcards));
+            null_safe = next_null_safe;
+        }
+        Path { segments }
+    }
+
+    /// Copies the path so it no longer borrows the text it was parsed from.
+    pub(crate) fn into_owned(self) -> Path<'static> {
+        self.into_shared(&mut Interner::default())
+    }
+
+    /// Copies the path like [`Path::into_owned`], sharing the names copied with `names`.
+    pub(crate) fn into_shared(self, names: &mut Interner) -> Path<'static> {
+        Path {
+            segments: self
+                .segments
+                .into_iter()
+                .map(|segment| match segment {
+                    Segment::Key { name, null_safe } => Segment::Key {
+                        name: name.into_shared(names),
+                        null_safe,
+                    },
+                    Segment::Wildcard => Segment::Wildcard,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the field name if the path is a single top-level key.
+    pub(crate) fn as_field(&self) -> Option<&'a str> {
+        match self.segments.as_slice() {
+            [Segment::Key {
+                name: Name::Borrowed(name),
+                null_safe: false,
+            }] => Some(*name),
+            _ => None,
+        }
+    }
+
+    /// Looks the path up in a Value. Returns `None` when a key is missing, unless a null-safe
+    /// step turns the missing value into null.
+    ///
+    /// A path with a wildcard yields an array of the values found under every element, skipping
+    /// elements the rest of the path is missing from. It is missing if the wildcard isn't applied to
+    /// an array.
+    pub(crate) fn resolve<'v>(&self, v: &'v Value) -> Option<Cow<'v, Value>> {
+        if !self.segments.contains(&Segment::Wildcard) {
+            return lookup(&self.segments, v, KeyCase::Exact).map(Cow::Borrowed);
+        }
+        let mut values = Vec::new();
+        collect(&self.segments, v, KeyCase::Exact, &mut values, &|| true)?;
+        Some(Cow::Owned(Value::Array(
+            values.into_iter().cloned().collect(),
+        )))
+    }
+
+    /// Looks the path up like [`Path::resolve`], treating it as missing if it is longer than the
+    /// context's limits allow or its wildcards visit more elements than are left. A path starting
+    /// with an alias of the options the Value doesn't have is read from the alias's target, and
+    /// missing keys are matched in the other cases the options allow.
+    pub(crate) fn resolve_limited<'v>(
+        &self,
+        v: &'v Value,
+        ctx: &Context,
+    ) -> Option<Cow<'v, Value>> {
+        match ctx.options.aliases.rewrite(self, v, ctx.options.key_case) {
+            Some(target) => target.resolve_within(v, ctx),
+            None => self.resolve_within(v, ctx),
+        }
+    }
+
+    fn resolve_within<'v>(&self, v: &'v Value, ctx: &Context) -> Option<Cow<'v, Value>> {
+        // Each step of the path spends fuel, and so does each element its wildcards visit.
+        if self.segments.len() > ctx.limits.max_depth
+            || !ctx.spend(self.segments.len().max(1) as u64)
+        {
+            return None;
+        }
+        let case = ctx.options.key_case;
+        if !self.segments.contains(&Segment::Wildcard) {
+            return lookup(&self.segments, v, case).map(Cow::Borrowed);
+        }
+        let mut values = Vec::new();
+        collect(&self.segments, v, case, &mut values, &|| ctx.visit())?;
+        if ctx.exhausted() {
+            return None;
+        }
+        Some(Cow::Owned(Value::Array(
+            values.into_iter().cloned().collect(),
+        )))
+    }
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str(".");
+        }
+        for segment in &self.segments {
+            match segment {
+                Segment::Key { name, null_safe } => {
+                    write!(f, "{}.{}", if *null_safe { "?" } else { "" }, name)?
+                }
+                Segment::Wildcard => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether two paths read the same values, whether their steps are null-safe or not.
+pub(crate) fn same_path(a: &Path, b: &Path) -> bool {
+    a.segments.len() == b.segments.len()
+        && a.segments.iter().zip(&b.segments).all(|pair| match pair {
+            (Segment::Key { name: a, .. }, Segment::Key { name: b, .. }) => a == b,
+            (Segment::Wildcard, Segment::Wildcard) => true,
+            _ => false,
+        })
+}
+
+/// Looks up a path without wildcards, matching keys as `case` allows.
+pub(crate) fn lookup<'v>(segments: &[Segment], v: &'v Value, case: KeyCase) -> Option<&'v Value> {
+    let mut current = Some(v);
+    for segment in segments {
+        match segment {
+            Segment::Key { name, null_safe } => {
+                if *null_safe && current.is_none_or(Value::is_null) {
+                    return Some(&NULL);
+                }
+                current = case::get(current?, name, case);
+            }
+            Segment::Wildcard => unreachable!("lookup is only called with paths without wildcards"),
+        }
+    }
+    current
+}
+
+/// Collects the values a path with wildcards finds, or returns `None` if the first wildcard isn't
+/// applied to an array. `visit` is called for every element a wildcard expands to, and collecting
+/// stops early when it returns `false`.
+fn collect<'v>(
+    segments: &[Segment],
+    v: &'v Value,
+    case: KeyCase,
+    values: &mut Vec<&'v Value>,
+    visit: &dyn Fn() -> bool,
+) -> Option<()> {
+    let Some(wildcard) = segments.iter().position(|s| *s == Segment::Wildcard) else {
+        values.extend(lookup(segments, v, case));
+        return Some(());
+    };
+    let rest = &segments[wildcard + 1..];
+    for element in lookup(&segments[..wildcard], v, case)?.as_array()? {
+        if !visit() {
+            break;
+        }
+        // Elements the rest of the path is missing from are skipped.
+        let _ = collect(rest, element, case, values, visit);
+    }
+    Some(())
+}
+
+/// The built-in functions that can be called in a filter expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Function {
+    Abs,
+    Min,
+    Max,
+    Round,
+    Floor,
+    Ceil,
+    Sum,
+    Count,
+    Avg,
+    Keys,
+    Values,
+    Type,
+}
+
+impl Function {
+    pub(crate) fn from_name(name: &str) -> Option<Function> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "round" => Function::Round,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "sum" => Function::Sum,
+            "count" => Function::Count,
+            "avg" => Function::Avg,
+            "keys" => Function::Keys,
+            "values" => Function::Values,
+            "type" => Function::Type,
+            _ => return None,
+        })
+    }
+
+    /// Returns the name the function is called by in a filter string.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Function::Abs => "abs",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Round => "round",
+            Function::Floor => "floor",
+            Function::Ceil => "ceil",
+            Function::Sum => "sum",
+            Function::Count => "count",
+            Function::Avg => "avg",
+            Function::Keys => "keys",
+            Function::Values => "values",
+            Function::Type => "type",
+        }
+    }
+
+    /// Returns the minimum and maximum number of arguments the function accepts.
+    pub(crate) fn arity(self) -> (usize, usize) {
+        match self {
+            Function::Abs
+            | Function::Round
+            | Function::Floor
+            | Function::Ceil
+            | Function::Sum
+            | Function::Count
+            | Function::Avg
+            | Function::Keys
+            | Function::Values
+            | Function::Type => (1, 1),
+            Function::Min | Function::Max => (1, usize::MAX),
+        }
+    }
+
+    fn call<'x>(self, args: Vec<Cow<'x, Value>>) -> Option<Cow<'x, Value>> {
+        match self {
+            Function::Abs => {
+                let n = args[0].as_number()?;
+                let result = match n.as_i64() {
+                    Some(i) => i.checked_abs().map(Number::from),
+                    None => Number::from_f64(n.as_f64()?.abs()),
+                };
+                result.map(|n| Cow::Owned(Value::Number(n)))
+            }
+            Function::Round => integral(&args[0], libm::round),
+            Function::Floor => integral(&args[0], libm::floor),
+            Function::Ceil => integral(&args[0], libm::ceil),
+            Function::Min => extreme(args, Ordering::Less),
+            Function::Max => extreme(args, Ordering::Greater),
+            Function::Sum => sum(args[0].as_array()?).map(|n| Cow::Owned(Value::Number(n))),
+            Function::Count => Some(Cow::Owned(Value::from(args[0].as_array()?.len()))),
+            Function::Avg => {
+                let values = args[0].as_array()?;
+                if values.is_empty() {
+                    return None;
+                }
+                let avg = sum(values)?.as_f64()? / values.len() as f64;
+                float_to_number(avg).map(|n| Cow::Owned(Value::Number(n)))
+            }
+            Function::Keys => {
+                let keys = args[0].as_object()?.keys().cloned().map(Value::String);
+                Some(Cow::Owned(Value::Array(keys.collect())))
+            }
+            Function::Values => {
+                let values = args[0].as_object()?.values().cloned();
+                Some(Cow::Owned(Value::Array(values.collect())))
+            }
+            Function::Type => {
+                let name = match args[0].as_ref() {
+                    Value::Null => "null",
+                    Value::Bool(_) => "boolean",
+                    Value::Number(_) => "number",
+                    Value::String(_) => "string",
+                    Value::Array(_) => "array",
+                    Value::Object(_) => "object",
+                };
+                Some(Cow::Owned(Value::from(name)))
+            }
+        }
+    }
+}
+
+/// Applies a rounding function, returning an integer Number when the result fits in an i64.
+fn integral<'x>(v: &Value, f: fn(f64) -> f64) -> Option<Cow<'x, Value>> {
+    let n = v.as_number()?;
+    if n.is_i64() || n.is_u64() {
+        return Some(Cow::Owned(Value::Number(n.clone())));
+    }
+    Some(Cow::Owned(Value::Number(float_to_number(f(n.as_f64()?))?)))
+}
+
+/// Adds up an array of numbers, exactly while the sum is an integer that fits in an i64.
+pub(crate) fn sum<'v>(values: impl IntoIterator<Item = &'v Value>) -> Option<Number> {
+    let mut total = Number::from(0);
+    for value in values {
+        total = arithmetic(&total, value.as_number()?, i64::checked_add, |a, b| a + b)?;
+    }
+    Some(total)
+}
+
+/// Picks the argument that compares as `wanted` against all others.
+fn extreme(args: Vec<Cow<'_, Value>>, wanted: Ordering) -> Option<Cow<'_, Value>> {
+    let mut best: Option<Cow<Value>> = None;
+    for arg in args {
+        let n = arg.as_number()?;
+        let replace = match &best {
+            Some(current) => compare_numbers(n, current.as_number()?) == Some(wanted),
+            None => true,
+        };
+        if replace {
+            best = Some(arg);
+        }
+    }
+    best
+}
+
+/// Converts a float into a Number, preferring an integer representation when it is exact.
+pub(crate) fn float_to_number(f: f64) -> Option<Number> {
+    if libm::trunc(f) == f && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Some(Number::from(f as i64))
+    } else {
+        Number::from_f64(f)
+    }
+}
+
+/// Compares two numbers, exactly when both are integers and as floats otherwise.
+pub(crate) fn compare_numbers(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+/// Multiplies two numbers, exactly when both are integers and as floats otherwise.
+pub(crate) fn multiply(a: &Number, b: &Number) -> Option<Number> {
+    arithmetic(a, b, i64::checked_mul, |a, b| a * b)
+}
+
+fn arithmetic(
+    a: &Number,
+    b: &Number,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Option<Number> {
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => int(a, b).map(Number::from),
+        _ => Number::from_f64(float(a.as_f64()?, b.as_f64()?)),
+    }
+}
+
+/// A regex compiled when the filter is parsed. Patterns compare equal when their source is equal.
+///
+/// The regex crate never backtracks, so matching takes time linear in the length of the text whatever
+/// the pattern. Only compiling a pattern can be expensive, which `size_limit` bounds.
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern(Regex);
+
+/// The regex crate's own limit on the compiled size of a pattern, in bytes.
+pub(crate) const REGEX_SIZE_LIMIT: usize = 10 * (1 << 20);
+
+impl Pattern {
+    /// Compiles a pattern, failing if the compiled program or the lazy DFA built while matching would
+    /// use more than `size_limit` bytes.
+    pub(crate) fn new(pattern: &str, size_limit: usize) -> Result<Pattern, regex::Error> {
+        RegexBuilder::new(pattern)
+            .size_limit(size_limit)
+            .dfa_size_limit(size_limit)
+            .build()
+            .map(Pattern)
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Returns the text of `group` in the first match in `haystack`.
+    ///
+    /// A pattern without groups captures the whole match, which is group 0.
+    fn capture<'h>(&self, haystack: &'h str, group: &Group) -> Option<&'h str> {
+        let captures = self.0.captures(haystack)?;
+        let m = match group {
+            Group::Index(1) if self.0.captures_len() == 1 => captures.get(0),
+            Group::Index(i) => captures.get(*i),
+            Group::Name(name) => captures.name(name),
+        };
+        m.map(|m| m.as_str())
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// Which group of a regex match `capture` extracts.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Group {
+    Index(usize),
+    Name(String),
+}
+
+/// An expression producing a value to compare, like a field path, a literal or a function call.
+///
+/// The expressions it is made of, such as the operands of `.a + 1`, are in the [`Arena`] of the
+/// clause it belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr<'a> {
+    Path(Path<'a>),
+    Literal(Value),
+    Mul(ExprId, ExprId),
+    Add(ExprId, ExprId),
+    Sub(ExprId, ExprId),
+    /// A value shifted by a duration in milliseconds, as in `now() - 7d`. Timestamp strings are
+    /// shifted in time, and numbers, taken to be epoch milliseconds, have the duration added.
+    Shift(ExprId, i64),
+    /// `now()`, the current time as an RFC 3339 timestamp.
+    #[cfg(feature = "chrono")]
+    Now,
+    Call(Function, ExprList),
+    /// The first of the expressions that is present and not null.
+    Coalesce(ExprList),
+    /// `capture(input, pattern, group)`: the text of a regex group matched in a string.
+    Capture(ExprId, Pattern, Group),
+    /// A list such as `('admin', .role)`, evaluating to an array.
+    List(ExprList),
+    /// A path into a Value enclosing the one being evaluated, such as `$root.budget` in the clauses
+    /// of a quantifier.
+    Anchored(Anchor, Path<'a>),
+    /// A call of a function of the plugins of the options, by name.
+    Plugin(Cow<'a, str>, ExprList),
+}
+
+/// The position of an expression in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExprId(u32);
+
+/// Expressions next to each other in an [`Arena`], such as the arguments of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExprList {
+    start: u32,
+    len: u32,
+}
+
+/// The expressions that the expressions of a clause are made of, kept together in one allocation
+/// instead of each in a box of its own, so evaluating a clause reads memory that is close together.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Arena<'a> {
+    exprs: Vec<Expr<'a>>,
+}
+
+impl<'a> Arena<'a> {
+    /// Adds an expression, returning where it is.
+    pub(crate) fn alloc(&mut self, expr: Expr<'a>) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    /// Removes the expression at `id` and those added after it.
+    pub(crate) fn truncate(&mut self, id: ExprId) {
+        self.exprs.truncate(id.0 as usize);
+    }
+
+    /// Adds expressions next to each other, returning where they are.
+    pub(crate) fn alloc_list(&mut self, exprs: Vec<Expr<'a>>) -> ExprList {
+        let start = self.exprs.len() as u32;
+        self.exprs.extend(exprs);
+        ExprList {
+            start,
+            len: self.exprs.len() as u32 - start,
+        }
+    }
+
+    /// Copies the expressions so they no longer borrow the text they were parsed from, sharing the
+    /// names copied with `names`.
+    pub(crate) fn into_shared(self, names: &mut Interner) -> Arena<'static> {
+        Arena {
+            exprs: self
+                .exprs
+                .into_iter()
+                .map(|expr| expr.into_shared(names))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Index<ExprId> for Arena<'a> {
+    type Output = Expr<'a>;
+
+    fn index(&self, id: ExprId) -> &Expr<'a> {
+        &self.exprs[id.0 as usize]
+    }
+}
+
+impl<'a> Index<ExprList> for Arena<'a> {
+    type Output = [Expr<'a>];
+
+    fn index(&self, list: ExprList) -> &[Expr<'a>] {
+        &self.exprs[list.start as usize..(list.start + list.len) as usize]
+    }
+}
+
+/// The Value an anchored path starts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Anchor {
+    /// `$root`, the Value the filters are applied to, even in the clauses of nested quantifiers.
+    Root,
+    /// `$parent`, the Value holding the array whose elements a quantifier's clauses are evaluated
+    /// against. Outside quantifiers, it is missing.
+    Parent,
+}
+
+impl Anchor {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Anchor::Root => "$root",
+            Anchor::Parent => "$parent",
+        }
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Copies the expression so it no longer borrows the text it was parsed from, sharing the names
+    /// copied with `names`. The expressions it is made of are copied with their [`Arena`].
+    pub(crate) fn into_shared(self, names: &mut Interner) -> Expr<'static> {
+        match self {
+            Expr::Path(path) => Expr::Path(path.into_shared(names)),
+            Expr::Literal(value) => Expr::Literal(value),
+            Expr::Mul(lhs, rhs) => Expr::Mul(lhs, rhs),
+            Expr::Add(lhs, rhs) => Expr::Add(lhs, rhs),
+            Expr::Sub(lhs, rhs) => Expr::Sub(lhs, rhs),
+            Expr::Shift(base, millis) => Expr::Shift(base, millis),
+            #[cfg(feature = "chrono")]
+            Expr::Now => Expr::Now,
+            Expr::Call(function, args) => Expr::Call(function, args),
+            Expr::Coalesce(exprs) => Expr::Coalesce(exprs),
+            Expr::Capture(input, pattern, group) => Expr::Capture(input, pattern, group),
+            Expr::List(exprs) => Expr::List(exprs),
+            Expr::Anchored(anchor, path) => Expr::Anchored(anchor, path.into_shared(names)),
+            Expr::Plugin(name, args) => Expr::Plugin(Cow::Owned(name.into_owned()), args),
+        }
+    }
+
+    /// Evaluates the expression against a JSON Value, finding the expressions it is made of in
+    /// `arena`.
+    ///
+    /// Returns `None` when a referenced field is missing or an argument has the wrong type.
+    pub(crate) fn eval<'x>(
+        &'x self,
+        arena: &'x Arena,
+        v: &'x Value,
+        ctx: &Context,
+    ) -> Option<Cow<'x, Value>> {
+        match self {
+            Expr::Path(path) => path
+                .resolve_limited(v, ctx)
+                .or_else(|| ctx.default(path, false)),
+            Expr::Literal(value) => Some(Cow::Borrowed(value)),
+            Expr::Mul(lhs, rhs) => binary(arena, *lhs, *rhs, v, ctx, multiply),
+            Expr::Add(lhs, rhs) => binary(arena, *lhs, *rhs, v, ctx, |a, b| {
+                arithmetic(a, b, i64::checked_add, |a, b| a + b)
+            }),
+            Expr::Sub(lhs, rhs) => binary(arena, *lhs, *rhs, v, ctx, |a, b| {
+                arithmetic(a, b, i64::checked_sub, |a, b| a - b)
+            }),
+            Expr::Shift(base, millis) => match arena[*base].eval(arena, v, ctx)?.as_ref() {
+                Value::Number(n) => n.as_i64()?.checked_add(*millis).map(Value::from),
+                #[cfg(feature = "chrono")]
+                Value::String(s) => time::shift(s, *millis, ctx.timezone()).map(Value::String),
+                _ => None,
+            }
+            .map(Cow::Owned),
+            #[cfg(feature = "chrono")]
+            Expr::Now => Some(Cow::Owned(Value::String(time::format(ctx.now())))),
+            Expr::Call(function, args) => {
+                if !ctx.spend(1) {
+                    return None;
+                }
+                let args = arena[*args]
+                    .iter()
+                    .map(|arg| arg.eval(arena, v, ctx))
+                    .collect::<Option<Vec<_>>>()?;
+                function.call(args)
+            }
+            Expr::Coalesce(exprs) => arena[*exprs]
+                .iter()
+                .filter_map(|expr| expr.eval(arena, v, ctx))
+                .find(|value| !value.is_null()),
+            Expr::Capture(input, pattern, group) => {
+                let input = arena[*input].eval(arena, v, ctx)?;
+                // Matching takes time linear in the length of the text.
+                if !ctx.spend(input.as_str()?.len() as u64) {
+                    return None;
+                }
+                let captured = pattern.capture(input.as_str()?, group)?;
+                Some(Cow::Owned(Value::String(captured.to_string())))
+            }
+            Expr::List(exprs) => arena[*exprs]
+                .iter()
+                .map(|expr| expr.eval(arena, v, ctx).map(Cow::into_owned))
+                .collect::<Option<Vec<_>>>()
+                .map(|values| Cow::Owned(Value::Array(values))),
+            // An enclosing Value doesn't live as long as `v`, so what is found in it is copied.
+            Expr::Anchored(Anchor::Root, path) => match ctx.root() {
+                Some(root) => path
+                    .resolve_limited(root, ctx)
+                    .map(|found| Cow::Owned(found.into_owned())),
+                None => path.resolve_limited(v, ctx),
+            }
+            .or_else(|| ctx.default(path, true)),
+            Expr::Anchored(Anchor::Parent, path) => path
+                .resolve_limited(ctx.parent()?, ctx)
+                .map(|found| Cow::Owned(found.into_owned())),
+            Expr::Plugin(name, args) => {
+                if !ctx.spend(1) {
+                    return None;
+                }
+                let plugin = ctx.options.plugins.function(name)?;
+                let args = arena[*args]
+                    .iter()
+                    .map(|arg| arg.eval(arena, v, ctx).map(Cow::into_owned))
+                    .collect::<Option<Vec<_>>>()?;
+                plugin.call(&args).map(Cow::Owned)
+            }
+        }
+    }
+}
+
+/// Writes expressions separated by commas.
+fn write_list(f: &mut fmt::Formatter<'_>, arena: &Arena, exprs: ExprList) -> fmt::Result {
+    for (i, expr) in arena[exprs].iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", expr.display(arena))?;
+    }
+    Ok(())
+}
+
+/// Writes a literal the way it is written in a filter string, with strings in single quotes.
+pub(crate) fn write_literal(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+        Value::Array(elements) => {
+            f.write_str("(")?;
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_literal(f, element)?;
+            }
+            f.write_str(")")
+        }
+        _ => write!(f, "{value}"),
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Displays the expression, finding the expressions it is made of in `arena`.
+    pub(crate) fn display<'e>(&'e self, arena: &'e Arena<'a>) -> DisplayExpr<'e, 'a> {
+        DisplayExpr { expr: self, arena }
+    }
+}
+
+/// An expression with the [`Arena`] it is in, displayed in filter string syntax.
+pub(crate) struct DisplayExpr<'e, 'a> {
+    expr: &'e Expr<'a>,
+    arena: &'e Arena<'a>,
+}
+
+impl fmt::Display for DisplayExpr<'_, '_> {
+    /// Writes the expression in filter string syntax.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arena = self.arena;
+        let shown = |id: ExprId| arena[id].display(arena);
+        match self.expr {
+            Expr::Path(path) => write!(f, "{path}"),
+            Expr::Anchored(anchor, path) if path.segments.is_empty() => f.write_str(anchor.name()),
+            Expr::Anchored(anchor, path) => write!(f, "{}{path}", anchor.name()),
+            Expr::Literal(value) => write_literal(f, value),
+            Expr::Mul(lhs, rhs) => write!(f, "{}*{}", shown(*lhs), shown(*rhs)),
+            Expr::Add(lhs, rhs) => write!(f, "{} + {}", shown(*lhs), shown(*rhs)),
+            Expr::Sub(lhs, rhs) => write!(f, "{} - {}", shown(*lhs), shown(*rhs)),
+            Expr::Shift(base, millis) => {
+                let sign = if *millis < 0 { '-' } else { '+' };
+                let millis = millis.unsigned_abs();
+                // The duration is written in the largest unit it is a whole number of.
+                let (size, unit) = [
+                    (604_800_000, "w"),
+                    (86_400_000, "d"),
+                    (3_600_000, "h"),
+                    (60_000, "m"),
+                    (1_000, "s"),
+                ]
+                .into_iter()
+                .find(|(size, _)| millis > 0 && millis % size == 0)
+                .unwrap_or((1, "ms"));
+                write!(f, "{} {sign} {}{unit}", shown(*base), millis / size)
+            }
+            #[cfg(feature = "chrono")]
+            Expr::Now => f.write_str("now()"),
+            Expr::Call(function, args) => {
+                write!(f, "{}(", function.name())?;
+                write_list(f, arena, *args)?;
+                f.write_str(")")
+            }
+            Expr::Coalesce(exprs) => {
+                f.write_str("coalesce(")?;
+                write_list(f, arena, *exprs)?;
+                f.write_str(")")
+            }
+            Expr::Plugin(name, args) => {
+                write!(f, "{name}(")?;
+                write_list(f, arena, *args)?;
+                f.write_str(")")
+            }
+            Expr::Capture(input, pattern, group) => {
+                write!(f, "capture({}, ", shown(*input))?;
+                write_literal(f, &Value::String(pattern.as_str().to_string()))?;
+                match group {
+                    Group::Index(1) => Ok(()),
+                    Group::Index(i) => write!(f, ", {i}"),
+                    Group::Name(name) => {
+                        f.write_str(", ")?;
+                        write_literal(f, &Value::String(name.to_string()))
+                    }
+                }?;
+                f.write_str(")")
+            }
+            Expr::List(exprs) => {
+                f.write_str("(")?;
+                write_list(f, arena, *exprs)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Evaluates a numeric operation on two expressions.
+fn binary<'x>(
+    arena: &'x Arena,
+    lhs: ExprId,
+    rhs: ExprId,
+    v: &'x Value,
+    ctx: &Context,
+    op: fn(&Number, &Number) -> Option<Number>,
+) -> Option<Cow<'x, Value>> {
+    let lhs = arena[lhs].eval(arena, v, ctx)?;
+    let rhs = arena[rhs].eval(arena, v, ctx)?;
+    op(lhs.as_number()?, rhs.as_number()?).map(|n| Cow::Owned(Value::Number(n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApplyOptions;
+    use serde_json::json;
+
+    fn eval(arena: &Arena, expr: &Expr, v: &Value) -> Option<Value> {
+        let options = ApplyOptions::default();
+        expr.eval(arena, v, &Context::new(&options))
+            .map(Cow::into_owned)
+    }
+
+    fn call(function: Function, args: Vec<Value>) -> Option<Value> {
+        let mut arena = Arena::default();
+        let args = arena.alloc_list(args.into_iter().map(Expr::Literal).collect());
+        eval(&arena, &Expr::Call(function, args), &Value::Null)
+    }
+
+    #[test]
+    fn test_null_safe_path() {
+        let path = Path::parse(".user?.address?.city");
+        let v = json!({ "user": { "address": { "city": "NYC" } } });
+        assert_eq!(path.resolve(&v).as_deref(), Some(&json!("NYC")));
+        assert_eq!(
+            path.resolve(&json!({ "user": {} })).as_deref(),
+            Some(&Value::Null)
+        );
+        assert_eq!(
+            path.resolve(&json!({ "user": null })).as_deref(),
+            Some(&Value::Null)
+        );
+        assert_eq!(path.resolve(&json!({})).as_deref(), Some(&Value::Null));
+        assert_eq!(
+            Path::parse(".user.address.city").resolve(&json!({ "user": {} })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_wildcard_path() {
+        let path = Path::parse(".items[*].price");
+        let v = json!({ "items": [{ "price": 3 }, { "name": "free" }, { "price": 4.5 }] });
+        assert_eq!(path.resolve(&v).as_deref(), Some(&json!([3, 4.5])));
+        assert_eq!(path.resolve(&json!({ "items": {} })), None);
+        let nested = json!({ "rows": [[1, 2], [3]] });
+        assert_eq!(
+            Path::parse(".rows[*][*]").resolve(&nested).as_deref(),
+            Some(&json!([1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn test_keys_and_values() {
+        let object = json!({ "team": "core", "tier": 1 });
+        assert_eq!(
+            call(Function::Keys, vec![object.clone()]),
+            Some(json!(["team", "tier"]))
+        );
+        assert_eq!(
+            call(Function::Values, vec![object]),
+            Some(json!(["core", 1]))
+        );
+        assert_eq!(call(Function::Keys, vec![json!(["team"])]), None);
+    }
+
+    #[test]
+    fn test_type() {
+        for (value, name) in [
+            (json!(null), "null"),
+            (json!(false), "boolean"),
+            (json!(1.5), "number"),
+            (json!("1.5"), "string"),
+            (json!([]), "array"),
+            (json!({}), "object"),
+        ] {
+            assert_eq!(call(Function::Type, vec![value]), Some(json!(name)));
+        }
+    }
+
+    #[test]
+    fn test_aggregates() {
+        assert_eq!(call(Function::Sum, vec![json!([1, 2, 3])]), Some(json!(6)));
+        assert_eq!(call(Function::Sum, vec![json!([1, 2.5])]), Some(json!(3.5)));
+        assert_eq!(call(Function::Sum, vec![json!([])]), Some(json!(0)));
+        assert_eq!(call(Function::Sum, vec![json!([1, "2"])]), None);
+        assert_eq!(
+            call(Function::Count, vec![json!([1, null])]),
+            Some(json!(2))
+        );
+        assert_eq!(call(Function::Count, vec![json!("abc")]), None);
+        assert_eq!(call(Function::Avg, vec![json!([1, 2])]), Some(json!(1.5)));
+        assert_eq!(call(Function::Avg, vec![json!([])]), None);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut arena = Arena::default();
+        let expr = Expr::Coalesce(arena.alloc_list(vec![
+            Expr::Path(Path::parse(".nickname")),
+            Expr::Path(Path::parse(".name")),
+        ]));
+        let v = json!({ "nickname": null, "name": "bob" });
+        assert_eq!(eval(&arena, &expr, &v), Some(json!("bob")));
+        assert_eq!(
+            eval(&arena, &expr, &json!({ "nickname": "b" })),
+            Some(json!("b"))
+        );
+        assert_eq!(eval(&arena, &expr, &json!({})), None);
+    }
+
+    #[test]
+    fn test_capture() {
+        let capture = |pattern: &str, group: Group, input: &str| {
+            let mut arena = Arena::default();
+            let expr = Expr::Capture(
+                arena.alloc(Expr::Literal(json!(input))),
+                Pattern::new(pattern, REGEX_SIZE_LIMIT).unwrap(),
+                group,
+            );
+            eval(&arena, &expr, &Value::Null)
+        };
+        assert_eq!(
+            capture(r"v(\d+)", Group::Index(1), "/api/v2/users"),
+            Some(json!("2"))
+        );
+        assert_eq!(
+            capture(r"v\d+", Group::Index(1), "/api/v2/users"),
+            Some(json!("v2"))
+        );
+        assert_eq!(
+            capture(
+                r"/(?P<res>\w+)$",
+                Group::Name("res".into()),
+                "/api/v2/users"
+            ),
+            Some(json!("users"))
+        );
+        assert_eq!(capture(r"v(\d+)", Group::Index(1), "/api/users"), None);
+        assert_eq!(capture(r"v(\d+)", Group::Index(2), "/api/v2"), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut arena = Arena::default();
+        let mut literal = |v: Value| arena.alloc(Expr::Literal(v));
+        let add = Expr::Add(literal(json!(2)), literal(json!(3)));
+        let sub = Expr::Sub(literal(json!(2)), literal(json!(0.5)));
+        let shift = Expr::Shift(literal(json!(1000)), -250);
+        let text = Expr::Add(literal(json!("a")), literal(json!(1)));
+        assert_eq!(eval(&arena, &add, &Value::Null), Some(json!(5)));
+        assert_eq!(eval(&arena, &sub, &Value::Null), Some(json!(1.5)));
+        assert_eq!(eval(&arena, &shift, &Value::Null), Some(json!(750)));
+        assert_eq!(eval(&arena, &text, &Value::Null), None);
+        assert_eq!(add.display(&arena).to_string(), "2 + 3");
+    }
+
+    #[test]
+    fn test_numeric_functions() {
+        assert_eq!(call(Function::Abs, vec![json!(-0.02)]), Some(json!(0.02)));
+        assert_eq!(call(Function::Abs, vec![json!(-3)]), Some(json!(3)));
+        assert_eq!(call(Function::Round, vec![json!(4.5)]), Some(json!(5)));
+        assert_eq!(call(Function::Floor, vec![json!(-1.5)]), Some(json!(-2)));
+        assert_eq!(call(Function::Ceil, vec![json!(1.2)]), Some(json!(2)));
+        assert_eq!(
+            call(Function::Min, vec![json!(3), json!(1.5), json!(2)]),
+            Some(json!(1.5))
+        );
+        assert_eq!(
+            call(Function::Max, vec![json!(3), json!(7)]),
+            Some(json!(7))
+        );
+        assert_eq!(call(Function::Abs, vec![json!("x")]), None);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use crate::{parser, CompiledFilter, Filter, ParseError, ParseLimits};
+
+/// An axum extractor for the `filter` query parameter of a request, such as
+/// `GET /orders?filter=.total > 100`, parsed and compiled for the handler to apply.
+///
+/// The filter is parsed with the [`ParseLimits`] in the request's extensions, which a `.layer(Extension(limits))`
+/// on the router sets, or with the default limits. A request without a filter, whose query string can't
+/// be read, or whose filter doesn't parse within the limits, is rejected with a [`FilterRejection`].
+#[derive(Debug)]
+pub struct FilterQuery(pub CompiledFilter<'static>);
+
+/// Why a request's `filter` query parameter was rejected, answered with `400 Bad Request` and a JSON
+/// body such as `{"error": "expected a value", "start": 7, "end": 7}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterRejection {
+    /// The request has no `filter` query parameter.
+    Missing,
+    /// The query string can't be read, with the error reading it.
+    InvalidQuery(String),
+    /// The filter doesn't parse, or exceeds the limits.
+    Invalid(ParseError),
+}
+
+impl fmt::Display for FilterRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterRejection::Missing => f.write_str("missing `filter` query parameter"),
+            FilterRejection::InvalidQuery(error) => write!(f, "invalid query string: {error}"),
+            FilterRejection::Invalid(error) => write!(f, "invalid filter: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for FilterRejection {}
+
+impl From<QueryRejection> for FilterRejection {
+    fn from(rejection: QueryRejection) -> FilterRejection {
+        FilterRejection::InvalidQuery(rejection.body_text())
+    }
+}
+
+impl IntoResponse for FilterRejection {
+    fn into_response(self) -> Response {
+        let body = match &self {
+            FilterRejection::Missing | FilterRejection::InvalidQuery(_) => {
+                json!({ "error": self.to_string() })
+            }
+            FilterRejection::Invalid(error) => json!({
+                "error": error.message,
+                "start": error.span.start,
+                "end": error.span.end,
+            }),
+        };
+        (
+            StatusCode::BAD_REQUEST,
+            [(header::CONTENT_TYPE, "application/json")],
+            body.to_string(),
+        )
+            .into_response()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for FilterQuery {
+    type Rejection = FilterRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(mut params) =
+            Query::<HashMap<String, String>>::from_request_parts(parts, state).await?;
+        let filter_string = params.remove("filter").ok_or(FilterRejection::Missing)?;
+        let limits = parts
+            .extensions
+            .get::<ParseLimits>()
+            .copied()
+            .unwrap_or_default();
+        let filters =
+            parser::parse_with_limits(&filter_string, &limits).map_err(FilterRejection::Invalid)?;
+        Ok(FilterQuery(CompiledFilter::new(Filter::into_owned_all(
+            filters,
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Extracts the filter of a request, whose future is ready at once as it doesn't read the body.
+    fn extract(uri: &str, limits: Option<ParseLimits>) -> Result<FilterQuery, FilterRejection> {
+        let mut request = Request::get(uri).body(()).unwrap();
+        if let Some(limits) = limits {
+            request.extensions_mut().insert(limits);
+        }
+        let (mut parts, ()) = request.into_parts();
+        let future = pin!(FilterQuery::from_request_parts(&mut parts, &()));
+        match future.poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(extracted) => extracted,
+            Poll::Pending => unreachable!("extracting a filter doesn't wait"),
+        }
+    }
+
+    #[test]
+    fn test_from_request_parts() {
+        let FilterQuery(filter) =
+            extract("/orders?filter=.total%20%3E%20100&page=2", None).unwrap();
+        assert!(filter.matches(&json!({ "total": 150 })));
+        assert!(!filter.matches(&json!({ "total": 50 })));
+
+        assert_eq!(
+            extract("/orders?page=2", None).unwrap_err(),
+            FilterRejection::Missing
+        );
+        let FilterRejection::Invalid(error) =
+            extract("/orders?filter=.total+%3E", None).unwrap_err()
+        else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(error.span.start, 8);
+
+        let limits = ParseLimits {
+            max_clauses: 1,
+            ..ParseLimits::default()
+        };
+        let uri = "/orders?filter=.a+%3D+1+AND+.b+%3D+2";
+        assert!(extract(uri, None).is_ok());
+        assert!(matches!(
+            extract(uri, Some(limits)),
+            Err(FilterRejection::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_into_response() {
+        let response = FilterRejection::Missing.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        let uri = "/orders?page=x".parse().unwrap();
+        let rejection = Query::<HashMap<String, u32>>::try_from_uri(&uri).unwrap_err();
+        let FilterRejection::InvalidQuery(error) = FilterRejection::from(rejection) else {
+            panic!("expected an invalid query");
+        };
+        assert!(error.contains("invalid digit"), "{error}");
+        assert_eq!(
+            FilterRejection::InvalidQuery(error.clone()).to_string(),
+            format!("invalid query string: {error}")
+        );
+    }
+}
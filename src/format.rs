@@ -0,0 +1,413 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::expr::{self, Expr, Path};
+use crate::parser::{self, ParseError};
+use crate::{Compound, Filter};
+
+/// How many spaces each level of nesting is indented by in pretty-printed filters.
+const INDENT: usize = 4;
+
+/// Writes a filter in filter string syntax.
+pub(crate) struct Clause<'f, 'a>(pub(crate) &'f Filter<'a>);
+
+impl Clause<'_, '_> {
+    fn write_all(f: &mut fmt::Formatter<'_>, filters: &[Filter]) -> fmt::Result {
+        write!(f, "{}", Clauses(filters))
+    }
+}
+
+/// Returns whether the `then` branch of a conditional is written in parentheses: it is unless it is a
+/// single clause, and that clause isn't itself a conditional, whose `else` would be read as the outer
+/// one's.
+fn grouped(then: &[Filter]) -> bool {
+    !matches!(then, [clause] if !matches!(clause.compound, Some(Compound::If { .. })))
+}
+
+/// Writes a list of filters joined by `AND` in filter string syntax.
+pub(crate) struct Clauses<'f, 'a>(pub(crate) &'f [Filter<'a>]);
+
+impl fmt::Display for Clauses<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, filter) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" AND ")?;
+            }
+            write!(f, "{}", Clause(filter))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the clause in canonical filter string syntax, which parses back to the same clause.
+impl fmt::Display for Filter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Clause(self))
+    }
+}
+
+impl fmt::Display for Clause<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.should {
+            f.write_str("SHOULD ")?;
+        }
+        self.write_clause(f)?;
+        write_weight(f, self.0)
+    }
+}
+
+/// Writes the `WEIGHT` of a clause that has one.
+fn write_weight(f: &mut impl fmt::Write, filter: &Filter) -> fmt::Result {
+    match filter.weight {
+        Some(weight) => write!(f, " WEIGHT {weight}"),
+        None => Ok(()),
+    }
+}
+
+impl Clause<'_, '_> {
+    /// Writes the clause without its weight.
+    fn write_clause(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let filter = self.0;
+        let arena = &filter.arena;
+        match &filter.compound {
+            Some(Compound::Quantified {
+                all,
+                array,
+                filters,
+            }) => {
+                let quantifier = if *all { "ALL" } else { "ANY" };
+                write!(f, "{quantifier} {} WHERE (", array.display(arena))?;
+                Clause::write_all(f, filters)?;
+                f.write_str(")")
+            }
+            Some(Compound::Fuzzy {
+                input,
+                target,
+                max_distance,
+            }) => write!(
+                f,
+                "{} FUZZY({}, {max_distance})",
+                input.display(arena),
+                target.display(arena)
+            ),
+            #[cfg(feature = "geo")]
+            Some(Compound::Within {
+                location,
+                radius_m,
+                center: (lat, lon),
+            }) => write!(
+                f,
+                "{} WITHIN {radius_m}m OF ({lat}, {lon})",
+                location.display(arena)
+            ),
+            Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                f.write_str("if ")?;
+                Clause::write_all(f, condition)?;
+                if grouped(then) {
+                    f.write_str(" then (")?;
+                    Clause::write_all(f, then)?;
+                    f.write_str(")")?;
+                } else {
+                    f.write_str(" then ")?;
+                    Clause::write_all(f, then)?;
+                }
+                if !otherwise.is_empty() {
+                    f.write_str(" else (")?;
+                    Clause::write_all(f, otherwise)?;
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+            Some(Compound::Match {
+                comparison,
+                threshold,
+                filters,
+            }) => {
+                write!(f, "MATCH {comparison} {threshold} WHERE (")?;
+                Clause::write_all(f, filters)?;
+                f.write_str(")")
+            }
+            None => {
+                match (&filter.lhs, filter.field.as_deref()) {
+                    // A list can't be written on the left, so arrays are written as JSON there.
+                    (Some(Expr::Literal(value @ Value::Array(_))), _) => write!(f, "{value}")?,
+                    (Some(lhs), _) => write!(f, "{}", lhs.display(arena))?,
+                    (None, field) => {
+                        if let Some(multiplier) = filter.multiplier_field {
+                            write!(f, "{multiplier}*")?;
+                        }
+                        write!(f, "{}", Path::parse(field.unwrap_or_default()))?;
+                    }
+                }
+                if filter.optional {
+                    f.write_str("?")?;
+                }
+                write!(f, " {}", filter.operator)?;
+                if filter.operator.starts_with("IS ") {
+                    return Ok(());
+                }
+                f.write_str(" ")?;
+                if let Some(multiplier) = filter.multiplier_value {
+                    write!(f, "{multiplier}*")?;
+                }
+                match (&filter.rhs, &filter.value, &filter.value_field) {
+                    (Some(rhs), ..) => write!(f, "{}", rhs.display(arena)),
+                    (None, Some(value), _) => expr::write_literal(f, value),
+                    (None, None, Some(field)) => write!(f, "{}", Path::parse(field)),
+                    (None, None, None) => f.write_str("null"),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn format(input: &str) -> Result<String, ParseError> {
+    Ok(Clauses(&parser::parse_filters(input)?).to_string())
+}
+
+pub(crate) fn format_pretty(input: &str, width: usize) -> Result<String, ParseError> {
+    let filters = parser::parse_filters(input)?;
+    let mut out = String::new();
+    Pretty {
+        out: &mut out,
+        width,
+    }
+    .all(&filters, 0);
+    Ok(out)
+}
+
+/// Writes filters over several lines, breaking lists of clauses that don't fit in the width so each
+/// clause starts a line with its `AND`, and indenting the clauses nested in conditionals and quantifiers.
+struct Pretty<'o> {
+    out: &'o mut String,
+    width: usize,
+}
+
+impl Pretty<'_> {
+    fn fits(&self, text: &str, level: usize) -> bool {
+        !text.contains('\n') && level * INDENT + text.len() <= self.width
+    }
+
+    fn newline(&mut self, level: usize) {
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(level * INDENT));
+    }
+
+    fn all(&mut self, filters: &[Filter], level: usize) {
+        let line = Clauses(filters).to_string();
+        if self.fits(&line, level) {
+            self.out.push_str(&line);
+            return;
+        }
+        for (i, filter) in filters.iter().enumerate() {
+            if i > 0 {
+                self.newline(level);
+                self.out.push_str("AND ");
+            }
+            self.clause(filter, level);
+        }
+    }
+
+    fn clause(&mut self, filter: &Filter, level: usize) {
+        let line = Clause(filter).to_string();
+        // `AND ` is written before every clause but the first.
+        let nested = matches!(
+            filter.compound,
+            Some(Compound::Quantified { .. } | Compound::If { .. } | Compound::Match { .. })
+        );
+        if !nested || self.fits(&line, level + 1) {
+            self.out.push_str(&line);
+            return;
+        }
+        if filter.should {
+            self.out.push_str("SHOULD ");
+        }
+        match &filter.compound {
+            Some(Compound::Quantified {
+                all,
+                array,
+                filters,
+            }) => {
+                let quantifier = if *all { "ALL" } else { "ANY" };
+                let array = array.display(&filter.arena);
+                self.out.push_str(&format!("{quantifier} {array} WHERE ("));
+                self.group(filters, level);
+                self.out.push(')');
+            }
+            Some(Compound::Match {
+                comparison,
+                threshold,
+                filters,
+            }) => {
+                self.out
+                    .push_str(&format!("MATCH {comparison} {threshold} WHERE ("));
+                self.group(filters, level);
+                self.out.push(')');
+            }
+            Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                self.out.push_str("if ");
+                self.all(condition, level + 1);
+                if grouped(then) {
+                    self.out.push_str(" then (");
+                    self.group(then, level);
+                    self.out.push(')');
+                } else {
+                    self.out.push_str(" then");
+                    self.newline(level + 1);
+                    self.all(then, level + 1);
+                }
+                if !otherwise.is_empty() {
+                    self.newline(level);
+                    self.out.push_str("else (");
+                    self.group(otherwise, level);
+                    self.out.push(')');
+                }
+            }
+            _ => unreachable!("only nested clauses are broken over lines"),
+        }
+        write_weight(self.out, filter).expect("writing to a string doesn't fail");
+    }
+
+    /// Writes clauses indented on their own lines, for the inside of parentheses.
+    fn group(&mut self, filters: &[Filter], level: usize) {
+        self.newline(level + 1);
+        self.all(filters, level + 1);
+        self.newline(level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format("status  ==  paid and_more AND .n>=2")
+                .unwrap_err()
+                .message,
+            "expected `AND`"
+        );
+        assert_eq!(
+            format(
+                "status == paid AND   .n>=2 AND 3*.x <\".limit\" AND tags HAS_ANY ( 'a',\"b\" )"
+            )
+            .unwrap(),
+            ".status = 'paid' AND .n >= 2 AND 3*.x < '.limit' AND .tags HAS_ANY ('a', 'b')"
+        );
+        assert_eq!(
+            format(r#".msg = "it's" AND .re = 'a\d' AND .cfg DEEP_EQ {"a": [1, 2]}"#).unwrap(),
+            r#".msg = 'it\'s' AND .re = 'a\\d' AND .cfg DEEP_EQ {"a":[1,2]}"#
+        );
+        assert_eq!(
+            format("ANY .items WHERE .qty>1 AND if .a = 1 then .b = 2 else .c = 3").unwrap(),
+            "ANY .items WHERE (.qty > 1) AND if .a = 1 then .b = 2 else (.c = 3)"
+        );
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        for input in [
+            ".a = 1 AND .user?.name != null AND .items[*].price HAS_ALL (1, 2.5, -3)",
+            "abs(.delta) > 0.01 AND round(.x) = max(.a, .b, 1) AND .a // .b = 'x'",
+            "capture(.url, 'v(\\d+)') = '2' AND .name FUZZY('jonh', 2) AND .id IS NOT UUID",
+            "ALL .orders WHERE (.total > 100 AND .status = 'paid') AND .ts > .start + 2h",
+            "if .kind = 'a' AND .n > 1 then .m < 2 AND .o = 3",
+            "ANY .items WHERE (.price > $root.budget AND .qty < $parent.limit[*].n)",
+            "MATCH >= 0.7 WHERE (.a = 1 WEIGHT 2 AND ANY .b WHERE .c = 1 WEIGHT 0.5) WEIGHT 3",
+            "SHOULD .a = 1 AND SHOULD if .b = 1 then .c = 2 AND SHOULD = 1",
+            ".a? > 1 AND $root.b.c? != 'x' AND ANY .d WHERE .e? = 1",
+        ] {
+            let formatted = format(input).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for input in [
+            ".a = 1 AND .user?.name != null AND .items[*].price HAS_ALL (1, 2.5, -3)",
+            "abs(.delta) > 0.01 AND round(.x) = max(.a, .b, 1) AND .a // .b = 'x'",
+            "capture(.url, 'v(\\d+)') = '2' AND .name FUZZY('jonh', 2) AND .id IS NOT UUID",
+            "ALL .orders WHERE (.total > 100 AND .status = 'paid') AND .ts > .start + 2h",
+            "if .kind = 'a' AND .n > 1 then .m < 2 AND .o = 3",
+            "ANY .items WHERE (.price > $root.budget AND .qty < $parent.limit[*].n)",
+            "MATCH >= 0.7 WHERE (.a = 1 WEIGHT 2 AND ANY .b WHERE .c = 1 WEIGHT 0.5) WEIGHT 3",
+            "SHOULD .a = 1 AND SHOULD if .b = 1 then .c = 2 AND SHOULD = 1",
+            ".a? > 1 AND $root.b.c? != 'x' AND ANY .d WHERE .e? = 1",
+            "status = paid AND 2*.n > 3*.m AND .a > .b AND 2*.a = 4",
+            ".s = 'it\\'s' AND .t = \"dq\" AND .u = 'a\\b' AND .v = ''",
+            ".config DEEP_EQ {\"retries\": 3, \"tls\": [true, null]} AND .l MATCHES_OBJECT {\"app\": \"web\"}",
+            ".n = 1e3 AND .m = -0.0 AND .k = 12345678901234567890 AND .f = 1.50",
+            ".client_ip IN_CIDR '10.0.0.0/8' AND .bio MATCHES_TEXT 'rust' AND .v SEMVER>= '1.2.3'",
+            "keys(.labels) CONTAINS 'team' AND sum(.items[*].price) > 500 AND count(.errors) = 0",
+            "coalesce(.nickname, .name) = 'x' AND type(.a) = 'string' AND .a + 1 > .b * 2",
+            ".x-y = 1 AND ._id != 'a' AND .user.first_name = 'z'",
+            "ANY .a WHERE ALL .b WHERE (.c = 1 AND ANY .d WHERE .e = $parent.f)",
+            "if .a = 1 then .b = 2 else (.c = 3 AND .d = 4) AND .e = 5",
+            "if .a = 1 then (.b = 2 AND .c = 3) else .d = 4 AND .e = 5",
+            "if .a = 1 then (if .b = 2 then .c = 3) else .d = 4",
+            "capture(.u, '(?P<n>x)', 'it\\'s') = 'x' AND capture(.u, '(?P<n>x)', 'n') = 'x'",
+        ] {
+            // Parsing the written form gives the same filters, down to how each part is stored.
+            let filters = parser::parse_filters(input).unwrap();
+            let written = crate::normalize(&filters);
+            let parsed = parser::parse_filters(&written).unwrap();
+            assert_eq!(format!("{parsed:?}"), format!("{filters:?}"), "{written}");
+            assert_eq!(crate::normalize(&parsed), written);
+            assert_eq!(filters[0].to_string(), Clause(&filters[0]).to_string());
+        }
+    }
+
+    #[test]
+    fn test_format_pretty() {
+        let input = ".status = 'paid' AND ANY .items WHERE (.qty > 1 AND .price < 10) \
+                     AND if .kind = 'a' then .n > 1 else (.m < 2 AND .o = 3)";
+        assert_eq!(format_pretty(input, 150).unwrap(), format(input).unwrap());
+        assert_eq!(
+            format_pretty(input, 30).unwrap(),
+            ".status = 'paid'
+AND ANY .items WHERE (
+    .qty > 1 AND .price < 10
+)
+AND if .kind = 'a' then
+    .n > 1
+else (
+    .m < 2 AND .o = 3
+)"
+        );
+        assert_eq!(
+            format_pretty(input, 20).unwrap(),
+            ".status = 'paid'
+AND ANY .items WHERE (
+    .qty > 1
+    AND .price < 10
+)
+AND if .kind = 'a' then
+    .n > 1
+else (
+    .m < 2
+    AND .o = 3
+)"
+        );
+        let pretty = format_pretty(input, 20).unwrap();
+        assert_eq!(format(&pretty).unwrap(), format(input).unwrap());
+        assert_eq!(
+            format_pretty("if .kind = 'a' then (.n > 1 AND .m < 2)", 30).unwrap(),
+            "if .kind = 'a' then (
+    .n > 1 AND .m < 2
+)"
+        );
+    }
+}
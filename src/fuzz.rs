@@ -0,0 +1,583 @@
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+use serde_json::{json, Value};
+
+use crate::{parse, Filter};
+
+/// How deeply generated clauses nest `ANY`, `ALL` and `if` clauses.
+const MAX_DEPTH: u32 = 2;
+
+const KEYS: &[&str] = &["a", "b", "id", "name", "tags", "items"];
+const WORDS: &[&str] = &["x", "rust", "NZ", "hello world", "a-b"];
+/// Strings that need escaping in a filter string.
+const ESCAPED: &[&str] = &["it's", "say \"hi\"", "C:\\temp", "\\'", "a\\b"];
+/// Regexes for `capture`, with their number of groups and the name of one if it has a name.
+const PATTERNS: &[(&str, usize, Option<&str>)] = &[
+    ("v(\\d+)", 1, None),
+    ("(\\w+)-(\\d+)", 2, None),
+    ("(?P<user>[a-z]+)@", 1, Some("user")),
+    ("it's (?P<what>.+)", 1, Some("what")),
+];
+const OPERATORS: &[&str] = &["=", "!=", "<", "<=", ">", ">="];
+const FUNCTIONS: &[&str] = &["abs", "round", "floor", "ceil", "count", "sum", "avg"];
+const CIDRS: &[&str] = &["10.0.0.0/8", "192.168.1.0/24", "2001:db8::/32", "::1"];
+const VERSIONS: &[&str] = &["1.2.3", "0.1.0-beta.1", "2.0.0+build.5"];
+const TIMESTAMPS: &[&str] = &[
+    "2024-01-01T00:00:00Z",
+    "2024-06-01",
+    "2024-06-01T12:30:00+02:00",
+];
+const DURATIONS: &[&str] = &["30s", "15m", "1.5h", "3d", "2w"];
+/// The clauses of the optional features the crate is built with.
+const FEATURE_KINDS: &[fn(&mut Unstructured, &mut String) -> Result<()>] = &[
+    #[cfg(feature = "chrono")]
+    since_now,
+    #[cfg(feature = "geo")]
+    within,
+];
+/// How many kinds of clauses `comparison` writes without nesting.
+const SIMPLE_KINDS: usize = 19 + FEATURE_KINDS.len();
+
+/// Generates any clause the parser accepts, such as `.a.b >= 3`, `count(.tags) = 2`,
+/// `SHOULD capture(.name, '(\w+)-(\d+)', 2) = 'it\'s' WEIGHT 2` or
+/// `ANY .items WHERE (.id != $root.a AND .name? < 4)`, for fuzzing and property tests. Every construct
+/// of the filter syntax is generated, including those of the `chrono` and `geo` features when they are
+/// enabled, except `@name` references, which only parse with the filters of a `FilterSet`, and
+/// functions and operators of plugins.
+impl<'a> Arbitrary<'a> for Filter<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Filter<'static>> {
+        let mut text = String::new();
+        clause(u, &mut text, MAX_DEPTH)?;
+        let mut filters = parse(&text).ok_or(Error::IncorrectFormat)?;
+        match filters.len() {
+            1 => Ok(filters.remove(0).into_owned()),
+            _ => Err(Error::IncorrectFormat),
+        }
+    }
+}
+
+fn clause(u: &mut Unstructured, text: &mut String, depth: u32) -> Result<()> {
+    if u.ratio(1, 8)? {
+        *text += "SHOULD ";
+    }
+    comparison(u, text, depth)?;
+    if u.ratio(1, 8)? {
+        *text += &format!(" WEIGHT {}", u.choose(&["0.5", "2", "3"])?);
+    }
+    Ok(())
+}
+
+fn comparison(u: &mut Unstructured, text: &mut String, depth: u32) -> Result<()> {
+    let kinds = if depth == 0 {
+        SIMPLE_KINDS
+    } else {
+        SIMPLE_KINDS + 4
+    };
+    match u.choose_index(kinds)? {
+        0 => {
+            path(u, text)?;
+            *text += &format!(" {} ", u.choose(OPERATORS)?);
+            literal(u, text)?;
+        }
+        1 => {
+            path(u, text)?;
+            *text += &format!(" {} ", u.choose(OPERATORS)?);
+            if u.ratio(1, 2)? {
+                *text += u.choose(&["$root", "$parent"])?;
+            }
+            path(u, text)?;
+        }
+        2 => {
+            *text += &format!("{}(", u.choose(FUNCTIONS)?);
+            path(u, text)?;
+            *text += &format!(") {} {}", u.choose(OPERATORS)?, number(u)?);
+        }
+        3 => {
+            path(u, text)?;
+            *text += "? ";
+            *text += &format!("{} {}", u.choose(OPERATORS)?, number(u)?);
+        }
+        4 => {
+            path(u, text)?;
+            *text += " CONTAINS ";
+            literal(u, text)?;
+        }
+        5 => {
+            path(u, text)?;
+            *text += &format!(" {} (", u.choose(&["HAS_ANY", "HAS_ALL", "SUBSET_OF"])?);
+            literal(u, text)?;
+            *text += ", ";
+            literal(u, text)?;
+            *text += ")";
+        }
+        6 => {
+            path(u, text)?;
+            *text += u.choose(&[" IS UUID", " IS NOT UUID"])?;
+        }
+        7 => {
+            path(u, text)?;
+            *text += &format!(" FUZZY('{}', {})", u.choose(WORDS)?, u.int_in_range(0..=2)?);
+        }
+        8 => {
+            let (pattern, groups, name) = *u.choose(PATTERNS)?;
+            *text += "capture(";
+            path(u, text)?;
+            *text += ", ";
+            string(u, text, pattern)?;
+            match u.choose_index(3)? {
+                0 => {}
+                1 => *text += &format!(", {}", u.int_in_range(0..=groups)?),
+                _ => {
+                    if let Some(name) = name {
+                        *text += ", ";
+                        string(u, text, name)?;
+                    }
+                }
+            }
+            *text += ") = ";
+            literal(u, text)?;
+        }
+        9 => {
+            operand(u, text)?;
+            *text += u.choose(&[" + ", " - ", "*"])?;
+            operand(u, text)?;
+            *text += &format!(" {} {}", u.choose(OPERATORS)?, number(u)?);
+        }
+        10 => {
+            match u.ratio(1, 2)? {
+                true => {
+                    *text += "coalesce(";
+                    path(u, text)?;
+                    *text += ", ";
+                    path(u, text)?;
+                    *text += ")";
+                }
+                false => {
+                    path(u, text)?;
+                    *text += " // ";
+                    literal(u, text)?;
+                }
+            }
+            *text += &format!(" {} ", u.choose(OPERATORS)?);
+            literal(u, text)?;
+        }
+        11 => {
+            path(u, text)?;
+            *text += &format!(" {} ", u.choose(&["DEEP_EQ", "MATCHES_OBJECT"])?);
+            json(u, text, 2)?;
+        }
+        12 => {
+            path(u, text)?;
+            *text += " IN (";
+            literal(u, text)?;
+            *text += ", ";
+            literal(u, text)?;
+            *text += ")";
+        }
+        13 => {
+            path(u, text)?;
+            *text += " IN_CIDR ";
+            let cidr = *u.choose(CIDRS)?;
+            string(u, text, cidr)?;
+        }
+        14 => {
+            path(u, text)?;
+            *text += &format!(" SEMVER{} ", u.choose(OPERATORS)?);
+            let version = *u.choose(VERSIONS)?;
+            string(u, text, version)?;
+        }
+        15 => {
+            path(u, text)?;
+            *text += " MATCHES_TEXT ";
+            let word = *u.choose(WORDS)?;
+            string(u, text, word)?;
+        }
+        16 => match u.choose_index(3)? {
+            0 => {
+                *text += "keys(";
+                path(u, text)?;
+                *text += ") CONTAINS ";
+                let key = *u.choose(KEYS)?;
+                string(u, text, key)?;
+            }
+            1 => {
+                *text += "values(";
+                path(u, text)?;
+                *text += ") CONTAINS ";
+                literal(u, text)?;
+            }
+            _ => {
+                *text += "type(";
+                path(u, text)?;
+                *text += ") = ";
+                let types = ["null", "boolean", "number", "string", "array", "object"];
+                let name = *u.choose(&types)?;
+                string(u, text, name)?;
+            }
+        },
+        17 => {
+            *text += &format!("{}(", u.choose(&["min", "max"])?);
+            for i in 0..u.int_in_range(1..=3)? {
+                if i > 0 {
+                    *text += ", ";
+                }
+                operand(u, text)?;
+            }
+            *text += &format!(") {} {}", u.choose(OPERATORS)?, number(u)?);
+        }
+        18 => {
+            path(u, text)?;
+            *text += &format!(" {} ", u.choose(OPERATORS)?);
+            match u.ratio(1, 2)? {
+                true => {
+                    let timestamp = *u.choose(TIMESTAMPS)?;
+                    string(u, text, timestamp)?;
+                }
+                false => {
+                    path(u, text)?;
+                    *text += &format!(" {} {}", u.choose(&["+", "-"])?, u.choose(DURATIONS)?);
+                }
+            }
+        }
+        kind if kind < SIMPLE_KINDS => FEATURE_KINDS[kind - 19](u, text)?,
+        kind => {
+            let depth = depth - 1;
+            let kind = kind - SIMPLE_KINDS;
+            if kind == 2 {
+                *text += &format!(
+                    "MATCH {} {} WHERE (",
+                    u.choose(OPERATORS)?,
+                    u.choose(&["0", "0.5", "1"])?
+                );
+                for i in 0..u.int_in_range(1..=3)? {
+                    if i > 0 {
+                        *text += " AND ";
+                    }
+                    clause(u, text, depth)?;
+                }
+                *text += ")";
+                return Ok(());
+            }
+            if kind == 3 {
+                *text += "if ";
+                clause(u, text, depth)?;
+                *text += " then ";
+                clause(u, text, depth)?;
+                *text += " else ";
+                return clause(u, text, depth);
+            }
+            *text += if kind == 0 { "ANY " } else { "ALL " };
+            path(u, text)?;
+            *text += " WHERE (";
+            for i in 0..u.int_in_range(1..=3)? {
+                if i > 0 {
+                    *text += " AND ";
+                }
+                clause(u, text, depth)?;
+            }
+            *text += ")";
+        }
+    }
+    Ok(())
+}
+
+/// A timestamp compared with the current time, such as `.a > now() - 7d`.
+#[cfg(feature = "chrono")]
+fn since_now(u: &mut Unstructured, text: &mut String) -> Result<()> {
+    path(u, text)?;
+    *text += &format!(" {} now()", u.choose(OPERATORS)?);
+    if u.ratio(2, 3)? {
+        *text += &format!(" {} {}", u.choose(&["+", "-"])?, u.choose(DURATIONS)?);
+    }
+    Ok(())
+}
+
+/// A location within a distance of a point, such as `.a WITHIN 5km OF (52.52, 13.4)`.
+#[cfg(feature = "geo")]
+fn within(u: &mut Unstructured, text: &mut String) -> Result<()> {
+    path(u, text)?;
+    let distance = u.choose(&["100m", "5km", "1.5mi", "0km"])?;
+    let lat = u.int_in_range(-89..=89)?;
+    let lon = u.int_in_range(-179..=179)?;
+    *text += &format!(" WITHIN {distance} OF ({lat}.5, {lon})");
+    Ok(())
+}
+
+fn path(u: &mut Unstructured, text: &mut String) -> Result<()> {
+    for i in 0..u.int_in_range(1..=3)? {
+        if i > 0 && u.ratio(1, 4)? {
+            *text += "?";
+        }
+        *text += &format!(".{}", u.choose(KEYS)?);
+        if u.ratio(1, 6)? {
+            *text += "[*]";
+        }
+    }
+    Ok(())
+}
+
+/// A path or a number, for arithmetic.
+fn operand(u: &mut Unstructured, text: &mut String) -> Result<()> {
+    match u.ratio(1, 2)? {
+        true => path(u, text),
+        false => {
+            *text += &u.int_in_range(0..=100)?.to_string();
+            Ok(())
+        }
+    }
+}
+
+fn literal(u: &mut Unstructured, text: &mut String) -> Result<()> {
+    *text += &match u.choose_index(6)? {
+        0 => number(u)?,
+        1 => format!("'{}'", u.choose(WORDS)?),
+        2 => {
+            let s = *u.choose(ESCAPED)?;
+            return string(u, text, s);
+        }
+        3 => "true".to_string(),
+        4 => "false".to_string(),
+        _ => "null".to_string(),
+    };
+    Ok(())
+}
+
+/// Writes `s` as a quoted string, in single or double quotes, escaping the quote and backslashes.
+fn string(u: &mut Unstructured, text: &mut String, s: &str) -> Result<()> {
+    let quote = *u.choose(&['\'', '"'])?;
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace(quote, &format!("\\{quote}"));
+    *text += &format!("{quote}{escaped}{quote}");
+    Ok(())
+}
+
+/// Writes a JSON object or array literal, such as `{"a": [1, "x"]}`.
+fn json(u: &mut Unstructured, text: &mut String, depth: u32) -> Result<()> {
+    let value = |u: &mut Unstructured| -> Result<Value> {
+        Ok(match u.choose_index(4)? {
+            0 => json!(u.int_in_range(-10..=10)?),
+            1 => json!(u.choose(ESCAPED)?),
+            2 => json!(bool::arbitrary(u)?),
+            _ => Value::Null,
+        })
+    };
+    let count = u.int_in_range(0..=3)?;
+    let object = u.ratio(2, 3)?;
+    *text += if object { "{" } else { "[" };
+    for i in 0..count {
+        if i > 0 {
+            *text += ", ";
+        }
+        if object {
+            *text += &format!("{}: ", json!(u.choose(KEYS)?));
+        }
+        match depth > 0 && u.ratio(1, 4)? {
+            true => json(u, text, depth - 1)?,
+            false => *text += &value(u)?.to_string(),
+        }
+    }
+    *text += if object { "}" } else { "]" };
+    Ok(())
+}
+
+fn number(u: &mut Unstructured) -> Result<String> {
+    let n = u.int_in_range(-1000..=1000)?;
+    Ok(match u.ratio(1, 4)? {
+        true => format!("{}.5", n),
+        false => n.to_string(),
+    })
+}
+
+/// Filters with two documents, one guaranteed to match them and one guaranteed not to, for property
+/// tests of code applying filters, such as checking that an index or a translation to SQL selects
+/// the same documents as [`crate::apply`].
+///
+/// Each clause constrains its own field, so the matching document is made of a value passing each
+/// clause, and the rejected document differs from it in the field of one clause, which it fails.
+#[derive(Debug)]
+pub struct FilterCase {
+    /// The filters, written in filter string syntax.
+    pub filter_string: String,
+    /// The filters, parsed.
+    pub filters: Vec<Filter<'static>>,
+    /// A document passing every clause.
+    pub matching: Value,
+    /// A document failing one clause.
+    pub rejected: Value,
+}
+
+impl<'a> Arbitrary<'a> for FilterCase {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<FilterCase> {
+        let count = u.int_in_range(1..=4)?;
+        let failing = u.choose_index(count)?;
+        let mut clauses = Vec::new();
+        let mut matching = serde_json::Map::new();
+        let mut rejected = serde_json::Map::new();
+        for i in 0..count {
+            let field = format!("f{i}");
+            let (clause, pass, fail) = constraint(u, &field)?;
+            clauses.push(clause);
+            matching.insert(field.clone(), pass.clone());
+            rejected.insert(field, if i == failing { fail } else { pass });
+        }
+        let filter_string = clauses.join(" AND ");
+        let filters = parse(&filter_string)
+            .ok_or(Error::IncorrectFormat)?
+            .into_iter()
+            .map(Filter::into_owned)
+            .collect();
+        Ok(FilterCase {
+            filter_string,
+            filters,
+            matching: Value::Object(matching),
+            rejected: Value::Object(rejected),
+        })
+    }
+}
+
+/// A clause on `field`, with a value of the field passing it and one failing it.
+fn constraint(u: &mut Unstructured, field: &str) -> Result<(String, Value, Value)> {
+    let n: i64 = u.int_in_range(-1000..=1000)?;
+    Ok(match u.choose_index(6)? {
+        0 | 1 => {
+            let operator = *u.choose(OPERATORS)?;
+            let (pass, fail) = around(operator, n);
+            let clause = format!(".{field} {operator} {n}");
+            match u.ratio(1, 2)? {
+                true => (clause, json!(pass), json!(fail)),
+                // The same comparison one object deeper.
+                false => (
+                    format!(".{field}.v {operator} {n}"),
+                    json!({ "v": pass }),
+                    json!({ "v": fail }),
+                ),
+            }
+        }
+        2 => {
+            let word = *u.choose(WORDS)?;
+            let other = format!("{word}!");
+            match u.ratio(1, 2)? {
+                true => (format!(".{field} = '{word}'"), json!(word), json!(other)),
+                false => (format!(".{field} != '{word}'"), json!(other), json!(word)),
+            }
+        }
+        3 => {
+            let b = bool::arbitrary(u)?;
+            (format!(".{field} = {b}"), json!(b), json!(!b))
+        }
+        4 => (
+            format!(".{field} CONTAINS {n}"),
+            json!([n - 1, n]),
+            json!([n - 1, n + 1]),
+        ),
+        _ => (
+            format!("ANY .{field} WHERE (.v > {n})"),
+            json!([{ "v": n }, { "v": n + 1 }]),
+            json!([{ "v": n }]),
+        ),
+    })
+}
+
+/// A number passing `operator` against `n`, and one failing it.
+fn around(operator: &str, n: i64) -> (i64, i64) {
+    match operator {
+        "=" => (n, n + 1),
+        "!=" => (n + 1, n),
+        "<" => (n - 1, n),
+        "<=" => (n, n + 1),
+        ">" => (n + 1, n),
+        _ => (n, n - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply;
+
+    /// Runs `check` on values generated from many pseudo-random inputs.
+    fn generated<T: for<'a> Arbitrary<'a>>(mut check: impl FnMut(T)) {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..2000 {
+            let bytes: Vec<u8> = (0..256)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            check(T::arbitrary(&mut Unstructured::new(&bytes)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_filter() {
+        let mut texts = Vec::new();
+        generated(|filter: Filter| {
+            // Writing a filter and parsing it again gives the same filter.
+            let text = filter.to_string();
+            let parsed = parse(&text).unwrap_or_else(|| panic!("{text} doesn't parse"));
+            assert_eq!(parsed.len(), 1, "{text}");
+            assert_eq!(format!("{:?}", parsed[0]), format!("{filter:?}"), "{text}");
+            let v = json!({ "a": 1, "tags": ["x"], "items": [{ "id": "x", "b": 2 }] });
+            assert_eq!(
+                apply(&v, std::slice::from_ref(&filter)),
+                apply(&v, &parsed),
+                "{text}"
+            );
+            texts.push(text);
+        });
+        // Each construct the generator writes is among the filters checked above.
+        for construct in [
+            "\\'",
+            "\"",
+            "\\\\",
+            "capture(",
+            "(?P<",
+            " + ",
+            " - ",
+            "*",
+            "coalesce(",
+            "?.",
+            "[*].",
+            "MATCH ",
+            "SHOULD ",
+            " WEIGHT ",
+            "$root.",
+            "$parent.",
+            " DEEP_EQ {",
+            " DEEP_EQ (",
+            " MATCHES_OBJECT {",
+            " IN (",
+            " IN_CIDR ",
+            " SEMVER",
+            " MATCHES_TEXT ",
+            "keys(",
+            "values(",
+            "type(",
+            "min(",
+            "max(",
+            "T00:00:00Z",
+            "3d",
+            "90m",
+            #[cfg(feature = "chrono")]
+            "now()",
+            #[cfg(feature = "geo")]
+            " WITHIN ",
+        ] {
+            assert!(
+                texts.iter().any(|text| text.contains(construct)),
+                "no generated filter has `{construct}`"
+            );
+        }
+    }
+
+    #[test]
+    fn test_filter_case() {
+        generated(|case: FilterCase| {
+            assert!(apply(&case.matching, &case.filters), "{case:?}");
+            assert!(!apply(&case.rejected, &case.filters), "{case:?}");
+        });
+    }
+}
@@ -0,0 +1,72 @@
+use serde_json::Value;
+
+/// The mean radius of the Earth in meters.
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Reads a location as `(lat, lon)` in degrees.
+///
+/// A location is an object with numeric `lat` and `lon` fields, or a `[lon, lat]` pair in GeoJSON
+/// order. Returns `None` for anything else, including coordinates out of range.
+pub(crate) fn location(v: &Value) -> Option<(f64, f64)> {
+    let (lat, lon) = match v {
+        Value::Object(map) => (map.get("lat")?.as_f64()?, map.get("lon")?.as_f64()?),
+        Value::Array(pair) if pair.len() == 2 => (pair[1].as_f64()?, pair[0].as_f64()?),
+        _ => return None,
+    };
+    valid(lat, lon).then_some((lat, lon))
+}
+
+/// Returns whether a latitude and longitude in degrees are in range.
+pub(crate) fn valid(lat: f64, lon: f64) -> bool {
+    (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)
+}
+
+/// The great-circle distance in meters between two `(lat, lon)` points, by the haversine formula.
+pub(crate) fn distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Converts a distance such as `5km` to meters.
+pub(crate) fn distance_meters(value: &str, unit: &str) -> Option<f64> {
+    let unit_meters = match unit {
+        "m" => 1.0,
+        "km" => 1_000.0,
+        "mi" => 1_609.344,
+        _ => return None,
+    };
+    let meters = value.parse::<f64>().ok()? * unit_meters;
+    (meters.is_finite() && meters >= 0.0).then_some(meters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_location() {
+        assert_eq!(
+            location(&json!({ "lat": 52.52, "lon": 13.4 })),
+            Some((52.52, 13.4))
+        );
+        assert_eq!(location(&json!([13.4, 52.52])), Some((52.52, 13.4)));
+        assert_eq!(location(&json!([13.4, 52.52, 30])), None);
+        assert_eq!(location(&json!({ "lat": 91, "lon": 0 })), None);
+        assert_eq!(location(&json!("52.52,13.4")), None);
+    }
+
+    #[test]
+    fn test_distance_m() {
+        // Berlin to Paris is about 878km.
+        let berlin = (52.52, 13.405);
+        let paris = (48.8566, 2.3522);
+        assert!((distance_m(berlin, paris) - 877_500.0).abs() < 1_000.0);
+        assert_eq!(distance_m(berlin, berlin), 0.0);
+        assert_eq!(distance_meters("1.5", "km"), Some(1_500.0));
+        assert_eq!(distance_meters("5", "parsecs"), None);
+    }
+}
@@ -0,0 +1,274 @@
+use alloc::vec::Vec;
+
+use crate::complete::{Context, Expecting};
+use crate::lexer::{tokenize, Span, Token, TokenKind};
+use crate::parser::OPERATORS;
+
+/// A token of a filter string, returned by [`crate::tokens`] for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyntaxToken {
+    /// What the token is.
+    pub kind: SyntaxKind,
+    /// The byte range of the token in the filter string.
+    pub span: Span,
+}
+
+/// The kinds of [`SyntaxToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    /// A field path such as `.user.name`, or a bare field name on the left of an operator.
+    Field,
+    /// A comparison operator such as `>=`, `CONTAINS` or `FUZZY`, or an arithmetic operator.
+    Operator,
+    /// A quoted string, or a bare word on the right of an operator.
+    String,
+    /// A number, or a quantity such as `7d` or `5km`.
+    Number,
+    /// A keyword such as `AND`, `if` or `WHERE`, or `true`, `false` and `null`.
+    Keyword,
+    /// The name of a function, such as `abs` in `abs(.delta)`.
+    Function,
+    /// A JSON object or array such as `{"retries": 3}`.
+    Json,
+    /// Parentheses and commas.
+    Punctuation,
+    /// A `{{name}}` placeholder for [`crate::parse_with_vars`].
+    Placeholder,
+    /// An `@name` reference to another filter of a [`crate::FilterSet`].
+    Reference,
+    /// A comment, such as `# note` or `/* note */`. Comments next to each other are one token.
+    Comment,
+    /// Text that isn't a valid token, such as an unterminated string.
+    Error,
+}
+
+const KEYWORDS: &[&str] = &[
+    "AND", "if", "then", "else", "WHERE", "OF", "IS", "NOT", "UUID",
+];
+
+pub(crate) fn tokens(input: &str) -> Vec<SyntaxToken> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    // Text that can't be read is marked as an error, and reading resumes after it.
+    while offset < input.len() {
+        let rest = &input[offset..];
+        let (read, error) = match tokenize(rest) {
+            Ok(read) => (read, None),
+            Err(error) => (
+                tokenize(&rest[..error.span.start]).unwrap_or_default(),
+                Some(error.span),
+            ),
+        };
+        tokens.extend(classify(&read).into_iter().map(|mut token| {
+            token.span.start += offset;
+            token.span.end += offset;
+            token
+        }));
+        let Some(span) = error else {
+            break;
+        };
+        let end = span
+            .end
+            .max(span.start + rest[span.start..].chars().next().map_or(1, char::len_utf8));
+        tokens.push(SyntaxToken {
+            kind: SyntaxKind::Error,
+            span: Span {
+                start: offset + span.start,
+                end: offset + end,
+            },
+        });
+        offset += end;
+    }
+    with_comments(input, tokens)
+}
+
+/// Adds a comment token for every gap between tokens that the lexer skipped but isn't whitespace.
+fn with_comments(input: &str, tokens: Vec<SyntaxToken>) -> Vec<SyntaxToken> {
+    let mut all = Vec::with_capacity(tokens.len());
+    let mut end = 0;
+    let comment = |all: &mut Vec<SyntaxToken>, start: usize, next: usize| {
+        let gap = &input[start..next];
+        let trimmed = gap.trim();
+        if !trimmed.is_empty() {
+            let start = start + gap.len() - gap.trim_start().len();
+            all.push(SyntaxToken {
+                kind: SyntaxKind::Comment,
+                span: Span {
+                    start,
+                    end: start + trimmed.len(),
+                },
+            });
+        }
+    };
+    for token in tokens {
+        comment(&mut all, end, token.span.start);
+        end = token.span.end;
+        all.push(token);
+    }
+    comment(&mut all, end, input.len());
+    all
+}
+
+fn classify(tokens: &[Token]) -> Vec<SyntaxToken> {
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let next = tokens.get(i + 1).map(|t| &t.kind);
+            let kind = match &token.kind {
+                TokenKind::Path(_) => SyntaxKind::Field,
+                TokenKind::Str(_) => SyntaxKind::String,
+                TokenKind::Json(_) => SyntaxKind::Json,
+                TokenKind::Var(_) => SyntaxKind::Placeholder,
+                TokenKind::Ref(_) => SyntaxKind::Reference,
+                TokenKind::Number(_) | TokenKind::Quantity(..) => SyntaxKind::Number,
+                TokenKind::Op(_)
+                | TokenKind::Star
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Alternative => SyntaxKind::Operator,
+                TokenKind::Comma | TokenKind::LParen | TokenKind::RParen => SyntaxKind::Punctuation,
+                TokenKind::Ident(word) => {
+                    let expecting = Context::read(&tokens[..i]).expecting;
+                    match *word {
+                        _ if KEYWORDS.contains(word) => SyntaxKind::Keyword,
+                        "ANY" | "ALL" if matches!(next, Some(TokenKind::Path(_))) => {
+                            SyntaxKind::Keyword
+                        }
+                        "MATCH" if matches!(next, Some(TokenKind::Op(_))) => SyntaxKind::Keyword,
+                        "SHOULD" if !matches!(next, None | Some(TokenKind::Op(_))) => {
+                            SyntaxKind::Keyword
+                        }
+                        "WEIGHT" if matches!(next, Some(TokenKind::Number(_))) => {
+                            SyntaxKind::Keyword
+                        }
+                        "FUZZY" | "WITHIN" => SyntaxKind::Operator,
+                        _ if OPERATORS.contains(word) => SyntaxKind::Operator,
+                        _ if next == Some(&TokenKind::LParen) => SyntaxKind::Function,
+                        "true" | "false" | "null" if expecting == Expecting::Value => {
+                            SyntaxKind::Keyword
+                        }
+                        _ if expecting == Expecting::Value => SyntaxKind::String,
+                        _ => SyntaxKind::Field,
+                    }
+                }
+            };
+            SyntaxToken {
+                kind,
+                span: token.span,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<(SyntaxKind, &str)> {
+        tokens(input)
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span.start..t.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds(
+                r#"abs(.delta) >= 1.5 AND status = paid AND .tags HAS_ANY ('a', "b") AND .on = true"#
+            ),
+            [
+                (Function, "abs"),
+                (Punctuation, "("),
+                (Field, ".delta"),
+                (Punctuation, ")"),
+                (Operator, ">="),
+                (Number, "1.5"),
+                (Keyword, "AND"),
+                (Field, "status"),
+                (Operator, "="),
+                (String, "paid"),
+                (Keyword, "AND"),
+                (Field, ".tags"),
+                (Operator, "HAS_ANY"),
+                (Punctuation, "("),
+                (String, "'a'"),
+                (Punctuation, ","),
+                (String, "\"b\""),
+                (Punctuation, ")"),
+                (Keyword, "AND"),
+                (Field, ".on"),
+                (Operator, "="),
+                (Keyword, "true"),
+            ]
+        );
+        assert_eq!(
+            kinds("ANY .o WHERE .n FUZZY('x', 1)"),
+            [
+                (Keyword, "ANY"),
+                (Field, ".o"),
+                (Keyword, "WHERE"),
+                (Field, ".n"),
+                (Operator, "FUZZY"),
+                (Punctuation, "("),
+                (String, "'x'"),
+                (Punctuation, ","),
+                (Number, "1"),
+                (Punctuation, ")"),
+            ]
+        );
+        assert_eq!(kinds(".n > {{min}}")[2], (Placeholder, "{{min}}"));
+        assert_eq!(kinds("@adults AND .n = 1")[0], (Reference, "@adults"));
+        assert_eq!(
+            kinds("SHOULD MATCH > 0.5 WHERE .n = 1 WEIGHT 2")
+                .iter()
+                .filter(|(kind, _)| *kind == Keyword)
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_tokens_with_comments() {
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds("# paid orders\n.a = 1 /* a */ /* b */ AND .b = 2 # done"),
+            [
+                (Comment, "# paid orders"),
+                (Field, ".a"),
+                (Operator, "="),
+                (Number, "1"),
+                (Comment, "/* a */ /* b */"),
+                (Keyword, "AND"),
+                (Field, ".b"),
+                (Operator, "="),
+                (Number, "2"),
+                (Comment, "# done"),
+            ]
+        );
+        assert_eq!(kinds(".a /* open"), [(Field, ".a"), (Error, "/* open")]);
+    }
+
+    #[test]
+    fn test_tokens_with_errors() {
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds(".a = 1 ; .b ! 2"),
+            [
+                (Field, ".a"),
+                (Operator, "="),
+                (Number, "1"),
+                (Error, ";"),
+                (Field, ".b"),
+                (Error, "!"),
+                (Number, "2"),
+            ]
+        );
+        assert_eq!(
+            kinds(".a = 'open"),
+            [(Field, ".a"), (Operator, "="), (Error, "'open")]
+        );
+    }
+}
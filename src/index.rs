@@ -0,0 +1,398 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+#[cfg(feature = "chrono")]
+use chrono::{Offset, Utc};
+use serde_json::Value;
+
+use crate::expr::{Expr, Path, Segment};
+#[cfg(feature = "chrono")]
+use crate::time;
+use crate::uuid;
+use crate::{apply_with, ApplyOptions, Filter};
+
+/// A list of JSON Values with indexes on some of their paths, for running many queries over the same
+/// Values without evaluating every query against every Value.
+///
+/// A query uses the indexes for its top-level clauses that compare an indexed path with a literal
+/// using `=` or, with a B-tree index, `<`, `<=`, `>` or `>=`, and for `IN` clauses written as
+/// `["a", "b"] CONTAINS .path`. Only the Values the indexes find are evaluated, and queries without
+/// such clauses scan every Value. Either way, the same Values are returned as filtering the list.
+#[derive(Debug, Default)]
+pub struct IndexedCollection {
+    values: Vec<Value>,
+    indexes: Vec<Index>,
+}
+
+/// The kinds of index an [`IndexedCollection`] can build on a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    /// A hash index, used by `=` and `IN` clauses.
+    Hash,
+    /// A B-tree index, also used by clauses comparing the path with a number using `<`, `<=`, `>`
+    /// or `>=`.
+    BTree,
+}
+
+#[derive(Debug)]
+struct Index {
+    path: Path<'static>,
+    entries: Entries,
+}
+
+/// The positions of the Values with each value at the indexed path, in increasing order.
+#[derive(Debug)]
+enum Entries {
+    Hash(HashMap<Key, Vec<usize>>),
+    BTree(BTreeMap<Key, Vec<usize>>),
+}
+
+/// A scalar as it is indexed. Numbers are indexed as floats, so integers too large to be exact
+/// floats may share a key: an index lookup can find too many Values, never too few, and the clause
+/// is evaluated against the Values it finds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Key {
+    Null,
+    Bool(bool),
+    Number(Float),
+    String(String),
+}
+
+/// A float that is never NaN, as JSON numbers aren't, so it can be ordered and hashed.
+#[derive(Debug, Clone, Copy)]
+struct Float(f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Float) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Float) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Float) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl Key {
+    /// The key of a value stored at an indexed path. Arrays and objects aren't indexed, as they are
+    /// never equal to a literal.
+    fn of(v: &Value) -> Option<Key> {
+        match v {
+            Value::Null => Some(Key::Null),
+            Value::Bool(b) => Some(Key::Bool(*b)),
+            // Adding 0.0 turns -0.0 into 0.0, which compares equal to it.
+            Value::Number(n) => Some(Key::Number(Float(n.as_f64()? + 0.0))),
+            Value::String(s) => Some(Key::String(s.clone())),
+            Value::Array(_) | Value::Object(_) => None,
+        }
+    }
+
+    /// The key to look a literal up by, if the Values equal to it are exactly the Values with its key.
+    /// UUIDs and timestamps are equal to strings written differently, and with `coerce_epoch_millis`
+    /// numbers are equal to timestamp strings, so those are looked up without an index.
+    #[cfg_attr(not(feature = "chrono"), allow(unused_variables))]
+    fn literal(v: &Value, options: &ApplyOptions) -> Option<Key> {
+        match v {
+            Value::String(s) if uuid::parse_uuid(s).is_some() => None,
+            #[cfg(feature = "chrono")]
+            Value::String(s) if time::parse_timestamp(s, Utc.fix()).is_some() => None,
+            #[cfg(feature = "chrono")]
+            Value::Number(_) if options.coerce_epoch_millis => None,
+            _ => Key::of(v),
+        }
+    }
+}
+
+impl IndexedCollection {
+    /// Creates a collection of Values without indexes.
+    pub fn new(values: Vec<Value>) -> IndexedCollection {
+        IndexedCollection {
+            values,
+            indexes: Vec::new(),
+        }
+    }
+
+    /// Builds an index on a path such as `.user.id`, to be used by later queries. Values without the
+    /// path, or with an array or object there, are left out of the index.
+    pub fn with_index(mut self, path: &str, kind: IndexKind) -> IndexedCollection {
+        let path = Path::parse(path).into_owned();
+        let mut entries = match kind {
+            IndexKind::Hash => Entries::Hash(HashMap::new()),
+            IndexKind::BTree => Entries::BTree(BTreeMap::new()),
+        };
+        for (i, v) in self.values.iter().enumerate() {
+            let Some(key) = path.resolve(v).and_then(|v| Key::of(&v)) else {
+                continue;
+            };
+            match &mut entries {
+                Entries::Hash(map) => map.entry(key).or_default().push(i),
+                Entries::BTree(map) => map.entry(key).or_default().push(i),
+            }
+        }
+        self.indexes.retain(|index| index.path != path);
+        self.indexes.push(Index { path, entries });
+        self
+    }
+
+    /// The Values of the collection, in the order they were given.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Returns the Values that pass every filter, in the order of the collection.
+    pub fn query(&self, filters: &[Filter]) -> Vec<&Value> {
+        self.query_with(filters, &ApplyOptions::default())
+    }
+
+    /// Returns the Values that pass every filter evaluated with `options`, in the order of the
+    /// collection.
+    pub fn query_with(&self, filters: &[Filter], options: &ApplyOptions) -> Vec<&Value> {
+        match self.candidates(filters, options) {
+            Some(candidates) => candidates
+                .into_iter()
+                .map(|i| &self.values[i])
+                .filter(|v| apply_with(v, filters, options))
+                .collect(),
+            None => self
+                .values
+                .iter()
+                .filter(|v| apply_with(v, filters, options))
+                .collect(),
+        }
+    }
+
+    /// The positions of the Values the indexes find for the clauses, in increasing order, or `None`
+    /// if no index can be used and every Value has to be scanned.
+    fn candidates(&self, filters: &[Filter], options: &ApplyOptions) -> Option<Vec<usize>> {
+        // Values without an indexed path may still have the values the clauses read elsewhere, and
+        // comparators may find values equal to others with different keys.
+        if options.fills_missing() || !options.comparators.is_empty() {
+            return None;
+        }
+        filters
+            .iter()
+            .filter_map(|filter| self.lookup(filter, options))
+            .reduce(|found, more| intersection(&found, &more))
+    }
+
+    /// Looks the Values passing a clause up in an index.
+    fn lookup(&self, filter: &Filter, options: &ApplyOptions) -> Option<Vec<usize>> {
+        if filter.should
+            || filter.optional
+            || filter.compound.is_some()
+            || filter.multiplier_field.is_some()
+            || filter.multiplier_value.is_some()
+        {
+            return None;
+        }
+        if filter.operator == "CONTAINS" {
+            // `["a", "b"] CONTAINS .path` keeps the Values with any of the literals at the path.
+            let Some(Expr::Literal(Value::Array(list))) = &filter.lhs else {
+                return None;
+            };
+            let path = match (&filter.rhs, filter.value_field.as_deref()) {
+                (Some(Expr::Path(path)), _) => path.clone(),
+                (None, Some(field)) if filter.value.is_none() => field_path(field),
+                _ => return None,
+            };
+            return self.index(&path)?.get_any(list, options);
+        }
+        let path = match (&filter.lhs, filter.field.as_deref()) {
+            (Some(Expr::Path(path)), _) => path.clone(),
+            (None, Some(field)) => field_path(field),
+            _ => return None,
+        };
+        let literal = match (&filter.rhs, &filter.value, &filter.value_field) {
+            (Some(Expr::Literal(v)), ..) | (None, Some(v), None) => v,
+            _ => return None,
+        };
+        let index = self.index(&path)?;
+        if filter.operator == "IN" {
+            // `.path IN ('a', 'b')` keeps the Values with any of the literals at the path.
+            let Value::Array(list) = literal else {
+                return None;
+            };
+            return index.get_any(list, options);
+        }
+        let key = Key::literal(literal, options)?;
+        match filter.operator.as_ref() {
+            "=" => Some(index.get(&key)),
+            operator @ ("<" | "<=" | ">" | ">=") => index.range(&key, operator),
+            _ => None,
+        }
+    }
+
+    fn index(&self, path: &Path) -> Option<&Index> {
+        self.indexes.iter().find(|index| index.path == *path)
+    }
+}
+
+impl Index {
+    fn get(&self, key: &Key) -> Vec<usize> {
+        let found = match &self.entries {
+            Entries::Hash(map) => map.get(key),
+            Entries::BTree(map) => map.get(key),
+        };
+        found.cloned().unwrap_or_default()
+    }
+
+    /// The Values with any of the literals at the path, in increasing order, or `None` if one of them
+    /// can't be looked up.
+    fn get_any(&self, literals: &[Value], options: &ApplyOptions) -> Option<Vec<usize>> {
+        let mut found = Vec::new();
+        for literal in literals {
+            found.extend(self.get(&Key::literal(literal, options)?));
+        }
+        found.sort_unstable();
+        found.dedup();
+        Some(found)
+    }
+
+    /// The Values with a number at the path on the side of `key` the operator keeps. Only B-trees
+    /// answer ranges, and only of numbers, as other values either have no order or are ordered in
+    /// ways an index can't follow, like UUIDs and timestamps.
+    fn range(&self, key: &Key, operator: &str) -> Option<Vec<usize>> {
+        let (Entries::BTree(map), Key::Number(Float(n))) = (&self.entries, key) else {
+            return None;
+        };
+        // The bounds are inclusive, as a bound on floats may be equal to integers it doesn't equal.
+        let bounds = match operator {
+            "<" | "<=" => (f64::NEG_INFINITY, *n),
+            _ => (*n, f64::INFINITY),
+        };
+        let mut found: Vec<usize> = map
+            .range(Key::Number(Float(bounds.0))..=Key::Number(Float(bounds.1)))
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect();
+        found.sort_unstable();
+        Some(found)
+    }
+}
+
+fn field_path(field: &str) -> Path<'_> {
+    Path {
+        segments: vec![Segment::Key {
+            name: field.into(),
+            null_safe: false,
+        }],
+    }
+}
+
+/// The positions in both sorted lists.
+fn intersection(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let mut both = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                both.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    both
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    fn collection() -> IndexedCollection {
+        let values = (0..20)
+            .map(|i| {
+                let status = ["open", "paid", "void"][i % 3];
+                json!({
+                    "id": i,
+                    "status": status,
+                    "user": { "age": 20.0 + i as f64 / 2.0 },
+                })
+            })
+            .chain([json!({ "id": "x" }), json!({ "status": ["open"] })])
+            .collect();
+        IndexedCollection::new(values)
+            .with_index(".status", IndexKind::Hash)
+            .with_index(".user.age", IndexKind::BTree)
+            .with_index(".id", IndexKind::Hash)
+    }
+
+    fn ids(values: Vec<&Value>) -> Vec<i64> {
+        values.iter().filter_map(|v| v["id"].as_i64()).collect()
+    }
+
+    #[test]
+    fn test_query() {
+        let collection = collection();
+        let options = ApplyOptions::default();
+        for filter_string in [
+            ".status = 'paid' AND .user.age >= 25",
+            ".user.age < 21 AND .id != 0",
+            ".id = 4.0",
+            r#"["open", "void"] CONTAINS .status AND .user.age > 28"#,
+            ".status IN ('open', 'void') AND .user.age > 28",
+            ".id IN (1, 4.0, 'x', 30)",
+            ".status = 'paid' AND .id > 10",
+            ".status SUBSET_OF ('open')",
+        ] {
+            let filters = parse(filter_string).unwrap();
+            let scanned: Vec<&Value> = collection
+                .values()
+                .iter()
+                .filter(|v| apply_with(v, &filters, &options))
+                .collect();
+            assert_eq!(collection.query(&filters), scanned, "{filter_string}");
+        }
+        assert_eq!(
+            ids(collection.query(&parse(".status = 'paid' AND .user.age >= 25").unwrap())),
+            [10, 13, 16, 19]
+        );
+    }
+
+    #[test]
+    fn test_query_uses_indexes() {
+        let collection = collection();
+        let options = ApplyOptions::default();
+        let candidates =
+            |filter_string| collection.candidates(&parse(filter_string).unwrap(), &options);
+        assert_eq!(
+            candidates(".status = 'paid' AND .user.age >= 27"),
+            Some(vec![16, 19])
+        );
+        assert_eq!(
+            candidates(r#"["open", "void"] CONTAINS .status AND .user.age < 21"#),
+            Some(vec![0, 2])
+        );
+        assert_eq!(
+            candidates(".status IN ('open', 'void') AND .user.age < 21"),
+            Some(vec![0, 2])
+        );
+        assert_eq!(candidates(".id IN (1, 'x', 4)"), Some(vec![1, 4, 20]));
+        // Hash indexes don't answer ranges, and clauses on paths without an index scan.
+        assert_eq!(candidates(".id > 3"), None);
+        assert_eq!(candidates(".user.name = 'x' AND .status != 'paid'"), None);
+        assert_eq!(
+            candidates(".status = '0c04ac1b-0000-4000-8000-000000000000'"),
+            None
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::Deref;
+
+/// A field name of a filter. Parsed filters borrow it from the filter string, and owned filters
+/// share one allocation between every clause naming the same field.
+#[derive(Clone)]
+pub(crate) enum Name<'a> {
+    Borrowed(&'a str),
+    Shared(Arc<str>),
+}
+
+impl Name<'_> {
+    /// Copies the name so it no longer borrows the filter string, sharing the copy made for the
+    /// same name before.
+    pub(crate) fn into_shared(self, names: &mut Interner) -> Name<'static> {
+        Name::Shared(names.intern(&self))
+    }
+}
+
+impl<'a> From<&'a str> for Name<'a> {
+    fn from(name: &'a str) -> Name<'a> {
+        Name::Borrowed(name)
+    }
+}
+
+impl Deref for Name<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Name::Borrowed(name) => name,
+            Name::Shared(name) => name,
+        }
+    }
+}
+
+impl AsRef<str> for Name<'_> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl PartialEq for Name<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<str> for Name<'_> {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl fmt::Debug for Name<'_> {
+    /// Writes the name as a string, whether it's borrowed or shared.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+/// The names copied while making filters owned, so each name is allocated once however many
+/// clauses name it.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    names: BTreeSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Returns the shared copy of `name`, making it if it is the first time `name` is seen.
+    pub(crate) fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(shared) = self.names.get(name) {
+            return Arc::clone(shared);
+        }
+        let shared: Arc<str> = Arc::from(name);
+        self.names.insert(Arc::clone(&shared));
+        shared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut names = Interner::default();
+        let a = Name::Borrowed("user").into_shared(&mut names);
+        let b = Name::Shared(Arc::from("user")).into_shared(&mut names);
+        match (&a, &b) {
+            (Name::Shared(a), Name::Shared(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("names aren't shared"),
+        }
+        assert_eq!(a, Name::Borrowed("user"));
+        assert_eq!(format!("{a:?}"), "\"user\"");
+        assert!(!Arc::ptr_eq(&names.intern("user"), &names.intern("name")));
+    }
+}
@@ -0,0 +1,102 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::batch::Operator;
+use crate::translate::{self, is_plain_key, Translatable, TranslateError};
+use crate::Filter;
+
+pub(crate) fn to_jsonpath(filters: &[Filter], array: &str) -> Result<String, TranslateError> {
+    let mut conditions = Vec::new();
+    for filter in filters {
+        let (keys, operator, literal) = match translate::translatable(filter)? {
+            Translatable::Always => continue,
+            Translatable::Compare {
+                keys,
+                operator,
+                literal,
+            } => (keys, operator, literal),
+            _ => {
+                return Err(TranslateError::new(format!(
+                    "`{}` can't be translated: only comparisons are translated to JSONPath",
+                    crate::format::Clause(filter)
+                )))
+            }
+        };
+        let path = path(&keys);
+        conditions.push(match (operator, literal) {
+            // `!=` passes values of other types in JSONPath, and null in filters, so it is written as
+            // the orderings that pass the values of the literal's type it differs from.
+            (Operator::Ne, Value::Number(_) | Value::String(_)) => {
+                format!("({path} < {literal} || {path} > {literal} || {path} == null)")
+            }
+            (Operator::Ne, Value::Bool(b)) => format!("({path} == {} || {path} == null)", !b),
+            (Operator::Ne, _) => {
+                return Err(TranslateError::new(format!(
+                    "`{}` can't be translated: JSONPath doesn't test for values that aren't null",
+                    crate::format::Clause(filter)
+                )))
+            }
+            (operator, literal) => {
+                let operator = match operator {
+                    Operator::Eq => "==",
+                    Operator::Lt => "<",
+                    Operator::Le => "<=",
+                    Operator::Gt => ">",
+                    _ => ">=",
+                };
+                format!("{path} {operator} {literal}")
+            }
+        });
+    }
+    Ok(match conditions.is_empty() {
+        true => format!("{array}[*]"),
+        false => format!("{array}[?({})]", conditions.join(" && ")),
+    })
+}
+
+/// The JSONPath of the keys from the element being filtered, such as `@.user.age`.
+fn path(keys: &[&str]) -> String {
+    let mut path = "@".to_string();
+    for key in keys {
+        match is_plain_key(key) {
+            true => path.push_str(&format!(".{key}")),
+            false => path.push_str(&format!("[{}]", Value::from(*key))),
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn jsonpath(filter_string: &str) -> Result<String, TranslateError> {
+        to_jsonpath(&parse(filter_string).unwrap(), "$.orders")
+    }
+
+    #[test]
+    fn test_to_jsonpath() {
+        assert_eq!(
+            jsonpath(".total > 100 AND .status = 'paid' AND 2 <= .customer.tier").unwrap(),
+            r#"$.orders[?(@.total > 100 && @.status == "paid" && @.customer.tier >= 2)]"#
+        );
+        assert_eq!(
+            jsonpath(".qty != 0 AND .gift != true").unwrap(),
+            "$.orders[?((@.qty < 0 || @.qty > 0 || @.qty == null) && (@.gift == false || @.gift == null))]"
+        );
+        assert_eq!(
+            jsonpath(".ship-to.city = null").unwrap(),
+            r#"$.orders[?(@["ship-to"].city == null)]"#
+        );
+        assert_eq!(jsonpath("SHOULD .a = 1").unwrap(), "$.orders[*]");
+        let error = |filter_string| jsonpath(filter_string).unwrap_err().to_string();
+        assert!(
+            error(".tags CONTAINS 'a'").ends_with("only comparisons are translated to JSONPath")
+        );
+        assert!(error(".a != null").ends_with("JSONPath doesn't test for values that aren't null"));
+    }
+}
@@ -0,0 +1,616 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::parser::ParseError;
+
+/// A byte range into a filter string, such as the one a token was read from or an error was found at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The kinds of tokens a filter string is made of.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    /// A field path such as `.field`, `.user.name`, `.user?.name` or `.items[*].price`, including
+    /// the leading dot, or a path starting from an enclosing Value, such as `$root.budget`. A `?`
+    /// directly after the path, as in `.retries?`, is part of the token.
+    Path(&'a str),
+    /// A bare word: a function name, a keyword such as `AND`, or an unquoted value.
+    Ident(&'a str),
+    /// A quoted string with its quotes removed and escapes resolved.
+    Str(String),
+    /// A JSON object or array such as `{"retries": 3}`.
+    Json(Value),
+    /// A numeric literal as written.
+    Number(&'a str),
+    /// A `{{name}}` placeholder for a value given to [`crate::parse_with_vars`], holding the name.
+    Var(&'a str),
+    /// An `@name` reference to another filter of a [`crate::FilterSet`], holding the name.
+    Ref(&'a str),
+    /// A number directly followed by a unit, such as `7d`.
+    Quantity(&'a str, &'a str),
+    /// A comparison operator such as `=`, `>=` or `SEMVER>=`.
+    Op(&'a str),
+    Star,
+    Plus,
+    Minus,
+    /// The `//` alternative operator.
+    Alternative,
+    Comma,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub(crate) kind: TokenKind<'a>,
+    pub(crate) span: Span,
+}
+
+/// Splits a filter string into tokens like [`tokenize`], skipping the text of each error and reading
+/// on after it, so every error is found. Returns the tokens read and the errors.
+pub(crate) fn tokenize_recovering(input: &str) -> (Vec<Token<'_>>, Vec<ParseError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    while offset < input.len() {
+        let rest = &input[offset..];
+        let shift = |span: Span| Span {
+            start: span.start + offset,
+            end: span.end + offset,
+        };
+        let (read, error) = match tokenize(rest) {
+            Ok(read) => (read, None),
+            Err(error) => (
+                tokenize(&rest[..error.span.start]).unwrap_or_default(),
+                Some(error),
+            ),
+        };
+        tokens.extend(read.into_iter().map(|token| Token {
+            kind: token.kind,
+            span: shift(token.span),
+        }));
+        let Some(error) = error else {
+            break;
+        };
+        // Reading resumes after the error, or after its first character if it is empty.
+        let skipped = match error.span.end > error.span.start {
+            true => error.span.end,
+            false => rest[error.span.start..]
+                .chars()
+                .next()
+                .map_or(rest.len(), |c| error.span.start + c.len_utf8()),
+        };
+        errors.push(ParseError::new(error.message, shift(error.span)));
+        offset += skipped;
+    }
+    (tokens, errors)
+}
+
+/// Splits a filter string into tokens.
+///
+/// Newlines are whitespace, and so is a backslash that ends a line. Comments, from `#` to the end of
+/// the line or between `/*` and `*/`, are skipped.
+/// Single- and double-quoted strings may contain spaces; inside them `\\` and an escaped quote
+/// are the only escapes, every other backslash is kept as written so regex patterns survive.
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        // A backslash at the end of a line continues the filter on the next line, as in shell scripts.
+        if c == b'\\' {
+            let rest = &input[pos + 1..];
+            if let Some(after) = rest.strip_prefix('\n').or(rest.strip_prefix("\r\n")) {
+                pos = input.len() - after.len();
+                continue;
+            }
+        }
+        // Comments are skipped like whitespace: `#` to the end of the line, and `/* ... */`.
+        if c == b'#' {
+            pos = input[pos..].find('\n').map_or(input.len(), |i| pos + i);
+            continue;
+        }
+        if input[pos..].starts_with("/*") {
+            match input[pos + 2..].find("*/") {
+                Some(i) => pos += 2 + i + 2,
+                None => {
+                    return Err(ParseError::new(
+                        "unterminated comment",
+                        Span {
+                            start: pos,
+                            end: input.len(),
+                        },
+                    ))
+                }
+            }
+            continue;
+        }
+
+        let start = pos;
+        let kind = match c {
+            b'(' => {
+                pos += 1;
+                TokenKind::LParen
+            }
+            b')' => {
+                pos += 1;
+                TokenKind::RParen
+            }
+            b',' => {
+                pos += 1;
+                TokenKind::Comma
+            }
+            b'*' => {
+                pos += 1;
+                TokenKind::Star
+            }
+            b'/' if bytes.get(pos + 1) == Some(&b'/') => {
+                pos += 2;
+                TokenKind::Alternative
+            }
+            b'=' | b'!' | b'<' | b'>' => {
+                pos = read_comparison(bytes, pos);
+                let op = &input[start..pos];
+                if op == "!" {
+                    return Err(ParseError::new("expected `!=`", Span { start, end: pos }));
+                }
+                TokenKind::Op(op)
+            }
+            b'\'' | b'"' => {
+                let (value, end) = read_string(input, start)?;
+                pos = end;
+                TokenKind::Str(value)
+            }
+            b'{' if bytes.get(pos + 1) == Some(&b'{') => {
+                let Some(close) = input[pos + 2..].find("}}") else {
+                    return Err(ParseError::new(
+                        "unterminated `{{`",
+                        Span {
+                            start,
+                            end: input.len(),
+                        },
+                    ));
+                };
+                pos += 2 + close + 2;
+                let name = input[start + 2..pos - 2].trim();
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(ParseError::new(
+                        "invalid placeholder name",
+                        Span { start, end: pos },
+                    ));
+                }
+                TokenKind::Var(name)
+            }
+            b'{' | b'[' => {
+                let (value, end) = read_json(input, start)?;
+                pos = end;
+                TokenKind::Json(value)
+            }
+            b'.' => {
+                pos = read_path(bytes, pos + 1);
+                TokenKind::Path(&input[start..pos])
+            }
+            b'@' => {
+                pos = read_word(input, pos + 1);
+                if pos == start + 1 {
+                    return Err(ParseError::new(
+                        "expected a filter name after `@`",
+                        Span { start, end: pos },
+                    ));
+                }
+                TokenKind::Ref(&input[start + 1..pos])
+            }
+            b'-' | b'+' if starts_number(bytes, pos + 1) && !follows_operand(&tokens) => {
+                pos = read_number(bytes, pos + 1);
+                number_or_quantity(input, start, &mut pos)
+            }
+            c if c.is_ascii_digit() => {
+                pos = read_number(bytes, pos);
+                number_or_quantity(input, start, &mut pos)
+            }
+            b'-' => {
+                pos += 1;
+                TokenKind::Minus
+            }
+            b'+' => {
+                pos += 1;
+                TokenKind::Plus
+            }
+            _ => {
+                pos = read_word(input, pos);
+                if pos == start {
+                    let end = start + input[start..].chars().next().map_or(1, char::len_utf8);
+                    return Err(ParseError::new(
+                        format!("unexpected character `{}`", &input[start..end]),
+                        Span { start, end },
+                    ));
+                }
+                let word = &input[start..pos];
+                if matches!(word, "$root" | "$parent") {
+                    pos = read_path(bytes, pos);
+                    tokens.push(Token {
+                        kind: TokenKind::Path(&input[start..pos]),
+                        span: Span { start, end: pos },
+                    });
+                    continue;
+                }
+                // An uppercase word directly followed by a comparison, as in `SEMVER>=`, is a
+                // single operator.
+                if word.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+                    && matches!(bytes.get(pos), Some(b'=' | b'!' | b'<' | b'>'))
+                {
+                    pos = read_comparison(bytes, pos);
+                    TokenKind::Op(&input[start..pos])
+                } else {
+                    TokenKind::Ident(word)
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: Span { start, end: pos },
+        });
+    }
+    Ok(tokens)
+}
+
+fn read_comparison(bytes: &[u8], mut pos: usize) -> usize {
+    pos += 1;
+    if pos < bytes.len() && bytes[pos] == b'=' {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns whether a character may appear in a field name or bare word.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '$'
+}
+
+fn read_word(input: &str, start: usize) -> usize {
+    input[start..]
+        .char_indices()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(input.len(), |(i, _)| start + i)
+}
+
+fn read_path(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c == b'?' && bytes.get(pos + 1) == Some(&b'.') {
+            pos += 2;
+        } else if c == b'?' {
+            // A `?` after the path, as in `.retries? > 3`, ends it.
+            pos += 1;
+            break;
+        } else if bytes[pos..].starts_with(b"[*]") {
+            pos += 3;
+        } else if c == b'.'
+            || c.is_ascii_alphanumeric()
+            || c == b'_'
+            || c == b'-'
+            || c == b'$'
+            || c >= 0x80
+        {
+            pos += 1;
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+/// Reads the unit directly following a number, if there is one.
+fn number_or_quantity<'a>(input: &'a str, start: usize, pos: &mut usize) -> TokenKind<'a> {
+    let number_end = *pos;
+    let unit_end = input[number_end..]
+        .char_indices()
+        .find(|&(_, c)| !c.is_alphabetic())
+        .map_or(input.len(), |(i, _)| number_end + i);
+    *pos = unit_end;
+    if unit_end == number_end {
+        TokenKind::Number(&input[start..number_end])
+    } else {
+        TokenKind::Quantity(&input[start..number_end], &input[number_end..unit_end])
+    }
+}
+
+fn starts_number(bytes: &[u8], pos: usize) -> bool {
+    pos < bytes.len() && bytes[pos].is_ascii_digit()
+}
+
+fn read_number(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+        pos += 1;
+    }
+    if pos + 1 < bytes.len() && bytes[pos] == b'.' && bytes[pos + 1].is_ascii_digit() {
+        pos += 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+            pos += 1;
+        }
+    }
+    if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+        let mut exp = pos + 1;
+        if exp < bytes.len() && (bytes[exp] == b'+' || bytes[exp] == b'-') {
+            exp += 1;
+        }
+        if starts_number(bytes, exp) {
+            pos = exp;
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+        }
+    }
+    pos
+}
+
+/// A `-` or `+` directly before a digit is the sign of a number unless it follows something that
+/// could be the left side of a subtraction or an addition.
+fn follows_operand(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last().map(|t| &t.kind),
+        Some(
+            TokenKind::Path(_)
+                | TokenKind::Number(_)
+                | TokenKind::Quantity(..)
+                | TokenKind::Str(_)
+                | TokenKind::Var(_)
+                | TokenKind::RParen
+        )
+    )
+}
+
+fn read_string(input: &str, start: usize) -> Result<(String, usize), ParseError> {
+    let quote = input.as_bytes()[start] as char;
+    let mut value = String::new();
+    let mut chars = input[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, next)) if next == quote || next == '\\' => value.push(next),
+                Some((_, next)) => {
+                    value.push('\\');
+                    value.push(next);
+                }
+                None => value.push('\\'),
+            },
+            c if c == quote => return Ok((value, start + 1 + i + 1)),
+            c => value.push(c),
+        }
+    }
+    Err(ParseError::new(
+        "unterminated string",
+        Span {
+            start,
+            end: input.len(),
+        },
+    ))
+}
+
+/// Reads a JSON object or array literal, returning it and the position after it.
+fn read_json(input: &str, start: usize) -> Result<(Value, usize), ParseError> {
+    let mut values = serde_json::Deserializer::from_str(&input[start..]).into_iter::<Value>();
+    match values.next() {
+        Some(Ok(value)) => Ok((value, start + values.byte_offset())),
+        Some(Err(e)) => Err(ParseError::new(
+            format!("invalid JSON: {e}"),
+            Span {
+                start,
+                end: input.len(),
+            },
+        )),
+        None => unreachable!("the input starts with `{{` or `[`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind<'_>> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            kinds("abs(.delta) >= -0.5 AND .name != 'a b'"),
+            vec![
+                TokenKind::Ident("abs"),
+                TokenKind::LParen,
+                TokenKind::Path(".delta"),
+                TokenKind::RParen,
+                TokenKind::Op(">="),
+                TokenKind::Number("-0.5"),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".name"),
+                TokenKind::Op("!="),
+                TokenKind::Str("a b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_anchored_paths() {
+        assert_eq!(
+            kinds(".price > $root.budget + $parent"),
+            vec![
+                TokenKind::Path(".price"),
+                TokenKind::Op(">"),
+                TokenKind::Path("$root.budget"),
+                TokenKind::Plus,
+                TokenKind::Path("$parent"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_refs() {
+        assert_eq!(
+            kinds("@is_premium AND .a = 1"),
+            vec![
+                TokenKind::Ref("is_premium"),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".a"),
+                TokenKind::Op("="),
+                TokenKind::Number("1"),
+            ]
+        );
+        assert!(tokenize("@ AND .a = 1").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_lines() {
+        assert_eq!(
+            kinds(".a\t=\r\n1\nAND \\\n.b = \\\r\n2"),
+            vec![
+                TokenKind::Path(".a"),
+                TokenKind::Op("="),
+                TokenKind::Number("1"),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".b"),
+                TokenKind::Op("="),
+                TokenKind::Number("2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        assert_eq!(
+            kinds(".a = '#1' # the id\nAND /* a // b */ .b // .c /**/"),
+            vec![
+                TokenKind::Path(".a"),
+                TokenKind::Op("="),
+                TokenKind::Str("#1".to_string()),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".b"),
+                TokenKind::Alternative,
+                TokenKind::Path(".c"),
+            ]
+        );
+        assert_eq!(
+            tokenize(".a = 1 /* open").unwrap_err(),
+            ParseError::new("unterminated comment", Span { start: 7, end: 14 })
+        );
+    }
+
+    #[test]
+    fn test_tokenize_placeholders() {
+        assert_eq!(
+            kinds(".n > {{ min }} AND .tags HAS_ANY {{tags}} AND .c DEEP_EQ {\"a\": {}}"),
+            vec![
+                TokenKind::Path(".n"),
+                TokenKind::Op(">"),
+                TokenKind::Var("min"),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".tags"),
+                TokenKind::Ident("HAS_ANY"),
+                TokenKind::Var("tags"),
+                TokenKind::Ident("AND"),
+                TokenKind::Path(".c"),
+                TokenKind::Ident("DEEP_EQ"),
+                TokenKind::Json(serde_json::json!({ "a": {} })),
+            ]
+        );
+        assert_eq!(kinds("{{n}} -1")[1], TokenKind::Minus);
+        assert!(tokenize(".n > {{min").is_err());
+        assert!(tokenize(".n > {{a b}}").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_json() {
+        assert_eq!(
+            kinds(r#".config DEEP_EQ {"retries": 3, "hosts": ["a", "b"]} AND"#),
+            vec![
+                TokenKind::Path(".config"),
+                TokenKind::Ident("DEEP_EQ"),
+                TokenKind::Json(serde_json::json!({ "retries": 3, "hosts": ["a", "b"] })),
+                TokenKind::Ident("AND"),
+            ]
+        );
+        assert!(tokenize(r#".config DEEP_EQ {"retries": }"#).is_err());
+    }
+
+    #[test]
+    fn test_tokenize_arithmetic() {
+        assert_eq!(
+            kinds("now() - 7d"),
+            vec![
+                TokenKind::Ident("now"),
+                TokenKind::LParen,
+                TokenKind::RParen,
+                TokenKind::Minus,
+                TokenKind::Quantity("7", "d"),
+            ]
+        );
+        assert_eq!(
+            kinds(".a-1 > -1.5h"),
+            vec![
+                TokenKind::Path(".a-1"),
+                TokenKind::Op(">"),
+                TokenKind::Quantity("-1.5", "h"),
+            ]
+        );
+        assert_eq!(
+            kinds(".a - 1"),
+            vec![
+                TokenKind::Path(".a"),
+                TokenKind::Minus,
+                TokenKind::Number("1")
+            ]
+        );
+        assert_eq!(
+            kinds(".a = +18 + 1"),
+            vec![
+                TokenKind::Path(".a"),
+                TokenKind::Op("="),
+                TokenKind::Number("+18"),
+                TokenKind::Plus,
+                TokenKind::Number("1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_word_operators() {
+        assert_eq!(
+            kinds(".version SEMVER>= '1.4.0' AND name=x"),
+            vec![
+                TokenKind::Path(".version"),
+                TokenKind::Op("SEMVER>="),
+                TokenKind::Str("1.4.0".to_string()),
+                TokenKind::Ident("AND"),
+                TokenKind::Ident("name"),
+                TokenKind::Op("="),
+                TokenKind::Ident("x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_keeps_regex_backslashes() {
+        assert_eq!(
+            kinds(r"'v(\d+)'"),
+            vec![TokenKind::Str(r"v(\d+)".to_string())]
+        );
+        assert_eq!(kinds(r"'it\'s'"), vec![TokenKind::Str("it's".to_string())]);
+        assert!(tokenize("'open").is_err());
+    }
+}
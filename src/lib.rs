@@ -1,11 +1,155 @@
-use serde_json::Number;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod aggregate;
+mod alias;
+#[cfg(feature = "std")]
+mod audit;
+mod batch;
+#[cfg(feature = "std")]
+mod cache;
+mod case;
+#[cfg(feature = "std")]
+mod collection;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod comparator;
+mod compiled;
+mod complete;
+mod encoding;
+#[cfg(feature = "std")]
+mod env;
+mod eval;
+mod expr;
+#[cfg(feature = "axum")]
+mod extract;
+mod format;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+#[cfg(feature = "geo")]
+mod geo;
+mod highlight;
+#[cfg(feature = "std")]
+mod index;
+mod intern;
+mod jsonpath;
+mod lexer;
+#[cfg(feature = "std")]
+mod lint;
+mod locate;
+mod net;
+mod observer;
+mod parser;
+mod patch;
+mod pipeline;
+mod plan;
+mod plugin;
+mod postgres;
+mod projection;
+mod query;
+#[cfg(feature = "std")]
+mod raw;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+mod sort;
+mod sqlite;
+mod stats;
+mod syntax;
+#[cfg(feature = "std")]
+pub mod testing;
+mod text;
+#[cfg(feature = "chrono")]
+mod time;
+mod translate;
+mod update;
+mod uuid;
+#[cfg(feature = "std")]
+mod validate;
+mod version;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "watch")]
+mod watch;
+mod zone;
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, FixedOffset, Utc};
+#[cfg(feature = "std")]
+use serde_json::Map;
 use serde_json::Value;
 
+use crate::eval::Context;
+use crate::expr::{Arena, Expr, Path};
+use crate::intern::{Interner, Name};
+
+#[cfg(feature = "std")]
+pub use crate::aggregate::Aggregation;
+pub use crate::alias::AliasMap;
+#[cfg(feature = "std")]
+pub use crate::audit::{AuditEntry, AuditLog, Auditor, DocumentId};
+pub use crate::batch::BitVec;
+#[cfg(feature = "std")]
+pub use crate::cache::{CacheStats, FilterCache};
+pub use crate::case::KeyCase;
+#[cfg(feature = "std")]
+pub use crate::collection::JoinKind;
+#[cfg(feature = "arrow")]
+pub use crate::columnar::ParquetError;
+pub use crate::comparator::{CaseInsensitive, Comparator, Comparators, SemVer};
+pub use crate::compiled::{CompiledFilter, PathLimits};
+pub use crate::complete::{Suggestion, SuggestionKind};
+pub use crate::encoding::DecodeError;
+pub use crate::eval::EvalError;
+#[cfg(feature = "axum")]
+pub use crate::extract::{FilterQuery, FilterRejection};
+#[cfg(feature = "arbitrary")]
+pub use crate::fuzz::FilterCase;
+pub use crate::highlight::{SyntaxKind, SyntaxToken};
+#[cfg(feature = "std")]
+pub use crate::index::{IndexKind, IndexedCollection};
+pub use crate::lexer::Span;
+#[cfg(feature = "std")]
+pub use crate::lint::{LintKind, LintWarning};
+pub use crate::observer::EvalObserver;
+pub use crate::parser::{Expected, ParseError, ParseLimits, PartialParse};
+pub use crate::patch::{Patch, PatchError, PatchOperation};
+pub use crate::pipeline::Pipeline;
+pub use crate::plan::{Access, Plan, PlanStep};
+pub use crate::plugin::{FunctionPlugin, OperatorPlugin, PluginHost};
+pub use crate::projection::Projection;
+pub use crate::query::Query;
+#[cfg(feature = "std")]
+pub use crate::registry::{FilterSet, FilterSetError, NamedFilter};
+pub use crate::sort::{Nulls, SortAs, SortKey};
+pub use crate::stats::{ClauseStats, FilterStats};
+pub use crate::syntax::{ClauseKind, SyntaxClause, SyntaxTree};
+pub use crate::translate::TranslateError;
+pub use crate::update::Update;
+#[cfg(feature = "std")]
+pub use crate::validate::{IssueKind, Shape, ValidationIssue};
+#[cfg(feature = "wasm")]
+pub use crate::wasm::{WasmError, WasmLimits, WasmModule};
+#[cfg(feature = "watch")]
+pub use crate::watch::WatchedFilterSet;
+pub use crate::zone::ZoneMap;
+
 /// A struct representing a filter that can be applied on a JSON Value.
 ///
 /// A filter consists of a field, an operator, and a value to compare with.
 /// The field and value can be optionally multiplied by a multiplier.
 /// The value to compare with can also be taken from another field.
+/// Either side can instead be an expression, such as a function call.
 ///
 /// # Fields
 ///
@@ -15,36 +159,217 @@ use serde_json::Value;
 /// * `value_field` - The name of the field in the JSON Value to take the comparison value from.
 /// * `multiplier_field` - The multiplier for the field value.
 /// * `multiplier_value` - The multiplier for the comparison value.
+/// * `lhs` - An expression computing the value to compare, used instead of `field`.
+/// * `rhs` - An expression computing the value to compare with, used instead of `value` and `value_field`.
+/// * `compound` - A clause that is not a single comparison, such as `if ... then ... else ...`.
+/// * `arena` - The expressions that `lhs`, `rhs` and `compound` are made of, such as the operands of `.a + 1`.
+/// * `weight` - How much the clause counts in a score, set with `WEIGHT`. `None` counts as 1.
+/// * `should` - Whether the clause is a preference, written `SHOULD <clause>`, that counts in scores but
+///   never excludes a Value.
+/// * `optional` - Whether the clause passes Values without the path on its left, written `.field? > 5`.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Filter<'a> {
-    field: Option<&'a str>,
-    operator: &'a str,
+    field: Option<Name<'a>>,
+    operator: Cow<'a, str>,
     value: Option<Value>,
     value_field: Option<String>,
     multiplier_field: Option<i64>,
     multiplier_value: Option<i64>,
+    lhs: Option<Expr<'a>>,
+    rhs: Option<Expr<'a>>,
+    compound: Option<Compound<'a>>,
+    arena: Arena<'a>,
+    weight: Option<f64>,
+    should: bool,
+    optional: bool,
+}
+
+/// A clause that is more than a comparison of two values, usually because it is made of other filters.
+#[derive(Debug, Clone)]
+enum Compound<'a> {
+    /// `ANY <array> WHERE <filters>`, or `ALL` when `all` is set, matching the filters against the
+    /// elements of an array.
+    Quantified {
+        all: bool,
+        array: Expr<'a>,
+        filters: Vec<Filter<'a>>,
+    },
+    /// `<input> FUZZY(<target>, <max distance>)`, matching strings at most `max_distance` edits apart.
+    Fuzzy {
+        input: Expr<'a>,
+        target: Expr<'a>,
+        max_distance: usize,
+    },
+    /// `<location> WITHIN <distance> OF (<lat>, <lon>)`, with the distance in meters.
+    #[cfg(feature = "geo")]
+    Within {
+        location: Expr<'a>,
+        radius_m: f64,
+        center: (f64, f64),
+    },
+    /// `if <condition> then <then> else <otherwise>`. Without an `else`, `otherwise` is empty and passes.
+    If {
+        condition: Vec<Filter<'a>>,
+        then: Vec<Filter<'a>>,
+        otherwise: Vec<Filter<'a>>,
+    },
+    /// `MATCH <comparison> <threshold> WHERE <filters>`, matching when the weighted fraction of the
+    /// filters that pass compares with the threshold, as in `MATCH >= 0.7`.
+    Match {
+        comparison: &'static str,
+        threshold: f64,
+        filters: Vec<Filter<'a>>,
+    },
 }
 
 impl<'a> Default for Filter<'a> {
     fn default() -> Self {
         Filter {
             field: None,
-            operator: "=",
+            operator: Cow::Borrowed("="),
             value: None,
             value_field: None,
             multiplier_field: None,
             multiplier_value: None,
+            lhs: None,
+            rhs: None,
+            compound: None,
+            arena: Arena::default(),
+            weight: None,
+            should: false,
+            optional: false,
+        }
+    }
+}
+
+impl Filter<'_> {
+    /// Copies the filter so it no longer borrows the filter string it was parsed from, so it can be kept
+    /// after the string is dropped, for example in a cache or another thread.
+    pub fn into_owned(self) -> Filter<'static> {
+        self.into_shared(&mut Interner::default())
+    }
+
+    /// Copies filters like [`Filter::into_owned`], allocating each field name once however many of
+    /// the filters name it.
+    pub(crate) fn into_owned_all(filters: Vec<Filter>) -> Vec<Filter<'static>> {
+        let mut names = Interner::default();
+        filters
+            .into_iter()
+            .map(|filter| filter.into_shared(&mut names))
+            .collect()
+    }
+
+    /// Copies the filter like [`Filter::into_owned`], sharing the names copied with `names`.
+    pub(crate) fn into_shared(self, names: &mut Interner) -> Filter<'static> {
+        Filter {
+            field: self.field.map(|field| field.into_shared(names)),
+            operator: Cow::Owned(self.operator.into_owned()),
+            value: self.value,
+            value_field: self.value_field,
+            multiplier_field: self.multiplier_field,
+            multiplier_value: self.multiplier_value,
+            lhs: self.lhs.map(|lhs| lhs.into_shared(names)),
+            rhs: self.rhs.map(|rhs| rhs.into_shared(names)),
+            compound: self.compound.map(|compound| compound.into_shared(names)),
+            arena: self.arena.into_shared(names),
+            weight: self.weight,
+            should: self.should,
+            optional: self.optional,
+        }
+    }
+}
+
+impl Compound<'_> {
+    fn into_shared(self, names: &mut Interner) -> Compound<'static> {
+        let owned = |filters: Vec<Filter>, names: &mut Interner| -> Vec<Filter<'static>> {
+            filters
+                .into_iter()
+                .map(|filter| filter.into_shared(names))
+                .collect()
+        };
+        match self {
+            Compound::Quantified {
+                all,
+                array,
+                filters,
+            } => Compound::Quantified {
+                all,
+                array: array.into_shared(names),
+                filters: owned(filters, names),
+            },
+            Compound::Fuzzy {
+                input,
+                target,
+                max_distance,
+            } => Compound::Fuzzy {
+                input: input.into_shared(names),
+                target: target.into_shared(names),
+                max_distance,
+            },
+            #[cfg(feature = "geo")]
+            Compound::Within {
+                location,
+                radius_m,
+                center,
+            } => Compound::Within {
+                location: location.into_shared(names),
+                radius_m,
+                center,
+            },
+            Compound::If {
+                condition,
+                then,
+                otherwise,
+            } => Compound::If {
+                condition: owned(condition, names),
+                then: owned(then, names),
+                otherwise: owned(otherwise, names),
+            },
+            Compound::Match {
+                comparison,
+                threshold,
+                filters,
+            } => Compound::Match {
+                comparison,
+                threshold,
+                filters: owned(filters, names),
+            },
         }
     }
 }
 
 /// Parses a filter string into a list of Filters.
 ///
-/// The function splits the filter string by " AND " to get a list of filter parts.
-/// Each part is further split into field, operator, and value.
-/// The field and value can optionally have a multiplier and be prefixed with a multiplier followed by "*".
-/// The value can also be a reference to a field if it starts with ".".
+/// The filter string is a list of comparisons joined by `AND`. Each comparison has a left-hand side,
+/// an operator, and a right-hand side.
+/// Either side can be a field (prefixed with "."), a string (surrounded by `'`), a number, `true`, `false`, `null`, or a call
+/// to one of the numeric functions `abs`, `min`, `max`, `round`, `floor` and `ceil`, or the aggregates `sum`, `count`
+/// and `avg` over an array, `keys` and `values`, which return the keys or values of an object as an array, or `type`,
+/// which returns the JSON type name of a value, such as `'object'`.
+/// Fields and numbers can be added, subtracted and multiplied, as in `2*.field`.
+/// A duration such as `7d` can be added to or subtracted from a timestamp, as in `now() - 7d`.
+/// `coalesce(.a, .b)`, or `.a // .b`, takes the first of its operands that is present and not null.
+/// `capture(.url, 'v(\d+)')` extracts the first group a regex matches in a string field.
+/// Fields can be nested, as in `.user.name`; `.user?.name` is null instead of missing when `user` is.
+/// `.items[*].price` is the array of the `price` fields of every element of `items`.
+///
+/// A clause can also be a conditional, `if <filters> then <filters> else <clause>`, where the `else` branch is
+/// optional. Parentheses group several clauses into one, as in `else (.a = 1 AND .b = 2)`.
+/// `ANY .orders WHERE (.total > 100 AND .status = 'paid')` matches if at least one element of an array passes the
+/// clauses in the `WHERE`, and `ALL .orders WHERE ...` if every element does.
+///
+/// The right-hand side can also be a JSON object or array, as in `.config DEEP_EQ {"retries": 3}`, which compares whole
+/// values, and `.labels MATCHES_OBJECT {"app": "web"}`, which checks that an object has at least the given keys and values.
+/// `.tags CONTAINS 'rust'` checks that an array field has an element equal to a value, and `HAS_ALL`, `HAS_ANY` and
+/// `SUBSET_OF` compare an array field with a list such as `('admin', 'billing')`. `.role IN ('admin', 'billing')`
+/// checks that a field is equal to an element of a list.
+/// `.name FUZZY('jonh', 2)` matches strings that are at most 2 typos away from `jonh`.
+///
+/// With the `geo` feature, `.location WITHIN 5km OF (52.52, 13.40)` checks that a location is within a
+/// distance in `m`, `km` or `mi` of a point.
+///
+/// Comments run from `#` to the end of the line, or between `/*` and `*/`, and are ignored.
 ///
 /// # Arguments
 ///
@@ -54,72 +379,343 @@ impl<'a> Default for Filter<'a> {
 ///
 /// * `Option<Vec<Filter>>` - Returns a list of Filters if the parsing is successful, otherwise returns None.
 ///
-pub fn parse(filter_string: &str) -> Option<Vec<Filter>> {
-    let filters = filter_string
-        .split(" AND ")
-        .map(|filter_part| {
-            let parts: Vec<&str> = filter_part.split_whitespace().collect();
-
-            let field_parts: Vec<&str> = parts[0].split('*').collect();
-            let multiplier_field = if field_parts.len() == 2 {
-                field_parts[0].parse::<i64>().ok()
-            } else {
-                None
-            };
-            let field = if field_parts.len() == 1 || multiplier_field.is_some() {
-                Some(field_parts[field_parts.len() - 1].trim_start_matches('.'))
-            } else {
-                None
-            };
-
-            let operator = parts[1];
-
-            let value_parts: Vec<&str> = parts[2].split('*').collect();
-            let multiplier_value = if value_parts.len() == 2 {
-                value_parts[0].parse::<i64>().ok()
-            } else {
-                None
-            };
-
-            let value = value_parts[value_parts.len() - 1].trim_matches('\'');
-
-            let value_field = if value.starts_with('.') {
-                Some(value.trim_start_matches('.').to_string())
-            } else {
-                None
-            };
-
-            let value = if value_field.is_none() {
-                if let Ok(n) = value.parse::<i64>() {
-                    Some(Value::Number(Number::from(n)))
-                } else {
-                    Some(Value::String(value.to_string()))
-                }
-            } else {
-                None
-            };
+pub fn parse(filter_string: &str) -> Option<Vec<Filter<'_>>> {
+    parser::parse_filters(filter_string).ok()
+}
 
-            Filter {
-                field,
-                operator,
-                value,
-                value_field,
-                multiplier_field,
-                multiplier_value,
-            }
-        })
-        .collect();
-    Some(filters)
+/// Parses a filter string like [`parse`], rejecting it if it is longer, has more clauses, is nested deeper
+/// or has longer strings than `limits` allow. Use it for filters from users, so a large filter is
+/// rejected before it uses much memory or time.
+///
+/// # Arguments
+///
+/// * `filter_string` - The string representation of filters to parse.
+/// * `limits` - The bounds to check, such as `ParseLimits::default()`.
+///
+/// # Returns
+///
+/// * `Result<Vec<Filter>, ParseError>` - The filters, or the error for the first limit exceeded or the first syntax error.
+///
+pub fn parse_with_limits<'a>(
+    filter_string: &'a str,
+    limits: &ParseLimits,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    parser::parse_with_limits(filter_string, limits)
+}
+
+/// Parses a filter string like [`parse`], also accepting calls of the functions of `plugins`, such as
+/// `sha256(.email) = .hash`. The operators of plugins need no host to be parsed, as unknown operators
+/// are accepted. Evaluate the filters with the same host in [`ApplyOptions::plugins`].
+///
+/// # Arguments
+///
+/// * `filter_string` - The string representation of filters to parse.
+/// * `plugins` - The plugins whose functions may be called.
+///
+/// # Returns
+///
+/// * `Result<Vec<Filter>, ParseError>` - The filters, or the first syntax error, such as a call of a function no plugin adds.
+///
+pub fn parse_with_plugins<'a>(
+    filter_string: &'a str,
+    plugins: &'a PluginHost,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    parser::parse_with_plugins(filter_string, plugins)
+}
+
+/// Parses a filter string with `{{name}}` placeholders, such as `.total > {{min}} AND .status = {{status}}`,
+/// replacing each with its value from `vars`.
+///
+/// Every value is a literal, so strings need no quotes and can't change the filter, whatever they contain.
+/// Arrays can be used where a list is expected, as in `.tags HAS_ANY {{tags}}`.
+///
+/// # Arguments
+///
+/// * `template` - The filter string with placeholders.
+/// * `vars` - The value of each placeholder, by name.
+///
+/// # Returns
+///
+/// * `Result<Vec<Filter>, ParseError>` - The filters, or an error if the template doesn't parse or a placeholder has no value.
+///
+#[cfg(feature = "std")]
+pub fn parse_with_vars<'a>(
+    template: &'a str,
+    vars: &HashMap<String, Value>,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    parser::parse_with_vars(template, vars)
+}
+
+/// Prepares filters for being applied to many Values, reordering their clauses so the cheapest are
+/// evaluated first.
+///
+/// # Arguments
+///
+/// * `filters` - The Filters to compile, such as the result of [`parse`].
+///
+/// # Returns
+///
+/// * `CompiledFilter` - The compiled filters, which pass exactly the Values the Filters pass.
+///
+pub fn compile(filters: Vec<Filter<'_>>) -> CompiledFilter<'_> {
+    CompiledFilter::new(filters)
+}
+
+/// Describes how a compiled filter is evaluated: the order of its clauses, their estimated cost, and
+/// which of them an index could answer and which need a full scan.
+///
+/// # Arguments
+///
+/// * `compiled` - The compiled filters to describe.
+///
+/// # Returns
+///
+/// * `Plan` - The plan, which displays as one line per clause followed by the total cost.
+///
+pub fn explain(compiled: &CompiledFilter) -> Plan {
+    plan::explain(compiled)
+}
+
+/// Reports suspicious constructs in filters, which are likely mistakes even without knowing the Values
+/// the filters will be applied to.
+///
+/// It warns about unknown operators, strings ordered with `>` and the like against strings that are
+/// neither UUIDs nor timestamps, clauses that always or never pass whatever the Value, clauses repeated
+/// among the clauses they are joined with, and lists or arithmetic mixing types, such as
+/// `.tags HAS_ANY ('a', 1)`.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to check.
+///
+/// # Returns
+///
+/// * `Vec<LintWarning>` - The warnings, in the order of the clauses. Empty if nothing suspicious was found.
+///
+#[cfg(feature = "std")]
+pub fn lint(filters: &[Filter]) -> Vec<LintWarning> {
+    lint::lint(filters)
+}
+
+/// Checks filters against a JSON Schema or a sample of the Values they will be applied to, finding
+/// clauses that can never pass before the filters ever run.
+///
+/// It reports fields that aren't in the schema or sample, operators that can't compare values of a
+/// field's type, such as `>` on a string compared with a number, and array or object operators used on
+/// fields of another type, or the other way around.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to check.
+/// * `shape` - The schema or sample to check the filters against.
+///
+/// # Returns
+///
+/// * `Vec<ValidationIssue>` - The problems found, in the order of the clauses. Empty if none were found.
+///
+#[cfg(feature = "std")]
+pub fn validate(filters: &[Filter], shape: Shape) -> Vec<ValidationIssue> {
+    validate::validate(filters, shape)
+}
+
+/// Suggests completions for the word being typed in an unfinished filter string, for building a query
+/// bar on top of this crate.
+///
+/// Depending on what can come next at the cursor, the suggestions are the fields of the sample
+/// document, including the fields of array elements inside `ANY` and `ALL`, the operators, the values
+/// the sample has for the field being compared, or keywords such as `AND`. Only suggestions starting with
+/// the part of the word already typed are returned, ignoring case.
+///
+/// # Arguments
+///
+/// * `partial_filter` - The filter string being edited.
+/// * `cursor` - The byte offset of the cursor in the filter string.
+/// * `sample` - A document like the ones the filter will be applied to.
+///
+/// # Returns
+///
+/// * `Vec<Suggestion>` - The suggestions, each with the byte range it replaces. Empty when there is nothing to suggest.
+///
+pub fn complete(partial_filter: &str, cursor: usize, sample: &Value) -> Vec<Suggestion> {
+    complete::complete(partial_filter, cursor, sample)
+}
+
+/// Suggests the field a missing path was probably meant to be, from the fields of a document.
+///
+/// The first field of the path the document doesn't have is compared with the keys of the object it
+/// was looked up in, ignoring case, `_` and `-`, and replaced by the closest one if it is close enough
+/// to be a likely misspelling. This is the suggestion [`validate`] makes for unknown fields.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value the path was looked up in.
+/// * `path` - The path, such as `.user.created_at`.
+///
+/// # Returns
+///
+/// * `Option<String>` - The suggested path, such as `.user.createdAt`, or None if the document has the path or no key is close enough.
+///
+#[cfg(feature = "std")]
+pub fn suggest_field(v: &Value, path: &str) -> Option<String> {
+    validate::suggest_field(v, &Path::parse(path))
+}
+
+/// Replaces `${NAME}` placeholders in a filter string with environment variables, so deployment-specific
+/// values such as `.latency > ${SLO_MS}` can live in config files. `${NAME:-default}` falls back to a
+/// default when the variable isn't set, and `$${` is written as a literal `${`.
+///
+/// Values are inserted as written, so a string value needs quotes around its placeholder, as in
+/// `.region = '${REGION}'`. Parse the result with [`parse`].
+///
+/// # Arguments
+///
+/// * `filter_string` - The filter string with placeholders.
+///
+/// # Returns
+///
+/// * `Result<String, ParseError>` - The filter string with every placeholder replaced, or an error for a variable that isn't set.
+///
+#[cfg(feature = "std")]
+pub fn expand_env(filter_string: &str) -> Result<String, ParseError> {
+    env::expand(filter_string, |name| std::env::var(name).ok())
+}
+
+/// Rewrites a filter string in canonical form: one space around operators and `AND`, strings in single
+/// quotes, fields with their leading `.`, and operators in their canonical spelling, such as `=` for `==`.
+///
+/// The result parses to the same filters as the input. Comments are dropped.
+///
+/// # Arguments
+///
+/// * `filter_string` - The filter string to format.
+///
+/// # Returns
+///
+/// * `Result<String, ParseError>` - The formatted filter string on one line, or the error if the input doesn't parse.
+///
+pub fn format(filter_string: &str) -> Result<String, ParseError> {
+    format::format(filter_string)
+}
+
+/// Writes filters in canonical form, the same as [`format`] gives for the filter string they were
+/// parsed from, so filters can be stored canonically and compared by their string.
+///
+/// Parsing the result gives the same filters: `parse(&normalize(&filters))` is equivalent to `filters`,
+/// and normalizing it again gives the same string. Each Filter also displays as its clause in
+/// canonical form.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to write.
+///
+/// # Returns
+///
+/// * `String` - The filters in canonical form on one line, joined by `AND`.
+///
+pub fn normalize(filters: &[Filter]) -> String {
+    format::Clauses(filters).to_string()
+}
+
+/// Rewrites a filter string in canonical form like [`format`], breaking it across lines when it is
+/// longer than `width`.
+///
+/// Clauses that don't fit start their own line with their `AND`, and the clauses inside `WHERE (...)`,
+/// `then` and `else (...)` are indented by four spaces per level.
+///
+/// # Arguments
+///
+/// * `filter_string` - The filter string to format.
+/// * `width` - The number of characters a line should fit in. A single clause longer than that stays on one line.
+///
+/// # Returns
+///
+/// * `Result<String, ParseError>` - The formatted filter string, or the error if the input doesn't parse.
+///
+pub fn format_pretty(filter_string: &str, width: usize) -> Result<String, ParseError> {
+    format::format_pretty(filter_string, width)
+}
+
+/// Splits a filter string into tokens for syntax highlighting, each with its kind and byte range.
+///
+/// Every part of the filter string other than whitespace is covered by a token, including text that
+/// isn't valid, which has the `Error` kind, so highlighting works while a filter is being typed.
+///
+/// # Arguments
+///
+/// * `filter_string` - The filter string to split.
+///
+/// # Returns
+///
+/// * `Vec<SyntaxToken>` - The tokens, in the order they appear.
+///
+pub fn tokens(filter_string: &str) -> Vec<SyntaxToken> {
+    highlight::tokens(filter_string)
+}
+
+/// Parses a filter string into a syntax tree for tooling, keeping the span of every clause and all of
+/// the text, so formatters, linters and refactoring tools such as renaming a field across stored
+/// filters can change one part of a filter without losing the user's formatting.
+///
+/// `{{name}}` placeholders and `@name` references are accepted without their values.
+///
+/// # Arguments
+///
+/// * `filter_string` - The filter string to parse.
+///
+/// # Returns
+///
+/// * `Result<SyntaxTree, ParseError>` - The syntax tree, or the first error found.
+///
+pub fn parse_syntax(filter_string: &str) -> Result<SyntaxTree<'_>, ParseError> {
+    syntax::parse(filter_string)
+}
+
+/// Parses a filter string like [`parse`], but instead of stopping at the first syntax error, returns
+/// every error with its span, so an editor can underline every mistake at once.
+///
+/// After an error, the rest of its clause is skipped up to the next `AND` outside parentheses, and
+/// parsing resumes with the clause after it, so each clause reports at most one error.
+///
+/// # Arguments
+///
+/// * `filter_string` - The string representation of filters to parse.
+///
+/// # Returns
+///
+/// * `Result<Vec<Filter>, Vec<ParseError>>` - The filters, or every error found, in the order of their spans.
+///
+pub fn parse_all_errors(filter_string: &str) -> Result<Vec<Filter<'_>>, Vec<ParseError>> {
+    parser::parse_recovering(filter_string)
+}
+
+/// Parses a filter string that may be unfinished, such as one being typed, like `.price >`.
+///
+/// Unlike [`parse`], it doesn't give up on an error: it returns the clauses parsed before it, the error,
+/// and what could come next at the end of the input, so an editor can validate the filter as it is typed.
+///
+/// # Arguments
+///
+/// * `filter_string` - The string representation of filters to parse.
+///
+/// # Returns
+///
+/// * `PartialParse` - The clauses parsed, the first error if there was one, and what is expected next.
+///
+pub fn parse_partial(filter_string: &str) -> PartialParse<'_> {
+    parser::parse_partial(filter_string)
 }
 
 /// Applies a set of filters on a JSON Value and returns whether the Value passes the filters.
 ///
 /// The function iterates over a list of filters and applies each filter on the Value `v`.
-/// The field to be compared is extracted from the Value, based on the `field` attribute of the filter.
-/// The value to compare with is determined based on the `value_field` or `value` attributes of the filter.
+/// The value to be compared is extracted from the Value, based on the `field` attribute of the filter,
+/// or computed from the filter's left-hand expression.
+/// The value to compare with is determined based on the `value_field` or `value` attributes of the filter,
+/// or computed from the filter's right-hand expression.
 ///
 /// The comparison is done either as a string comparison or as a number comparison,
 /// depending on the types of the extracted field and value.
+/// Integers are compared exactly, any other numbers are compared as floats.
 /// For number comparisons, a multiplier can be applied to the field or value.
 ///
 /// If a filter comparison is unsuccessful, the function immediately returns `false`.
@@ -135,111 +731,1778 @@ pub fn parse(filter_string: &str) -> Option<Vec<Filter>> {
 /// * `bool` - Returns `true` if the Value `v` passes all the filters, otherwise returns `false`.
 ///
 pub fn apply(v: &Value, filters: &[Filter]) -> bool {
-    for filter in filters {
-        // The field we're comparing is taken from the JSON value.
-        let f = filter.field.as_deref().and_then(|field| v.get(field));
-        let f_is_number = matches!(f, Some(Value::Number(_)));
-
-        // If the filter has a value_field, we take the value to compare from the JSON value.
-        // If there is no value_field, we use the value directly.
-        let value = filter.value_field.as_deref().and_then(|vf| v.get(vf));
-
-        // Then we perform the comparison according to the operator in the filter.
-        // If both are strings, compare them as strings. If not, try to compare as numbers.
-        let comparison = if !f_is_number {
-            let f_str = f.and_then(|val| val.as_str());
-            // if value id true get from value, if not get from value_filed
-            let value_str = if filter.value.is_some() {
-                filter.value.as_ref().and_then(|val| val.as_str())
-            } else {
-                filter
-                    .value_field
-                    .as_deref()
-                    .and_then(|vf| v.get(vf))
-                    .and_then(|val| val.as_str())
-            };
-            match (f_str, value_str) {
-                (Some(f_str), Some(value_str)) => match filter.operator {
-                    "=" => f_str == value_str,
-                    "!=" => f_str != value_str,
-                    _ => false, // Unknown operator for string comparisons
-                },
-                _ => false, // In case there's a mismatch in type (one is number and the other is string)
-            }
-        } else {
-            // Now we multiply it by its multiplier if there is one.
-            let f = if let (Some(mult), Some(val)) = (filter.multiplier_field, f) {
-                val.as_i64().map(|v| v * mult)
-            } else {
-                f.and_then(|val| val.as_i64())
-            };
-
-            let value = if let (Some(mult), Some(val)) = (filter.multiplier_value, value) {
-                val.as_i64().map(|v| v * mult)
-            } else {
-                value
-                    .and_then(|val| val.as_i64())
-                    .or_else(|| filter.value.clone().and_then(|val| val.as_i64()))
-            };
-
-            match (f, value) {
-                (Some(f), Some(value)) => match filter.operator {
-                    "=" => f == value,
-                    "!=" => f != value,
-                    ">=" => f >= value,
-                    ">" => f > value,
-                    "<=" => f <= value,
-                    "<" => f < value,
-                    _ => false, // Unknown operator
-                },
-                _ => false, // In case there's a mismatch in type (one is number and the other is string)
-            }
-        };
+    apply_with(v, filters, &ApplyOptions::default())
+}
 
-        // If the comparison is false, we return false immediately.
-        if !comparison {
-            return false;
-        }
+/// Applies a set of filters on each element of a JSON array, like [`apply`] on each of them. A Value
+/// that isn't an array is filtered as a whole. `apply` on an array reads fields of the array itself,
+/// which it doesn't have, so use this when filtering a list of documents such as a parsed JSON file.
+///
+/// # Arguments
+///
+/// * `v` - The JSON array whose elements to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the elements.
+///
+/// # Returns
+///
+/// * `Vec<bool>` - Returns whether each element passes all the filters, in order, or a single result for a Value that isn't an array.
+///
+pub fn apply_elements(v: &Value, filters: &[Filter]) -> Vec<bool> {
+    match v {
+        Value::Array(elements) => elements.iter().map(|e| apply(e, filters)).collect(),
+        _ => vec![apply(v, filters)],
     }
-    // If none of the filters returned false, we return true.
-    true
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+/// Scores how well a JSON Value matches a set of filters, to rank Values by best match rather than
+/// keep only those passing every filter. Unlike [`apply`], every filter is evaluated.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to score.
+/// * `filters` - A slice of Filters to apply on the Value.
+///
+/// # Returns
+///
+/// * `f64` - The fraction of the filters the Value passes, weighted by their `WEIGHT`, from `0.0` to `1.0`. Filters with no weight in total score `1.0`.
+///
+pub fn score(v: &Value, filters: &[Filter]) -> f64 {
+    eval::score(v, filters, &Context::new(&ApplyOptions::default()))
+}
 
-    #[test]
-    fn test_parse() {
-        let filter_string = ".field = 'hello' AND .value >= 20";
-        let filters = parse(filter_string).unwrap();
-        assert_eq!(filters.len(), 2);
-        assert_eq!(filters[0].field, Some("field"));
-        assert_eq!(filters[0].operator, "=");
-        assert_eq!(filters[0].value, Some(json!("hello")));
-        assert_eq!(filters[1].field, Some("value"));
-        assert_eq!(filters[1].operator, ">=");
-        assert_eq!(filters[1].value, Some(json!(20)));
-    }
+/// Returns the positions of the Values that pass a set of filters, so that slices kept alongside
+/// `values`, such as metadata for each Value, can be filtered the same way.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to apply the filters on.
+/// * `filters` - A slice of Filters to apply on each Value.
+///
+/// # Returns
+///
+/// * `Vec<usize>` - The positions in `values` of the Values that pass all the filters, in increasing order.
+///
+pub fn matching_indices(values: &[Value], filters: &[Filter]) -> Vec<usize> {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| apply(v, filters))
+        .map(|(i, _)| i)
+        .collect()
+}
 
-    #[test]
-    fn test_apply() {
-        let v = json!({ "field": 100, "hello": "world" });
-        let filters = vec![
-            Filter {
-                field: Some("field"),
-                operator: ">",
-                value: Some(json!(50)),
-                ..Default::default()
-            },
-            Filter {
-                field: Some("hello"),
-                operator: "=",
+/// Finds where in a JSON Value a set of filters matches, such as to highlight matches in a document
+/// viewer. Every object in the Value, and the Value itself, is checked against the filters.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to search.
+/// * `filters` - A slice of Filters an object must pass.
+///
+/// # Returns
+///
+/// * `Vec<String>` - The JSON pointers (RFC 6901) of the objects that pass all the filters, such as `/items/0`, each object before the ones inside it. The Value itself is the empty pointer.
+///
+pub fn find_matching_paths(v: &Value, filters: &[Filter]) -> Vec<String> {
+    let mut paths = Vec::new();
+    locate::matching_paths(v, filters, &mut String::new(), &mut paths);
+    paths
+}
+
+/// Options controlling how filters are evaluated by [`apply_with`].
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// The instant `now()` evaluates to. When `None`, the system clock is read each time `now()` is
+    /// evaluated. Set it to get reproducible results, for example in tests.
+    #[cfg(feature = "chrono")]
+    pub now: Option<DateTime<Utc>>,
+    /// The offset of timestamps that don't specify one, such as `2024-06-01` or
+    /// `2024-06-01T08:00:00`. When `None`, they are taken to be in UTC.
+    #[cfg(feature = "chrono")]
+    pub timezone: Option<FixedOffset>,
+    /// Compare numbers with timestamp strings by reading the numbers as milliseconds since the Unix
+    /// epoch. When `false`, a number never matches a string.
+    #[cfg(feature = "chrono")]
+    pub coerce_epoch_millis: bool,
+    /// Reduce words to their stems for `MATCHES_TEXT`, so `filtering` matches `filters`. When `false`,
+    /// words must match exactly, ignoring case.
+    pub stem_text: bool,
+    /// Compare arrays without regard to element order for `DEEP_EQ` and `MATCHES_OBJECT`, so `[1, 2, 3]` equals
+    /// `[3, 1, 2]`. Duplicates still count, so `[1, 1, 2]` doesn't equal `[1, 2, 2]`.
+    pub unordered_arrays: bool,
+    /// Other paths to read fields from in Values that don't have them, such as `.customer.profile`
+    /// for `.user`. When empty, paths are read as they are written.
+    pub aliases: AliasMap,
+    /// How keys match the keys of objects that don't have them as written, such as `createdAt` for
+    /// `.created_at` with [`KeyCase::Camel`]. By default, keys only match as written.
+    pub key_case: KeyCase,
+    /// A document of values to read in place of paths a Value doesn't have, such as
+    /// `{"retries": 0}`, so `.retries >= 3` compares 0 for Values without retries. Paths in the
+    /// clauses of `ANY` and `ALL`, other than `$root` paths, read array elements and have no defaults.
+    pub defaults: Option<Value>,
+    /// Comparators of paths, such as [`CaseInsensitive`] for `.name`, ordering their values in place of
+    /// the usual comparison for `=`, `!=`, `<`, `<=`, `>` and `>=`. When empty, values compare as usual.
+    pub comparators: Comparators,
+    /// The plugins evaluating the operators and functions they add, which filters parsed with
+    /// [`parse_with_plugins`] may use. Without them, such operators never match and such functions
+    /// are missing.
+    pub plugins: PluginHost,
+}
+
+impl ApplyOptions {
+    /// Returns whether fields missing from a Value may be read from elsewhere, so looking fields up
+    /// directly, or reading only the fields the filters name, may not find the Values filters read.
+    pub(crate) fn fills_missing(&self) -> bool {
+        !self.aliases.is_empty() || self.key_case != KeyCase::Exact || self.defaults.is_some()
+    }
+}
+
+/// Applies a set of filters on a JSON Value like [`apply`], with options controlling the evaluation.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `options` - The options to evaluate the filters with.
+///
+/// # Returns
+///
+/// * `bool` - Returns `true` if the Value `v` passes all the filters, otherwise returns `false`.
+///
+pub fn apply_with(v: &Value, filters: &[Filter], options: &ApplyOptions) -> bool {
+    eval::apply(v, filters, &Context::new(options))
+}
+
+/// Applies a set of filters on a JSON Value like [`apply_with`], within a budget of fuel, so a filter from
+/// one tenant of a service can't take much more time than others. Each clause, step of a path, array
+/// element a wildcard visits and function call spends one unit of fuel, and matching text spends more the
+/// longer it is.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `options` - The options to evaluate the filters with.
+/// * `budget` - The fuel the evaluation may spend.
+///
+/// # Returns
+///
+/// * `Result<bool, EvalError>` - Whether the Value `v` passes all the filters, or `EvalError::BudgetExceeded` if the evaluation spent more than `budget`.
+///
+pub fn apply_with_budget(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    budget: u64,
+) -> Result<bool, EvalError> {
+    eval::apply_budgeted(v, filters, &Context::new(options).with_budget(budget))
+}
+
+/// Applies a set of filters on a JSON Value like [`apply_with`], reporting whether it passed and which
+/// clause rejected it to `observer`, such as one incrementing metrics counters.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `options` - The options to evaluate the filters with.
+/// * `observer` - The observer to report to.
+///
+/// # Returns
+///
+/// * `bool` - Returns `true` if the Value `v` passes all the filters, otherwise returns `false`.
+///
+pub fn apply_observed(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    observer: &dyn EvalObserver,
+) -> bool {
+    eval::apply(v, filters, &Context::new(options).observed(observer))
+}
+
+/// Applies a set of filters on a JSON Value like [`apply_with`], recording the filter's id, the
+/// document's id or hash, the result and how long the evaluation took to the auditor's log.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `options` - The options to evaluate the filters with.
+/// * `auditor` - The log to record to, with how to identify the filter and the document.
+///
+/// # Returns
+///
+/// * `bool` - Returns `true` if the Value `v` passes all the filters, otherwise returns `false`.
+///
+#[cfg(feature = "std")]
+pub fn apply_audited(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    auditor: &Auditor,
+) -> bool {
+    audit::apply(v, filters, options, PathLimits::UNLIMITED, auditor)
+}
+
+/// Applies a set of filters on a JSON object, without wrapping it into a Value. The object can be
+/// one a caller assembled from parts of other documents. Clauses comparing top-level fields look
+/// them up in the map; other clauses are evaluated against a copy of only the fields they read.
+///
+/// # Arguments
+///
+/// * `map` - The JSON object to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the object.
+///
+/// # Returns
+///
+/// * `bool` - Returns `true` if the object passes all the filters, otherwise returns `false`.
+///
+#[cfg(feature = "std")]
+pub fn apply_map(map: &Map<String, Value>, filters: &[Filter]) -> bool {
+    eval::matches_map(map, filters, &Context::new(&ApplyOptions::default()))
+}
+
+/// Applies a set of filters on a JSON document given as text, like [`apply_with`]. Only the top-level
+/// fields the filters read are built into Values; the others are checked to be valid JSON and
+/// skipped, which saves most of the parsing work when filters look at a few fields of large
+/// documents. A `&serde_json::value::RawValue` can be filtered with `apply_str(raw.get(), ..)`.
+///
+/// # Arguments
+///
+/// * `json` - The JSON document to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the document.
+/// * `options` - The options to evaluate the filters with.
+///
+/// # Returns
+///
+/// * `Result<bool, serde_json::Error>` - Returns whether the document passes all the filters, or an error if it isn't valid JSON.
+///
+#[cfg(feature = "std")]
+pub fn apply_str(
+    json: &str,
+    filters: &[Filter],
+    options: &ApplyOptions,
+) -> Result<bool, serde_json::Error> {
+    let v = match raw::read_keys(filters, options) {
+        Some(keys) => raw::parse_keys(json, &keys)?,
+        None => serde_json::from_str(json)?,
+    };
+    Ok(apply_with(&v, filters, options))
+}
+
+/// Applies many compiled filters on one JSON document given as text, such as the routing rules of a
+/// message broker, and returns the positions of the filters it passes. The document is parsed once
+/// for all of them, and only the top-level fields at least one of the filters reads are built into
+/// Values, so each field is extracted once however many filters read it.
+///
+/// # Arguments
+///
+/// * `json` - The JSON document to apply the filters on.
+/// * `filters` - The compiled filters to apply on the document.
+/// * `options` - The options to evaluate the filters with.
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, serde_json::Error>` - Returns the positions in `filters` of the filters the document passes, in increasing order, or an error if it isn't valid JSON.
+///
+#[cfg(feature = "std")]
+pub fn apply_many(
+    json: &str,
+    filters: &[CompiledFilter],
+    options: &ApplyOptions,
+) -> Result<Vec<usize>, serde_json::Error> {
+    let keys: Option<HashSet<&str>> = filters
+        .iter()
+        .map(|filter| raw::read_keys(filter.filters(), options))
+        .try_fold(HashSet::new(), |mut all, keys| {
+            all.extend(keys?);
+            Some(all)
+        });
+    let v = match keys {
+        Some(keys) => raw::parse_keys(json, &keys)?,
+        None => serde_json::from_str(json)?,
+    };
+    Ok(filters
+        .iter()
+        .enumerate()
+        .filter(|(_, filter)| filter.matches_with(&v, options))
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Applies a set of filters on a JSON Value like [`apply_with`], counting in `stats` which clause
+/// rejected it. Calling it for every Value of a batch shows which clauses do the filtering.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `options` - The options to evaluate the filters with.
+/// * `stats` - The counts to add to, starting from `FilterStats::default()` and always used with the same filters.
+///
+/// # Returns
+///
+/// * `bool` - Returns `true` if the Value `v` passes all the filters, otherwise returns `false`.
+///
+pub fn apply_with_stats(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    stats: &mut FilterStats,
+) -> bool {
+    stats::matches_all(v, filters, options, stats)
+}
+
+/// Applies a set of filters on a JSON Value and, if it passes, returns only the requested parts of it.
+///
+/// This saves walking the Value again after filtering when only a few fields are needed.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value to apply the filters on.
+/// * `filters` - A slice of Filters to apply on the Value.
+/// * `projection` - The paths to keep from the Value.
+///
+/// # Returns
+///
+/// * `Option<Value>` - Returns the projected Value if `v` passes all the filters, otherwise returns None.
+///
+pub fn apply_select(v: &Value, filters: &[Filter], projection: &Projection) -> Option<Value> {
+    apply(v, filters).then(|| projection.project(v))
+}
+
+/// Sorts a list of JSON Values by one or more keys, such as the filtered results of [`apply`].
+///
+/// Values are ordered by the first key, then by the second among values the first key orders equally, and so on.
+/// The sort is stable, so values every key orders equally keep their order. Missing fields sort as null.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to sort in place.
+/// * `keys` - The keys to sort by, each with a direction, a placement for nulls and a comparison mode.
+///
+pub fn sort_by(values: &mut [Value], keys: &[SortKey]) {
+    sort::sort_by(values, keys)
+}
+
+/// Removes duplicates from a list of JSON Values by a key, keeping the first Value for each key.
+///
+/// Values without the key count as having a null key, so only the first of them is kept.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to deduplicate, such as the ones that passed a set of filters.
+/// * `path` - The path of the key, such as `.id` or `.user.email`.
+///
+/// # Returns
+///
+/// * `Vec<Value>` - The first Value for each distinct key, in their original order.
+///
+#[cfg(feature = "std")]
+pub fn distinct_by(values: Vec<Value>, path: &str) -> Vec<Value> {
+    collection::distinct_by(values, path)
+}
+
+/// Groups the JSON Values that pass a set of filters by a key, and computes aggregations for every group.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to group.
+/// * `filters` - A slice of Filters a Value must pass to be included.
+/// * `key` - The path of the key to group by, such as `.status`.
+/// * `aggregations` - The values to compute for each group, such as a count or the sum of a field.
+///
+/// # Returns
+///
+/// * `Value` - An array with an object per group, in the order the groups were first seen. Each object has the key
+///   of the group under `"key"`, and the result of each aggregation under its name.
+///
+#[cfg(feature = "std")]
+pub fn group_by(
+    values: &[Value],
+    filters: &[Filter],
+    key: &str,
+    aggregations: &[Aggregation],
+) -> Value {
+    let passing = values.iter().filter(|v| apply(v, filters));
+    aggregate::group_by(passing, key, aggregations)
+}
+
+/// Joins two lists of JSON Values on a key, merging each left Value with the right Values that have the same key.
+///
+/// The merged Value is the left object with the fields of the right object added; fields the left object already has
+/// are kept. This lets filters use fields from both sides, as in joining orders with their customers. A left Value with
+/// several matches yields a merged Value per match, and missing or null keys never match.
+///
+/// # Arguments
+///
+/// * `left` - The JSON Values to enrich.
+/// * `right` - The JSON Values to enrich them with.
+/// * `on_left` - The path of the key in the left Values, such as `.customer_id`.
+/// * `on_right` - The path of the key in the right Values, such as `.id`.
+/// * `kind` - Whether left Values without a match are dropped (`JoinKind::Inner`) or kept as they are (`JoinKind::Left`).
+///
+/// # Returns
+///
+/// * `Vec<Value>` - The merged Values, in the order of the left Values.
+///
+#[cfg(feature = "std")]
+pub fn join(
+    left: &[Value],
+    right: &[Value],
+    on_left: &str,
+    on_right: &str,
+    kind: JoinKind,
+) -> Vec<Value> {
+    collection::join(left, right, on_left, on_right, kind)
+}
+
+/// Changes every JSON Value in a list that passes a set of filters.
+///
+/// The filters are evaluated against each Value before it is changed.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to update in place.
+/// * `filters` - A slice of Filters a Value must pass to be changed.
+/// * `update` - The changes to make, such as setting fields or a JSON merge patch.
+///
+/// # Returns
+///
+/// * `usize` - The number of Values that were changed.
+///
+pub fn apply_update(values: &mut [Value], filters: &[Filter], update: &Update) -> usize {
+    let mut updated = 0;
+    for v in values.iter_mut().filter(|v| apply(v, filters)) {
+        update.apply(v);
+        updated += 1;
+    }
+    updated
+}
+
+/// Removes the elements that pass a set of filters from an array inside a JSON Value, in place.
+///
+/// The path can have wildcards, as in `.orders[*].items`, to remove elements from several arrays. Values at the path
+/// that are not arrays are left as they are.
+///
+/// # Arguments
+///
+/// * `v` - The JSON Value holding the array.
+/// * `path` - The path of the array, such as `.cart.items`.
+/// * `filters` - A slice of Filters, applied on each element of the array.
+///
+/// # Returns
+///
+/// * `usize` - The number of elements that were removed.
+///
+pub fn remove_matching_at(v: &mut Value, path: &str, filters: &[Filter]) -> usize {
+    let path = Path::parse(path);
+    let mut removed = 0;
+    update::for_each_at(&path.segments, v, &mut |array| {
+        if let Value::Array(elements) = array {
+            let before = elements.len();
+            elements.retain(|element| !apply(element, filters));
+            removed += before - elements.len();
+        }
+    });
+    removed
+}
+
+/// Applies a patch to the JSON Values that pass a set of filters, and returns the patched Values.
+///
+/// The patch is a JSON merge patch (RFC 7386) or a JSON patch (RFC 6902). The Values are not changed; patched copies
+/// of the passing ones are returned.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values to apply the filters on.
+/// * `filters` - A slice of Filters a Value must pass to be patched.
+/// * `patch` - The patch to apply.
+///
+/// # Returns
+///
+/// * `Result<Vec<Value>, PatchError>` - The patched Values, or the error of the first Value the patch failed on.
+///
+pub fn apply_patch(
+    values: &[Value],
+    filters: &[Filter],
+    patch: &Patch,
+) -> Result<Vec<Value>, PatchError> {
+    apply_patch_at(values, "", filters, patch)
+}
+
+/// Applies a patch to the parts of JSON Values at a path that pass a set of filters, like [`apply_patch`].
+///
+/// The filters and the patch are applied to each value at the path, such as every element of `.items[*]`, instead of
+/// to the whole Value.
+///
+/// # Arguments
+///
+/// * `values` - The JSON Values holding the parts to patch.
+/// * `path` - The path of the parts, such as `.items[*]`.
+/// * `filters` - A slice of Filters a part must pass to be patched.
+/// * `patch` - The patch to apply, with JSON pointers relative to the part.
+///
+/// # Returns
+///
+/// * `Result<Vec<Value>, PatchError>` - Copies of the Values with at least one part patched, or the first error.
+///
+pub fn apply_patch_at(
+    values: &[Value],
+    path: &str,
+    filters: &[Filter],
+    patch: &Patch,
+) -> Result<Vec<Value>, PatchError> {
+    let path = Path::parse(path);
+    let mut patched = Vec::new();
+    for v in values {
+        let mut v = v.clone();
+        let mut result = Ok(false);
+        update::for_each_at(&path.segments, &mut v, &mut |part| {
+            if result.is_ok() && apply(part, filters) {
+                result = patch.apply(part).map(|()| true);
+            }
+        });
+        if result? {
+            patched.push(v);
+        }
+    }
+    Ok(patched)
+}
+
+/// Translates filters into a condition for SQLite's JSON functions, such as
+/// `json_type(data, '$.price') IN ('integer', 'real') AND json_extract(data, '$.price') > 100`, to
+/// select the rows whose JSON documents pass the filters in the database rather than after loading
+/// them. Each comparison checks the type of the value too, as SQLite orders values of different types
+/// where filters never pass.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to translate.
+/// * `column` - The SQL expression of the JSON documents, such as a column name, copied into the condition as it is.
+///
+/// # Returns
+///
+/// * `Result<String, TranslateError>` - The condition, or an error for the first clause that can't be translated exactly: only comparisons of paths with literals, `CONTAINS`, `ANY` and `ALL` are translated.
+///
+pub fn to_sqlite_json1(filters: &[Filter], column: &str) -> Result<String, TranslateError> {
+    sqlite::to_sqlite_json1(filters, column)
+}
+
+/// Translates filters into a condition on a PostgreSQL `jsonb` column. Equality with literals,
+/// `CONTAINS`, and `ANY` of such clauses are merged into one containment document, as in
+/// `doc @> '{"status": "paid"}'`, which a GIN index answers, with either operator class. The other
+/// clauses are written as strict JSON path predicates, as in `doc @@ 'strict $."price" > 100'`, which
+/// `jsonb_path_ops` indexes answer when the predicate only tests equality.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to translate.
+/// * `column` - The SQL expression of the `jsonb` documents, such as a column name, copied into the condition as it is.
+///
+/// # Returns
+///
+/// * `Result<String, TranslateError>` - The condition, or an error for the first clause that can't be translated exactly, as for [`to_sqlite_json1`].
+///
+pub fn to_postgres_jsonb(filters: &[Filter], column: &str) -> Result<String, TranslateError> {
+    postgres::to_postgres_jsonb(filters, column)
+}
+
+/// Translates filters into a JSONPath selecting the elements of an array that pass them, such as
+/// `$.orders[?(@.total > 100 && @.status == "paid")]`, for `JSON.GET` in RedisJSON, so a cache
+/// selects the same elements as applying the filters would.
+///
+/// # Arguments
+///
+/// * `filters` - A slice of Filters to translate.
+/// * `array` - The JSONPath of the array whose elements are filtered, such as `$.orders`, copied into the path as it is.
+///
+/// # Returns
+///
+/// * `Result<String, TranslateError>` - The JSONPath, or an error for the first clause that can't be translated exactly. Only comparisons of paths with literals are translated, as in [`to_sqlite_json1`], and `!=` only with numbers, strings and booleans.
+///
+pub fn to_jsonpath(filters: &[Filter], array: &str) -> Result<String, TranslateError> {
+    jsonpath::to_jsonpath(filters, array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let filter_string = ".field = 'hello' AND .value >= 20";
+        let filters = parse(filter_string).unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].field.as_deref(), Some("field"));
+        assert_eq!(filters[0].operator, "=");
+        assert_eq!(filters[0].value, Some(json!("hello")));
+        assert_eq!(filters[1].field.as_deref(), Some("value"));
+        assert_eq!(filters[1].operator, ">=");
+        assert_eq!(filters[1].value, Some(json!(20)));
+    }
+
+    #[test]
+    fn test_apply() {
+        let v = json!({ "field": 100, "hello": "world" });
+        let filters = vec![
+            Filter {
+                field: Some("field".into()),
+                operator: ">".into(),
+                value: Some(json!(50)),
+                ..Default::default()
+            },
+            Filter {
+                field: Some("hello".into()),
+                operator: "=".into(),
                 value: Some(json!("world")),
                 ..Default::default()
             },
         ];
         assert!(apply(&v, &filters));
     }
+
+    #[test]
+    fn test_apply_numeric_functions() {
+        let v = json!({ "delta": -0.02, "score": 4.6, "low": 3, "high": 9 });
+        assert!(apply(&v, &parse("abs(.delta) > 0.01").unwrap()));
+        assert!(apply(&v, &parse("round(.score) = 5").unwrap()));
+        assert!(apply(
+            &v,
+            &parse("floor(.score) = 4 AND ceil(.score) = 5").unwrap()
+        ));
+        assert!(apply(
+            &v,
+            &parse("max(.low, .high) = 9 AND min(.low, .high, 1) = 1").unwrap()
+        ));
+        assert!(!apply(&v, &parse("abs(.missing) > 0").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_aggregates() {
+        let v = json!({
+            "items": [{ "price": 300 }, { "price": 250.5 }],
+            "errors": [],
+            "samples": [0.2, 0.4],
+        });
+        let filters =
+            parse("sum(.items[*].price) > 500 AND count(.errors) = 0 AND avg(.samples) < 0.5")
+                .unwrap();
+        assert!(apply(&v, &filters));
+        assert!(!apply(&v, &parse("count(.items) > 2").unwrap()));
+        assert!(!apply(&v, &parse("avg(.errors) < 1").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_null_safe_paths() {
+        let v = json!({ "user": { "address": { "city": "NYC" } } });
+        assert!(apply(&v, &parse(".user?.address?.city = 'NYC'").unwrap()));
+        assert!(apply(&v, &parse(".user.address.city = 'NYC'").unwrap()));
+
+        let v = json!({ "user": {} });
+        assert!(!apply(&v, &parse(".user.address.city != 'NYC'").unwrap()));
+        assert!(apply(&v, &parse(".user?.address?.city != 'NYC'").unwrap()));
+        assert!(apply(&v, &parse(".user?.address?.city = null").unwrap()));
+        assert!(!apply(&v, &parse(".user?.address?.city >= 1").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_conditional() {
+        let filters = parse("if .type = 'bulk' then .qty > 100 else .qty > 10").unwrap();
+        assert_eq!(filters.len(), 1);
+        assert!(apply(&json!({ "type": "bulk", "qty": 150 }), &filters));
+        assert!(!apply(&json!({ "type": "bulk", "qty": 50 }), &filters));
+        assert!(apply(&json!({ "type": "retail", "qty": 50 }), &filters));
+        assert!(!apply(&json!({ "type": "retail", "qty": 5 }), &filters));
+
+        let filters = parse(
+            "if .vip = 'yes' then .total > 0 else (.total > 100 AND .paid = 'yes') AND .id > 0",
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 2);
+        assert!(apply(
+            &json!({ "vip": "yes", "total": 1, "id": 1 }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "vip": "no", "total": 200, "id": 1 }),
+            &filters
+        ));
+        assert!(apply(
+            &json!({ "vip": "no", "total": 200, "paid": "yes", "id": 1 }),
+            &filters
+        ));
+
+        let filters = parse("if .type = 'bulk' then .qty > 100").unwrap();
+        assert!(apply(&json!({ "type": "retail" }), &filters));
+
+        // Like `else`, `then` takes a single clause unless several are in parentheses.
+        let filters = parse("if .type = 'bulk' then .qty > 100 AND .id > 0").unwrap();
+        assert_eq!(filters.len(), 2);
+        assert!(!apply(&json!({ "type": "retail", "id": 0 }), &filters));
+        let filters = parse("if .type = 'bulk' then (.qty > 100 AND .id > 0)").unwrap();
+        assert_eq!(filters.len(), 1);
+        assert!(apply(&json!({ "type": "retail", "id": 0 }), &filters));
+        assert!(!apply(
+            &json!({ "type": "bulk", "qty": 150, "id": 0 }),
+            &filters
+        ));
+    }
+
+    #[test]
+    fn test_apply_quantified() {
+        let v = json!({ "orders": [
+            { "total": 150, "status": "paid" },
+            { "total": 80, "status": "paid" },
+        ] });
+        let any = parse("ANY .orders WHERE (.total > 100 AND .status = 'paid')").unwrap();
+        assert!(apply(&v, &any));
+        let all = parse("ALL .orders WHERE .total > 100").unwrap();
+        assert!(!apply(&v, &all));
+        assert!(apply(
+            &v,
+            &parse("ALL .orders WHERE .status = 'paid'").unwrap()
+        ));
+
+        // No element passes ANY, and every element passes ALL.
+        let empty = json!({ "orders": [] });
+        assert!(!apply(&empty, &any));
+        assert!(apply(&empty, &all));
+        assert!(!apply(&json!({ "orders": { "total": 150 } }), &any));
+        assert!(!apply(&json!({}), &all));
+    }
+
+    #[test]
+    fn test_apply_root_and_parent() {
+        let v = json!({ "budget": 100, "orders": [
+            { "limit": 20, "items": [{ "price": 30 }, { "price": 10 }] },
+            { "limit": 5, "items": [{ "price": 120 }] },
+        ] });
+        let filters = parse("ALL .orders WHERE ANY .items WHERE .price > $root.budget").unwrap();
+        assert!(!apply(&v, &filters));
+        let filters = parse("ANY .orders WHERE ANY .items WHERE .price > $root.budget").unwrap();
+        assert!(apply(&v, &filters));
+        let filters = parse("ALL .orders WHERE ANY .items WHERE .price < $parent.limit").unwrap();
+        assert!(!apply(&v, &filters));
+        let filters = parse("ANY .orders WHERE ANY .items WHERE .price < $parent.limit").unwrap();
+        assert!(apply(&v, &filters));
+
+        // Outside a quantifier, `$root` is the Value itself and `$parent` is missing.
+        assert!(apply(&v, &parse("$root.budget = 100").unwrap()));
+        assert!(!apply(&v, &parse("$parent.budget = 100").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_capture() {
+        let filters = parse(r"capture(.url, 'v(\d+)') = '2'").unwrap();
+        assert!(apply(&json!({ "url": "/api/v2/users" }), &filters));
+        assert!(!apply(&json!({ "url": "/api/v1/users" }), &filters));
+        assert!(!apply(&json!({ "url": "/api/users" }), &filters));
+        assert!(!apply(&json!({ "url": 2 }), &filters));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_apply_timestamps() {
+        let filters = parse(".created_at >= '2024-01-01T00:00:00Z'").unwrap();
+        assert!(apply(
+            &json!({ "created_at": "2024-01-01T00:00:00Z" }),
+            &filters
+        ));
+        assert!(apply(
+            &json!({ "created_at": "2024-03-05T10:30:00+02:00" }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "created_at": "2023-12-31T23:59:59Z" }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "created_at": "2024-01-01T00:30:00+01:00" }),
+            &filters
+        ));
+        assert!(!apply(&json!({ "created_at": "yesterday" }), &filters));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_apply_timezone() {
+        let filters = parse(".ts >= '2024-06-01'").unwrap();
+        let v = json!({ "ts": "2024-06-01T01:00:00+02:00" });
+        assert!(!apply(&v, &filters));
+        let options = ApplyOptions {
+            timezone: FixedOffset::east_opt(2 * 3600),
+            ..Default::default()
+        };
+        assert!(apply_with(&v, &filters, &options));
+        assert!(!apply_with(
+            &json!({ "ts": "2024-05-31T21:59:59Z" }),
+            &filters,
+            &options
+        ));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_apply_epoch_millis_coercion() {
+        let filters = parse(".ts >= '2024-01-01T00:00:00Z' AND .start < .ts").unwrap();
+        let v = json!({ "ts": 1_704_067_200_000_i64, "start": "2023-12-31T23:59:59Z" });
+        assert!(!apply(&v, &filters));
+        let options = ApplyOptions {
+            coerce_epoch_millis: true,
+            ..Default::default()
+        };
+        assert!(apply_with(&v, &filters, &options));
+        let v = json!({ "ts": 1_704_067_199_999_i64, "start": "2023-12-31T23:59:59Z" });
+        assert!(!apply_with(&v, &filters, &options));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_apply_relative_time() {
+        let options = ApplyOptions {
+            now: Some("2024-01-08T12:00:00Z".parse().unwrap()),
+            ..Default::default()
+        };
+        let filters = parse(".created_at > now() - 7d").unwrap();
+        let v = json!({ "created_at": "2024-01-02T00:00:00Z" });
+        assert!(apply_with(&v, &filters, &options));
+        let v = json!({ "created_at": "2024-01-01T00:00:00Z" });
+        assert!(!apply_with(&v, &filters, &options));
+
+        let filters =
+            parse(".expires_at <= .issued_at + 1h AND .issued_at_ms + 30m = .expires_at_ms")
+                .unwrap();
+        let v = json!({
+            "issued_at": "2024-01-01T10:00:00+02:00",
+            "expires_at": "2024-01-01T09:00:00Z",
+            "issued_at_ms": 0,
+            "expires_at_ms": 1_800_000,
+        });
+        assert!(apply(&v, &filters));
+    }
+
+    #[test]
+    fn test_apply_semver() {
+        let filters = parse(".version SEMVER>= '1.4.0'").unwrap();
+        assert!(apply(&json!({ "version": "1.10.2" }), &filters));
+        assert!(apply(&json!({ "version": "v1.4" }), &filters));
+        assert!(!apply(&json!({ "version": "1.4.0-rc.1" }), &filters));
+        assert!(!apply(&json!({ "version": "1.3.9" }), &filters));
+        assert!(!apply(&json!({ "version": "nightly" }), &filters));
+        assert!(apply(
+            &json!({ "a": "2.0.0+b1", "b": "2.0.0" }),
+            &parse(".a SEMVER= .b").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_apply_in_cidr() {
+        let filters = parse(".client_ip IN_CIDR '10.0.0.0/8'").unwrap();
+        assert!(apply(&json!({ "client_ip": "10.20.30.40" }), &filters));
+        assert!(!apply(&json!({ "client_ip": "172.16.0.1" }), &filters));
+        assert!(!apply(&json!({ "client_ip": "not an ip" }), &filters));
+
+        let filters = parse(".client_ip IN_CIDR 'fd00::/8'").unwrap();
+        assert!(apply(&json!({ "client_ip": "fd12:3456::1" }), &filters));
+        assert!(!apply(&json!({ "client_ip": "10.20.30.40" }), &filters));
+    }
+
+    #[test]
+    fn test_apply_uuid() {
+        let filters =
+            parse(".id = '67e55044-10b1-426f-9247-bb680e5fe0c8' AND .id IS UUID").unwrap();
+        assert!(apply(
+            &json!({ "id": "67E5504410B1426F9247BB680E5FE0C8" }),
+            &filters
+        ));
+        assert!(apply(
+            &json!({ "id": "{67e55044-10b1-426f-9247-bb680e5fe0c8}" }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "id": "67e55044-10b1-426f-9247-bb680e5fe0c9" }),
+            &filters
+        ));
+
+        let filters = parse(".id IS NOT UUID").unwrap();
+        assert!(apply(&json!({ "id": "user-42" }), &filters));
+        assert!(apply(&json!({ "id": 42 }), &filters));
+        assert!(!apply(
+            &json!({ "id": "67e55044-10b1-426f-9247-bb680e5fe0c8" }),
+            &filters
+        ));
+        assert!(!apply(&json!({}), &filters));
+    }
+
+    #[test]
+    fn test_apply_contains() {
+        let v = json!({ "tags": ["rust", "json"], "ports": [80, 443.0], "flags": [false], "tag": "rust" });
+        assert!(apply(&v, &parse(".tags CONTAINS 'rust'").unwrap()));
+        assert!(!apply(&v, &parse(".tags CONTAINS 'go'").unwrap()));
+        assert!(apply(&v, &parse(".ports CONTAINS 443").unwrap()));
+        assert!(apply(&v, &parse(".flags CONTAINS false").unwrap()));
+        assert!(!apply(&v, &parse(".flags CONTAINS true").unwrap()));
+        assert!(!apply(&v, &parse(".tag CONTAINS 'rust'").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_keys_and_values() {
+        let v =
+            json!({ "labels": { "team": "core" }, "env": { "EU": "production", "US": "staging" } });
+        assert!(apply(&v, &parse("keys(.labels) CONTAINS 'team'").unwrap()));
+        assert!(apply(
+            &v,
+            &parse("values(.env) CONTAINS 'production'").unwrap()
+        ));
+        assert!(!apply(
+            &v,
+            &parse("keys(.env) CONTAINS 'production'").unwrap()
+        ));
+        assert!(apply(&v, &parse("count(keys(.env)) = 2").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_type() {
+        let filters = parse("type(.payload) = 'object' AND type(.id) != 'number'").unwrap();
+        assert!(apply(&json!({ "payload": {}, "id": "a1" }), &filters));
+        assert!(!apply(&json!({ "payload": [], "id": "a1" }), &filters));
+        assert!(!apply(&json!({ "payload": {}, "id": 1 }), &filters));
+        assert!(!apply(&json!({ "id": "a1" }), &filters));
+        assert!(apply(
+            &json!({ "p": null }),
+            &parse("type(.p?.q) = 'null'").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_apply_array_sets() {
+        let v = json!({ "roles": ["admin", "billing", "support"], "role": "admin" });
+        assert!(apply(
+            &v,
+            &parse(".roles HAS_ALL ('admin', 'billing')").unwrap()
+        ));
+        assert!(!apply(
+            &v,
+            &parse(".roles HAS_ALL ('admin', 'ops')").unwrap()
+        ));
+        assert!(apply(&v, &parse(".roles HAS_ANY ('ops', .role)").unwrap()));
+        assert!(!apply(&v, &parse(".roles HAS_ANY ('ops', 'dev')").unwrap()));
+        assert!(!apply(
+            &v,
+            &parse(".roles SUBSET_OF ('admin', 'billing')").unwrap()
+        ));
+        let filters = parse(".roles SUBSET_OF ('admin', 'billing', 'support', 'ops')").unwrap();
+        assert!(apply(&v, &filters));
+        assert!(!apply(&v, &parse(".role HAS_ANY ('admin')").unwrap()));
+        assert!(apply(&v, &parse(".role IN ('ops', 'admin')").unwrap()));
+        assert!(apply(&v, &parse(".role IN ('admin')").unwrap()));
+        assert!(!apply(&v, &parse(".role IN ('ops', 'dev')").unwrap()));
+        assert!(!apply(
+            &v,
+            &parse(".roles IN ('admin', 'billing')").unwrap()
+        ));
+        assert!(!apply(&v, &parse(".role IN 'admin'").unwrap()));
+        assert!(apply(&json!({ "n": 2.0 }), &parse(".n IN (1, 2)").unwrap()));
+        assert!(!apply(&json!({}), &parse(".n IN (1, null)").unwrap()));
+    }
+
+    #[test]
+    fn test_apply_deep_eq() {
+        let filters =
+            parse(r#".config DEEP_EQ {"retries": 3, "tls": true, "hosts": ["a", "b"]}"#).unwrap();
+        let v = json!({ "config": { "hosts": ["a", "b"], "tls": true, "retries": 3.0 } });
+        assert!(apply(&v, &filters));
+        let v = json!({ "config": { "hosts": ["b", "a"], "tls": true, "retries": 3 } });
+        assert!(!apply(&v, &filters));
+        let v =
+            json!({ "config": { "hosts": ["a", "b"], "tls": true, "retries": 3, "debug": false } });
+        assert!(!apply(&v, &filters));
+        assert!(apply(
+            &json!({ "a": [1, { "b": null }] }),
+            &parse(r#".a DEEP_EQ [1, {"b": null}]"#).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_apply_unordered_arrays() {
+        let filters = parse(".ids DEEP_EQ [1, 2, [3, 4]]").unwrap();
+        let v = json!({ "ids": [[4, 3], 1, 2] });
+        assert!(!apply(&v, &filters));
+        let options = ApplyOptions {
+            unordered_arrays: true,
+            ..Default::default()
+        };
+        assert!(apply_with(&v, &filters, &options));
+        assert!(!apply_with(
+            &json!({ "ids": [1, 1, [3, 4]] }),
+            &filters,
+            &options
+        ));
+        let filters = parse(r#".labels MATCHES_OBJECT {"zones": ["b", "a"]}"#).unwrap();
+        let v = json!({ "labels": { "zones": ["a", "b"], "app": "web" } });
+        assert!(apply_with(&v, &filters, &options));
+    }
+
+    #[test]
+    fn test_apply_matches_object() {
+        let filters =
+            parse(r#".labels MATCHES_OBJECT {"app": "web", "tier": {"name": "front"}}"#).unwrap();
+        let v = json!({ "labels": { "app": "web", "env": "prod", "tier": { "name": "front", "zone": 2 } } });
+        assert!(apply(&v, &filters));
+        let v = json!({ "labels": { "app": "api", "tier": { "name": "front" } } });
+        assert!(!apply(&v, &filters));
+        assert!(!apply(&json!({ "labels": { "app": "web" } }), &filters));
+        assert!(!apply(&json!({ "labels": "app=web" }), &filters));
+    }
+
+    #[test]
+    fn test_apply_booleans() {
+        let filters = parse(".active = true AND .deleted != true").unwrap();
+        assert!(apply(
+            &json!({ "active": true, "deleted": false }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "active": "true", "deleted": false }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "active": true, "deleted": true }),
+            &filters
+        ));
+    }
+
+    #[test]
+    fn test_apply_fuzzy() {
+        let filters = parse(".name FUZZY('jonh', 1)").unwrap();
+        assert!(apply(&json!({ "name": "John" }), &filters));
+        assert!(apply(&json!({ "name": "jon" }), &filters));
+        assert!(!apply(&json!({ "name": "Joan" }), &filters));
+        assert!(!apply(&json!({ "name": 7 }), &filters));
+
+        let filters = parse(".name FUZZY(.alias, 2)").unwrap();
+        assert!(apply(&json!({ "name": "Jane", "alias": "Jan" }), &filters));
+    }
+
+    #[cfg(feature = "geo")]
+    #[test]
+    fn test_apply_within() {
+        let filters = parse(".location WITHIN 5km OF (52.52, 13.40)").unwrap();
+        // About 2.7km from the center.
+        assert!(apply(
+            &json!({ "location": { "lat": 52.5, "lon": 13.43 } }),
+            &filters
+        ));
+        assert!(apply(&json!({ "location": [13.43, 52.5] }), &filters));
+        // Potsdam, about 27km away.
+        assert!(!apply(
+            &json!({ "location": { "lat": 52.39, "lon": 13.06 } }),
+            &filters
+        ));
+        assert!(!apply(&json!({ "location": "Berlin" }), &filters));
+        assert!(!apply(&json!({}), &filters));
+    }
+
+    #[test]
+    fn test_apply_matches_text() {
+        let filters = parse(".body MATCHES_TEXT 'json filter'").unwrap();
+        let v = json!({ "body": "Filtering JSON, fast." });
+        assert!(apply(
+            &json!({ "body": "A JSON filter for Rust" }),
+            &filters
+        ));
+        assert!(!apply(&v, &filters));
+        let options = ApplyOptions {
+            stem_text: true,
+            ..Default::default()
+        };
+        assert!(apply_with(&v, &filters, &options));
+        assert!(!apply_with(&json!({ "body": 42 }), &filters, &options));
+    }
+
+    #[test]
+    fn test_apply_select() {
+        let filters = parse(".age >= 18").unwrap();
+        let projection = Projection::new([".id", ".user.name"]);
+        let v =
+            json!({ "id": 7, "age": 30, "user": { "name": "ada", "email": "ada@example.com" } });
+        assert_eq!(
+            apply_select(&v, &filters, &projection),
+            Some(json!({ "id": 7, "user": { "name": "ada" } }))
+        );
+        assert_eq!(
+            apply_select(&json!({ "id": 8, "age": 12 }), &filters, &projection),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let filters = parse(".stock > 0").unwrap();
+        let mut values: Vec<Value> = vec![
+            json!({ "sku": "a", "stock": 3, "price": 20 }),
+            json!({ "sku": "b", "stock": 0, "price": 5 }),
+            json!({ "sku": "c", "stock": 1, "price": 7 }),
+            json!({ "sku": "d", "stock": 2 }),
+        ]
+        .into_iter()
+        .filter(|v| apply(v, &filters))
+        .collect();
+        sort_by(&mut values, &[SortKey::asc(".price").nulls(Nulls::First)]);
+        let skus: Vec<&str> = values.iter().map(|v| v["sku"].as_str().unwrap()).collect();
+        assert_eq!(skus, ["d", "c", "a"]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_group_by() {
+        let values = [
+            json!({ "region": "eu", "amount": 10, "refunded": false }),
+            json!({ "region": "us", "amount": 5, "refunded": false }),
+            json!({ "region": "eu", "amount": 7, "refunded": true }),
+            json!({ "region": "eu", "amount": 4, "refunded": false }),
+        ];
+        let filters = parse(".refunded = false").unwrap();
+        let aggregations = [
+            Aggregation::count("sales"),
+            Aggregation::sum("total", ".amount"),
+        ];
+        assert_eq!(
+            group_by(&values, &filters, ".region", &aggregations),
+            json!([
+                { "key": "eu", "sales": 2, "total": 14 },
+                { "key": "us", "sales": 1, "total": 5 },
+            ])
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_join() {
+        let orders = [
+            json!({ "id": 1, "customer_id": 10, "total": 80 }),
+            json!({ "id": 2, "customer_id": 20, "total": 90 }),
+        ];
+        let customers = [
+            json!({ "customer_id": 10, "tier": "gold" }),
+            json!({ "customer_id": 20, "tier": "basic" }),
+        ];
+        let filters = parse(".tier = 'gold' AND .total > 50").unwrap();
+        let matching: Vec<Value> = join(
+            &orders,
+            &customers,
+            ".customer_id",
+            ".customer_id",
+            JoinKind::Inner,
+        )
+        .into_iter()
+        .filter(|v| apply(v, &filters))
+        .collect();
+        assert_eq!(
+            matching,
+            [json!({ "id": 1, "customer_id": 10, "total": 80, "tier": "gold" })]
+        );
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let mut values = vec![
+            json!({ "id": 1, "status": "open", "age_days": 40 }),
+            json!({ "id": 2, "status": "open", "age_days": 3 }),
+        ];
+        let filters = parse(".status = 'open' AND .age_days > 30").unwrap();
+        let update = Update::new()
+            .set(".status", json!("archived"))
+            .merge(json!({ "meta": { "archived_by": "bot" }, "age_days": null }));
+        assert_eq!(apply_update(&mut values, &filters, &update), 1);
+        assert_eq!(
+            values,
+            [
+                json!({ "id": 1, "status": "archived", "meta": { "archived_by": "bot" } }),
+                json!({ "id": 2, "status": "open", "age_days": 3 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_matching_at() {
+        let mut v = json!({
+            "orders": [
+                { "items": [{ "sku": "a", "qty": 0 }, { "sku": "b", "qty": 2 }] },
+                { "items": [{ "sku": "c", "qty": 0 }] },
+                { "items": "none" },
+            ],
+        });
+        let filters = parse(".qty = 0").unwrap();
+        assert_eq!(remove_matching_at(&mut v, ".orders[*].items", &filters), 2);
+        assert_eq!(
+            v,
+            json!({
+                "orders": [
+                    { "items": [{ "sku": "b", "qty": 2 }] },
+                    { "items": [] },
+                    { "items": "none" },
+                ],
+            })
+        );
+        assert_eq!(remove_matching_at(&mut v, ".missing", &filters), 0);
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let values = [
+            json!({ "id": 1, "status": "open" }),
+            json!({ "id": 2, "status": "done" }),
+        ];
+        let filters = parse(".status = 'open'").unwrap();
+        let patch =
+            Patch::json(&json!([{ "op": "replace", "path": "/status", "value": "closed" }]))
+                .unwrap();
+        assert_eq!(
+            apply_patch(&values, &filters, &patch),
+            Ok(vec![json!({ "id": 1, "status": "closed" })])
+        );
+
+        let v = json!({ "items": [{ "sku": "a", "qty": 0 }, { "sku": "b", "qty": 2 }] });
+        let patch = Patch::Merge(json!({ "out_of_stock": true }));
+        assert_eq!(
+            apply_patch_at(&[v], ".items[*]", &parse(".qty = 0").unwrap(), &patch),
+            Ok(vec![
+                json!({ "items": [{ "sku": "a", "qty": 0, "out_of_stock": true }, { "sku": "b", "qty": 2 }] })
+            ])
+        );
+    }
+
+    #[test]
+    fn test_compile() {
+        let filters = parse(".name MATCHES_TEXT 'ada' AND .age > 30").unwrap();
+        let compiled = compile(parse(".name MATCHES_TEXT 'ada' AND .age > 30").unwrap());
+        for v in [
+            json!({ "name": "Ada Lovelace", "age": 36 }),
+            json!({ "name": "Ada Lovelace", "age": 20 }),
+            json!({ "name": "Grace Hopper", "age": 85 }),
+        ] {
+            assert_eq!(compiled.matches(&v), apply(&v, &filters));
+        }
+        let plan = explain(&compiled);
+        assert_eq!(
+            plan.steps[0].access,
+            Access::Index {
+                field: ".age".to_string()
+            }
+        );
+        assert_eq!(plan.steps[1].access, Access::Scan);
+        assert_eq!(plan.cost, 9);
+    }
+
+    #[test]
+    fn test_apply_with_stats() {
+        let filters = parse(".status = 'paid' AND .total > 100").unwrap();
+        let mut stats = FilterStats::default();
+        for total in [50, 150, 250] {
+            let v = json!({ "status": "paid", "total": total });
+            apply_with_stats(&v, &filters, &ApplyOptions::default(), &mut stats);
+        }
+        assert_eq!((stats.values, stats.passed), (3, 2));
+        assert_eq!(stats.clauses[0].rejected, 0);
+        assert_eq!(stats.clauses[1].rejected, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_lint() {
+        let filters = parse(".name > 'm' AND .age = 18 AND .age = 18").unwrap();
+        let kinds: Vec<LintKind> = lint(&filters).iter().map(|w| w.kind).collect();
+        assert_eq!(kinds, [LintKind::StringOrdering, LintKind::DuplicateClause]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_validate() {
+        let filters = parse(".status = 'paid' AND .totl > 100").unwrap();
+        let sample = json!({ "status": "open", "total": 5 });
+        let issues = validate(&filters, Shape::Sample(&sample));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::UnknownField);
+        assert_eq!(
+            issues[0].to_string(),
+            ".totl > 100: unknown field `.totl` (did you mean `.total`?)"
+        );
+        let schema = json!({ "type": "object", "properties": { "status": { "type": "number" } } });
+        let issues = validate(&filters[..1], Shape::Schema(&schema));
+        assert_eq!(issues[0].kind, IssueKind::TypeMismatch);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_suggest_field() {
+        let v = json!({ "user": { "createdAt": "2024-01-01", "name": "Ada" } });
+        assert_eq!(
+            suggest_field(&v, ".user.created_at").as_deref(),
+            Some(".user.createdAt")
+        );
+        assert_eq!(
+            suggest_field(&v, ".usr.name").as_deref(),
+            Some(".user.name")
+        );
+        assert_eq!(suggest_field(&v, ".user.name"), None);
+        assert_eq!(suggest_field(&v, ".user.email"), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        let sample = json!({ "status": "paid", "total": 10 });
+        let suggestions = complete(".status = 'paid' AND .t", 23, &sample);
+        assert_eq!(
+            suggestions,
+            [Suggestion {
+                kind: SuggestionKind::Field,
+                text: ".total".to_string(),
+                replace: 21..23,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format("status==paid AND  .n>=2").unwrap(),
+            ".status = 'paid' AND .n >= 2"
+        );
+        assert_eq!(
+            format_pretty("status==paid AND  .n>=2", 20).unwrap(),
+            ".status = 'paid'\nAND .n >= 2"
+        );
+        assert!(format(".a >").is_err());
+    }
+
+    #[test]
+    fn test_tokens() {
+        let kinds: Vec<SyntaxKind> = tokens(".age >= 18 AND .name = 'Ada'")
+            .iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                SyntaxKind::Field,
+                SyntaxKind::Operator,
+                SyntaxKind::Number,
+                SyntaxKind::Keyword,
+                SyntaxKind::Field,
+                SyntaxKind::Operator,
+                SyntaxKind::String,
+            ]
+        );
+        assert_eq!(tokens(".age >= 18")[2].span, Span { start: 8, end: 10 });
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let partial = parse_partial(".status = 'paid' AND .price >");
+        assert!(!partial.is_complete());
+        assert!(apply(&json!({ "status": "paid" }), &partial.filters));
+        assert_eq!(partial.expected, [Expected::Value]);
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let filters = parse(
+            "# Orders that are ready to ship
+            .status = 'paid' /* not 'pending' */
+            AND .note = '# 1' # a hash in a string isn't a comment",
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 2);
+        assert!(apply(&json!({ "status": "paid", "note": "# 1" }), &filters));
+        assert!(parse(".a = 1 /* unclosed").is_none());
+        assert_eq!(format(".a=1 # one").unwrap(), ".a = 1");
+    }
+
+    #[test]
+    fn test_parse_multiline() {
+        let filters = parse(
+            ".status = 'paid'
+            AND .total > 100 \\
+            AND .tags HAS_ANY (
+                'a',
+                'b'
+            )",
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 3);
+        assert!(apply(
+            &json!({ "status": "paid", "total": 150, "tags": ["b"] }),
+            &filters
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_expand_env() {
+        std::env::set_var("SIMPLE_JSON_FILTER_TEST_SLO_MS", "250");
+        let expanded = expand_env(".latency > ${SIMPLE_JSON_FILTER_TEST_SLO_MS}").unwrap();
+        let filters = parse(&expanded).unwrap();
+        assert!(apply(&json!({ "latency": 300 }), &filters));
+        assert!(!apply(&json!({ "latency": 200 }), &filters));
+        assert!(expand_env(".n > ${SIMPLE_JSON_FILTER_TEST_UNSET}").is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_with_vars() {
+        let vars = HashMap::from([
+            ("min".to_string(), json!(100)),
+            ("status".to_string(), json!("paid")),
+        ]);
+        let filters = parse_with_vars(".total > {{min}} AND .status = {{status}}", &vars).unwrap();
+        assert!(apply(&json!({ "total": 150, "status": "paid" }), &filters));
+        assert!(!apply(&json!({ "total": 50, "status": "paid" }), &filters));
+        assert!(parse_with_vars(".total > {{max}}", &vars).is_err());
+    }
+
+    #[test]
+    fn test_parse_with_limits() {
+        let limits = ParseLimits {
+            max_clauses: 2,
+            ..ParseLimits::default()
+        };
+        let filters = parse_with_limits(".a = 1 AND .b = 2", &limits).unwrap();
+        assert!(apply(&json!({ "a": 1, "b": 2 }), &filters));
+        assert!(parse_with_limits(".a = 1 AND .b = 2 AND .c = 3", &limits).is_err());
+        let deep = format!(".a = {}1{}", "abs(".repeat(20), ")".repeat(20));
+        assert!(parse(&deep).is_some());
+        assert!(parse_with_limits(&deep, &ParseLimits::default()).is_err());
+    }
+
+    /// Records the names of spans and the messages of events.
+    #[cfg(feature = "tracing")]
+    struct Recorder(std::sync::Mutex<Vec<String>>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut records = self.0.lock().unwrap();
+            records.push(format!("span {}", span.metadata().name()));
+            tracing::span::Id::from_u64(records.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Fields(String);
+            impl tracing::field::Visit for Fields {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    self.0 += &format!(" {}={:?}", field.name(), value);
+                }
+            }
+            let mut fields = Fields(String::from("event"));
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        let recorder = std::sync::Arc::new(Recorder(Default::default()));
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let filters = parse(".a = 1 AND .b = 2").unwrap();
+            let compiled = compile(filters);
+            assert!(!compiled.matches(&json!({ "a": 1, "b": 3 })));
+            assert!(parse(".a =").is_none());
+        });
+        let records = recorder.0.lock().unwrap();
+        assert_eq!(
+            records[..6],
+            [
+                "span parse",
+                "event message=parsed filter clauses=2",
+                "span compile",
+                "event message=compiled filter cost=4 order=.a = 1 AND .b = 2",
+                "span apply",
+                "event message=clause rejected the value clause=.b = 2",
+            ]
+        );
+        assert_eq!(
+            records[7],
+            "event message=invalid filter error=expected a value at 4..4"
+        );
+    }
+
+    #[test]
+    fn test_apply_observed() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct Counters {
+            evaluated: AtomicUsize,
+            matched: AtomicUsize,
+            rejected: [AtomicUsize; 2],
+        }
+
+        impl EvalObserver for Counters {
+            fn evaluated(&self, passed: bool) {
+                self.evaluated.fetch_add(1, Ordering::Relaxed);
+                if passed {
+                    self.matched.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            fn rejected(&self, index: usize, _: &Filter) {
+                self.rejected[index].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let filters = parse(".a = 1 AND .b = 2").unwrap();
+        let counters = Counters::default();
+        let options = ApplyOptions::default();
+        for v in [
+            json!({ "a": 1, "b": 2 }),
+            json!({ "a": 2 }),
+            json!({ "a": 1 }),
+        ] {
+            apply_observed(&v, &filters, &options, &counters);
+        }
+        assert!(!compile(filters).matches_observed(&json!({ "a": 1 }), &options, &counters));
+        assert_eq!(counters.evaluated.load(Ordering::Relaxed), 4);
+        assert_eq!(counters.matched.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.rejected[0].load(Ordering::Relaxed), 1);
+        assert_eq!(counters.rejected[1].load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_filter_into_owned() {
+        let filter_string = String::from(".user.name = 'ada' AND ANY .items WHERE .qty > 1");
+        let filters: Vec<Filter<'static>> = parse(&filter_string)
+            .unwrap()
+            .into_iter()
+            .map(Filter::into_owned)
+            .collect();
+        drop(filter_string);
+        assert!(apply(
+            &json!({ "user": { "name": "ada" }, "items": [] }),
+            &filters[..1]
+        ));
+        assert_eq!(
+            format::Clause(&filters[1]).to_string(),
+            "ANY .items WHERE (.qty > 1)"
+        );
+        // A name in several filters is copied once.
+        let filters = Filter::into_owned_all(parse(".qty > 1 AND .qty < 5").unwrap());
+        let field = |filter: &Filter| match &filter.field {
+            Some(Name::Shared(name)) => std::sync::Arc::clone(name),
+            field => panic!("{field:?} isn't shared"),
+        };
+        assert!(std::sync::Arc::ptr_eq(
+            &field(&filters[0]),
+            &field(&filters[1])
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_str() {
+        let filters = parse(".user.name = 'ada' AND .n > 1").unwrap();
+        let options = ApplyOptions::default();
+        let json = r#"{"user": {"name": "ada"}, "blob": [[1, 2], {"x": "y"}], "n": 2}"#;
+        assert!(apply_str(json, &filters, &options).unwrap());
+        assert!(!apply_str(r#"{"user": null, "n": 2}"#, &filters, &options).unwrap());
+        assert!(apply_str(r#"{"n": 2, "blob": [}"#, &filters, &options).is_err());
+        let raw: Box<serde_json::value::RawValue> = serde_json::from_str(json).unwrap();
+        assert!(apply_str(raw.get(), &filters, &options).unwrap());
+        let filters = parse(". = 1").unwrap();
+        assert!(apply_str("1", &filters, &options).unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_elements() {
+        let filters = parse(".age > 30").unwrap();
+        let v = json!([{ "age": 42 }, { "age": 20 }, 35, { "age": 31 }]);
+        assert!(!apply(&v, &filters));
+        assert_eq!(apply_elements(&v, &filters), [true, false, false, true]);
+        assert_eq!(apply_elements(&json!([]), &filters), Vec::<bool>::new());
+        assert_eq!(apply_elements(&json!({ "age": 42 }), &filters), [true]);
+    }
+
+    #[test]
+    fn test_score() {
+        let filters = parse(".a = 1 AND .b = 2 AND .c = 3 AND .d = 4").unwrap();
+        assert_eq!(
+            score(&json!({ "a": 1, "b": 2, "c": 3, "d": 4 }), &filters),
+            1.0
+        );
+        assert_eq!(score(&json!({ "a": 1, "c": 3 }), &filters), 0.5);
+        assert_eq!(score(&json!({ "b": 2, "d": 5 }), &filters), 0.25);
+        assert_eq!(score(&json!({}), &filters), 0.0);
+        assert_eq!(score(&json!({}), &[]), 1.0);
+
+        let filters = parse(".a = 1 WEIGHT 3 AND .b = 2 AND .c = 3 WEIGHT 0").unwrap();
+        assert_eq!(score(&json!({ "a": 1, "c": 3 }), &filters), 0.75);
+        assert_eq!(score(&json!({ "b": 2 }), &filters), 0.25);
+        // Weights only count in scores.
+        assert!(!apply(&json!({ "a": 1, "b": 2 }), &filters));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_should() {
+        let filters =
+            parse(".in_stock = true AND SHOULD .brand = 'acme' WEIGHT 2 AND SHOULD .color = 'red'")
+                .unwrap();
+        let values = [
+            json!({ "in_stock": true, "brand": "acme", "color": "red" }),
+            json!({ "in_stock": true, "brand": "other", "color": "red" }),
+            json!({ "in_stock": true }),
+            json!({ "in_stock": false, "brand": "acme", "color": "red" }),
+        ];
+        let passed: Vec<bool> = values.iter().map(|v| apply(v, &filters)).collect();
+        assert_eq!(passed, [true, true, true, false]);
+        let scores: Vec<f64> = values.iter().map(|v| score(v, &filters)).collect();
+        assert_eq!(scores, [1.0, 0.5, 0.25, 0.75]);
+        let map = values[2].as_object().unwrap();
+        assert!(apply_map(map, &filters));
+    }
+
+    #[test]
+    fn test_apply_weighted() {
+        let filters =
+            parse("MATCH >= 0.7 WHERE (.title FUZZY('rust', 1) WEIGHT 2 AND .lang = 'en' AND .year > 2020)")
+                .unwrap();
+        assert!(!apply(
+            &json!({ "title": "java", "lang": "en", "year": 1999 }),
+            &filters
+        ));
+        assert!(apply(
+            &json!({ "title": "rust", "lang": "fr", "year": 2021 }),
+            &filters
+        ));
+        assert!(apply(
+            &json!({ "title": "rustc", "lang": "en", "year": 2000 }),
+            &filters
+        ));
+        assert!(!apply(
+            &json!({ "title": "go", "lang": "en", "year": 2021 }),
+            &filters
+        ));
+        let filters = parse("MATCH < 0.5 WHERE (.a = 1 AND .b = 1)").unwrap();
+        assert!(apply(&json!({}), &filters));
+        assert!(!apply(&json!({ "a": 1 }), &filters));
+    }
+
+    #[test]
+    fn test_matching_indices() {
+        let values = [
+            json!({ "n": 1 }),
+            json!({ "n": 5 }),
+            json!({}),
+            json!({ "n": 7 }),
+        ];
+        assert_eq!(matching_indices(&values, &parse(".n > 2").unwrap()), [1, 3]);
+        assert!(matching_indices(&[], &parse(".n > 2").unwrap()).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_map() {
+        let v = json!({ "name": "ann", "age": 42, "limit": 40, "tags": ["a"], "x": { "y": 1 } });
+        let map = v.as_object().unwrap();
+        for filter_string in [
+            ".age > 40 AND .name = 'ann'",
+            ".age > .limit AND .age IS NOT UUID",
+            ".age > 2*.limit",
+            ".tags CONTAINS 'a' AND .x.y = 1 AND abs(.age) < 50",
+            "ANY .tags WHERE . = 'a'",
+            ". MATCHES_OBJECT {\"x\": {\"y\": 1}}",
+            ".missing = 1",
+        ] {
+            let filters = parse(filter_string).unwrap();
+            assert_eq!(
+                apply_map(map, &filters),
+                apply(&v, &filters),
+                "{filter_string}"
+            );
+        }
+        let mut partial = Map::new();
+        partial.insert("age".to_string(), json!(42));
+        assert!(apply_map(&partial, &parse(".age > 40").unwrap()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_many() {
+        let routes: Vec<CompiledFilter> = [
+            ".kind = 'order' AND .total > 100",
+            ".kind = 'order'",
+            "ANY .items WHERE .sku = 'a1'",
+            ".region = 'eu'",
+        ]
+        .into_iter()
+        .map(|filter_string| compile(parse(filter_string).unwrap()))
+        .collect();
+        let options = ApplyOptions::default();
+        let json = r#"{"kind": "order", "total": 50, "items": [{"sku": "a1"}], "log": [1, 2]}"#;
+        assert_eq!(apply_many(json, &routes, &options).unwrap(), [1, 2]);
+        assert!(apply_many(json, &[], &options).unwrap().is_empty());
+        assert!(apply_many("{", &routes, &options).is_err());
+        let whole = [compile(parse(". = 1").unwrap())];
+        assert_eq!(apply_many("1", &whole, &options).unwrap(), [0]);
+    }
+
+    #[test]
+    fn test_apply_coalesce() {
+        let filters = parse("coalesce(.nickname, .name) = 'bob'").unwrap();
+        assert!(apply(&json!({ "name": "bob" }), &filters));
+        assert!(apply(&json!({ "nickname": null, "name": "bob" }), &filters));
+        assert!(!apply(
+            &json!({ "nickname": "rob", "name": "bob" }),
+            &filters
+        ));
+
+        let filters = parse(".nickname // .name // 'anonymous' = 'anonymous'").unwrap();
+        assert!(apply(&json!({}), &filters));
+    }
+
+    #[test]
+    fn test_apply_optional() {
+        let filters = parse(".retries? > 3 AND .user.age? >= 18 AND .kind = 'job'").unwrap();
+        let values = [
+            json!({ "kind": "job" }),
+            json!({ "kind": "job", "retries": 5, "user": { "age": 20 } }),
+            json!({ "kind": "job", "retries": 1 }),
+            json!({ "kind": "job", "retries": null }),
+            json!({ "retries": 5 }),
+        ];
+        let passed: Vec<bool> = values.iter().map(|v| apply(v, &filters)).collect();
+        assert_eq!(passed, [true, true, false, false, false]);
+        #[cfg(feature = "std")]
+        assert!(apply_map(values[0].as_object().unwrap(), &filters));
+        assert!(apply(&json!({}), &parse(".id? IS UUID").unwrap()));
+        assert_eq!(
+            parse_with_limits("abs(.n?) > 1", &ParseLimits::default())
+                .unwrap_err()
+                .message,
+            "`?` after a path is only allowed before the operator of a comparison, as in `.field? > 5`"
+        );
+        assert!(to_sqlite_json1(&filters, "data").is_err());
+    }
 }
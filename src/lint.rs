@@ -0,0 +1,255 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::eval::Context;
+use crate::expr::{Arena, Expr};
+use crate::format::Clause;
+use crate::parser::OPERATORS;
+use crate::validate::orderable;
+use crate::{ApplyOptions, Compound, Filter};
+
+/// A suspicious construct [`crate::lint`] found in a clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    /// What kind of construct it is.
+    pub kind: LintKind,
+    /// The clause with the construct, written in filter string syntax.
+    pub clause: String,
+    /// A description of the construct.
+    pub message: String,
+}
+
+/// The kinds of [`LintWarning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// The operator isn't one the evaluator knows, so the clause never passes.
+    UnknownOperator,
+    /// A string is ordered with `>`, `>=`, `<` or `<=` against a string that is neither a UUID nor a
+    /// timestamp, so the clause never passes.
+    StringOrdering,
+    /// The clause doesn't depend on the Value, and always passes.
+    AlwaysTrue,
+    /// The clause doesn't depend on the Value, and never passes.
+    AlwaysFalse,
+    /// The same clause appears earlier among the clauses it is joined with.
+    DuplicateClause,
+    /// Values of different types are compared or combined, such as a list of strings and numbers, or
+    /// a string in arithmetic. Whether a quoted number like `'18'` is a mistake depends on the field,
+    /// so that is left to [`crate::validate`].
+    MixedTypes,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.clause, self.message)
+    }
+}
+
+pub(crate) fn lint(filters: &[Filter]) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    lint_all(filters, &mut warnings);
+    warnings
+}
+
+fn lint_all(filters: &[Filter], warnings: &mut Vec<LintWarning>) {
+    let clauses: Vec<String> = filters.iter().map(|f| Clause(f).to_string()).collect();
+    for (i, filter) in filters.iter().enumerate() {
+        let mut warn = |kind: LintKind, message: String| {
+            warnings.push(LintWarning {
+                kind,
+                clause: clauses[i].clone(),
+                message,
+            })
+        };
+        if clauses[..i].contains(&clauses[i]) {
+            warn(
+                LintKind::DuplicateClause,
+                "duplicates an earlier clause".to_string(),
+            );
+        }
+        match &filter.compound {
+            Some(Compound::Quantified { filters, .. } | Compound::Match { filters, .. }) => {
+                lint_all(filters, warnings)
+            }
+            Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                lint_all(condition, warnings);
+                lint_all(then, warnings);
+                lint_all(otherwise, warnings);
+            }
+            Some(_) => {}
+            None => lint_comparison(filter, &mut warn),
+        }
+    }
+}
+
+fn lint_comparison(filter: &Filter, warn: &mut impl FnMut(LintKind, String)) {
+    let operator = filter.operator.as_ref();
+    if !OPERATORS.contains(&operator) {
+        warn(
+            LintKind::UnknownOperator,
+            format!("unknown operator `{operator}` never matches"),
+        );
+        return;
+    }
+    let right = match (&filter.rhs, &filter.value) {
+        (Some(Expr::Literal(value)), _) | (None, Some(value)) => Some(value),
+        _ => None,
+    };
+    let constant = filter.field.is_none()
+        && filter.value_field.is_none()
+        && filter
+            .lhs
+            .as_ref()
+            .is_some_and(|lhs| is_constant(&filter.arena, lhs))
+        && filter
+            .rhs
+            .as_ref()
+            .is_none_or(|rhs| is_constant(&filter.arena, rhs));
+    if constant {
+        let passes = filter.matches(&Value::Null, &Context::new(&ApplyOptions::default()));
+        let (kind, result) = if passes {
+            (LintKind::AlwaysTrue, "always passes")
+        } else {
+            (LintKind::AlwaysFalse, "never passes")
+        };
+        warn(kind, format!("doesn't depend on the value and {result}"));
+        return;
+    }
+    if let (Some(Value::String(s)), ">=" | ">" | "<=" | "<") = (right, operator) {
+        if !orderable(s) {
+            warn(
+                LintKind::StringOrdering,
+                format!("`{operator}` only orders strings that are UUIDs or timestamps, so this never passes"),
+            );
+        }
+    }
+    if let Some(Value::Array(elements)) = right {
+        let mut types = elements.iter().map(type_name);
+        if let Some(first) = types.next() {
+            if let Some(other) = types.find(|t| *t != first) {
+                warn(
+                    LintKind::MixedTypes,
+                    format!("the list mixes {first} and {other} elements"),
+                );
+            }
+        }
+    }
+    for expr in [filter.lhs.as_ref(), filter.rhs.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        if has_string_arithmetic(&filter.arena, expr) {
+            warn(
+                LintKind::MixedTypes,
+                "arithmetic on a string literal never produces a value".to_string(),
+            );
+        }
+    }
+}
+
+fn type_name(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Returns whether an expression evaluates to the same value whatever it is evaluated against.
+fn is_constant(arena: &Arena, expr: &Expr) -> bool {
+    let constant = |expr| is_constant(arena, expr);
+    match expr {
+        // A plugin's function may depend on more than its arguments.
+        Expr::Path(_) | Expr::Anchored(..) | Expr::Plugin(..) => false,
+        #[cfg(feature = "chrono")]
+        Expr::Now => false,
+        Expr::Literal(_) => true,
+        Expr::Mul(lhs, rhs) | Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            constant(&arena[*lhs]) && constant(&arena[*rhs])
+        }
+        Expr::Shift(base, _) | Expr::Capture(base, ..) => constant(&arena[*base]),
+        Expr::Call(_, exprs) | Expr::Coalesce(exprs) | Expr::List(exprs) => {
+            arena[*exprs].iter().all(constant)
+        }
+    }
+}
+
+/// Returns whether an expression multiplies, adds or subtracts a string literal.
+fn has_string_arithmetic(arena: &Arena, expr: &Expr) -> bool {
+    let nested = |expr| has_string_arithmetic(arena, expr);
+    match expr {
+        Expr::Mul(lhs, rhs) | Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            [&arena[*lhs], &arena[*rhs]]
+                .into_iter()
+                .any(|e| matches!(e, Expr::Literal(Value::String(_))) || nested(e))
+        }
+        Expr::Shift(base, _) | Expr::Capture(base, ..) => nested(&arena[*base]),
+        Expr::Call(_, exprs) | Expr::Coalesce(exprs) | Expr::List(exprs) => {
+            arena[*exprs].iter().any(nested)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn kinds(filter: &str) -> Vec<LintKind> {
+        lint(&parse(filter).unwrap())
+            .into_iter()
+            .map(|w| w.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(kinds(".a = 1 AND .b > 'c'"), [LintKind::StringOrdering]);
+        assert_eq!(kinds(".a LIKE 'x'"), [LintKind::UnknownOperator]);
+        assert_eq!(
+            kinds("1 = 1 AND abs(-2) < 1"),
+            [LintKind::AlwaysTrue, LintKind::AlwaysFalse]
+        );
+        assert_eq!(
+            kinds(".a = 1 AND .b = 2 AND .a = 1"),
+            [LintKind::DuplicateClause]
+        );
+        assert_eq!(
+            kinds(".age = '18' AND .tags HAS_ANY ('a', 1) AND .n + 'x' > 1"),
+            [LintKind::MixedTypes, LintKind::MixedTypes]
+        );
+        assert_eq!(
+            kinds("ANY .o WHERE (.x = 1 AND .x = 1)"),
+            [LintKind::DuplicateClause]
+        );
+        // Timestamps can only be ordered with the chrono feature.
+        let expected = if cfg!(feature = "chrono") {
+            vec![]
+        } else {
+            vec![LintKind::StringOrdering]
+        };
+        assert_eq!(
+            kinds(".created > '2024-01-01T00:00:00Z' AND .id > '123e4567-e89b-12d3-a456-426614174000'"),
+            expected
+        );
+        assert!(kinds(".a = 1 AND .b != 'x' AND .c CONTAINS 'y'").is_empty());
+    }
+
+    #[test]
+    fn test_lint_message() {
+        let warnings = lint(&parse(".tags HAS_ANY ('a', 1)").unwrap());
+        assert_eq!(
+            warnings[0].to_string(),
+            ".tags HAS_ANY ('a', 1): the list mixes string and number elements"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::{apply, Filter};
+
+/// Collects the JSON pointers (RFC 6901) of the objects inside `v`, and `v` itself, that pass every
+/// filter, each object before the values inside it. `pointer` is the pointer of `v`.
+pub(crate) fn matching_paths(
+    v: &Value,
+    filters: &[Filter],
+    pointer: &mut String,
+    paths: &mut Vec<String>,
+) {
+    if v.is_object() && apply(v, filters) {
+        paths.push(pointer.clone());
+    }
+    let len = pointer.len();
+    match v {
+        Value::Object(map) => {
+            for (key, value) in map {
+                push_token(pointer, key);
+                matching_paths(value, filters, pointer, paths);
+                pointer.truncate(len);
+            }
+        }
+        Value::Array(elements) => {
+            for (i, element) in elements.iter().enumerate() {
+                push_token(pointer, &i.to_string());
+                matching_paths(element, filters, pointer, paths);
+                pointer.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Appends a reference token to a JSON pointer, escaping `~` as `~0` and `/` as `~1`.
+fn push_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    #[test]
+    fn test_matching_paths() {
+        let v = json!({
+            "status": "open",
+            "items": [
+                { "status": "open", "sub": { "status": "open" } },
+                { "status": "closed" },
+                "open",
+            ],
+            "a/b~c": { "status": "open" },
+        });
+        let mut paths = Vec::new();
+        let filters = parse(".status = 'open'").unwrap();
+        matching_paths(&v, &filters, &mut String::new(), &mut paths);
+        assert_eq!(paths, ["", "/a~1b~0c", "/items/0", "/items/0/sub"]);
+        for path in &paths {
+            assert!(apply(v.pointer(path).unwrap(), &filters));
+        }
+    }
+}
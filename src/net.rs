@@ -0,0 +1,68 @@
+use core::net::IpAddr;
+
+/// Returns whether `addr` is inside the network `cidr`, such as `10.0.0.0/8` or `2001:db8::/32`.
+///
+/// A network without a prefix length is a single address. IPv4-mapped IPv6 addresses such as
+/// `::ffff:10.1.2.3` are treated as the IPv4 address they map. Returns `None` if either string is
+/// not valid.
+pub(crate) fn in_cidr(addr: &str, cidr: &str) -> Option<bool> {
+    let addr = canonical(addr.parse().ok()?);
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((network, prefix)) => (canonical(network.parse().ok()?), Some(prefix.parse().ok()?)),
+        None => (canonical(cidr.parse().ok()?), None),
+    };
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            let prefix = prefix.unwrap_or(32);
+            Some(
+                prefix <= 32
+                    && masked(u32::from(addr).into(), prefix, 32)
+                        == masked(u32::from(network).into(), prefix, 32),
+            )
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            let prefix = prefix.unwrap_or(128);
+            Some(
+                prefix <= 128
+                    && masked(addr.into(), prefix, 128) == masked(network.into(), prefix, 128),
+            )
+        }
+        _ => Some(false),
+    }
+}
+
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+/// Keeps the first `prefix` bits of a `bits`-wide address.
+fn masked(addr: u128, prefix: u32, bits: u32) -> u128 {
+    if prefix == 0 {
+        0
+    } else {
+        addr >> (bits - prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_cidr() {
+        assert_eq!(in_cidr("10.1.2.3", "10.0.0.0/8"), Some(true));
+        assert_eq!(in_cidr("11.1.2.3", "10.0.0.0/8"), Some(false));
+        assert_eq!(in_cidr("192.168.1.7", "192.168.1.7"), Some(true));
+        assert_eq!(in_cidr("8.8.8.8", "0.0.0.0/0"), Some(true));
+        assert_eq!(in_cidr("2001:db8::1", "2001:db8::/32"), Some(true));
+        assert_eq!(in_cidr("2001:db9::1", "2001:db8::/32"), Some(false));
+        assert_eq!(in_cidr("::ffff:10.1.2.3", "10.0.0.0/8"), Some(true));
+        assert_eq!(in_cidr("10.1.2.3", "2001:db8::/32"), Some(false));
+        assert_eq!(in_cidr("10.1.2.3", "10.0.0.0/33"), Some(false));
+        assert_eq!(in_cidr("localhost", "10.0.0.0/8"), None);
+        assert_eq!(in_cidr("10.1.2.3", "10.0.0.0/x"), None);
+    }
+}
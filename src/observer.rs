@@ -0,0 +1,20 @@
+use crate::Filter;
+
+/// Receives the outcome of every evaluation done with [`crate::apply_observed`] or
+/// [`crate::CompiledFilter::matches_observed`], so a service can count documents and rejections in its
+/// own metrics, such as Prometheus counters.
+///
+/// The methods take `&self` so one observer can be shared across threads; use atomics or other
+/// interior mutability to count. Both methods do nothing by default.
+pub trait EvalObserver {
+    /// Called once for every Value evaluated, with whether it passed every clause.
+    fn evaluated(&self, passed: bool) {
+        let _ = passed;
+    }
+
+    /// Called when the top-level clause at `index` rejects a Value, before [`EvalObserver::evaluated`].
+    /// Clauses after it aren't evaluated, so at most one clause rejects each Value.
+    fn rejected(&self, index: usize, clause: &Filter) {
+        let _ = (index, clause);
+    }
+}
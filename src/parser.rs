@@ -0,0 +1,1514 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use serde_json::{Number, Value};
+
+use crate::complete::{Context, Expecting};
+use crate::expr::{Anchor, Arena, Expr, Function, Group, Path, Pattern, REGEX_SIZE_LIMIT};
+#[cfg(feature = "geo")]
+use crate::geo;
+use crate::intern::Name;
+use crate::lexer::{tokenize, tokenize_recovering, Span, Token, TokenKind};
+use crate::syntax::ClauseKind;
+use crate::{Compound, Filter, PluginHost};
+
+/// The comparison operators the evaluator understands, in their canonical spelling.
+pub(crate) const OPERATORS: &[&str] = &[
+    "=",
+    "!=",
+    ">=",
+    ">",
+    "<=",
+    "<",
+    "SEMVER=",
+    "SEMVER!=",
+    "SEMVER>=",
+    "SEMVER>",
+    "SEMVER<=",
+    "SEMVER<",
+    "IN_CIDR",
+    "IS UUID",
+    "IS NOT UUID",
+    "MATCHES_TEXT",
+    "CONTAINS",
+    "HAS_ALL",
+    "HAS_ANY",
+    "SUBSET_OF",
+    "IN",
+    "DEEP_EQ",
+    "MATCHES_OBJECT",
+];
+
+/// Words of the filter syntax that a bare word in the value position can't be, so `.a > AND .b = 1` is a
+/// missing value rather than a comparison with the string `"AND"`.
+const KEYWORDS: &[&str] = &["AND", "if", "then", "else", "WHERE", "OF", "WEIGHT"];
+
+/// An error found while parsing a filter string, with the byte range it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// A description of the error, such as ``expected `)` ``.
+    pub message: String,
+    /// The byte range of the filter string the error was found at, which is empty at its end.
+    pub span: Span,
+}
+
+impl ParseError {
+    pub(crate) fn new(message: impl Into<String>, span: Span) -> ParseError {
+        ParseError {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// The line and column the error starts at, both counted from 1, for filter strings that span
+    /// several lines. Columns count characters, not bytes.
+    pub fn line_col(&self, input: &str) -> (usize, usize) {
+        let before = &input[..self.span.start.min(input.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+/// Which side of an operator an expression is on. A bare word is a field name on the left and a
+/// string on the right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl core::error::Error for ParseError {}
+
+/// The result of parsing a filter string that may be unfinished, returned by [`crate::parse_partial`].
+#[derive(Debug)]
+pub struct PartialParse<'a> {
+    /// The clauses parsed before the first error, or every clause if there was none.
+    pub filters: Vec<Filter<'a>>,
+    /// The first error, which for input still being typed is usually at its end.
+    pub error: Option<ParseError>,
+    /// What could come next at the end of the input.
+    pub expected: Vec<Expected>,
+}
+
+impl PartialParse<'_> {
+    /// Returns whether the whole input parsed, so the filters are the same as [`crate::parse`] returns.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// What can come next in a filter string, as reported by [`PartialParse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    /// The left side of a comparison, such as a field, or a keyword starting a clause.
+    Field,
+    /// An operator, such as `>=` or `CONTAINS`.
+    Operator,
+    /// The right side of a comparison, such as a literal or a field.
+    Value,
+    /// A keyword continuing the filter, such as `AND` or `then`.
+    Keyword(&'static str),
+    /// A `)` closing a group.
+    CloseParen,
+    /// The end of the input.
+    End,
+}
+
+pub(crate) fn parse_partial(input: &str) -> PartialParse<'_> {
+    let (tokens, mut error) = match tokenize(input) {
+        Ok(tokens) => (tokens, None),
+        // The input is parsed up to the token that couldn't be read.
+        Err(error) => (
+            tokenize(&input[..error.span.start]).unwrap_or_default(),
+            Some(error),
+        ),
+    };
+    let context = Context::read(&tokens);
+    let mut parser = Parser::new(tokens, input.len(), ParseLimits::UNLIMITED);
+    let mut filters = Vec::new();
+    loop {
+        match parser.clause() {
+            Ok(filter) => filters.push(filter),
+            Err(e) => {
+                error.get_or_insert(e);
+                break;
+            }
+        }
+        if parser.peek().is_none() {
+            break;
+        }
+        if !parser.eat(&TokenKind::Ident("AND")) {
+            error.get_or_insert(ParseError::new("expected `AND`", parser.span()));
+            break;
+        }
+    }
+    let mut expected = match context.expecting {
+        Expecting::Field => vec![Expected::Field],
+        Expecting::Operator => vec![Expected::Operator],
+        Expecting::Value => vec![Expected::Value],
+        Expecting::Connective => vec![Expected::Keyword("AND")],
+    };
+    if context.expecting == Expecting::Connective {
+        if context.in_condition {
+            expected.push(Expected::Keyword("then"));
+        }
+        if context.depth > 0 {
+            expected.push(Expected::CloseParen);
+        } else if error.is_none() {
+            expected.push(Expected::End);
+        }
+    }
+    PartialParse {
+        filters,
+        error,
+        expected,
+    }
+}
+
+pub(crate) fn parse_filters(input: &str) -> Result<Vec<Filter<'_>>, ParseError> {
+    traced(input, || {
+        parse_tokens(tokenize(input)?, input.len(), ParseLimits::UNLIMITED)
+    })
+}
+
+/// Parses every clause of `input` that can be parsed, returning the errors of the others. A clause with
+/// an error is skipped up to the next `AND` outside parentheses, where parsing resumes, and clauses
+/// with an error from the lexer aren't reported again by the parser.
+pub(crate) fn parse_recovering(input: &str) -> Result<Vec<Filter<'_>>, Vec<ParseError>> {
+    let (tokens, lexer_errors) = tokenize_recovering(input);
+    let mut parser = Parser::new(tokens, input.len(), ParseLimits::UNLIMITED);
+    let mut filters = Vec::new();
+    let mut errors = lexer_errors.clone();
+    while parser.peek().is_some() {
+        let start = parser.pos;
+        let result = parser
+            .clause_or_ref(&mut filters)
+            .and_then(|()| match parser.peek() {
+                Some(token) if token.kind != TokenKind::Ident("AND") => {
+                    Err(ParseError::new("expected `AND`", token.span))
+                }
+                _ => Ok(()),
+            });
+        if let Err(error) = result {
+            let from = parser.tokens[start].span.start;
+            let failed_at = parser.pos;
+            parser.pos = start;
+            parser.skip_clause(failed_at);
+            let to = parser.span().start;
+            let lexed = lexer_errors
+                .iter()
+                .any(|e| (from..=to).contains(&e.span.start));
+            if !lexed && !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        parser.eat(&TokenKind::Ident("AND"));
+    }
+    if errors.is_empty() {
+        return Ok(filters);
+    }
+    errors.sort_by_key(|error| error.span.start);
+    Err(errors)
+}
+
+/// Runs `parse` on `input`. With the `tracing` feature, it is a debug-level `parse` span, and its result
+/// a debug event.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn traced<'a>(
+    input: &'a str,
+    parse: impl FnOnce() -> Result<Vec<Filter<'a>>, ParseError>,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse", len = input.len()).entered();
+    let result = parse();
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(filters) => tracing::debug!(clauses = filters.len(), "parsed filter"),
+        Err(error) => tracing::debug!(%error, "invalid filter"),
+    }
+    result
+}
+
+/// Bounds on the size of a filter string, checked while parsing it with [`crate::parse_with_limits`], so
+/// filters from users can't use unbounded memory or time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// The longest filter string accepted, in bytes. Longer input is rejected before it is read.
+    pub max_length: usize,
+    /// The most clauses accepted, counting those inside `ANY`, `ALL` and conditionals.
+    pub max_clauses: usize,
+    /// How deeply clauses, function calls and lists may be nested. A single comparison such as
+    /// `.a = 1` has a depth of 1, and `ANY .items WHERE .qty > abs(.min)` a depth of 3.
+    pub max_depth: usize,
+    /// The longest quoted string or JSON literal accepted, in bytes.
+    pub max_string_length: usize,
+    /// The most memory a regex may be compiled to, in bytes, and the most its matcher may cache while
+    /// matching. Matching always takes time linear in the length of the text, so this bounds the rest.
+    pub max_regex_size: usize,
+}
+
+impl ParseLimits {
+    pub(crate) const UNLIMITED: ParseLimits = ParseLimits {
+        max_length: usize::MAX,
+        max_clauses: usize::MAX,
+        max_depth: usize::MAX,
+        max_string_length: usize::MAX,
+        // Regexes keep the limit they had before limits could be set.
+        max_regex_size: REGEX_SIZE_LIMIT,
+    };
+}
+
+impl Default for ParseLimits {
+    /// Limits that ordinary filters stay well within: 64 KiB of input, 256 clauses, a depth of 16,
+    /// strings of 4 KiB and regexes of 256 KiB.
+    fn default() -> ParseLimits {
+        ParseLimits {
+            max_length: 64 * 1024,
+            max_clauses: 256,
+            max_depth: 16,
+            max_string_length: 4 * 1024,
+            max_regex_size: 256 * 1024,
+        }
+    }
+}
+
+pub(crate) fn parse_with_limits<'a>(
+    input: &'a str,
+    limits: &ParseLimits,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    traced(input, || {
+        if input.len() > limits.max_length {
+            return Err(ParseError::new(
+                format!("filter longer than {} bytes", limits.max_length),
+                Span {
+                    start: limits.max_length,
+                    end: input.len(),
+                },
+            ));
+        }
+        let tokens = tokenize(input)?;
+        for token in &tokens {
+            let length = match &token.kind {
+                TokenKind::Str(value) => value.len(),
+                TokenKind::Json(_) => token.span.end - token.span.start,
+                _ => continue,
+            };
+            if length > limits.max_string_length {
+                return Err(ParseError::new(
+                    format!("string longer than {} bytes", limits.max_string_length),
+                    token.span,
+                ));
+            }
+        }
+        parse_tokens(tokens, input.len(), *limits)
+    })
+}
+
+/// Parses a filter string with `{{name}}` placeholders, each replaced by its value from `vars` as a
+/// literal, so a value can never change the structure of the filter.
+#[cfg(feature = "std")]
+pub(crate) fn parse_with_vars<'a>(
+    input: &'a str,
+    vars: &HashMap<String, Value>,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    traced(input, || {
+        let mut tokens = tokenize(input)?;
+        for token in &mut tokens {
+            if let TokenKind::Var(name) = token.kind {
+                let value = vars
+                    .get(name)
+                    .ok_or_else(|| missing_var(name, token.span))?;
+                token.kind = TokenKind::Json(value.clone());
+            }
+        }
+        parse_tokens(tokens, input.len(), ParseLimits::UNLIMITED)
+    })
+}
+
+fn missing_var(name: &str, span: Span) -> ParseError {
+    ParseError::new(format!("no value for placeholder `{{{{{name}}}}}`"), span)
+}
+
+/// Parses a filter string whose `@name` references are replaced by the clauses `resolve` returns for
+/// the names, or fail with the message it returns. The clauses of references count towards
+/// `limits.max_clauses`.
+#[cfg(feature = "std")]
+pub(crate) fn parse_with_refs<'a>(
+    input: &'a str,
+    resolve: &'a Resolve<'a>,
+    limits: ParseLimits,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    traced(input, || {
+        let mut parser = Parser::new(tokenize(input)?, input.len(), limits);
+        parser.resolve = Some(resolve);
+        parser.all()
+    })
+}
+
+/// The number of clauses in `filters`, counting those inside `ANY`, `ALL`, `MATCH` and conditionals.
+fn clause_count(filters: &[Filter]) -> usize {
+    filters
+        .iter()
+        .map(|filter| {
+            1 + match &filter.compound {
+                Some(Compound::Quantified { filters, .. } | Compound::Match { filters, .. }) => {
+                    clause_count(filters)
+                }
+                Some(Compound::If {
+                    condition,
+                    then,
+                    otherwise,
+                }) => clause_count(condition) + clause_count(then) + clause_count(otherwise),
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+/// Parses a filter string, returning the kind and span of each clause, in the order they end, for a
+/// [`crate::SyntaxTree`]. Placeholders and `@name` references are accepted without their values.
+pub(crate) fn parse_clause_spans(input: &str) -> Result<Vec<(ClauseKind, Span)>, ParseError> {
+    let mut tokens = tokenize(input)?;
+    for token in &mut tokens {
+        if let TokenKind::Var(_) = token.kind {
+            token.kind = TokenKind::Json(Value::Null);
+        }
+    }
+    let resolve = |_: &str| Ok(Vec::new());
+    let mut parser = Parser::new(tokens, input.len(), ParseLimits::UNLIMITED);
+    parser.resolve = Some(&resolve);
+    parser.recorded = Some(Vec::new());
+    parser.all()?;
+    Ok(parser.recorded.unwrap_or_default())
+}
+
+/// Parses a filter string that may call the functions of `plugins`.
+pub(crate) fn parse_with_plugins<'a>(
+    input: &'a str,
+    plugins: &'a PluginHost,
+) -> Result<Vec<Filter<'a>>, ParseError> {
+    traced(input, || {
+        let mut parser = Parser::new(tokenize(input)?, input.len(), ParseLimits::UNLIMITED);
+        parser.plugins = Some(plugins);
+        parser.all()
+    })
+}
+
+/// Looks up the clauses of the filter an `@name` reference names.
+pub(crate) type Resolve<'r> = dyn Fn(&str) -> Result<Vec<Filter<'static>>, String> + 'r;
+
+fn parse_tokens(
+    tokens: Vec<Token<'_>>,
+    len: usize,
+    limits: ParseLimits,
+) -> Result<Vec<Filter<'_>>, ParseError> {
+    Parser::new(tokens, len, limits).all()
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    len: usize,
+    limits: ParseLimits,
+    /// The clauses read so far and how deeply the current one is nested, checked against `limits`.
+    clauses: usize,
+    depth: usize,
+    /// The expressions of the clause being read.
+    arena: Arena<'a>,
+    /// How `@name` references are resolved, if they are allowed.
+    resolve: Option<&'a Resolve<'a>>,
+    /// The plugins whose functions may be called.
+    plugins: Option<&'a PluginHost>,
+    /// The kind and span of every clause read, if they are recorded for a [`crate::SyntaxTree`].
+    recorded: Option<Vec<(ClauseKind, Span)>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<Token<'a>>, len: usize, limits: ParseLimits) -> Parser<'a> {
+        Parser {
+            tokens,
+            pos: 0,
+            len,
+            limits,
+            clauses: 0,
+            depth: 0,
+            arena: Arena::default(),
+            resolve: None,
+            plugins: None,
+            recorded: None,
+        }
+    }
+
+    /// Reads every clause of the filter string.
+    fn all(&mut self) -> Result<Vec<Filter<'a>>, ParseError> {
+        let filters = self.filters()?;
+        match self.peek() {
+            Some(token) => Err(ParseError::new("expected `AND`", token.span)),
+            None => Ok(filters),
+        }
+    }
+
+    /// Reads something nested one level deeper, such as a clause or the arguments of a call, which
+    /// starts at `span`.
+    fn nested<T>(
+        &mut self,
+        span: Span,
+        read: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(ParseError::new(
+                format!("nested more than {} deep", self.limits.max_depth),
+                span,
+            ));
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Moves to the next `AND` outside parentheses from the position `failed_at` on, or to the end of
+    /// the input. An `AND` before `failed_at` was read by the clause, such as one in the condition of
+    /// a conditional.
+    fn skip_clause(&mut self, failed_at: usize) {
+        let mut depth = 0_usize;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Ident("AND") if depth == 0 && self.pos >= failed_at => break,
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Records a clause that started at the token `start` and ended before the current one.
+    fn record(&mut self, kind: ClauseKind, start: usize) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push((
+                kind,
+                Span {
+                    start: self.tokens[start].span.start,
+                    end: self.tokens[self.pos - 1].span.end,
+                },
+            ));
+        }
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind<'a>> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// The span of the next token, or an empty span at the end of the input.
+    fn span(&self) -> Span {
+        self.peek().map_or(
+            Span {
+                start: self.len,
+                end: self.len,
+            },
+            |t| t.span,
+        )
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek_kind() == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind, what: &str) -> Result<(), ParseError> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            Err(ParseError::new(format!("expected {what}"), self.span()))
+        }
+    }
+
+    fn filters(&mut self) -> Result<Vec<Filter<'a>>, ParseError> {
+        let mut filters = Vec::new();
+        self.clause_or_ref(&mut filters)?;
+        while self.eat(&TokenKind::Ident("AND")) {
+            self.clause_or_ref(&mut filters)?;
+        }
+        Ok(filters)
+    }
+
+    /// Reads a clause, or the clauses of the filter an `@name` reference names.
+    fn clause_or_ref(&mut self, filters: &mut Vec<Filter<'a>>) -> Result<(), ParseError> {
+        let span = self.span();
+        let Some(&TokenKind::Ref(name)) = self.peek_kind() else {
+            filters.push(self.clause()?);
+            return Ok(());
+        };
+        self.pos += 1;
+        self.record(ClauseKind::Reference, self.pos - 1);
+        let resolve = self.resolve.ok_or_else(|| {
+            ParseError::new(
+                format!("`@{name}` can only refer to a filter of a filter set"),
+                span,
+            )
+        })?;
+        let resolved = resolve(name).map_err(|message| ParseError::new(message, span))?;
+        // A reference can expand to many more clauses than it is written with, as each filter it
+        // names can refer to others.
+        self.clauses = self.clauses.saturating_add(clause_count(&resolved));
+        if self.clauses > self.limits.max_clauses {
+            return Err(ParseError::new(
+                format!("more than {} clauses", self.limits.max_clauses),
+                span,
+            ));
+        }
+        filters.extend(resolved);
+        Ok(())
+    }
+
+    fn clause(&mut self) -> Result<Filter<'a>, ParseError> {
+        let start = self.pos;
+        self.clauses += 1;
+        if self.clauses > self.limits.max_clauses {
+            return Err(ParseError::new(
+                format!("more than {} clauses", self.limits.max_clauses),
+                self.span(),
+            ));
+        }
+        // `SHOULD` is only a keyword before a clause, so it can still be a field name.
+        let should = self.peek_kind() == Some(&TokenKind::Ident("SHOULD"))
+            && !matches!(
+                self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                None | Some(TokenKind::Op(_) | TokenKind::Ident("IS" | "FUZZY" | "WITHIN"))
+            );
+        if should {
+            self.pos += 1;
+        }
+        // The clauses nested in this one have arenas of their own.
+        let outer = core::mem::take(&mut self.arena);
+        let filter = self.nested(self.span(), Self::comparison);
+        let arena = core::mem::replace(&mut self.arena, outer);
+        let mut filter = filter?;
+        filter.arena = arena;
+        filter.should = should;
+        if self.eat(&TokenKind::Ident("WEIGHT")) {
+            filter.weight = Some(self.fraction("a weight")?);
+        }
+        let kind = match filter.compound {
+            Some(Compound::Quantified { .. }) => ClauseKind::Quantified,
+            Some(Compound::Match { .. }) => ClauseKind::Match,
+            Some(Compound::If { .. }) => ClauseKind::If,
+            _ => ClauseKind::Comparison,
+        };
+        self.record(kind, start);
+        Ok(filter)
+    }
+
+    /// A non-negative number, such as a weight or a score threshold.
+    fn fraction(&mut self, what: &str) -> Result<f64, ParseError> {
+        let span = self.span();
+        match self.next().map(|t| t.kind) {
+            Some(TokenKind::Number(text)) => text
+                .parse()
+                .ok()
+                .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+                .ok_or_else(|| {
+                    ParseError::new(format!("{what} must be a non-negative number"), span)
+                }),
+            _ => Err(ParseError::new(format!("expected {what}"), span)),
+        }
+    }
+
+    /// A clause: a comparison, a quantifier or a conditional.
+    fn comparison(&mut self) -> Result<Filter<'a>, ParseError> {
+        if self.eat(&TokenKind::Ident("if")) {
+            return self.conditional();
+        }
+        if let Some(TokenKind::Ident(quantifier @ ("ANY" | "ALL"))) = self.peek_kind() {
+            // `ANY` and `ALL` are only keywords before a path, so they can still be field names.
+            if matches!(
+                self.tokens.get(self.pos + 1),
+                Some(Token {
+                    kind: TokenKind::Path(_),
+                    ..
+                })
+            ) {
+                let all = *quantifier == "ALL";
+                self.pos += 1;
+                return self.quantified(all);
+            }
+        }
+        // `MATCH` is only a keyword before a threshold and `WHERE`, so it can still be a field name.
+        if self.peek_kind() == Some(&TokenKind::Ident("MATCH"))
+            && matches!(
+                self.tokens.get(self.pos + 1),
+                Some(Token {
+                    kind: TokenKind::Op(_),
+                    ..
+                })
+            )
+            && self.tokens.get(self.pos + 3).map(|t| &t.kind) == Some(&TokenKind::Ident("WHERE"))
+        {
+            self.pos += 1;
+            return self.weighted();
+        }
+        // A path followed by `?` before the operator, as in `.retries? > 3`, makes the clause pass
+        // Values without the path.
+        let optional = match (
+            self.peek_kind(),
+            self.tokens.get(self.pos + 1).map(|t| &t.kind),
+        ) {
+            (Some(TokenKind::Path(text)), Some(TokenKind::Op(_) | TokenKind::Ident("IS"))) => {
+                text.strip_suffix('?')
+            }
+            _ => None,
+        };
+        let lhs = match optional {
+            Some(text) => {
+                self.pos += 1;
+                path(text)
+            }
+            None => self.expr(Side::Left)?,
+        };
+        #[cfg(feature = "geo")]
+        if self.eat(&TokenKind::Ident("WITHIN")) {
+            return self.within(lhs);
+        }
+        if self.eat(&TokenKind::Ident("FUZZY")) {
+            return self.fuzzy(lhs);
+        }
+        let left = self.simple_operand(&lhs);
+        let (operator, rhs) = if self.eat(&TokenKind::Ident("IS")) {
+            (self.type_check()?, None)
+        } else {
+            (self.operator()?, Some(self.expr(Side::Right)?))
+        };
+        let right = match &rhs {
+            Some(rhs) => self.simple_operand(rhs),
+            None => None,
+        };
+
+        let mut filter = Filter {
+            operator: Cow::Borrowed(operator),
+            optional: optional.is_some(),
+            ..Default::default()
+        };
+        match left {
+            Some((field, multiplier)) => {
+                filter.field = Some(Name::Borrowed(field));
+                filter.multiplier_field = multiplier;
+            }
+            None => filter.lhs = Some(lhs),
+        }
+        match rhs {
+            None => {}
+            Some(Expr::Literal(value)) => filter.value = Some(value),
+            Some(rhs) => match right {
+                Some((field, multiplier)) => {
+                    filter.value_field = Some(field.to_string());
+                    filter.multiplier_value = multiplier;
+                }
+                None => filter.rhs = Some(rhs),
+            },
+        }
+        Ok(filter)
+    }
+
+    /// Recognizes the forms the flat Filter fields can express: `.field` and `2*.field`. The
+    /// operands of `2*.field` were the last expressions added to the arena, and are removed from it
+    /// as the fields replace them.
+    fn simple_operand(&mut self, expr: &Expr<'a>) -> Option<(&'a str, Option<i64>)> {
+        let operand = match expr {
+            Expr::Path(path) => Some((path.as_field()?, None)),
+            Expr::Mul(multiplier, path) => match (&self.arena[*multiplier], &self.arena[*path]) {
+                (Expr::Literal(Value::Number(n)), Expr::Path(path)) => {
+                    Some((path.as_field()?, Some(n.as_i64()?)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }?;
+        if let Expr::Mul(multiplier, _) = expr {
+            self.arena.truncate(*multiplier);
+        }
+        Some(operand)
+    }
+
+    /// The rest of `IS [NOT] <type>`, returning the operator.
+    fn type_check(&mut self) -> Result<&'a str, ParseError> {
+        let negated = self.eat(&TokenKind::Ident("NOT"));
+        self.expect(&TokenKind::Ident("UUID"), "a type such as `UUID`")?;
+        Ok(if negated { "IS NOT UUID" } else { "IS UUID" })
+    }
+
+    /// The rest of `<location> WITHIN <distance> OF (<lat>, <lon>)`.
+    #[cfg(feature = "geo")]
+    fn within(&mut self, location: Expr<'a>) -> Result<Filter<'a>, ParseError> {
+        let span = self.span();
+        let radius_m = match self.next().map(|t| t.kind) {
+            Some(TokenKind::Quantity(value, unit)) => geo::distance_meters(value, unit)
+                .ok_or_else(|| {
+                    ParseError::new(format!("invalid distance `{value}{unit}`"), span)
+                })?,
+            _ => return Err(ParseError::new("expected a distance such as `5km`", span)),
+        };
+        self.expect(&TokenKind::Ident("OF"), "`OF`")?;
+        let span = self.span();
+        self.expect(&TokenKind::LParen, "`(`")?;
+        let lat = self.coordinate()?;
+        self.expect(&TokenKind::Comma, "`,`")?;
+        let lon = self.coordinate()?;
+        self.expect(&TokenKind::RParen, "`)`")?;
+        if !geo::valid(lat, lon) {
+            return Err(ParseError::new("coordinates out of range", span));
+        }
+        Ok(Filter {
+            operator: Cow::Borrowed("WITHIN"),
+            compound: Some(Compound::Within {
+                location,
+                radius_m,
+                center: (lat, lon),
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[cfg(feature = "geo")]
+    fn coordinate(&mut self) -> Result<f64, ParseError> {
+        let span = self.span();
+        match self.next().map(|t| t.kind) {
+            Some(TokenKind::Number(text)) => text
+                .parse()
+                .map_err(|_| ParseError::new("invalid number", span)),
+            _ => Err(ParseError::new("expected a coordinate", span)),
+        }
+    }
+
+    /// The rest of `<input> FUZZY(<target>, <max distance>)`.
+    fn fuzzy(&mut self, input: Expr<'a>) -> Result<Filter<'a>, ParseError> {
+        self.expect(&TokenKind::LParen, "`(`")?;
+        let target = self.expr(Side::Right)?;
+        self.expect(&TokenKind::Comma, "`,`")?;
+        let span = self.span();
+        let max_distance = match self.next().map(|t| t.kind) {
+            Some(TokenKind::Number(text)) => text.parse().map_err(|_| {
+                ParseError::new("edit distance must be a non-negative integer", span)
+            })?,
+            _ => return Err(ParseError::new("expected an edit distance", span)),
+        };
+        self.expect(&TokenKind::RParen, "`)`")?;
+        Ok(Filter {
+            operator: Cow::Borrowed("FUZZY"),
+            compound: Some(Compound::Fuzzy {
+                input,
+                target,
+                max_distance,
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// The rest of `ANY <array> WHERE <clause>` or `ALL <array> WHERE <clause>`.
+    fn quantified(&mut self, all: bool) -> Result<Filter<'a>, ParseError> {
+        let array = self.expr(Side::Left)?;
+        self.expect(&TokenKind::Ident("WHERE"), "`WHERE`")?;
+        let filters = self.branch()?;
+        Ok(Filter {
+            operator: Cow::Borrowed(if all { "ALL" } else { "ANY" }),
+            compound: Some(Compound::Quantified {
+                all,
+                array,
+                filters,
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// The rest of `MATCH <comparison> <threshold> WHERE <clause>`.
+    fn weighted(&mut self) -> Result<Filter<'a>, ParseError> {
+        let span = self.span();
+        let comparison = match self.next().map(|t| t.kind) {
+            Some(TokenKind::Op(op)) => OPERATORS[..6].iter().find(|&&known| known == op),
+            _ => None,
+        }
+        .ok_or_else(|| ParseError::new("expected a comparison such as `>=`", span))?;
+        let threshold = self.fraction("a threshold")?;
+        self.expect(&TokenKind::Ident("WHERE"), "`WHERE`")?;
+        let filters = self.branch()?;
+        Ok(Filter {
+            operator: Cow::Borrowed("MATCH"),
+            compound: Some(Compound::Match {
+                comparison,
+                threshold,
+                filters,
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn conditional(&mut self) -> Result<Filter<'a>, ParseError> {
+        let condition = self.filters()?;
+        self.expect(&TokenKind::Ident("then"), "`then`")?;
+        let then = self.branch()?;
+        let otherwise = if self.eat(&TokenKind::Ident("else")) {
+            self.branch()?
+        } else {
+            Vec::new()
+        };
+        Ok(Filter {
+            operator: Cow::Borrowed("if"),
+            compound: Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// A single clause, or several clauses grouped in parentheses.
+    fn branch(&mut self) -> Result<Vec<Filter<'a>>, ParseError> {
+        if !self.eat(&TokenKind::LParen) {
+            let mut filters = Vec::new();
+            self.clause_or_ref(&mut filters)?;
+            return Ok(filters);
+        }
+        let filters = self.filters()?;
+        self.expect(&TokenKind::RParen, "`)`")?;
+        Ok(filters)
+    }
+
+    fn operator(&mut self) -> Result<&'a str, ParseError> {
+        let span = self.span();
+        match self.next().map(|t| t.kind) {
+            Some(TokenKind::Op("==")) => Ok("="),
+            // Unknown operators are accepted and never match.
+            Some(TokenKind::Op(op) | TokenKind::Ident(op)) if op != "AND" => Ok(OPERATORS
+                .iter()
+                .find(|&&known| known == op)
+                .copied()
+                .unwrap_or(op)),
+            _ => Err(ParseError::new("expected an operator", span)),
+        }
+    }
+
+    fn expr(&mut self, side: Side) -> Result<Expr<'a>, ParseError> {
+        let expr = self.sum(side)?;
+        if self.peek_kind() != Some(&TokenKind::Alternative) {
+            return Ok(expr);
+        }
+        let mut alternatives = vec![expr];
+        while self.eat(&TokenKind::Alternative) {
+            alternatives.push(self.sum(side)?);
+        }
+        Ok(Expr::Coalesce(self.arena.alloc_list(alternatives)))
+    }
+
+    fn sum(&mut self, side: Side) -> Result<Expr<'a>, ParseError> {
+        let mut expr = self.product(side)?;
+        loop {
+            let negate = match self.peek_kind() {
+                Some(TokenKind::Plus) => false,
+                Some(TokenKind::Minus) => true,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let span = self.span();
+            if let Some(TokenKind::Quantity(value, unit)) = self.peek_kind() {
+                let millis = duration_millis(value, unit).ok_or_else(|| {
+                    ParseError::new(format!("invalid duration `{value}{unit}`"), span)
+                })?;
+                self.pos += 1;
+                let base = self.arena.alloc(expr);
+                expr = Expr::Shift(base, if negate { -millis } else { millis });
+                continue;
+            }
+            let lhs = self.arena.alloc(expr);
+            let rhs = self.product(side)?;
+            let rhs = self.arena.alloc(rhs);
+            expr = if negate {
+                Expr::Sub(lhs, rhs)
+            } else {
+                Expr::Add(lhs, rhs)
+            };
+        }
+    }
+
+    fn product(&mut self, side: Side) -> Result<Expr<'a>, ParseError> {
+        let mut expr = self.primary(side)?;
+        while self.eat(&TokenKind::Star) {
+            let lhs = self.arena.alloc(expr);
+            let rhs = self.primary(side)?;
+            expr = Expr::Mul(lhs, self.arena.alloc(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self, side: Side) -> Result<Expr<'a>, ParseError> {
+        let span = self.span();
+        // The keyword is left for the clause after the missing value.
+        if let (Side::Right, Some(TokenKind::Ident(word))) = (side, self.peek_kind()) {
+            if KEYWORDS.contains(word) {
+                return Err(ParseError::new("expected a value", span));
+            }
+        }
+        match self.next().map(|t| t.kind) {
+            Some(TokenKind::Path(text)) if text.ends_with('?') => Err(ParseError::new(
+                "`?` after a path is only allowed before the operator of a comparison, as in `.field? > 5`",
+                span,
+            )),
+            Some(TokenKind::Path(text)) => Ok(path(text)),
+            Some(TokenKind::Number(text)) => number(text)
+                .map(|n| Expr::Literal(Value::Number(n)))
+                .ok_or_else(|| ParseError::new("invalid number", span)),
+            Some(TokenKind::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(TokenKind::Json(value)) => Ok(Expr::Literal(value)),
+            Some(TokenKind::Var(name)) => Err(missing_var(name, span)),
+            Some(TokenKind::Quantity(..)) => Err(ParseError::new(
+                "a duration can only be added to or subtracted from a value",
+                span,
+            )),
+            Some(TokenKind::Ident(name)) if self.peek_kind() == Some(&TokenKind::LParen) => {
+                self.nested(span, |parser| parser.call(name, side, span))
+            }
+            Some(TokenKind::LParen) if side == Side::Right => self.nested(span, Self::list),
+            Some(TokenKind::Ident(word)) => Ok(match side {
+                Side::Left => Expr::Path(Path::parse(word)),
+                Side::Right if word == "null" => Expr::Literal(Value::Null),
+                Side::Right if word == "true" => Expr::Literal(Value::Bool(true)),
+                Side::Right if word == "false" => Expr::Literal(Value::Bool(false)),
+                Side::Right => Expr::Literal(Value::String(word.to_string())),
+            }),
+            _ => Err(ParseError::new("expected a value", span)),
+        }
+    }
+
+    /// The rest of a list such as `('admin', 'billing')`. A list of literals is itself a literal.
+    fn list(&mut self) -> Result<Expr<'a>, ParseError> {
+        let mut exprs = Vec::new();
+        if !self.eat(&TokenKind::RParen) {
+            loop {
+                exprs.push(self.expr(Side::Right)?);
+                if self.eat(&TokenKind::RParen) {
+                    break;
+                }
+                self.expect(&TokenKind::Comma, "`,` or `)`")?;
+            }
+        }
+        if !exprs.iter().all(|expr| matches!(expr, Expr::Literal(_))) {
+            return Ok(Expr::List(self.arena.alloc_list(exprs)));
+        }
+        let values = exprs
+            .into_iter()
+            .map(|expr| match expr {
+                Expr::Literal(value) => value,
+                _ => unreachable!("every element is a literal"),
+            })
+            .collect();
+        Ok(Expr::Literal(Value::Array(values)))
+    }
+
+    fn call(&mut self, name: &'a str, side: Side, span: Span) -> Result<Expr<'a>, ParseError> {
+        let function = Function::from_name(name);
+        let plugin = self.plugins.and_then(|plugins| plugins.function(name));
+        let (min, max) = match (function, name) {
+            (Some(function), _) => function.arity(),
+            (None, "coalesce") => (1, usize::MAX),
+            (None, "capture") => (2, 3),
+            #[cfg(feature = "chrono")]
+            (None, "now") => (0, 0),
+            (None, _) => match plugin {
+                Some(plugin) => plugin.arity(),
+                None => return Err(ParseError::new(format!("unknown function `{name}`"), span)),
+            },
+        };
+        self.expect(&TokenKind::LParen, "`(`")?;
+        let mut args = Vec::new();
+        if !self.eat(&TokenKind::RParen) {
+            loop {
+                args.push(self.expr(side)?);
+                if self.eat(&TokenKind::RParen) {
+                    break;
+                }
+                self.expect(&TokenKind::Comma, "`,` or `)`")?;
+            }
+        }
+        if args.len() < min || args.len() > max {
+            return Err(ParseError::new(
+                format!("wrong number of arguments to `{name}`"),
+                span,
+            ));
+        }
+        let arena = &mut self.arena;
+        match function {
+            Some(function) => Ok(Expr::Call(function, arena.alloc_list(args))),
+            None if name == "coalesce" => Ok(Expr::Coalesce(arena.alloc_list(args))),
+            #[cfg(feature = "chrono")]
+            None if name == "now" => Ok(Expr::Now),
+            None if plugin.is_some() => {
+                Ok(Expr::Plugin(Cow::Borrowed(name), arena.alloc_list(args)))
+            }
+            None => capture(arena, args, span, self.limits.max_regex_size),
+        }
+    }
+}
+
+/// Builds `capture(input, pattern[, group])`. The pattern must be a string literal so it can be
+/// compiled once; the group is an index or a name and defaults to the first group.
+fn capture<'a>(
+    arena: &mut Arena<'a>,
+    mut args: Vec<Expr<'a>>,
+    span: Span,
+    size_limit: usize,
+) -> Result<Expr<'a>, ParseError> {
+    let group = match args.get(2) {
+        None => Group::Index(1),
+        Some(Expr::Literal(Value::Number(n))) => n
+            .as_u64()
+            .map(|i| Group::Index(i as usize))
+            .ok_or_else(|| ParseError::new("capture group must be a non-negative integer", span))?,
+        Some(Expr::Literal(Value::String(name))) => Group::Name(name.clone()),
+        Some(_) => {
+            return Err(ParseError::new(
+                "capture group must be an integer or a string literal",
+                span,
+            ))
+        }
+    };
+    let pattern = match &args[1] {
+        Expr::Literal(Value::String(pattern)) => Pattern::new(pattern, size_limit)
+            .map_err(|e| ParseError::new(format!("invalid regex: {e}"), span))?,
+        _ => {
+            return Err(ParseError::new(
+                "capture pattern must be a string literal",
+                span,
+            ))
+        }
+    };
+    args.truncate(1);
+    let input = args.pop().expect("capture has an input argument");
+    Ok(Expr::Capture(arena.alloc(input), pattern, group))
+}
+
+/// Reads a path token, which starts from an enclosing Value when it starts with `$root` or `$parent`.
+fn path(text: &str) -> Expr<'_> {
+    for anchor in [Anchor::Root, Anchor::Parent] {
+        if let Some(rest) = text.strip_prefix(anchor.name()) {
+            return Expr::Anchored(anchor, Path::parse(rest));
+        }
+    }
+    Expr::Path(Path::parse(text))
+}
+
+/// Reads a number literal. A leading `+` and leading zeros are allowed, as they were before the
+/// filter syntax had a grammar, so `+18` and `0018` are both 18.
+fn number(text: &str) -> Option<Number> {
+    let text = text.strip_prefix('+').unwrap_or(text);
+    serde_json::from_str(text)
+        .ok()
+        .or_else(|| match text.parse::<i64>() {
+            Ok(n) => Some(Number::from(n)),
+            Err(_) => text.parse().ok().and_then(Number::from_f64),
+        })
+}
+
+/// Converts a duration literal such as `7d` or `1.5h` to milliseconds.
+fn duration_millis(value: &str, unit: &str) -> Option<i64> {
+    let unit_millis = match unit {
+        "ms" => 1.0,
+        "s" => 1_000.0,
+        "m" => 60_000.0,
+        "h" => 3_600_000.0,
+        "d" => 86_400_000.0,
+        "w" => 604_800_000.0,
+        _ => return None,
+    };
+    let millis = libm::round(value.parse::<f64>().ok()? * unit_millis);
+    (millis.abs() < i64::MAX as f64).then_some(millis as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_function_call() {
+        let filters = parse_filters("abs(.delta) > 0.01 AND 2*.count <= .limit").unwrap();
+        assert_eq!(filters[0].field.as_deref(), None);
+        let arena = &filters[0].arena;
+        match &filters[0].lhs {
+            Some(Expr::Call(Function::Abs, args)) => {
+                assert_eq!(arena[*args], [Expr::Path(Path::parse(".delta"))]);
+            }
+            lhs => panic!("unexpected lhs {lhs:?}"),
+        }
+        assert_eq!(filters[0].value, Some(json!(0.01)));
+        assert_eq!(filters[1].field.as_deref(), Some("count"));
+        assert_eq!(filters[1].multiplier_field, Some(2));
+        assert_eq!(filters[1].value_field.as_deref(), Some("limit"));
+        // `2*.count` compares the field itself, so nothing is left in the arena.
+        assert_eq!(filters[1].arena, Arena::default());
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        let value = |filter_string| parse_filters(filter_string).unwrap().remove(0).value;
+        assert_eq!(value(".age = 18"), Some(json!(18)));
+        assert_eq!(value(".age = +18"), Some(json!(18)));
+        assert_eq!(value(".age = 0018"), Some(json!(18)));
+        assert_eq!(value(".age = -007"), Some(json!(-7)));
+        assert_eq!(value(".ratio = 00.5"), Some(json!(0.5)));
+        // Quoted numbers are strings, and quoted paths are strings too.
+        assert_eq!(value(".code = '200'"), Some(json!("200")));
+        assert_eq!(value(".a = '.b'"), Some(json!(".b")));
+    }
+
+    #[test]
+    fn test_parse_capture() {
+        let filters = parse_filters(r"capture(.url, 'v(\d+)') = '2'").unwrap();
+        assert!(matches!(
+            &filters[0].lhs,
+            Some(Expr::Capture(_, pattern, Group::Index(1))) if pattern.as_str() == r"v(\d+)"
+        ));
+        assert!(parse_filters("capture(.url, '(') = '2'").is_err());
+        assert!(parse_filters("capture(.url, .pattern) = '2'").is_err());
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        let filters = parse_filters(".ends_at < .starts_at + 1.5h - 90s").unwrap();
+        let arena = &filters[0].arena;
+        let Some(Expr::Shift(inner, -90_000)) = &filters[0].rhs else {
+            panic!("unexpected rhs {:?}", filters[0].rhs);
+        };
+        let Expr::Shift(starts_at, 5_400_000) = &arena[*inner] else {
+            panic!("unexpected shift {:?}", arena[*inner]);
+        };
+        assert_eq!(arena[*starts_at], Expr::Path(Path::parse(".starts_at")));
+        assert!(parse_filters(".a < .b - 7parsecs").is_err());
+        assert!(parse_filters(".a < 7d").is_err());
+    }
+
+    #[cfg(feature = "geo")]
+    #[test]
+    fn test_parse_within() {
+        let filters = parse_filters(".location WITHIN 5km OF (52.52, -13.40)").unwrap();
+        assert!(matches!(
+            &filters[0].compound,
+            Some(Compound::Within { radius_m, center, .. })
+                if *radius_m == 5_000.0 && *center == (52.52, -13.4)
+        ));
+        assert!(parse_filters(".location WITHIN 5 OF (52.52, 13.40)").is_err());
+        assert!(parse_filters(".location WITHIN 5km OF (152.52, 13.40)").is_err());
+        assert!(parse_filters(".location WITHIN 5km (52.52, 13.40)").is_err());
+    }
+
+    #[test]
+    fn test_parse_fuzzy() {
+        let filters = parse_filters(".name FUZZY('jonh', 2)").unwrap();
+        assert!(matches!(
+            &filters[0].compound,
+            Some(Compound::Fuzzy { target: Expr::Literal(target), max_distance: 2, .. })
+                if target == "jonh"
+        ));
+        assert!(parse_filters(".name FUZZY('jonh', -1)").is_err());
+        assert!(parse_filters(".name FUZZY('jonh')").is_err());
+    }
+
+    #[test]
+    fn test_parse_lists() {
+        let filters =
+            parse_filters(".roles HAS_ALL ('admin', 2, null) AND .role SUBSET_OF ()").unwrap();
+        assert_eq!(filters[0].value, Some(json!(["admin", 2, null])));
+        assert_eq!(filters[1].value, Some(json!([])));
+        let filters = parse_filters(".roles HAS_ANY ('admin', .role)").unwrap();
+        let Some(Expr::List(items)) = &filters[0].rhs else {
+            panic!("unexpected rhs {:?}", filters[0].rhs);
+        };
+        assert_eq!(
+            filters[0].arena[*items],
+            [
+                Expr::Literal(json!("admin")),
+                Expr::Path(Path::parse(".role"))
+            ]
+        );
+        assert!(parse_filters(".roles HAS_ANY ('admin' 'billing')").is_err());
+        assert!(parse_filters("('admin') HAS_ANY .roles").is_err());
+    }
+
+    #[test]
+    fn test_parse_quantified() {
+        let filters =
+            parse_filters("ANY .orders WHERE (.total > 100 AND .status = 'paid') AND ANY = 1")
+                .unwrap();
+        assert!(matches!(
+            &filters[0].compound,
+            Some(Compound::Quantified { all: false, filters, .. }) if filters.len() == 2
+        ));
+        assert_eq!(filters[1].field.as_deref(), Some("ANY"));
+        assert!(parse_filters("ALL .orders (.total > 100)").is_err());
+    }
+
+    #[test]
+    fn test_parse_weighted() {
+        let filters =
+            parse_filters("MATCH >= 0.7 WHERE (.a = 1 WEIGHT 2 AND .b = 2) AND MATCH = 1").unwrap();
+        assert!(matches!(
+            &filters[0].compound,
+            Some(Compound::Match { comparison: ">=", threshold, filters })
+                if *threshold == 0.7 && filters[0].weight == Some(2.0) && filters[1].weight.is_none()
+        ));
+        assert_eq!(filters[1].field.as_deref(), Some("MATCH"));
+        assert!(parse_filters("MATCH >= 0.7 (.a = 1)").is_err());
+        assert!(parse_filters("MATCH SEMVER= 0.7 WHERE .a = 1").is_err());
+        assert!(parse_filters(".a = 1 WEIGHT -1").is_err());
+        assert!(parse_filters(".a = 1 WEIGHT .b").is_err());
+    }
+
+    #[test]
+    fn test_parse_should() {
+        let filters = parse_filters(
+            "SHOULD .a = 1 AND SHOULD ANY .b WHERE .c = 1 WEIGHT 2 AND SHOULD = 1 AND .d = 1",
+        )
+        .unwrap();
+        let should: Vec<bool> = filters.iter().map(|filter| filter.should).collect();
+        assert_eq!(should, [true, true, false, false]);
+        assert_eq!(filters[2].field.as_deref(), Some("SHOULD"));
+        assert_eq!(filters[1].weight, None);
+        assert!(parse_filters("SHOULD").is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_refs() {
+        let resolve = |name: &str| match name {
+            "adults" => Ok(parse_filters(".age >= 18 AND .id != 0")
+                .unwrap()
+                .into_iter()
+                .map(Filter::into_owned)
+                .collect()),
+            _ => Err(format!("unknown filter `@{name}`")),
+        };
+        let filters = parse_with_refs(
+            ".a = 1 AND @adults AND .b = 2",
+            &resolve,
+            ParseLimits::UNLIMITED,
+        )
+        .unwrap();
+        let fields: Vec<_> = filters
+            .iter()
+            .map(|filter| filter.field.as_deref())
+            .collect();
+        assert_eq!(fields, [Some("a"), Some("age"), Some("id"), Some("b")]);
+        let error = parse_with_refs("@kids", &resolve, ParseLimits::UNLIMITED).unwrap_err();
+        assert_eq!(error.to_string(), "unknown filter `@kids` at 0..5");
+        assert!(parse_filters("@adults").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_filters("round(.a, .b) = 1").is_err());
+        assert!(parse_filters("nope(.a) = 1").is_err());
+        assert!(parse_filters(".a >").is_err());
+        assert!(parse_filters(".a = 1 .b = 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let input = ".a >< 1 AND .b = 2 AND .c ~ 3 AND .d = 1 .e AND .f = 'x";
+        assert_eq!(
+            parse_recovering(input).unwrap_err(),
+            [
+                ParseError::new("expected a value", Span { start: 4, end: 5 }),
+                ParseError::new("unexpected character `~`", Span { start: 26, end: 27 }),
+                ParseError::new("expected `AND`", Span { start: 41, end: 43 }),
+                ParseError::new("unterminated string", Span { start: 53, end: 55 }),
+            ]
+        );
+        // A keyword isn't a value, and is left for the next clause.
+        assert_eq!(
+            parse_recovering(".a > AND .b = 1 AND .c >").unwrap_err(),
+            [
+                ParseError::new("expected a value", Span { start: 5, end: 8 }),
+                ParseError::new("expected a value", Span { start: 24, end: 24 }),
+            ]
+        );
+        for input in [".a = then", ".a = else", ".a = WHERE", ".a IN (1, AND)"] {
+            assert!(parse_filters(input).is_err(), "{input}");
+        }
+        let filters = parse_filters(".a = SHOULD").unwrap();
+        assert_eq!(filters[0].value, Some(json!("SHOULD")));
+        // An `AND` in the condition of a conditional, or inside parentheses, doesn't end the clause
+        // with the error.
+        assert_eq!(
+            parse_recovering("if .a = 1 AND .b = 2 then .c > AND .d = 1").unwrap_err(),
+            [ParseError::new(
+                "expected a value",
+                Span { start: 31, end: 34 }
+            )]
+        );
+        assert_eq!(
+            parse_recovering("ANY .t WHERE (.x > AND .y = 1) AND .z = ").unwrap_err(),
+            [
+                ParseError::new("expected a value", Span { start: 19, end: 22 }),
+                ParseError::new("expected a value", Span { start: 40, end: 40 }),
+            ]
+        );
+        let input = ".a = 1 AND ANY .t WHERE (.x > 2 AND .y = 'z')";
+        assert_eq!(
+            format!("{:?}", parse_recovering(input).unwrap()),
+            format!("{:?}", parse_filters(input).unwrap())
+        );
+        assert!(parse_recovering("").unwrap().is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_with_vars() {
+        let vars = HashMap::from([
+            ("min".to_string(), json!(10)),
+            ("name".to_string(), json!("x' OR .admin = true")),
+            ("tags".to_string(), json!(["a", "b"])),
+        ]);
+        let filters = parse_with_vars(
+            ".n > {{min}} AND .name = {{name}} AND .tags HAS_ANY {{tags}}",
+            &vars,
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 3);
+        assert_eq!(filters[0].value, Some(json!(10)));
+        assert_eq!(filters[1].value, Some(json!("x' OR .admin = true")));
+        assert_eq!(filters[2].value, Some(json!(["a", "b"])));
+        assert_eq!(
+            parse_with_vars(".n > {{max}}", &vars).unwrap_err(),
+            ParseError::new(
+                "no value for placeholder `{{max}}`",
+                Span { start: 5, end: 12 }
+            )
+        );
+        assert!(parse_filters(".n > {{min}}").is_err());
+    }
+
+    #[test]
+    fn test_parse_with_limits() {
+        let limits = ParseLimits {
+            max_length: 64,
+            max_clauses: 3,
+            max_depth: 3,
+            max_string_length: 4,
+            ..ParseLimits::default()
+        };
+        assert!(parse_with_limits("ANY .items WHERE .qty > abs(.min)", &limits).is_ok());
+        assert_eq!(
+            parse_with_limits("ANY .items WHERE .qty > abs(abs(.min))", &limits).unwrap_err(),
+            ParseError::new("nested more than 3 deep", Span { start: 28, end: 31 })
+        );
+        assert_eq!(
+            parse_with_limits(".a = 1 AND .b = 2 AND .c = 3 AND .d = 4", &limits)
+                .unwrap_err()
+                .message,
+            "more than 3 clauses"
+        );
+        // The conditional itself is a clause, as well as those inside it.
+        assert!(parse_with_limits("if .a = 1 then .b = 2", &limits).is_ok());
+        assert!(parse_with_limits("if .a = 1 then .b = 2 else .c = 3", &limits).is_err());
+        assert!(parse_with_limits(".a = 'abcd'", &limits).is_ok());
+        assert_eq!(
+            parse_with_limits(".a = 'abcde'", &limits)
+                .unwrap_err()
+                .message,
+            "string longer than 4 bytes"
+        );
+        assert!(parse_with_limits(".a DEEP_EQ [1, 2]", &limits).is_err());
+        assert_eq!(
+            parse_with_limits(&".a".repeat(40), &limits)
+                .unwrap_err()
+                .span,
+            Span { start: 64, end: 80 }
+        );
+    }
+
+    #[test]
+    fn test_parse_regex_limits() {
+        let limits = ParseLimits {
+            max_regex_size: 10 * 1024,
+            ..ParseLimits::default()
+        };
+        assert!(parse_with_limits(r"capture(.url, 'v(\d+)') = '2'", &limits).is_ok());
+        let error = parse_with_limits(r"capture(.s, '\w{20}') = 'a'", &limits).unwrap_err();
+        assert!(error.message.starts_with("invalid regex"));
+        assert!(parse_filters(r"capture(.s, '\w{20}') = 'a'").is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_line_col() {
+        let input = ".a = 1\nAND .é = 2\nAND .b >";
+        let error = parse_filters(input).unwrap_err();
+        assert_eq!(error.line_col(input), (3, 9));
+        let input = ".a = 1 AND .b = ";
+        assert_eq!(parse_filters(input).unwrap_err().line_col(input), (1, 17));
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let partial = parse_partial(".status = 'paid' AND .price >");
+        assert_eq!(partial.filters.len(), 1);
+        assert_eq!(partial.filters[0].field.as_deref(), Some("status"));
+        let error = partial.error.unwrap();
+        assert_eq!(error.message, "expected a value");
+        assert_eq!(error.span, Span { start: 29, end: 29 });
+        assert_eq!(partial.expected, [Expected::Value]);
+
+        let partial = parse_partial(".a = 1 AND .b");
+        assert_eq!(partial.filters.len(), 1);
+        assert_eq!(partial.expected, [Expected::Operator]);
+        assert_eq!(parse_partial(".a = 1 AND").expected, [Expected::Field]);
+        assert_eq!(
+            parse_partial(".a = 'unfinished").expected,
+            [Expected::Value]
+        );
+        assert_eq!(
+            parse_partial("if .a = 1").expected,
+            [Expected::Keyword("AND"), Expected::Keyword("then")]
+        );
+        assert_eq!(
+            parse_partial("ANY .o WHERE (.a = 1").expected,
+            [Expected::Keyword("AND"), Expected::CloseParen]
+        );
+
+        let partial = parse_partial(".a = 1 AND .b > 2");
+        assert!(partial.is_complete());
+        assert_eq!(partial.filters.len(), 2);
+        assert_eq!(partial.expected, [Expected::Keyword("AND"), Expected::End]);
+    }
+}
@@ -0,0 +1,317 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::eval;
+use crate::update;
+
+/// An error found while reading or applying a patch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchError {
+    message: String,
+}
+
+impl PatchError {
+    fn new(message: impl Into<String>) -> PatchError {
+        PatchError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for PatchError {}
+
+/// A change to a JSON document, either a JSON merge patch or a JSON patch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Patch {
+    /// A JSON merge patch (RFC 7386): the fields of the patch are set recursively, and fields that
+    /// are null in the patch are removed.
+    Merge(Value),
+    /// A JSON patch (RFC 6902): a list of operations, applied in order. If one fails, the document
+    /// is left unchanged.
+    Json(Vec<PatchOperation>),
+}
+
+/// A JSON patch operation. Paths are JSON pointers (RFC 6901) such as `/items/0/price`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl Patch {
+    /// Reads a JSON patch document, an array of operations such as
+    /// `[{"op": "replace", "path": "/status", "value": "done"}]`.
+    pub fn json(patch: &Value) -> Result<Patch, PatchError> {
+        let operations = patch
+            .as_array()
+            .ok_or_else(|| PatchError::new("a JSON patch must be an array of operations"))?;
+        operations
+            .iter()
+            .map(PatchOperation::from_value)
+            .collect::<Result<_, _>>()
+            .map(Patch::Json)
+    }
+
+    /// Applies the patch to a document.
+    pub fn apply(&self, v: &mut Value) -> Result<(), PatchError> {
+        match self {
+            Patch::Merge(patch) => {
+                update::merge_patch(v, patch);
+                Ok(())
+            }
+            Patch::Json(operations) => {
+                // Operations are applied to a copy so a failure leaves the document unchanged.
+                let mut patched = v.clone();
+                for operation in operations {
+                    operation.apply(&mut patched)?;
+                }
+                *v = patched;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl PatchOperation {
+    fn from_value(v: &Value) -> Result<PatchOperation, PatchError> {
+        let member = |name: &str| {
+            v.get(name)
+                .ok_or_else(|| PatchError::new(format!("patch operation is missing `{name}`")))
+        };
+        let string = |name: &str| {
+            member(name)?
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| PatchError::new(format!("`{name}` must be a string")))
+        };
+        let path = string("path")?;
+        Ok(match string("op")?.as_str() {
+            "add" => PatchOperation::Add {
+                path,
+                value: member("value")?.clone(),
+            },
+            "remove" => PatchOperation::Remove { path },
+            "replace" => PatchOperation::Replace {
+                path,
+                value: member("value")?.clone(),
+            },
+            "move" => PatchOperation::Move {
+                from: string("from")?,
+                path,
+            },
+            "copy" => PatchOperation::Copy {
+                from: string("from")?,
+                path,
+            },
+            "test" => PatchOperation::Test {
+                path,
+                value: member("value")?.clone(),
+            },
+            op => return Err(PatchError::new(format!("unknown patch operation `{op}`"))),
+        })
+    }
+
+    fn apply(&self, v: &mut Value) -> Result<(), PatchError> {
+        match self {
+            PatchOperation::Add { path, value } => add(v, &pointer(path)?, value.clone()),
+            PatchOperation::Remove { path } => remove(v, &pointer(path)?).map(|_| ()),
+            PatchOperation::Replace { path, value } => {
+                let tokens = pointer(path)?;
+                let target = get_mut(v, &tokens)
+                    .ok_or_else(|| PatchError::new(format!("no value at `{path}`")))?;
+                *target = value.clone();
+                Ok(())
+            }
+            PatchOperation::Move { from, path } => {
+                if path.starts_with(&format!("{from}/")) {
+                    return Err(PatchError::new(format!(
+                        "cannot move `{from}` into one of its children"
+                    )));
+                }
+                let value = remove(v, &pointer(from)?)?;
+                add(v, &pointer(path)?, value)
+            }
+            PatchOperation::Copy { from, path } => {
+                let value = get_mut(v, &pointer(from)?)
+                    .ok_or_else(|| PatchError::new(format!("no value at `{from}`")))?
+                    .clone();
+                add(v, &pointer(path)?, value)
+            }
+            PatchOperation::Test { path, value } => match get_mut(v, &pointer(path)?) {
+                Some(actual) if eval::deep_eq(actual, value, false) => Ok(()),
+                _ => Err(PatchError::new(format!("test of `{path}` failed"))),
+            },
+        }
+    }
+}
+
+/// Splits a JSON pointer into its reference tokens, resolving `~1` and `~0`.
+fn pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = path
+        .strip_prefix('/')
+        .ok_or_else(|| PatchError::new(format!("invalid JSON pointer `{path}`")))?;
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn get_mut<'v>(v: &'v mut Value, tokens: &[String]) -> Option<&'v mut Value> {
+    tokens.iter().try_fold(v, |v, token| match v {
+        Value::Object(map) => map.get_mut(token),
+        Value::Array(elements) => elements.get_mut(index(token)?),
+        _ => None,
+    })
+}
+
+/// Reads an array index, which has no sign or leading zeros.
+fn index(token: &str) -> Option<usize> {
+    if token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+    if !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// The value holding the last token of a path, and that token. Fails for the root.
+fn parent<'v, 't>(
+    v: &'v mut Value,
+    tokens: &'t [String],
+) -> Result<(&'v mut Value, &'t str), PatchError> {
+    let (last, parents) = tokens
+        .split_last()
+        .ok_or_else(|| PatchError::new("the whole document cannot be removed"))?;
+    let parent = get_mut(v, parents)
+        .ok_or_else(|| PatchError::new(format!("no value at `/{}`", parents.join("/"))))?;
+    Ok((parent, last))
+}
+
+fn add(v: &mut Value, tokens: &[String], value: Value) -> Result<(), PatchError> {
+    if tokens.is_empty() {
+        *v = value;
+        return Ok(());
+    }
+    match parent(v, tokens)? {
+        (Value::Object(map), key) => {
+            map.insert(key.to_string(), value);
+            Ok(())
+        }
+        (Value::Array(elements), "-") => {
+            elements.push(value);
+            Ok(())
+        }
+        (Value::Array(elements), token) => match index(token) {
+            Some(i) if i <= elements.len() => {
+                elements.insert(i, value);
+                Ok(())
+            }
+            _ => Err(PatchError::new(format!("invalid array index `{token}`"))),
+        },
+        (_, token) => Err(PatchError::new(format!(
+            "cannot add `{token}` to a value that is not an object or array"
+        ))),
+    }
+}
+
+fn remove(v: &mut Value, tokens: &[String]) -> Result<Value, PatchError> {
+    let removed = match parent(v, tokens)? {
+        (Value::Object(map), key) => map.remove(key),
+        (Value::Array(elements), token) => match index(token) {
+            Some(i) if i < elements.len() => Some(elements.remove(i)),
+            _ => None,
+        },
+        _ => None,
+    };
+    removed.ok_or_else(|| PatchError::new(format!("no value at `/{}`", tokens.join("/"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patched(mut v: Value, patch: Value) -> Result<Value, PatchError> {
+        Patch::json(&patch)?.apply(&mut v)?;
+        Ok(v)
+    }
+
+    #[test]
+    fn test_json_patch() {
+        // Examples from RFC 6902, appendix A.
+        assert_eq!(
+            patched(
+                json!({ "foo": ["bar", "baz"] }),
+                json!([{ "op": "add", "path": "/foo/1", "value": "qux" }])
+            ),
+            Ok(json!({ "foo": ["bar", "qux", "baz"] }))
+        );
+        assert_eq!(
+            patched(
+                json!({ "baz": "qux", "foo": "bar" }),
+                json!([
+                    { "op": "replace", "path": "/baz", "value": "boo" },
+                    { "op": "remove", "path": "/foo" },
+                    { "op": "add", "path": "/hello", "value": ["world"] },
+                ])
+            ),
+            Ok(json!({ "baz": "boo", "hello": ["world"] }))
+        );
+        assert_eq!(
+            patched(
+                json!({ "foo": { "bar": "baz", "waldo": "fred" }, "qux": { "corge": "grault" } }),
+                json!([{ "op": "move", "from": "/foo/waldo", "path": "/qux/thud" }])
+            ),
+            Ok(json!({ "foo": { "bar": "baz" }, "qux": { "corge": "grault", "thud": "fred" } }))
+        );
+        assert_eq!(
+            patched(
+                json!({ "a/b": [1], "m~n": 2 }),
+                json!([
+                    { "op": "copy", "from": "/m~0n", "path": "/a~1b/-" },
+                    { "op": "test", "path": "/a~1b", "value": [1, 2.0] },
+                ])
+            ),
+            Ok(json!({ "a/b": [1, 2], "m~n": 2 }))
+        );
+    }
+
+    #[test]
+    fn test_failed_json_patch_changes_nothing() {
+        let mut v = json!({ "status": "open" });
+        let patch = Patch::json(&json!([
+            { "op": "replace", "path": "/status", "value": "done" },
+            { "op": "test", "path": "/owner", "value": "ada" },
+        ]))
+        .unwrap();
+        assert!(patch.apply(&mut v).is_err());
+        assert_eq!(v, json!({ "status": "open" }));
+
+        assert!(Patch::json(&json!([{ "op": "frobnicate", "path": "/a" }])).is_err());
+        assert!(Patch::json(&json!([{ "op": "add", "path": "/a" }])).is_err());
+        assert!(patched(
+            json!([1]),
+            json!([{ "op": "add", "path": "/01", "value": 0 }])
+        )
+        .is_err());
+        assert!(patched(json!({}), json!([{ "op": "remove", "path": "" }])).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::{apply_with, sort, ApplyOptions, Filter, Projection, SortKey};
+
+/// A chain of stages that JSON Values flow through: filters, maps, sorts, projections, skips and
+/// limits, in the order they were added.
+///
+/// Values are processed lazily, one at a time, so a pipeline without a sort stops reading its input
+/// once a limit is reached. A sort stage has to read every Value that reaches it before passing the
+/// first one on.
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    stages: Vec<Stage<'a>>,
+    options: ApplyOptions,
+}
+
+enum Stage<'a> {
+    Filter(Vec<Filter<'a>>),
+    Map(Box<dyn Fn(Value) -> Value + 'a>),
+    Sort(Vec<SortKey>),
+    Project(Projection),
+    Skip(usize),
+    Limit(usize),
+}
+
+impl<'a> Pipeline<'a> {
+    /// Creates a pipeline that passes every Value through unchanged.
+    pub fn new() -> Pipeline<'a> {
+        Pipeline::default()
+    }
+
+    /// Sets the options filter stages are evaluated with.
+    pub fn options(self, options: ApplyOptions) -> Pipeline<'a> {
+        Pipeline { options, ..self }
+    }
+
+    /// Keeps only the Values that pass every filter.
+    pub fn filter(self, filters: Vec<Filter<'a>>) -> Pipeline<'a> {
+        self.stage(Stage::Filter(filters))
+    }
+
+    /// Replaces each Value with the result of a function.
+    pub fn map(self, f: impl Fn(Value) -> Value + 'a) -> Pipeline<'a> {
+        self.stage(Stage::Map(Box::new(f)))
+    }
+
+    /// Orders the Values by one or more keys, like [`crate::sort_by`].
+    pub fn sort(self, keys: Vec<SortKey>) -> Pipeline<'a> {
+        self.stage(Stage::Sort(keys))
+    }
+
+    /// Keeps only the paths of a projection in each Value.
+    pub fn project(self, projection: Projection) -> Pipeline<'a> {
+        self.stage(Stage::Project(projection))
+    }
+
+    /// Drops the first `n` Values.
+    pub fn skip(self, n: usize) -> Pipeline<'a> {
+        self.stage(Stage::Skip(n))
+    }
+
+    /// Passes on at most `n` Values.
+    pub fn limit(self, n: usize) -> Pipeline<'a> {
+        self.stage(Stage::Limit(n))
+    }
+
+    fn stage(mut self, stage: Stage<'a>) -> Pipeline<'a> {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Runs Values through the pipeline, returning an iterator over the Values that come out.
+    pub fn run<'p, I>(&'p self, values: I) -> Box<dyn Iterator<Item = Value> + 'p>
+    where
+        I: IntoIterator<Item = Value>,
+        I::IntoIter: 'p,
+    {
+        let mut values: Box<dyn Iterator<Item = Value> + 'p> = Box::new(values.into_iter());
+        for stage in &self.stages {
+            values = match stage {
+                Stage::Filter(filters) => {
+                    Box::new(values.filter(move |v| apply_with(v, filters, &self.options)))
+                }
+                Stage::Map(f) => Box::new(values.map(f)),
+                Stage::Sort(keys) => {
+                    let mut input = Some(values);
+                    let mut sorted = Vec::new().into_iter();
+                    Box::new(core::iter::from_fn(move || {
+                        if let Some(input) = input.take() {
+                            let mut all: Vec<Value> = input.collect();
+                            sort::sort_by(&mut all, keys);
+                            sorted = all.into_iter();
+                        }
+                        sorted.next()
+                    }))
+                }
+                Stage::Project(projection) => Box::new(values.map(|v| projection.project(&v))),
+                Stage::Skip(n) => Box::new(values.skip(*n)),
+                Stage::Limit(n) => Box::new(values.take(*n)),
+            };
+        }
+        values
+    }
+}
+
+impl fmt::Debug for Stage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Filter(filters) => f.debug_tuple("Filter").field(filters).finish(),
+            Stage::Map(_) => f.write_str("Map(..)"),
+            Stage::Sort(keys) => f.debug_tuple("Sort").field(keys).finish(),
+            Stage::Project(projection) => f.debug_tuple("Project").field(projection).finish(),
+            Stage::Skip(n) => f.debug_tuple("Skip").field(n).finish(),
+            Stage::Limit(n) => f.debug_tuple("Limit").field(n).finish(),
+        }
+    }
+}
+
+impl fmt::Debug for Pipeline<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.stages)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_run() {
+        let values = (1..=6).map(|i| json!({ "id": i, "score": (i * 7) % 10, "secret": "x" }));
+        let pipeline = Pipeline::new()
+            .filter(parse(".score >= 4").unwrap())
+            .map(|mut v| {
+                v["double"] = json!(v["score"].as_i64().unwrap() * 2);
+                v
+            })
+            .sort(vec![SortKey::desc(".score")])
+            .skip(1)
+            .limit(2)
+            .project(Projection::new([".id", ".double"]));
+        assert_eq!(
+            pipeline.run(values).collect::<Vec<_>>(),
+            [
+                json!({ "id": 1, "double": 14 }),
+                json!({ "id": 5, "double": 10 })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_is_lazy() {
+        let read = Cell::new(0);
+        let values = (0..100).map(|i| {
+            read.set(read.get() + 1);
+            json!({ "i": i })
+        });
+        let pipeline = Pipeline::new().filter(parse(".i > 9").unwrap()).limit(2);
+        assert_eq!(pipeline.run(values).count(), 2);
+        assert_eq!(read.get(), 12);
+    }
+}
@@ -0,0 +1,168 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::compiled::{self, CompiledFilter};
+use crate::expr::{Expr, Segment};
+use crate::format::Clause;
+use crate::Filter;
+
+/// How a compiled filter is evaluated, returned by [`crate::explain`], in the spirit of SQL's `EXPLAIN`.
+///
+/// Displaying a plan lists its steps, one per line, followed by the total cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    /// The top-level clauses, in the order they are evaluated.
+    pub steps: Vec<PlanStep>,
+    /// The estimated cost of evaluating every clause against one Value.
+    pub cost: u32,
+}
+
+/// A top-level clause of a [`Plan`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanStep {
+    /// The clause, written in filter string syntax.
+    pub clause: String,
+    /// The estimated cost of evaluating the clause against one Value, in arbitrary units where looking
+    /// up a field and comparing it with a literal costs 2.
+    pub cost: u32,
+    /// Whether an index could answer the clause, or every Value has to be scanned.
+    pub access: Access,
+}
+
+/// How the Values passing a clause can be found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// The clause compares a field with a literal using `=` or a range operator, or with a list of
+    /// literals using `IN` or `CONTAINS`, so an index on the field, such as a B-tree, could find the
+    /// passing Values.
+    Index { field: String },
+    /// The clause has to be evaluated against every Value.
+    Scan,
+}
+
+pub(crate) fn explain(compiled: &CompiledFilter) -> Plan {
+    let steps: Vec<PlanStep> = compiled
+        .filters()
+        .iter()
+        .map(|filter| PlanStep {
+            clause: Clause(filter).to_string(),
+            cost: compiled::cost(filter),
+            access: access(filter),
+        })
+        .collect();
+    Plan {
+        cost: steps.iter().map(|step| step.cost).sum(),
+        steps,
+    }
+}
+
+fn access(filter: &Filter) -> Access {
+    let plain = !filter.should
+        && !filter.optional
+        && filter.compound.is_none()
+        && filter.multiplier_field.is_none()
+        && filter.multiplier_value.is_none();
+    // `['a', 'b'] CONTAINS .field` looks each literal up, like `.field IN ('a', 'b')`.
+    if filter.operator == "CONTAINS" && matches!(filter.lhs, Some(Expr::Literal(Value::Array(_)))) {
+        return match indexed_field(filter.rhs.as_ref(), filter.value_field.as_deref()) {
+            Some(field) if plain && filter.value.is_none() => Access::Index { field },
+            _ => Access::Scan,
+        };
+    }
+    let indexable = plain
+        && match filter.operator.as_ref() {
+            "=" | ">=" | ">" | "<=" | "<" => true,
+            "IN" => matches!(filter.value, Some(Value::Array(_))),
+            _ => false,
+        }
+        && filter.value_field.is_none()
+        && matches!(filter.rhs, None | Some(Expr::Literal(_)));
+    match indexed_field(filter.lhs.as_ref(), filter.field.as_deref()) {
+        Some(field) if indexable => Access::Index { field },
+        _ => Access::Scan,
+    }
+}
+
+/// The path an index would be on for one side of a clause, which is either an expression or a field.
+fn indexed_field(expr: Option<&Expr>, field: Option<&str>) -> Option<String> {
+    match expr {
+        Some(Expr::Path(path)) if !path.segments.contains(&Segment::Wildcard) => {
+            Some(path.to_string())
+        }
+        Some(_) => None,
+        None => field.map(|field| format!(".{field}")),
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            write!(f, "{}. {} (cost {}, ", i + 1, step.clause, step.cost)?;
+            match &step.access {
+                Access::Index { field } => writeln!(f, "index on {field})")?,
+                Access::Scan => writeln!(f, "full scan)")?,
+            }
+        }
+        write!(f, "Total cost: {}", self.cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_explain() {
+        let compiled = CompiledFilter::new(
+            parse(".bio MATCHES_TEXT 'rust' AND .user.age >= 18 AND .tags CONTAINS 'a' AND abs(.x) < 2 AND .a = .b")
+                .unwrap(),
+        );
+        let plan = explain(&compiled);
+        assert_eq!(
+            plan.to_string(),
+            "1. .user.age >= 18 (cost 2, index on .user.age)\n\
+             2. .tags CONTAINS 'a' (cost 3, full scan)\n\
+             3. abs(.x) < 2 (cost 3, full scan)\n\
+             4. .a = .b (cost 3, full scan)\n\
+             5. .bio MATCHES_TEXT 'rust' (cost 7, full scan)\n\
+             Total cost: 18"
+        );
+    }
+
+    #[test]
+    fn test_explain_lists() {
+        let access = |filter_string: &str| {
+            let compiled = CompiledFilter::new(parse(filter_string).unwrap());
+            explain(&compiled).steps.remove(0).access
+        };
+        let status = Access::Index {
+            field: ".status".to_string(),
+        };
+        assert_eq!(access(".status IN ('a', 'b')"), status);
+        assert_eq!(access(r#"["a", "b"] CONTAINS .status"#), status);
+        assert_eq!(access(".status IN 'a'"), Access::Scan);
+        assert_eq!(access(".tags CONTAINS 'a'"), Access::Scan);
+        assert_eq!(access(r#"["a"] CONTAINS .items[*].status"#), Access::Scan);
+    }
+
+    #[test]
+    fn test_explain_compound_clauses() {
+        let compiled = CompiledFilter::new(
+            parse(r#"ANY .items[*].tags WHERE (.name = "it's") AND if .kind = 'a' then .n > 1 else (.m < 2)"#)
+                .unwrap(),
+        );
+        let clauses: Vec<_> = explain(&compiled)
+            .steps
+            .into_iter()
+            .map(|s| s.clause)
+            .collect();
+        assert_eq!(clauses.len(), 2);
+        assert_eq!(clauses[0], "if .kind = 'a' then .n > 1 else (.m < 2)");
+        assert_eq!(clauses[1], r"ANY .items[*].tags WHERE (.name = 'it\'s')");
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::expr::Function;
+use crate::lexer::is_word_char;
+use crate::parser::OPERATORS;
+
+/// An operator written between the two sides of a clause, such as `.location NEAR .office`, shipped by
+/// another crate and registered in a [`PluginHost`].
+pub trait OperatorPlugin: Send + Sync {
+    /// The name of the operator in filters, a word such as `NEAR` or `SHA256_OF`.
+    fn name(&self) -> &str;
+
+    /// Returns whether the value on the left of a clause passes the operator with the value on its
+    /// right. A missing side fails the clause without calling it.
+    fn matches(&self, left: &Value, right: &Value) -> bool;
+}
+
+/// A function called in the expressions of clauses, such as `sha256(.email) = .hash`, shipped by
+/// another crate and registered in a [`PluginHost`].
+pub trait FunctionPlugin: Send + Sync {
+    /// The name of the function in filters, a word such as `sha256`.
+    fn name(&self) -> &str;
+
+    /// The smallest and largest numbers of arguments the function takes, checked when parsing. Any
+    /// number by default.
+    fn arity(&self) -> (usize, usize) {
+        (0, usize::MAX)
+    }
+
+    /// Computes the value of a call, or returns `None` if the arguments have the wrong types, which
+    /// fails the clause. A missing argument fails the clause without calling it.
+    fn call(&self, args: &[Value]) -> Option<Value>;
+}
+
+/// The operators and functions of plugins, parsed by [`crate::parse_with_plugins`] and evaluated with
+/// the host in [`crate::ApplyOptions::plugins`].
+///
+/// A crate shipping a pack of plugins exposes a function adding them, such as
+/// `fn register(host: PluginHost) -> PluginHost`, for [`PluginHost::with_pack`].
+#[derive(Clone, Default)]
+pub struct PluginHost {
+    operators: Vec<Arc<dyn OperatorPlugin>>,
+    functions: Vec<Arc<dyn FunctionPlugin>>,
+}
+
+impl PluginHost {
+    /// Creates a host without plugins.
+    pub fn new() -> PluginHost {
+        PluginHost::default()
+    }
+
+    /// Adds an operator, replacing an earlier one of the same name. Panics if the name isn't a word
+    /// or is taken by a built-in operator.
+    pub fn with_operator(mut self, plugin: impl OperatorPlugin + 'static) -> PluginHost {
+        let name = plugin.name();
+        assert!(
+            is_name(name) && name != "AND",
+            "`{name}` can't name an operator"
+        );
+        assert!(
+            !OPERATORS.contains(&name),
+            "`{name}` is a built-in operator"
+        );
+        self.operators.retain(|known| known.name() != name);
+        self.operators.push(Arc::new(plugin));
+        self
+    }
+
+    /// Adds a function, replacing an earlier one of the same name. Panics if the name isn't a word
+    /// or is taken by a built-in function.
+    pub fn with_function(mut self, plugin: impl FunctionPlugin + 'static) -> PluginHost {
+        let name = plugin.name();
+        assert!(is_name(name), "`{name}` can't name a function");
+        assert!(!is_built_in(name), "`{name}` is a built-in function");
+        self.functions.retain(|known| known.name() != name);
+        self.functions.push(Arc::new(plugin));
+        self
+    }
+
+    /// Adds the plugins of a pack, given as the function adding them.
+    pub fn with_pack(self, register: impl FnOnce(PluginHost) -> PluginHost) -> PluginHost {
+        register(self)
+    }
+
+    /// Returns whether the host has no plugins.
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty() && self.functions.is_empty()
+    }
+
+    pub(crate) fn operator(&self, name: &str) -> Option<&dyn OperatorPlugin> {
+        self.operators
+            .iter()
+            .find(|plugin| plugin.name() == name)
+            .map(|plugin| plugin.as_ref())
+    }
+
+    pub(crate) fn function(&self, name: &str) -> Option<&dyn FunctionPlugin> {
+        self.functions
+            .iter()
+            .find(|plugin| plugin.name() == name)
+            .map(|plugin| plugin.as_ref())
+    }
+}
+
+/// Returns whether a plugin function may be named `name`.
+#[cfg(feature = "wasm")]
+pub(crate) fn is_function_name(name: &str) -> bool {
+    is_name(name) && !is_built_in(name)
+}
+
+fn is_built_in(name: &str) -> bool {
+    Function::from_name(name).is_some() || matches!(name, "coalesce" | "capture" | "now")
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && name.chars().all(is_word_char)
+}
+
+impl fmt::Debug for PluginHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operators: Vec<&str> = self.operators.iter().map(|p| p.name()).collect();
+        let functions: Vec<&str> = self.functions.iter().map(|p| p.name()).collect();
+        f.debug_struct("PluginHost")
+            .field("operators", &operators)
+            .field("functions", &functions)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_with, format, parse, parse_with_plugins, ApplyOptions};
+    use serde_json::json;
+
+    struct Near;
+
+    impl OperatorPlugin for Near {
+        fn name(&self) -> &str {
+            "NEAR"
+        }
+
+        fn matches(&self, left: &Value, right: &Value) -> bool {
+            match (left.as_f64(), right.as_f64()) {
+                (Some(l), Some(r)) => (l - r).abs() <= 1.0,
+                _ => false,
+            }
+        }
+    }
+
+    struct Reverse;
+
+    impl FunctionPlugin for Reverse {
+        fn name(&self) -> &str {
+            "reverse"
+        }
+
+        fn arity(&self) -> (usize, usize) {
+            (1, 1)
+        }
+
+        fn call(&self, args: &[Value]) -> Option<Value> {
+            Some(Value::from(
+                args[0].as_str()?.chars().rev().collect::<String>(),
+            ))
+        }
+    }
+
+    fn pack(host: PluginHost) -> PluginHost {
+        host.with_operator(Near).with_function(Reverse)
+    }
+
+    #[test]
+    fn test_plugins() {
+        let host = PluginHost::new().with_pack(pack);
+        let options = ApplyOptions {
+            plugins: host.clone(),
+            ..Default::default()
+        };
+        let filters = parse_with_plugins(
+            ".n NEAR 10 AND reverse(.name) = 'bob' AND reverse(.id) = 'cba'",
+            &host,
+        )
+        .unwrap();
+        let passes = |v: Value| apply_with(&v, &filters, &options);
+        assert!(passes(json!({ "n": 10.5, "name": "bob", "id": "abc" })));
+        assert!(!passes(json!({ "n": 12, "name": "bob", "id": "abc" })));
+        assert!(!passes(json!({ "n": 10, "name": 1, "id": "abc" })));
+        // Without the host, the operator never matches and the function is missing.
+        assert!(!apply_with(
+            &json!({ "n": 10, "name": "bob", "id": "abc" }),
+            &filters,
+            &ApplyOptions::default()
+        ));
+        assert_eq!(
+            format!("{}", crate::format::Clauses(&filters)),
+            ".n NEAR 10 AND reverse(.name) = 'bob' AND reverse(.id) = 'cba'"
+        );
+        assert!(parse("reverse(.name) = 'bob'").is_none());
+        assert!(format("reverse(.name) = 'bob'").is_err());
+        assert_eq!(
+            parse_with_plugins("reverse(.a, .b) = 'x'", &host)
+                .unwrap_err()
+                .message,
+            "wrong number of arguments to `reverse`"
+        );
+        assert_eq!(
+            format!("{host:?}"),
+            r#"PluginHost { operators: ["NEAR"], functions: ["reverse"] }"#
+        );
+    }
+}
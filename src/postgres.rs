@@ -0,0 +1,222 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde_json::{Map, Value};
+
+use crate::batch::Operator;
+use crate::translate::{self, sql_string, Translatable, TranslateError};
+use crate::Filter;
+
+pub(crate) fn to_postgres_jsonb(
+    filters: &[Filter],
+    column: &str,
+) -> Result<String, TranslateError> {
+    // Clauses that can be written as containment are merged into one document for `@>`, which a
+    // GIN index with `jsonb_path_ops` answers; the others are written as JSON path predicates.
+    let mut document: Option<Value> = None;
+    let mut conditions = Vec::new();
+    for filter in filters {
+        let clause = translate::translatable(filter)?;
+        if let Translatable::Always = clause {
+            continue;
+        }
+        let fragment = match &clause {
+            // A document that is a scalar or an array at its root would contain more than the clause
+            // asks for, as an array at the root contains its scalar elements.
+            Translatable::Compare { keys, .. } | Translatable::Quantified { keys, .. }
+                if keys.is_empty() =>
+            {
+                None
+            }
+            _ => contained(&clause)?,
+        };
+        let merged = match (&mut document, fragment) {
+            (Some(document), Some(fragment)) => merge(document, fragment),
+            (document @ None, Some(fragment)) => {
+                *document = Some(fragment);
+                true
+            }
+            (_, None) => false,
+        };
+        if !merged {
+            let predicate = predicate(&clause, "$")?.expect("clauses that always pass are skipped");
+            conditions.push(format!(
+                "{column} @@ {}",
+                sql_string(&format!("strict {predicate}"))
+            ));
+        }
+    }
+    if let Some(document) = document {
+        conditions.insert(
+            0,
+            format!("{column} @> {}", sql_string(&document.to_string())),
+        );
+    }
+    Ok(match conditions.is_empty() {
+        true => "TRUE".to_string(),
+        false => conditions.join(" AND "),
+    })
+}
+
+/// The document a Value contains exactly when it passes a clause, if there is one: equality with a
+/// literal, `CONTAINS`, and `ANY` of such clauses.
+fn contained(clause: &Translatable) -> Result<Option<Value>, TranslateError> {
+    let (keys, inner) = match clause {
+        Translatable::Compare {
+            keys,
+            operator: Operator::Eq,
+            literal,
+        } => (keys, (*literal).clone()),
+        Translatable::Contains { keys, literal } => (keys, Value::Array(vec![(*literal).clone()])),
+        Translatable::Quantified {
+            all: false,
+            keys,
+            filters,
+        } => {
+            let mut element: Option<Value> = None;
+            for filter in filters.iter() {
+                let Some(fragment) = contained(&translate::translatable(filter)?)? else {
+                    return Ok(None);
+                };
+                match &mut element {
+                    Some(element) => {
+                        if !merge(element, fragment) {
+                            return Ok(None);
+                        }
+                    }
+                    None => element = Some(fragment),
+                }
+            }
+            let Some(element) = element else {
+                return Ok(None);
+            };
+            (keys, Value::Array(vec![element]))
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(keys.iter().rev().fold(inner, |inner, key| {
+        Value::Object(Map::from_iter([(key.to_string(), inner)]))
+    })))
+}
+
+/// Merges a document into another, so the result contains what both did, or returns `false`, leaving
+/// `document` as it was, if they can't be merged.
+fn merge(document: &mut Value, fragment: Value) -> bool {
+    match (document, fragment) {
+        (Value::Object(document), Value::Object(fragment)) => {
+            let mut merged = document.clone();
+            for (key, value) in fragment {
+                match merged.get_mut(&key) {
+                    Some(existing) => {
+                        if !merge(existing, value) {
+                            return false;
+                        }
+                    }
+                    None => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+            *document = merged;
+            true
+        }
+        (Value::Array(document), Value::Array(fragment)) => {
+            document.extend(fragment);
+            true
+        }
+        (document, fragment) => *document == fragment,
+    }
+}
+
+/// The JSON path predicate of a clause, in strict mode, about the item `base` is: `$` for the
+/// document, or `@` for the element of an array being filtered. Comparisons of mismatched types or
+/// of missing fields are unknown, which never passes, as in filters.
+fn predicate(clause: &Translatable, base: &str) -> Result<Option<String>, TranslateError> {
+    Ok(Some(match clause {
+        Translatable::Always => return Ok(None),
+        Translatable::Compare {
+            keys,
+            operator,
+            literal,
+        } => {
+            let operator = match operator {
+                Operator::Eq => "==",
+                Operator::Ne => "!=",
+                Operator::Lt => "<",
+                Operator::Le => "<=",
+                Operator::Gt => ">",
+                Operator::Ge => ">=",
+            };
+            format!("{} {operator} {literal}", path(base, keys))
+        }
+        Translatable::Contains { keys, literal } => format!("{}[*] == {literal}", path(base, keys)),
+        Translatable::Quantified { all, keys, filters } => {
+            let elements = format!("{}[*]", path(base, keys));
+            let mut predicates = Vec::new();
+            for filter in filters.iter() {
+                if let Some(predicate) = predicate(&translate::translatable(filter)?, "@")? {
+                    predicates.push(predicate);
+                }
+            }
+            let condition = predicates.join(" && ");
+            match (all, predicates.is_empty()) {
+                (false, true) => format!("exists({elements})"),
+                (false, false) => format!("exists({elements} ? ({condition}))"),
+                (true, true) => format!("{}.type() == \"array\"", path(base, keys)),
+                // Elements whose condition is unknown fail too.
+                (true, false) => format!(
+                    "({}.type() == \"array\" && !(exists({elements} ? (!({condition}) || ({condition}) is unknown))))",
+                    path(base, keys)
+                ),
+            }
+        }
+    }))
+}
+
+/// The JSON path of the keys from `base`, such as `$."user"."age"`.
+fn path(base: &str, keys: &[&str]) -> String {
+    let mut path = base.to_string();
+    for key in keys {
+        path.push('.');
+        path.push_str(&Value::from(*key).to_string());
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn sql(filter_string: &str) -> Result<String, TranslateError> {
+        to_postgres_jsonb(&parse(filter_string).unwrap(), "doc")
+    }
+
+    #[test]
+    fn test_to_postgres_jsonb() {
+        assert_eq!(
+            sql(".status = 'paid' AND .user.country = 'NZ' AND .user.vip = true").unwrap(),
+            r#"doc @> '{"status":"paid","user":{"country":"NZ","vip":true}}'"#
+        );
+        assert_eq!(
+            sql(".tags CONTAINS 'sale' AND ANY .items WHERE (.sku = 'a1' AND .qty = 2)").unwrap(),
+            r#"doc @> '{"items":[{"qty":2,"sku":"a1"}],"tags":["sale"]}'"#
+        );
+        assert_eq!(
+            sql(".price > 100 AND .status = 'paid' AND .status = 'sent'").unwrap(),
+            r#"doc @> '{"status":"paid"}' AND doc @@ 'strict $."price" > 100' AND doc @@ 'strict $."status" == "sent"'"#
+        );
+        assert_eq!(
+            sql("ANY .items WHERE (.qty > 1 AND .note != 'it\\'s')").unwrap(),
+            r#"doc @@ 'strict exists($."items"[*] ? (@."qty" > 1 && @."note" != "it''s"))'"#
+        );
+        assert_eq!(
+            sql("ALL .items WHERE .qty >= 1").unwrap(),
+            r#"doc @@ 'strict ($."items".type() == "array" && !(exists($."items"[*] ? (!(@."qty" >= 1) || (@."qty" >= 1) is unknown))))'"#
+        );
+        assert_eq!(sql("SHOULD .a = 1").unwrap(), "TRUE");
+        assert!(sql(".name > 'a'").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde_json::{Map, Value};
+
+use crate::expr::{Path, Segment};
+
+/// A set of paths to keep from a JSON Value, such as `.id` and `.user.name`.
+///
+/// Projecting a Value keeps the requested paths where they are and drops everything else, so
+/// `.user.name` projected from `{"id": 1, "user": {"name": "a", "age": 3}}` is
+/// `{"user": {"name": "a"}}`. Paths the Value doesn't have are left out. A wildcard path such as
+/// `.items[*].price` keeps the field in every element of the array, and elements without it become
+/// null so the other elements stay at their positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    paths: Vec<Path<'static>>,
+}
+
+impl Projection {
+    /// Creates a projection from paths written as in a filter string, such as `.user.name`.
+    pub fn new<I, S>(paths: I) -> Projection
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Projection {
+            paths: paths
+                .into_iter()
+                .map(|path| Path::parse(path.as_ref()).into_owned())
+                .collect(),
+        }
+    }
+
+    /// Returns a copy of `v` with only the paths of the projection.
+    pub fn project(&self, v: &Value) -> Value {
+        let mut projected = Value::Null;
+        for path in &self.paths {
+            insert(&path.segments, v, &mut projected);
+        }
+        if projected.is_null() && v.is_object() {
+            // None of the paths were found.
+            return Value::Object(Map::new());
+        }
+        projected
+    }
+}
+
+/// Copies the value at `segments` in `source` to the same place in `target`.
+fn insert(segments: &[Segment], source: &Value, target: &mut Value) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *target = source.clone();
+        return;
+    };
+    match segment {
+        Segment::Key { name, .. } => {
+            let Some(source) = source.get(name.as_ref()) else {
+                return;
+            };
+            if target.is_null() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                let entry = target.entry(name.as_ref()).or_insert(Value::Null);
+                insert(rest, source, entry);
+            }
+        }
+        Segment::Wildcard => {
+            let Some(source) = source.as_array() else {
+                return;
+            };
+            if target.is_null() {
+                *target = Value::Array(vec![Value::Null; source.len()]);
+            }
+            if let Value::Array(target) = target {
+                for (source, target) in source.iter().zip(target) {
+                    insert(rest, source, target);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_project() {
+        let v = json!({
+            "id": 1,
+            "user": { "name": "ada", "age": 36 },
+            "items": [{ "sku": "a", "price": 3 }, { "sku": "b" }],
+        });
+        let projection =
+            Projection::new([".id", ".user.name", ".items[*].price", ".missing.field"]);
+        assert_eq!(
+            projection.project(&v),
+            json!({ "id": 1, "user": { "name": "ada" }, "items": [{ "price": 3 }, null] })
+        );
+        let projection = Projection::new([".user", ".user.name"]);
+        assert_eq!(
+            projection.project(&v),
+            json!({ "user": { "name": "ada", "age": 36 } })
+        );
+        assert_eq!(Projection::new([".missing"]).project(&v), json!({}));
+    }
+}
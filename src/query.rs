@@ -0,0 +1,81 @@
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::{apply_with, sort, ApplyOptions, Filter, SortKey};
+
+/// A whole list query over JSON Values: which Values to keep, in what order, and which page of them
+/// to return.
+///
+/// # Fields
+///
+/// * `filters` - The filters a Value must pass to be returned.
+/// * `sort` - The keys to order the passing Values by. When empty, they keep their order.
+/// * `limit` - The maximum number of Values to return. When `None`, every Value after the offset is returned.
+/// * `offset` - The number of passing Values to skip, after sorting.
+/// * `options` - The options to evaluate the filters with.
+///
+#[derive(Debug, Default)]
+pub struct Query<'a> {
+    pub filters: Vec<Filter<'a>>,
+    pub sort: Vec<SortKey>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub options: ApplyOptions,
+}
+
+impl Query<'_> {
+    /// Runs the query over a list of Values and returns the page of passing Values.
+    pub fn run(&self, values: Vec<Value>) -> Vec<Value> {
+        let passing = values
+            .into_iter()
+            .filter(|v| apply_with(v, &self.filters, &self.options));
+        let limit = self.limit.unwrap_or(usize::MAX);
+        if self.sort.is_empty() {
+            // Without sorting, stop as soon as the page is full.
+            return passing.skip(self.offset).take(limit).collect();
+        }
+        let mut passing: Vec<Value> = passing.collect();
+        sort::sort_by(&mut passing, &self.sort);
+        passing.into_iter().skip(self.offset).take(limit).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    #[test]
+    fn test_run() {
+        let values: Vec<Value> = (1..=10)
+            .map(|i| json!({ "id": i, "even": i % 2 == 0 }))
+            .collect();
+        let query = Query {
+            filters: parse(".even = true").unwrap(),
+            sort: vec![SortKey::desc(".id")],
+            limit: Some(2),
+            offset: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            query.run(values.clone()),
+            [
+                json!({ "id": 8, "even": true }),
+                json!({ "id": 6, "even": true })
+            ]
+        );
+
+        let query = Query {
+            offset: 8,
+            ..Default::default()
+        };
+        assert_eq!(query.run(values.clone()).len(), 2);
+        let query = Query {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert!(query.run(values).is_empty());
+    }
+}
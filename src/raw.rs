@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::eval::{self, Context};
+use crate::expr::{Anchor, Arena, Expr, Path, Segment};
+use crate::intern::Name;
+use crate::{ApplyOptions, Compound, Filter};
+
+/// The paths into a Value that filters read. The paths in the clauses of a quantifier are joined to
+/// the path of its array with `[*]`, as they read the elements of the array, unless they start at
+/// `$root` or `$parent`.
+pub(crate) fn referenced_paths<'f>(filters: &'f [Filter]) -> Vec<Path<'f>> {
+    let mut paths = Vec::new();
+    for filter in filters {
+        filter_paths(filter, &[Path::default()], &mut paths);
+    }
+    paths
+}
+
+/// The top-level keys of a Value that filters read, or `None` if they read the Value as a whole, as
+/// `.` or `[*]` at the start of a path do.
+pub(crate) fn referenced_keys<'f>(filters: &'f [Filter]) -> Option<HashSet<&'f str>> {
+    referenced_paths(filters)
+        .iter()
+        .map(|path| match path.segments.first() {
+            Some(Segment::Key {
+                name: Name::Borrowed(name),
+                ..
+            }) => Some(*name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The top-level keys of a Value that filters evaluated with `options` read, like
+/// [`referenced_keys`], or `None` if the options may read other fields of Values without them.
+pub(crate) fn read_keys<'f>(
+    filters: &'f [Filter],
+    options: &ApplyOptions,
+) -> Option<HashSet<&'f str>> {
+    match options.fills_missing() {
+        true => None,
+        false => referenced_keys(filters),
+    }
+}
+
+/// Collects the paths a filter reads. `scopes` holds the paths of the Values enclosing the clause,
+/// from the document to the element being filtered, which is last.
+fn filter_paths<'f>(filter: &'f Filter, scopes: &[Path<'f>], paths: &mut Vec<Path<'f>>) {
+    let arena = &filter.arena;
+    match &filter.compound {
+        Some(Compound::Quantified { array, filters, .. }) => {
+            let start = paths.len();
+            expr_paths(arena, array, scopes, paths);
+            let arrays: Vec<Path> = paths[start..].to_vec();
+            for array in arrays {
+                let mut inner = scopes.to_vec();
+                inner.push(joined(&array, [Segment::Wildcard]));
+                for filter in filters {
+                    filter_paths(filter, &inner, paths);
+                }
+            }
+        }
+        Some(Compound::Fuzzy { input, target, .. }) => {
+            expr_paths(arena, input, scopes, paths);
+            expr_paths(arena, target, scopes, paths);
+        }
+        #[cfg(feature = "geo")]
+        Some(Compound::Within { location, .. }) => expr_paths(arena, location, scopes, paths),
+        Some(Compound::If {
+            condition,
+            then,
+            otherwise,
+        }) => {
+            for filter in condition.iter().chain(then).chain(otherwise) {
+                filter_paths(filter, scopes, paths);
+            }
+        }
+        Some(Compound::Match { filters, .. }) => {
+            for filter in filters {
+                filter_paths(filter, scopes, paths);
+            }
+        }
+        None => {
+            let key = |name: &'f str| Segment::Key {
+                name: Name::Borrowed(name),
+                null_safe: false,
+            };
+            match &filter.lhs {
+                Some(lhs) => expr_paths(arena, lhs, scopes, paths),
+                None => paths.extend(
+                    filter
+                        .field
+                        .as_deref()
+                        .map(|field| joined(prefix(scopes), [key(field)])),
+                ),
+            }
+            match &filter.rhs {
+                Some(rhs) => expr_paths(arena, rhs, scopes, paths),
+                None => paths.extend(
+                    filter
+                        .value_field
+                        .as_deref()
+                        .map(|field| joined(prefix(scopes), [key(field)])),
+                ),
+            }
+        }
+    }
+}
+
+fn expr_paths<'f>(
+    arena: &'f Arena,
+    expr: &'f Expr,
+    scopes: &[Path<'f>],
+    paths: &mut Vec<Path<'f>>,
+) {
+    let at = |scope: &Path<'f>, path: &'f Path| joined(scope, path.segments.iter().map(borrowed));
+    match expr {
+        Expr::Path(path) => paths.push(at(prefix(scopes), path)),
+        Expr::Anchored(Anchor::Root, path) => paths.push(at(&scopes[0], path)),
+        // At the top level, `$parent` reads nothing.
+        Expr::Anchored(Anchor::Parent, path) => {
+            if let [.., parent, _] = scopes {
+                paths.push(at(parent, path));
+            }
+        }
+        Expr::Literal(_) => {}
+        #[cfg(feature = "chrono")]
+        Expr::Now => {}
+        Expr::Mul(lhs, rhs) | Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            expr_paths(arena, &arena[*lhs], scopes, paths);
+            expr_paths(arena, &arena[*rhs], scopes, paths);
+        }
+        Expr::Shift(expr, _) | Expr::Capture(expr, ..) => {
+            expr_paths(arena, &arena[*expr], scopes, paths)
+        }
+        Expr::Call(_, exprs)
+        | Expr::Plugin(_, exprs)
+        | Expr::Coalesce(exprs)
+        | Expr::List(exprs) => {
+            for expr in &arena[*exprs] {
+                expr_paths(arena, expr, scopes, paths);
+            }
+        }
+    }
+}
+
+/// The path of the Value being filtered.
+fn prefix<'s, 'f>(scopes: &'s [Path<'f>]) -> &'s Path<'f> {
+    scopes.last().expect("the document is a scope")
+}
+
+fn joined<'f>(prefix: &Path<'f>, segments: impl IntoIterator<Item = Segment<'f>>) -> Path<'f> {
+    let mut path = prefix.clone();
+    path.segments.extend(segments);
+    path
+}
+
+/// Borrows the key of a segment, dropping its null-safety, which doesn't change what is read.
+fn borrowed<'f>(segment: &'f Segment) -> Segment<'f> {
+    match segment {
+        Segment::Key { name, .. } => Segment::Key {
+            name: Name::Borrowed(name),
+            null_safe: false,
+        },
+        Segment::Wildcard => Segment::Wildcard,
+    }
+}
+
+/// Parses a JSON object, keeping only the given keys. The values of other keys are checked to be valid
+/// JSON and skipped without being built. JSON that isn't an object is parsed whole.
+pub(crate) fn parse_keys(json: &str, keys: &HashSet<&str>) -> serde_json::Result<Value> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let value = Keys(keys).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Returns whether a JSON document passes every clause, reading a JSON object one key at a time. Only
+/// the values of the keys the clauses read are built, and each clause is evaluated as soon as the
+/// keys it reads have been parsed, then again if one of them is repeated, whose last value counts as
+/// it does when parsing the whole document.
+pub(crate) fn matches(json: &str, filters: &[Filter], ctx: &Context) -> serde_json::Result<bool> {
+    let clauses: Option<Vec<HashSet<&str>>> = filters
+        .iter()
+        .map(|filter| referenced_keys(std::slice::from_ref(filter)))
+        .collect();
+    match clauses {
+        Some(clauses) if json.trim_start_matches(WHITESPACE).starts_with('{') => {
+            scan(json, filters, &clauses, ctx).ok_or_else(|| {
+                // The full parser reports the same error, with its position.
+                serde_json::from_str::<IgnoredAny>(json)
+                    .err()
+                    .unwrap_or_else(|| serde::de::Error::custom("invalid JSON"))
+            })
+        }
+        _ => Ok(eval::apply(&serde_json::from_str(json)?, filters, ctx)),
+    }
+}
+
+const WHITESPACE: [char; 4] = [' ', '\t', '\n', '\r'];
+
+/// Reads the keys of a JSON object, evaluating clauses as their keys are read. Returns `None` if the
+/// document isn't valid JSON.
+fn scan(json: &str, filters: &[Filter], clauses: &[HashSet<&str>], ctx: &Context) -> Option<bool> {
+    let needed: HashSet<&str> = clauses.iter().flatten().copied().collect();
+    let mut object = Value::Object(Map::new());
+    // Whether each clause passes, once the keys it reads have been read.
+    let mut results: Vec<Option<bool>> = vec![None; filters.len()];
+    let mut pos = skip_whitespace(json, 0) + 1;
+    let mut first = true;
+    loop {
+        pos = skip_whitespace(json, pos);
+        match json.as_bytes().get(pos) {
+            Some(b'}') => {
+                pos += 1;
+                break;
+            }
+            Some(b',') if !first => pos += 1,
+            _ if first => {}
+            _ => return None,
+        }
+        first = false;
+        let key: String = read(json, &mut pos)?;
+        pos = skip_whitespace(json, pos);
+        if json.as_bytes().get(pos) != Some(&b':') {
+            return None;
+        }
+        pos += 1;
+        if !needed.contains(key.as_str()) {
+            read::<IgnoredAny>(json, &mut pos)?;
+            continue;
+        }
+        let value = read(json, &mut pos)?;
+        let read_object = object.as_object_mut().expect("the document is an object");
+        // A repeated key replaces the value read before it.
+        read_object.insert(key.clone(), value);
+        for (i, keys) in clauses.iter().enumerate() {
+            let read_object = object.as_object().expect("the document is an object");
+            if keys.contains(key.as_str()) && keys.iter().all(|key| read_object.contains_key(*key))
+            {
+                results[i] = Some(eval::apply(&object, std::slice::from_ref(&filters[i]), ctx));
+            }
+        }
+    }
+    if skip_whitespace(json, pos) != json.len() {
+        return None;
+    }
+    // The clauses left read keys the document doesn't have.
+    Some(results.iter().enumerate().all(|(i, result)| {
+        result.unwrap_or_else(|| eval::apply(&object, std::slice::from_ref(&filters[i]), ctx))
+    }))
+}
+
+fn skip_whitespace(json: &str, pos: usize) -> usize {
+    json.len() - json[pos..].trim_start_matches(WHITESPACE).len()
+}
+
+/// Reads one JSON value starting at `pos`, moving `pos` past it.
+fn read<'de, T: Deserialize<'de>>(json: &'de str, pos: &mut usize) -> Option<T> {
+    let mut values = serde_json::Deserializer::from_str(&json[*pos..]).into_iter::<T>();
+    let value = values.next()?.ok()?;
+    *pos += values.byte_offset();
+    Some(value)
+}
+
+struct Keys<'k, 'f>(&'k HashSet<&'f str>);
+
+impl<'de> DeserializeSeed<'de> for Keys<'_, '_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Keys<'_, '_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = Map::new();
+        while let Some(key) = map.next_key::<std::borrow::Cow<'de, str>>()? {
+            if self.0.contains(key.as_ref()) {
+                object.insert(key.into_owned(), map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(Value::Object(object))
+    }
+
+    // Anything other than an object is kept whole, as the filters would see it.
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = Vec::new();
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Value::Array(elements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    fn keys(filter_string: &str) -> Option<Vec<String>> {
+        let filters = parse(filter_string).unwrap();
+        let mut keys: Vec<String> = referenced_keys(&filters)?
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        keys.sort();
+        Some(keys)
+    }
+
+    #[test]
+    fn test_referenced_keys() {
+        assert_eq!(
+            keys(".a = 1 AND .b < .c AND abs(.d.e) > 1 AND ANY .items WHERE .qty > 1").unwrap(),
+            ["a", "b", "c", "d", "items"]
+        );
+        assert_eq!(
+            keys("if .kind = 'x' then .n > 1 else .m FUZZY('ab', 1)").unwrap(),
+            ["kind", "m", "n"]
+        );
+        assert_eq!(keys(". = 1"), None);
+        assert_eq!(
+            keys("ANY .items WHERE .price > $root.budget AND $parent.x = 1").unwrap(),
+            ["budget", "items"]
+        );
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = HashSet::from(["a", "c"]);
+        let json = r#"{"a": 1, "b": {"big": [1, 2, 3]}, "c": {"d": "x"}, "eA": 2}"#;
+        assert_eq!(
+            parse_keys(json, &keys).unwrap(),
+            json!({ "a": 1, "c": { "d": "x" } })
+        );
+        assert_eq!(parse_keys("[1, 2]", &keys).unwrap(), json!([1, 2]));
+        assert!(parse_keys(r#"{"a": 1, "b": [1,}"#, &keys).is_err());
+        assert!(parse_keys(r#"{"a": 1} x"#, &keys).is_err());
+    }
+}
@@ -0,0 +1,606 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::parser::{self, ParseLimits};
+use crate::{CompiledFilter, Filter};
+
+/// The most clauses a filter of a [`FilterSet`] may have once its `@name` references are replaced,
+/// so filters referring to a filter more than once can't grow without bound.
+const MAX_CLAUSES: usize = 4_096;
+
+/// Named filters loaded from a filter file, compiled and keyed by name.
+///
+/// A filter file is a JSON object, or a TOML table with the `toml` feature, mapping each name to a
+/// filter string, or to an object with the filter string under `filter` and an optional
+/// `description`:
+///
+/// ```json
+/// {
+///     "adults": ".age >= 18",
+///     "premium": { "filter": ".plan = 'premium'", "description": "Paying customers" }
+/// }
+/// ```
+///
+/// Files ending in `.jsf` list a filter on each line, and may include other filter files:
+///
+/// ```text
+/// # Rules shared between services
+/// include "common_filters.jsf"
+///
+/// adults = .age >= 18
+/// ```
+///
+/// A filter may refer to another filter of the file, or of the files it includes, with `@name`,
+/// which stands for its clauses. A filter may have at most 4096 clauses once its references are
+/// replaced.
+#[derive(Debug, Default)]
+pub struct FilterSet {
+    filters: BTreeMap<String, NamedFilter>,
+}
+
+/// A filter of a [`FilterSet`], with its name and description.
+#[derive(Debug)]
+pub struct NamedFilter {
+    name: String,
+    description: Option<String>,
+    filter_string: String,
+    compiled: CompiledFilter<'static>,
+}
+
+impl NamedFilter {
+    /// The name the filter is defined under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The description of the filter, if the file gives one.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The filter string, as written in the file.
+    pub fn filter_string(&self) -> &str {
+        &self.filter_string
+    }
+
+    /// The compiled filter.
+    pub fn compiled(&self) -> &CompiledFilter<'static> {
+        &self.compiled
+    }
+}
+
+/// An error found while loading a [`FilterSet`]: a file that can't be read or isn't a filter file,
+/// or a filter that doesn't parse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSetError {
+    message: String,
+}
+
+impl FilterSetError {
+    pub(crate) fn new(message: impl Into<String>) -> FilterSetError {
+        FilterSetError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FilterSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for FilterSetError {}
+
+impl FilterSet {
+    /// Loads a filter file. Files ending in `.jsf` are read as a list of filters, files ending in
+    /// `.toml` as TOML, which needs the `toml` feature, and other files as JSON.
+    pub fn load(path: impl AsRef<Path>) -> Result<FilterSet, FilterSetError> {
+        FilterSet::load_files(path.as_ref()).map(|(set, _)| set)
+    }
+
+    /// Loads a filter file, returning the files read with it, including the ones it includes.
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    pub(crate) fn load_files(path: &Path) -> Result<(FilterSet, HashSet<PathBuf>), FilterSetError> {
+        let mut loader = Loader::default();
+        loader.load(path)?;
+        Ok((FilterSet::compile(loader.definitions)?, loader.loaded))
+    }
+
+    /// Reads a filter file in JSON.
+    pub fn from_json(text: &str) -> Result<FilterSet, FilterSetError> {
+        let mut definitions = BTreeMap::new();
+        define_json(text, None, &mut definitions)?;
+        FilterSet::compile(definitions)
+    }
+
+    /// Reads a filter file in TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<FilterSet, FilterSetError> {
+        let mut definitions = BTreeMap::new();
+        define_toml(text, None, &mut definitions)?;
+        FilterSet::compile(definitions)
+    }
+
+    /// Parses every filter once all are defined, as filters may refer to filters after them.
+    fn compile(definitions: BTreeMap<String, Definition>) -> Result<FilterSet, FilterSetError> {
+        let resolver = Resolver {
+            definitions: &definitions,
+            expanding: RefCell::new(Vec::new()),
+            resolved: RefCell::new(HashMap::new()),
+            failure: RefCell::new(None),
+        };
+        let mut set = FilterSet::default();
+        for (name, definition) in &definitions {
+            let filters = resolver.resolve(name).map_err(|message| {
+                in_file(
+                    definition.file.as_deref(),
+                    format!("filter `{name}`: {message}"),
+                )
+            })?;
+            set.filters.insert(
+                name.clone(),
+                NamedFilter {
+                    name: name.clone(),
+                    description: definition.description.clone(),
+                    filter_string: definition.filter_string.clone(),
+                    compiled: CompiledFilter::new(filters),
+                },
+            );
+        }
+        Ok(set)
+    }
+
+    /// Returns the filter defined under a name.
+    pub fn get(&self, name: &str) -> Option<&NamedFilter> {
+        self.filters.get(name)
+    }
+
+    /// The filters, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &NamedFilter> {
+        self.filters.values()
+    }
+
+    /// The number of filters.
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Returns whether the set has no filters.
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+}
+
+/// Parses the filters of a filter file, replacing `@name` references with the clauses of the filter
+/// they name.
+struct Resolver<'d> {
+    /// The filter string and description of each filter, by name.
+    definitions: &'d BTreeMap<String, Definition>,
+    /// The names of the filters being parsed, each referenced by the one before it.
+    expanding: RefCell<Vec<String>>,
+    /// The clauses of each filter parsed so far, so a filter referred to many times is parsed once.
+    resolved: RefCell<HashMap<String, Vec<Filter<'static>>>>,
+    /// The first error found, passed on unchanged by the filters referring to the one it is in.
+    failure: RefCell<Option<String>>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&self, name: &str) -> Result<Vec<Filter<'static>>, String> {
+        let Some(definition) = self.definitions.get(name) else {
+            return Err(format!("unknown filter `@{name}`"));
+        };
+        if let Some(filters) = self.resolved.borrow().get(name) {
+            return Ok(filters.clone());
+        }
+        let mut expanding = self.expanding.borrow_mut();
+        if let Some(start) = expanding.iter().position(|expanded| expanded == name) {
+            let cycle: Vec<String> = expanding[start..]
+                .iter()
+                .chain([&name.to_string()])
+                .map(|name| format!("@{name}"))
+                .collect();
+            let message = format!("filters refer to each other: {}", cycle.join(" -> "));
+            return Err(self.fail(|| message));
+        }
+        expanding.push(name.to_string());
+        drop(expanding);
+        let resolve = |name: &str| self.resolve(name);
+        let limits = ParseLimits {
+            max_clauses: MAX_CLAUSES,
+            ..ParseLimits::UNLIMITED
+        };
+        let filters = parser::parse_with_refs(&definition.filter_string, &resolve, limits)
+            .map(Filter::into_owned_all)
+            .map_err(|error| {
+                let nested = self.expanding.borrow().len() > 1;
+                self.fail(|| match nested {
+                    true => format!("in `@{name}`: {error}"),
+                    false => error.to_string(),
+                })
+            });
+        self.expanding.borrow_mut().pop();
+        if let Ok(filters) = &filters {
+            self.resolved
+                .borrow_mut()
+                .insert(name.to_string(), filters.clone());
+        }
+        filters
+    }
+
+    /// Records an error unless an earlier one was, and returns the one recorded.
+    fn fail(&self, message: impl FnOnce() -> String) -> String {
+        self.failure
+            .borrow_mut()
+            .get_or_insert_with(message)
+            .clone()
+    }
+}
+
+/// A filter of a filter file, before it is parsed.
+struct Definition {
+    filter_string: String,
+    description: Option<String>,
+    /// The file defining the filter, if it was loaded from one.
+    file: Option<PathBuf>,
+}
+
+/// Reads filter files and the files they include into one set of definitions.
+#[derive(Default)]
+struct Loader {
+    definitions: BTreeMap<String, Definition>,
+    /// The files being read, each included by the one before it.
+    including: Vec<PathBuf>,
+    /// The files read so far, which aren't read again when included twice.
+    loaded: HashSet<PathBuf>,
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path) -> Result<(), FilterSetError> {
+        let text = std::fs::read_to_string(path).map_err(|error| {
+            FilterSetError::new(format!("cannot read `{}`: {error}", path.display()))
+        })?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(start) = self.including.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = self.including[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(FilterSetError::new(format!(
+                "files include each other: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if !self.loaded.insert(canonical.clone()) {
+            return Ok(());
+        }
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsf") => {
+                self.including.push(canonical);
+                let loaded = self.define_jsf(&text, path);
+                self.including.pop();
+                loaded
+            }
+            #[cfg(feature = "toml")]
+            Some("toml") => define_toml(&text, Some(path), &mut self.definitions),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(in_file(
+                Some(path),
+                "reading TOML filter files needs the `toml` feature",
+            )),
+            _ => define_json(&text, Some(path), &mut self.definitions),
+        }
+    }
+
+    /// Reads a `.jsf` file: a filter on each line as `name = filter string`, `include "file"` lines
+    /// reading another filter file, relative to this one, and `#` comments.
+    fn define_jsf(&mut self, text: &str, path: &Path) -> Result<(), FilterSetError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let at_line = |message: &str| {
+                FilterSetError::new(format!("{}:{}: {message}", path.display(), i + 1))
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(included) = line
+                .strip_prefix("include")
+                .map(str::trim_start)
+                .filter(|rest| rest.starts_with('"'))
+            {
+                let included = included
+                    .strip_prefix('"')
+                    .and_then(|rest| rest.strip_suffix('"'))
+                    .filter(|file| !file.is_empty() && !file.contains('"'))
+                    .ok_or_else(|| at_line("expected a quoted file name after `include`"))?;
+                let dir = path.parent().unwrap_or(Path::new(""));
+                self.load(&dir.join(included))?;
+                continue;
+            }
+            let Some((name, filter_string)) = line.split_once('=') else {
+                return Err(at_line("expected `name = filter` or `include \"file\"`"));
+            };
+            let name = name.trim();
+            if !is_name(name) {
+                return Err(at_line(&format!("invalid filter name `{name}`")));
+            }
+            let definition = Definition {
+                filter_string: filter_string.trim().to_string(),
+                description: None,
+                file: Some(path.to_path_buf()),
+            };
+            define(&mut self.definitions, name.to_string(), definition)
+                .map_err(|error| at_line(&error.message))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether a name can name a filter of a `.jsf` file, and be referred to with `@name`.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn define_json(
+    text: &str,
+    file: Option<&Path>,
+    definitions: &mut BTreeMap<String, Definition>,
+) -> Result<(), FilterSetError> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|error| in_file(file, format!("invalid JSON: {error}")))?;
+    define_value(value, file, definitions)
+}
+
+#[cfg(feature = "toml")]
+fn define_toml(
+    text: &str,
+    file: Option<&Path>,
+    definitions: &mut BTreeMap<String, Definition>,
+) -> Result<(), FilterSetError> {
+    let value: Value =
+        toml::from_str(text).map_err(|error| in_file(file, format!("invalid TOML: {error}")))?;
+    define_value(value, file, definitions)
+}
+
+fn define_value(
+    value: Value,
+    file: Option<&Path>,
+    definitions: &mut BTreeMap<String, Definition>,
+) -> Result<(), FilterSetError> {
+    let Value::Object(entries) = value else {
+        return Err(in_file(
+            file,
+            "a filter file maps names to filters, but this isn't an object",
+        ));
+    };
+    for (name, entry) in entries {
+        let (filter_string, description) =
+            read_entry(&name, entry).map_err(|error| in_file(file, error.message))?;
+        let definition = Definition {
+            filter_string,
+            description,
+            file: file.map(Path::to_path_buf),
+        };
+        define(definitions, name, definition).map_err(|error| in_file(file, error.message))?;
+    }
+    Ok(())
+}
+
+/// Adds a definition, unless a file read before defines a filter of the same name.
+fn define(
+    definitions: &mut BTreeMap<String, Definition>,
+    name: String,
+    definition: Definition,
+) -> Result<(), FilterSetError> {
+    if let Some(defined) = definitions.get(&name) {
+        let message = match &defined.file {
+            Some(file) => format!("filter `{name}` is already defined in `{}`", file.display()),
+            None => format!("filter `{name}` is already defined"),
+        };
+        return Err(FilterSetError::new(message));
+    }
+    definitions.insert(name, definition);
+    Ok(())
+}
+
+/// An error, prefixed with the file it is in, if there is one.
+fn in_file(file: Option<&Path>, message: impl Into<String>) -> FilterSetError {
+    let message = message.into();
+    match file {
+        Some(file) => FilterSetError::new(format!("{}: {message}", file.display())),
+        None => FilterSetError::new(message),
+    }
+}
+
+/// Reads the filter string and the description of an entry of a filter file.
+fn read_entry(name: &str, entry: Value) -> Result<(String, Option<String>), FilterSetError> {
+    let invalid = |message: &str| FilterSetError::new(format!("filter `{name}`: {message}"));
+    let mut entry: Map<String, Value> = match entry {
+        Value::String(filter_string) => return Ok((filter_string, None)),
+        Value::Object(entry) => entry,
+        _ => return Err(invalid("expected a filter string or an object")),
+    };
+    let Some(Value::String(filter_string)) = entry.remove("filter") else {
+        return Err(invalid("expected a filter string under `filter`"));
+    };
+    let description = match entry.remove("description") {
+        Some(Value::String(description)) => Some(description),
+        None => None,
+        Some(_) => return Err(invalid("expected a string under `description`")),
+    };
+    if let Some(key) = entry.keys().next() {
+        return Err(invalid(&format!("unknown key `{key}`")));
+    }
+    Ok((filter_string, description))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_json() {
+        let set = FilterSet::from_json(
+            r#"{
+                "adults": ".age >= 18",
+                "premium": { "filter": ".plan = 'premium'", "description": "Paying customers" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(set.len(), 2);
+        let names: Vec<&str> = set.iter().map(NamedFilter::name).collect();
+        assert_eq!(names, ["adults", "premium"]);
+        let premium = set.get("premium").unwrap();
+        assert_eq!(premium.description(), Some("Paying customers"));
+        assert_eq!(premium.filter_string(), ".plan = 'premium'");
+        assert!(premium.compiled().matches(&json!({ "plan": "premium" })));
+        assert_eq!(set.get("adults").unwrap().description(), None);
+        assert!(set.get("missing").is_none());
+
+        let error = |text| FilterSet::from_json(text).unwrap_err().to_string();
+        assert_eq!(
+            error(r#"["a"]"#),
+            "a filter file maps names to filters, but this isn't an object"
+        );
+        assert_eq!(
+            error(r#"{"a": 1}"#),
+            "filter `a`: expected a filter string or an object"
+        );
+        assert_eq!(
+            error(r#"{"a": {"filter": ".a = 1", "desc": "x"}}"#),
+            "filter `a`: unknown key `desc`"
+        );
+        assert!(error(r#"{"a": ".a ="}"#).starts_with("filter `a`: expected a value"));
+    }
+
+    #[test]
+    fn test_refs() {
+        let set = FilterSet::from_json(
+            r#"{
+                "adults": ".age >= 18",
+                "premium_adults": "@adults AND .plan = 'premium'",
+                "active": "@premium_adults AND .active = true"
+            }"#,
+        )
+        .unwrap();
+        let active = set.get("active").unwrap().compiled();
+        assert!(active.matches(&json!({ "age": 30, "plan": "premium", "active": true })));
+        assert!(!active.matches(&json!({ "age": 12, "plan": "premium", "active": true })));
+        assert_eq!(
+            set.get("active").unwrap().filter_string(),
+            "@premium_adults AND .active = true"
+        );
+
+        let error = |text| FilterSet::from_json(text).unwrap_err().to_string();
+        assert_eq!(
+            error(r#"{"a": "@b AND .x = 1", "b": "@c", "c": "@a"}"#),
+            "filter `a`: filters refer to each other: @a -> @b -> @c -> @a"
+        );
+        assert!(error(r#"{"a": "@b", "b": ".x ="}"#)
+            .starts_with("filter `a`: in `@b`: expected a value"));
+        assert_eq!(
+            error(r#"{"a": ".x = 1 AND @missing"}"#),
+            "filter `a`: unknown filter `@missing` at 11..19"
+        );
+
+        // Each filter doubles the clauses of the one before it.
+        let chain = |length: usize| {
+            let mut definitions = vec![r#""d0": ".a = 1""#.to_string()];
+            for i in 1..length {
+                definitions.push(format!(r#""d{i}": "@d{j} AND @d{j}""#, j = i - 1));
+            }
+            FilterSet::from_json(&format!("{{{}}}", definitions.join(", ")))
+        };
+        let set = chain(12).unwrap();
+        let d11 = set.get("d11").unwrap().compiled();
+        assert!(d11.matches(&json!({ "a": 1 })));
+        assert!(!d11.matches(&json!({ "a": 2 })));
+        let error = chain(40).unwrap_err().to_string();
+        assert!(error.contains("more than 4096 clauses"), "{error}");
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_from_toml() {
+        let set = FilterSet::from_toml(
+            r#"
+            adults = ".age >= 18"
+
+            [premium]
+            filter = ".plan = 'premium'"
+            description = "Paying customers"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(
+            set.get("premium").unwrap().description(),
+            Some("Paying customers")
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("filters-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"adults": ".age >= 18"}"#).unwrap();
+        let set = FilterSet::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(set.unwrap().get("adults").is_some());
+        assert!(FilterSet::load(&path)
+            .unwrap_err()
+            .to_string()
+            .starts_with("cannot read"));
+    }
+
+    #[test]
+    fn test_load_includes() {
+        let dir = std::env::temp_dir().join(format!("filters-{}-includes", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        let write = |file: &str, text: &str| std::fs::write(dir.join(file), text).unwrap();
+        write(
+            "main.jsf",
+            "# Rules of the service\n\
+             include \"common/people.jsf\"\n\
+             include \"plans.json\"\n\
+             \n\
+             premium_adults = @adults AND @premium\n",
+        );
+        write(
+            "common/people.jsf",
+            "adults = .age >= 18\ninclude \"../plans.json\"\n",
+        );
+        write("plans.json", r#"{"premium": ".plan = 'premium'"}"#);
+        write("cycle.jsf", "include \"common/cycle.jsf\"\n");
+        write("common/cycle.jsf", "include \"../cycle.jsf\"\n");
+        write(
+            "twice.jsf",
+            "include \"plans.json\"\npremium = .plan = 'gold'\n",
+        );
+        write("bad.jsf", "adults = .age >= 18\n.age < 18\n");
+
+        let set = FilterSet::load(dir.join("main.jsf")).unwrap();
+        let names: Vec<&str> = set.iter().map(NamedFilter::name).collect();
+        assert_eq!(names, ["adults", "premium", "premium_adults"]);
+        let premium_adults = set.get("premium_adults").unwrap().compiled();
+        assert!(premium_adults.matches(&json!({ "age": 30, "plan": "premium" })));
+        assert!(!premium_adults.matches(&json!({ "age": 30, "plan": "free" })));
+
+        let error = |file: &str| FilterSet::load(dir.join(file)).unwrap_err().to_string();
+        assert!(error("cycle.jsf").starts_with("files include each other: "));
+        assert!(error("twice.jsf").ends_with("plans.json`"));
+        assert!(error("twice.jsf").contains("twice.jsf:2: filter `premium` is already defined"));
+        assert!(
+            error("bad.jsf").ends_with("bad.jsf:2: expected `name = filter` or `include \"file\"`")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
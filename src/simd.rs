@@ -0,0 +1,86 @@
+use std::arch::x86_64::*;
+
+use crate::batch::Operator;
+
+/// Compares up to 64 numbers with `value` four at a time, setting the bit of each that passes, like
+/// the scalar comparison of a numeric clause.
+///
+/// # Safety
+///
+/// The CPU must support AVX.
+#[target_feature(enable = "avx")]
+pub(crate) unsafe fn compare(column: &[f64], operator: Operator, value: f64) -> u64 {
+    // The ordered predicates are false when a number is NaN, as the scalar comparisons are, and
+    // `!=` is unordered, so it is true then.
+    match operator {
+        Operator::Eq => bits::<_CMP_EQ_OQ>(column, value),
+        Operator::Ne => bits::<_CMP_NEQ_UQ>(column, value),
+        Operator::Lt => bits::<_CMP_LT_OQ>(column, value),
+        Operator::Le => bits::<_CMP_LE_OQ>(column, value),
+        Operator::Gt => bits::<_CMP_GT_OQ>(column, value),
+        Operator::Ge => bits::<_CMP_GE_OQ>(column, value),
+    }
+}
+
+#[target_feature(enable = "avx")]
+unsafe fn bits<const PREDICATE: i32>(column: &[f64], value: f64) -> u64 {
+    let values = _mm256_set1_pd(value);
+    let lanes = |numbers: &[f64; 4]| {
+        // SAFETY: the array has the 4 numbers the load reads.
+        let numbers = unsafe { _mm256_loadu_pd(numbers.as_ptr()) };
+        _mm256_movemask_pd(_mm256_cmp_pd::<PREDICATE>(numbers, values)) as u64
+    };
+    let chunks = column.chunks_exact(4);
+    let rest = chunks.remainder();
+    let mut bits = 0;
+    for (i, chunk) in chunks.enumerate() {
+        bits |= lanes(chunk.try_into().expect("chunks have 4 numbers")) << (i * 4);
+    }
+    if !rest.is_empty() {
+        let mut last = [0.0; 4];
+        last[..rest.len()].copy_from_slice(rest);
+        let passed = lanes(&last) & ((1 << rest.len()) - 1);
+        bits |= passed << (column.len() - rest.len());
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+        let column: Vec<f64> = (0..64)
+            .map(|i| match i % 7 {
+                0 => f64::NAN,
+                1 => 2.0,
+                _ => (i as f64 - 30.0) / 4.0,
+            })
+            .collect();
+        let scalar = |passes: &dyn Fn(f64) -> bool, column: &[f64]| {
+            column
+                .iter()
+                .enumerate()
+                .fold(0, |bits, (i, &n)| bits | (passes(n) as u64) << i)
+        };
+        for len in [0, 1, 3, 4, 5, 33, 64] {
+            let column = &column[..len];
+            for (operator, passes) in [
+                (Operator::Eq, &(|n| n == 2.0) as &dyn Fn(f64) -> bool),
+                (Operator::Ne, &|n| n != 2.0),
+                (Operator::Lt, &|n| n < 2.0),
+                (Operator::Le, &|n| n <= 2.0),
+                (Operator::Gt, &|n| n > 2.0),
+                (Operator::Ge, &|n| n >= 2.0),
+            ] {
+                // SAFETY: AVX is supported.
+                let bits = unsafe { compare(column, operator, 2.0) };
+                assert_eq!(bits, scalar(passes, column), "{len} numbers");
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use alloc::string::{String, ToString};
+use core::cmp::Ordering;
+
+use serde_json::Value;
+
+use crate::expr::{self, Path};
+
+/// Where values that are null or missing go in a sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Nulls {
+    First,
+    #[default]
+    Last,
+}
+
+/// How the values of a sort key are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortAs {
+    /// Values of the same type are compared naturally: numbers by value, strings lexicographically
+    /// and `false` before `true`. Values of different types are ordered booleans, numbers, strings,
+    /// arrays, then objects.
+    #[default]
+    Natural,
+    /// Values are compared as numbers, reading strings such as `"10"` as numbers. Values that aren't
+    /// numbers sort as null.
+    Numeric,
+    /// Values are compared as strings, writing numbers and booleans as they appear in JSON.
+    String,
+}
+
+/// A path to sort JSON Values by, with a direction, a placement for nulls and a comparison mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    path: Path<'static>,
+    descending: bool,
+    nulls: Nulls,
+    sort_as: SortAs,
+}
+
+impl SortKey {
+    /// Sorts by a path, such as `.price` or `.user.name`, in ascending order.
+    pub fn asc(path: &str) -> SortKey {
+        SortKey {
+            path: Path::parse(path).into_owned(),
+            descending: false,
+            nulls: Nulls::default(),
+            sort_as: SortAs::default(),
+        }
+    }
+
+    /// Sorts by a path in descending order.
+    pub fn desc(path: &str) -> SortKey {
+        SortKey {
+            descending: true,
+            ..SortKey::asc(path)
+        }
+    }
+
+    /// Sets where null and missing values go. They go last by default, whatever the direction.
+    pub fn nulls(self, nulls: Nulls) -> SortKey {
+        SortKey { nulls, ..self }
+    }
+
+    /// Sets how values are compared.
+    pub fn sort_as(self, sort_as: SortAs) -> SortKey {
+        SortKey { sort_as, ..self }
+    }
+
+    fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        let a = self.path.resolve(a);
+        let b = self.path.resolve(b);
+        let a = a.as_deref().and_then(|a| self.key(a));
+        let b = b.as_deref().and_then(|b| self.key(b));
+        match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => self.nulls_ordering(),
+            (Some(_), None) => self.nulls_ordering().reverse(),
+            (Some(a), Some(b)) => {
+                let ordering = a.cmp(&b);
+                if self.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        }
+    }
+
+    /// Where a null goes relative to a value that isn't null.
+    fn nulls_ordering(&self) -> Ordering {
+        match self.nulls {
+            Nulls::First => Ordering::Less,
+            Nulls::Last => Ordering::Greater,
+        }
+    }
+
+    /// Converts a value into what it is sorted by, or `None` if it sorts as null.
+    fn key<'v>(&self, v: &'v Value) -> Option<Key<'v>> {
+        match (self.sort_as, v) {
+            (_, Value::Null) => None,
+            (SortAs::Natural, v) => Some(Key::Natural(v)),
+            (SortAs::Numeric, Value::Number(n)) => n.as_f64().map(Key::Number),
+            (SortAs::Numeric, Value::String(s)) => s.trim().parse().ok().map(Key::Number),
+            (SortAs::Numeric, _) => None,
+            (SortAs::String, Value::String(s)) => Some(Key::String(s.clone())),
+            (SortAs::String, v) => Some(Key::String(v.to_string())),
+        }
+    }
+}
+
+/// A value ready to be compared with others of the same key.
+enum Key<'v> {
+    Natural(&'v Value),
+    Number(f64),
+    String(String),
+}
+
+impl Key<'_> {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Natural(a), Key::Natural(b)) => natural(a, b),
+            (Key::Number(a), Key::Number(b)) => a.total_cmp(b),
+            (Key::String(a), Key::String(b)) => a.cmp(b),
+            _ => Ordering::Equal, // Both keys come from the same SortKey, so this doesn't happen
+        }
+    }
+}
+
+fn natural(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            expr::compare_numbers(a, b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+fn type_rank(v: &Value) -> u8 {
+    match v {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// Sorts JSON Values by several keys: by the first key, then by the second among values the first
+/// key orders equally, and so on. The sort is stable.
+pub(crate) fn sort_by(values: &mut [Value], keys: &[SortKey]) {
+    values.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ids(values: &[Value]) -> Vec<i64> {
+        values.iter().map(|v| v["id"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let mut values = vec![
+            json!({ "id": 1, "price": 10, "name": "b" }),
+            json!({ "id": 2, "price": null, "name": "a" }),
+            json!({ "id": 3, "price": 2.5, "name": "c" }),
+            json!({ "id": 4, "name": "a" }),
+            json!({ "id": 5, "price": 10, "name": "a" }),
+        ];
+        sort_by(&mut values, &[SortKey::asc(".price")]);
+        assert_eq!(ids(&values), [3, 1, 5, 2, 4]);
+        sort_by(
+            &mut values,
+            &[SortKey::desc(".price"), SortKey::asc(".name")],
+        );
+        assert_eq!(ids(&values), [5, 1, 3, 2, 4]);
+        sort_by(&mut values, &[SortKey::desc(".price").nulls(Nulls::First)]);
+        assert_eq!(ids(&values), [2, 4, 5, 1, 3]);
+    }
+
+    #[test]
+    fn test_sort_as() {
+        let mut values = vec![
+            json!({ "id": 1, "v": "10" }),
+            json!({ "id": 2, "v": 9 }),
+            json!({ "id": 3, "v": "n/a" }),
+            json!({ "id": 4, "v": "9.5" }),
+        ];
+        sort_by(&mut values, &[SortKey::asc(".v")]);
+        assert_eq!(ids(&values), [2, 1, 4, 3]);
+        sort_by(&mut values, &[SortKey::asc(".v").sort_as(SortAs::Numeric)]);
+        assert_eq!(ids(&values), [2, 4, 1, 3]);
+        sort_by(&mut values, &[SortKey::asc(".v").sort_as(SortAs::String)]);
+        assert_eq!(ids(&values), [1, 2, 4, 3]);
+    }
+}
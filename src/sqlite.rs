@@ -0,0 +1,194 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::batch::Operator;
+use crate::translate::{
+    self, is_plain_key, sql_operator, sql_string, Translatable, TranslateError,
+};
+use crate::Filter;
+
+/// Where the paths of clauses are looked up: in the JSON of `column`, from its root or, in the
+/// clauses of `ANY` and `ALL`, from the element of an array `json_each` is at.
+struct Scope<'c> {
+    column: &'c str,
+    /// The SQL expression of the JSON path of the element, such as `e0.fullkey`.
+    element: Option<String>,
+    depth: usize,
+}
+
+impl Scope<'_> {
+    /// The SQL expression of the JSON path of a field, such as `'$.user.age'`.
+    fn path(&self, keys: &[&str]) -> Result<String, TranslateError> {
+        let mut path = String::new();
+        for key in keys {
+            if is_plain_key(key) {
+                path.push('.');
+                path.push_str(key);
+            } else if !key.contains('"') {
+                path.push_str(&format!(".\"{key}\""));
+            } else {
+                return Err(TranslateError::new(format!(
+                    "the key `{key}` can't be written as a JSON path"
+                )));
+            }
+        }
+        Ok(match &self.element {
+            None => sql_string(&format!("${path}")),
+            Some(element) if path.is_empty() => element.clone(),
+            Some(element) => format!("{element} || {}", sql_string(&path)),
+        })
+    }
+}
+
+pub(crate) fn to_sqlite_json1(filters: &[Filter], column: &str) -> Result<String, TranslateError> {
+    let scope = Scope {
+        column,
+        element: None,
+        depth: 0,
+    };
+    conjunction(filters, &scope)
+}
+
+fn conjunction(filters: &[Filter], scope: &Scope) -> Result<String, TranslateError> {
+    let mut conditions = Vec::new();
+    for filter in filters {
+        if let Some(condition) = clause(filter, scope)? {
+            conditions.push(condition);
+        }
+    }
+    Ok(match conditions.is_empty() {
+        true => "TRUE".to_string(),
+        false => conditions.join(" AND "),
+    })
+}
+
+/// The condition of a clause, or `None` for a clause that always passes. Conditions joined with
+/// `OR` are in parentheses, so conditions can be joined with `AND`.
+fn clause(filter: &Filter, scope: &Scope) -> Result<Option<String>, TranslateError> {
+    let column = scope.column;
+    Ok(Some(match translate::translatable(filter)? {
+        Translatable::Always => return Ok(None),
+        Translatable::Compare {
+            keys,
+            operator,
+            literal,
+        } => {
+            let path = scope.path(&keys)?;
+            let json_type = format!("json_type({column}, {path})");
+            let value = format!("json_extract({column}, {path})");
+            // Values of other types never pass `=` or the orderings, and only null passes `!=`.
+            let (types, literal) = match literal {
+                Value::Null => {
+                    return Ok(Some(format!(
+                        "{json_type} {} 'null'",
+                        sql_operator(operator)
+                    )))
+                }
+                Value::Bool(b) => {
+                    return Ok(Some(match operator {
+                        Operator::Eq => format!("{json_type} = '{b}'"),
+                        _ => format!("({json_type} = '{}' OR {json_type} = 'null')", !b),
+                    }))
+                }
+                Value::Number(n) => ("IN ('integer', 'real')", n.to_string()),
+                Value::String(s) => ("= 'text'", sql_string(s)),
+                _ => unreachable!("arrays and objects aren't translatable"),
+            };
+            let compared = format!(
+                "{json_type} {types} AND {value} {} {literal}",
+                sql_operator(operator)
+            );
+            match operator {
+                Operator::Ne => format!("({compared} OR {json_type} = 'null')"),
+                _ => compared,
+            }
+        }
+        Translatable::Contains { keys, literal } => {
+            let path = scope.path(&keys)?;
+            let e = format!("e{}", scope.depth);
+            let element = match literal {
+                Value::Null => format!("{e}.type = 'null'"),
+                Value::Bool(b) => format!("{e}.type = '{b}'"),
+                Value::Number(n) => format!("{e}.type IN ('integer', 'real') AND {e}.value = {n}"),
+                Value::String(s) => format!("{e}.type = 'text' AND {e}.value = {}", sql_string(s)),
+                _ => unreachable!("arrays and objects aren't translatable"),
+            };
+            format!(
+                "json_type({column}, {path}) = 'array' AND EXISTS \
+                 (SELECT 1 FROM json_each({column}, {path}) AS {e} WHERE {element})"
+            )
+        }
+        Translatable::Quantified { all, keys, filters } => {
+            let path = scope.path(&keys)?;
+            let e = format!("e{}", scope.depth);
+            let inner = Scope {
+                column,
+                element: Some(format!("{e}.fullkey")),
+                depth: scope.depth + 1,
+            };
+            let condition = conjunction(filters, &inner)?;
+            let array = format!("json_type({column}, {path}) = 'array'");
+            let elements = format!("SELECT 1 FROM json_each({column}, {path}) AS {e}");
+            match all {
+                // Conditions are null when a field is missing, which `IS NOT TRUE` counts as failing.
+                true if condition == "TRUE" => array,
+                true => {
+                    format!("{array} AND NOT EXISTS ({elements} WHERE ({condition}) IS NOT TRUE)")
+                }
+                false => format!("{array} AND EXISTS ({elements} WHERE {condition})"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn sql(filter_string: &str) -> Result<String, TranslateError> {
+        to_sqlite_json1(&parse(filter_string).unwrap(), "data")
+    }
+
+    #[test]
+    fn test_to_sqlite_json1() {
+        assert_eq!(
+            sql(".price > 100").unwrap(),
+            "json_type(data, '$.price') IN ('integer', 'real') AND json_extract(data, '$.price') > 100"
+        );
+        assert_eq!(
+            sql("'it\\'s' = .user.name").unwrap(),
+            "json_type(data, '$.user.name') = 'text' AND json_extract(data, '$.user.name') = 'it''s'"
+        );
+        assert_eq!(
+            sql(".active != true AND .deleted = null").unwrap(),
+            "(json_type(data, '$.active') = 'false' OR json_type(data, '$.active') = 'null') \
+             AND json_type(data, '$.deleted') = 'null'"
+        );
+        assert_eq!(
+            sql(".tags CONTAINS 'new'").unwrap(),
+            "json_type(data, '$.tags') = 'array' AND EXISTS (SELECT 1 FROM json_each(data, '$.tags') \
+             AS e0 WHERE e0.type = 'text' AND e0.value = 'new')"
+        );
+        assert_eq!(
+            sql("ALL .items WHERE .qty >= 1").unwrap(),
+            "json_type(data, '$.items') = 'array' AND NOT EXISTS (SELECT 1 FROM json_each(data, \
+             '$.items') AS e0 WHERE (json_type(data, e0.fullkey || '.qty') IN ('integer', 'real') \
+             AND json_extract(data, e0.fullkey || '.qty') >= 1) IS NOT TRUE)"
+        );
+        assert_eq!(sql("SHOULD .a = 1").unwrap(), "TRUE");
+
+        let error = |filter_string| sql(filter_string).unwrap_err().to_string();
+        assert_eq!(
+            error(".name > 'a'"),
+            "`.name > 'a'` can't be translated: only numbers are ordered"
+        );
+        assert!(error(".a = .b").ends_with("only a path is compared with a literal"));
+        assert!(error(".id = '7c1b2c9e-5d3a-4f1e-9a8b-2c3d4e5f6a7b'")
+            .ends_with("UUIDs aren't compared as text"));
+        assert!(error("abs(.n) = 1").ends_with("only paths and literals are compared"));
+    }
+}
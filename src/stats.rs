@@ -0,0 +1,115 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::eval::Context;
+use crate::format::Clause;
+use crate::{ApplyOptions, Filter};
+
+/// Counts of how a batch of Values fared against a list of filters, recorded by
+/// [`crate::apply_with_stats`].
+///
+/// Use one `FilterStats` per list of filters: the clause counts are matched to the filters by position.
+/// Displaying the stats lists each clause with how many Values it rejected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterStats {
+    /// The number of Values the filters were applied to.
+    pub values: usize,
+    /// The number of Values that passed every clause.
+    pub passed: usize,
+    /// The counts of each top-level clause, in the order of the filters.
+    pub clauses: Vec<ClauseStats>,
+}
+
+/// How many Values reached a clause, and how many of them it rejected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClauseStats {
+    /// The clause, written in filter string syntax.
+    pub clause: String,
+    /// The number of Values the clause was evaluated against. Values rejected by an earlier clause
+    /// never reach it.
+    pub evaluated: usize,
+    /// The number of Values the clause rejected.
+    pub rejected: usize,
+}
+
+impl FilterStats {
+    /// The share of the Values that passed, between 0 and 1, or 0 when no Values were recorded.
+    pub fn pass_rate(&self) -> f64 {
+        match self.values {
+            0 => 0.0,
+            values => self.passed as f64 / values as f64,
+        }
+    }
+}
+
+pub(crate) fn matches_all(
+    v: &Value,
+    filters: &[Filter],
+    options: &ApplyOptions,
+    stats: &mut FilterStats,
+) -> bool {
+    if stats.clauses.is_empty() {
+        stats.clauses = filters
+            .iter()
+            .map(|filter| ClauseStats {
+                clause: Clause(filter).to_string(),
+                ..Default::default()
+            })
+            .collect();
+    }
+    let ctx = Context::new(options);
+    stats.values += 1;
+    for (filter, clause) in filters.iter().zip(&mut stats.clauses) {
+        clause.evaluated += 1;
+        if !filter.matches(v, &ctx) {
+            clause.rejected += 1;
+            return false;
+        }
+    }
+    stats.passed += 1;
+    true
+}
+
+impl fmt::Display for FilterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for clause in &self.clauses {
+            writeln!(
+                f,
+                "{}: rejected {} of {}",
+                clause.clause, clause.rejected, clause.evaluated
+            )?;
+        }
+        write!(f, "Passed {} of {}", self.passed, self.values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches_all() {
+        let filters = parse(".age >= 18 AND .country = 'NL'").unwrap();
+        let mut stats = FilterStats::default();
+        let passed = [
+            json!({ "age": 30, "country": "NL" }),
+            json!({ "age": 12, "country": "NL" }),
+            json!({ "age": 40, "country": "BE" }),
+            json!({ "age": 50, "country": "BE" }),
+        ]
+        .iter()
+        .filter(|v| matches_all(v, &filters, &ApplyOptions::default(), &mut stats))
+        .count();
+        assert_eq!(passed, 1);
+        assert_eq!(
+            stats.to_string(),
+            ".age >= 18: rejected 1 of 4\n.country = 'NL': rejected 2 of 3\nPassed 1 of 4"
+        );
+        assert_eq!(stats.pass_rate(), 0.25);
+    }
+}
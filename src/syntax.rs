@@ -0,0 +1,312 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+
+use crate::highlight::{self, SyntaxKind, SyntaxToken};
+use crate::lexer::Span;
+use crate::parser::{self, ParseError};
+
+/// A filter string parsed for tooling, returned by [`crate::parse_syntax`]. Unlike [`crate::Filter`],
+/// it keeps all of the text it was parsed from, so a formatter, linter or refactoring tool can find
+/// and change one part of a filter while leaving the whitespace, comments and quoting of the rest as
+/// they were written.
+///
+/// The tree displays as the filter string it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree<'a> {
+    source: &'a str,
+    tokens: Vec<SyntaxToken>,
+    clauses: Vec<SyntaxClause>,
+}
+
+/// A clause of a [`SyntaxTree`], with the clauses nested in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxClause {
+    /// What the clause is.
+    pub kind: ClauseKind,
+    /// The byte range of the clause in the filter string, including a `SHOULD` before it and a
+    /// `WEIGHT` after it.
+    pub span: Span,
+    /// The clauses of an `ANY`, `ALL`, `MATCH` or `if` clause, in the order they appear.
+    pub clauses: Vec<SyntaxClause>,
+}
+
+/// The kinds of [`SyntaxClause`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClauseKind {
+    /// A clause compared with an operator, such as `.a >= 1`, `.name FUZZY('ann', 1)` or
+    /// `.id IS UUID`.
+    Comparison,
+    /// `ANY <array> WHERE <clauses>` or `ALL <array> WHERE <clauses>`.
+    Quantified,
+    /// `MATCH <comparison> <threshold> WHERE <clauses>`.
+    Match,
+    /// `if <condition> then <clauses> else <clauses>`.
+    If,
+    /// An `@name` reference to another filter of a [`crate::FilterSet`].
+    Reference,
+}
+
+pub(crate) fn parse(source: &str) -> Result<SyntaxTree<'_>, ParseError> {
+    let mut spans = parser::parse_clause_spans(source)?;
+    // A clause starts where the clauses nested in it do, or before, and ends after them.
+    spans.sort_by_key(|(_, span)| (span.start, Reverse(span.end)));
+    let mut clauses = Vec::new();
+    let mut open: Vec<SyntaxClause> = Vec::new();
+    for (kind, span) in spans {
+        while open
+            .last()
+            .is_some_and(|clause| clause.span.end <= span.start)
+        {
+            close(&mut open, &mut clauses);
+        }
+        open.push(SyntaxClause {
+            kind,
+            span,
+            clauses: Vec::new(),
+        });
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut clauses);
+    }
+    Ok(SyntaxTree {
+        source,
+        tokens: highlight::tokens(source),
+        clauses,
+    })
+}
+
+/// Adds the innermost open clause to the one around it, or to the top-level clauses.
+fn close(open: &mut Vec<SyntaxClause>, clauses: &mut Vec<SyntaxClause>) {
+    let clause = open.pop().expect("an open clause");
+    match open.last_mut() {
+        Some(parent) => parent.clauses.push(clause),
+        None => clauses.push(clause),
+    }
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// The filter string the tree was parsed from.
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// The top-level clauses, in the order they appear.
+    pub fn clauses(&self) -> &[SyntaxClause] {
+        &self.clauses
+    }
+
+    /// Every token of the filter string, comments included, like [`crate::tokens`]. The text between
+    /// two tokens is whitespace.
+    pub fn tokens(&self) -> &[SyntaxToken] {
+        &self.tokens
+    }
+
+    /// The text of the filter string at `span`, such as a clause or a token.
+    pub fn text(&self, span: Span) -> &'a str {
+        &self.source[span.start..span.end]
+    }
+
+    /// Returns the filter string with the text at each span replaced, and all other text kept as
+    /// written. An edit overlapping an earlier one is ignored.
+    pub fn replace(&self, mut edits: Vec<(Span, String)>) -> String {
+        edits.sort_by_key(|(span, _)| span.start);
+        let mut text = String::with_capacity(self.source.len());
+        let mut end = 0;
+        for (span, replacement) in edits {
+            if span.start < end {
+                continue;
+            }
+            text.push_str(&self.source[end..span.start]);
+            text.push_str(&replacement);
+            end = span.end;
+        }
+        text.push_str(&self.source[end..]);
+        text
+    }
+
+    /// Returns the filter string with every reference to the field at the path `from`, such as
+    /// `.user.name`, and to the fields inside it, such as `.user.name.first`, changed to the path `to`,
+    /// and all other text kept as written.
+    ///
+    /// Paths are compared as written, so `.user?.name` is a different path. The clauses of `ANY` and
+    /// `ALL` read the fields of array elements, so only their `$root` paths are changed, and paths
+    /// from `$parent` are never changed.
+    pub fn rename_field(&self, from: &str, to: &str) -> String {
+        let mut elements = Vec::new();
+        self.element_clauses(&self.clauses, &mut elements);
+        let edits = self
+            .tokens
+            .iter()
+            .filter(|token| token.kind == SyntaxKind::Field)
+            .filter_map(|token| {
+                let text = self.text(token.span);
+                let nested = elements
+                    .iter()
+                    .any(|span| span.start <= token.span.start && token.span.end <= span.end);
+                let renamed = match text.strip_prefix("$root") {
+                    Some(path) => format!("$root{}", renamed(path, from, to)?),
+                    None if nested || text.starts_with('$') => return None,
+                    None if text.starts_with('.') => renamed(text, from, to)?,
+                    // A bare field name stays bare if the new path is one too.
+                    None => {
+                        let path = renamed(&format!(".{text}"), from, to)?;
+                        match path.strip_prefix('.') {
+                            Some(name) if is_bare(name) => name.to_string(),
+                            _ => path,
+                        }
+                    }
+                };
+                Some((token.span, renamed))
+            })
+            .collect();
+        self.replace(edits)
+    }
+
+    /// Collects the spans of the clauses evaluated against array elements, after the `WHERE` of
+    /// each `ANY` and `ALL`.
+    fn element_clauses(&self, clauses: &[SyntaxClause], spans: &mut Vec<Span>) {
+        for clause in clauses {
+            if clause.kind == ClauseKind::Quantified {
+                let start = self
+                    .tokens
+                    .iter()
+                    .find(|token| {
+                        token.span.start >= clause.span.start && self.text(token.span) == "WHERE"
+                    })
+                    .map_or(clause.span.start, |token| token.span.end);
+                spans.push(Span {
+                    start,
+                    end: clause.span.end,
+                });
+            }
+            self.element_clauses(&clause.clauses, spans);
+        }
+    }
+}
+
+/// The path `path` with its prefix `from` changed to `to`, if it is `from` or a path inside it.
+fn renamed(path: &str, from: &str, to: &str) -> Option<String> {
+    let rest = path.strip_prefix(from)?;
+    match rest.is_empty() || rest.starts_with(['.', '[', '?']) {
+        true => Some(format!("{to}{rest}")),
+        false => None,
+    }
+}
+
+fn is_bare(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = "SHOULD .a  >=  1 WEIGHT 2 AND /* vip */ ANY .tags WHERE (. = \"vip\" AND @b)\n  AND .n = {{n}}";
+        let tree = parse(source).unwrap();
+        assert_eq!(tree.to_string(), source);
+        fn texts<'a>(
+            tree: &SyntaxTree<'a>,
+            clauses: &[SyntaxClause],
+        ) -> Vec<(ClauseKind, &'a str)> {
+            clauses
+                .iter()
+                .map(|clause| (clause.kind, tree.text(clause.span)))
+                .collect()
+        }
+        assert_eq!(
+            texts(&tree, tree.clauses()),
+            [
+                (ClauseKind::Comparison, "SHOULD .a  >=  1 WEIGHT 2"),
+                (
+                    ClauseKind::Quantified,
+                    "ANY .tags WHERE (. = \"vip\" AND @b)"
+                ),
+                (ClauseKind::Comparison, ".n = {{n}}"),
+            ]
+        );
+        assert_eq!(
+            texts(&tree, &tree.clauses()[1].clauses),
+            [
+                (ClauseKind::Comparison, ". = \"vip\""),
+                (ClauseKind::Reference, "@b"),
+            ]
+        );
+        assert_eq!(
+            tree.tokens()
+                .iter()
+                .filter(|token| token.kind == SyntaxKind::Comment)
+                .map(|token| tree.text(token.span))
+                .collect::<Vec<_>>(),
+            ["/* vip */"]
+        );
+        let tree = parse("if .a = 1 then MATCH >= 1 WHERE (.b = 2) else .c = 3").unwrap();
+        let clause = &tree.clauses()[0];
+        assert_eq!(clause.kind, ClauseKind::If);
+        assert_eq!(
+            texts(&tree, &clause.clauses),
+            [
+                (ClauseKind::Comparison, ".a = 1"),
+                (ClauseKind::Match, "MATCH >= 1 WHERE (.b = 2)"),
+                (ClauseKind::Comparison, ".c = 3"),
+            ]
+        );
+        assert_eq!(clause.clauses[1].clauses.len(), 1);
+        assert!(parse(".a = ").is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let tree = parse(".a = 'x'  AND .b = 2").unwrap();
+        let value = tree.tokens()[2].span;
+        assert_eq!(
+            tree.replace(vec![
+                (tree.clauses()[1].span, ".c IS UUID".to_string()),
+                (value, "\"y\"".to_string()),
+                (value, "'z'".to_string()),
+            ]),
+            ".a = \"y\"  AND .c IS UUID"
+        );
+    }
+
+    #[test]
+    fn test_rename_field() {
+        let rename =
+            |source: &str, from: &str, to: &str| parse(source).unwrap().rename_field(from, to);
+        assert_eq!(
+            rename(
+                ".user.name = \"ann\"   # the owner\nAND abs(.user.name.len) > .username AND .user = 1",
+                ".user.name",
+                ".owner.name"
+            ),
+            ".owner.name = \"ann\"   # the owner\nAND abs(.owner.name.len) > .username AND .user = 1"
+        );
+        assert_eq!(
+            rename("status = paid AND .status != 'x'", ".status", ".state"),
+            "state = paid AND .state != 'x'"
+        );
+        assert_eq!(
+            rename("status = paid", ".status", ".order.state"),
+            ".order.state = paid"
+        );
+        // Fields inside `ANY` and `ALL` are those of the elements.
+        assert_eq!(
+            rename(
+                "ANY .id WHERE (.id = $root.id AND .x < $parent.id) AND .id?.v = 1",
+                ".id",
+                ".key"
+            ),
+            "ANY .key WHERE (.id = $root.key AND .x < $parent.id) AND .key?.v = 1"
+        );
+    }
+}
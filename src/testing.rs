@@ -0,0 +1,245 @@
+//! Unit tests for stored filters: fixture documents a filter is expected to match or reject, run with
+//! a report naming the clauses that rejected each document expected to match, and the values they
+//! read from it.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::eval::Context;
+use crate::format::{Clause, Clauses};
+use crate::{ApplyOptions, Filter, NamedFilter};
+
+/// A filter with fixture documents it is expected to match or reject.
+#[derive(Debug)]
+pub struct FilterTest<'f> {
+    filters: &'f [Filter<'f>],
+    options: ApplyOptions,
+    fixtures: Vec<Fixture>,
+}
+
+/// A document a [`FilterTest`] is expected to match or reject.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    /// The name the fixture is reported with.
+    pub name: String,
+    /// The document.
+    pub document: Value,
+    /// Whether the filter is expected to match the document.
+    pub matches: bool,
+}
+
+impl<'f> FilterTest<'f> {
+    /// Creates a test of filters, without fixtures, evaluating them with the default options.
+    pub fn new(filters: &'f [Filter<'f>]) -> FilterTest<'f> {
+        FilterTest {
+            filters,
+            options: ApplyOptions::default(),
+            fixtures: Vec::new(),
+        }
+    }
+
+    /// Creates a test of a filter loaded in a [`crate::FilterSet`].
+    pub fn named(filter: &'f NamedFilter) -> FilterTest<'f> {
+        FilterTest::new(filter.compiled().filters())
+    }
+
+    /// Evaluates the filters with `options`, such as the aliases or comparators of the service
+    /// applying them.
+    pub fn with_options(mut self, options: ApplyOptions) -> FilterTest<'f> {
+        self.options = options;
+        self
+    }
+
+    /// Adds a document the filters are expected to match.
+    pub fn expect_match(mut self, name: &str, document: Value) -> FilterTest<'f> {
+        self.fixtures.push(Fixture {
+            name: name.to_string(),
+            document,
+            matches: true,
+        });
+        self
+    }
+
+    /// Adds a document the filters are expected to reject.
+    pub fn expect_reject(mut self, name: &str, document: Value) -> FilterTest<'f> {
+        self.fixtures.push(Fixture {
+            name: name.to_string(),
+            document,
+            matches: false,
+        });
+        self
+    }
+
+    /// Evaluates the filters on every fixture.
+    pub fn run(&self) -> TestReport {
+        let ctx = Context::new(&self.options);
+        let mut report = TestReport {
+            filter: Clauses(self.filters).to_string(),
+            passed: 0,
+            failures: Vec::new(),
+        };
+        for fixture in &self.fixtures {
+            let rejected_by: Vec<ClauseResult> = self
+                .filters
+                .iter()
+                .filter(|filter| !filter.should && !filter.matches(&fixture.document, &ctx))
+                .map(|filter| ClauseResult {
+                    clause: Clause(filter).to_string(),
+                    left: filter.left_operand(&fixture.document, &ctx),
+                })
+                .collect();
+            if rejected_by.is_empty() == fixture.matches {
+                report.passed += 1;
+                continue;
+            }
+            report.failures.push(TestFailure {
+                fixture: fixture.name.clone(),
+                expected_match: fixture.matches,
+                rejected_by,
+            });
+        }
+        report
+    }
+
+    /// Runs the test, panicking with the report if any fixture failed, for use in `#[test]`
+    /// functions.
+    #[track_caller]
+    pub fn assert(&self) {
+        let report = self.run();
+        if !report.is_ok() {
+            panic!("{report}");
+        }
+    }
+}
+
+/// The outcome of a [`FilterTest`], which displays as one paragraph for each failed fixture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestReport {
+    /// The filter tested, written in filter string syntax.
+    pub filter: String,
+    /// The number of fixtures with the expected result.
+    pub passed: usize,
+    /// The fixtures with another result, in the order they were added.
+    pub failures: Vec<TestFailure>,
+}
+
+impl TestReport {
+    /// Returns whether every fixture had the expected result.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A fixture a [`FilterTest`] didn't give the expected result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestFailure {
+    /// The name of the fixture.
+    pub fixture: String,
+    /// Whether the fixture was expected to match.
+    pub expected_match: bool,
+    /// The clauses that rejected the fixture, empty if it matched.
+    pub rejected_by: Vec<ClauseResult>,
+}
+
+/// A clause that rejected a fixture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClauseResult {
+    /// The clause, written in filter string syntax.
+    pub clause: String,
+    /// The left side of the clause and its value in the fixture, `None` if the fixture doesn't have
+    /// it. Absent for clauses without a left side, such as `ANY .. WHERE ..`.
+    pub left: Option<(String, Option<Value>)>,
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} fixtures failed for `{}`",
+            self.failures.len(),
+            self.failures.len() + self.passed,
+            self.filter
+        )?;
+        for failure in &self.failures {
+            write!(f, "\n\n{failure}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.expected_match {
+            return write!(
+                f,
+                "fixture `{}`: expected a reject, but every clause passed",
+                self.fixture
+            );
+        }
+        write!(
+            f,
+            "fixture `{}`: expected a match, but it was rejected by",
+            self.fixture
+        )?;
+        for result in &self.rejected_by {
+            write!(f, "\n  {}", result.clause)?;
+            match &result.left {
+                Some((left, Some(value))) => write!(f, "\n    {left} is {value}")?,
+                Some((left, None)) => write!(f, "\n    {left} is missing")?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, FilterSet};
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_test() {
+        let filters =
+            parse(".age >= 18 AND .country = 'NZ' AND ANY .tags WHERE . = 'vip'").unwrap();
+        let test = FilterTest::new(&filters)
+            .expect_match(
+                "vip",
+                json!({ "age": 30, "country": "NZ", "tags": ["vip"] }),
+            )
+            .expect_match("minor", json!({ "age": 12, "tags": [] }))
+            .expect_reject(
+                "adult",
+                json!({ "age": 30, "country": "NZ", "tags": ["vip"] }),
+            )
+            .expect_reject("from AU", json!({ "age": 30, "country": "AU" }));
+        let report = test.run();
+        assert!(!report.is_ok());
+        assert_eq!(report.passed, 2);
+        assert_eq!(
+            report.to_string(),
+            "2 of 4 fixtures failed for `.age >= 18 AND .country = 'NZ' AND ANY .tags WHERE (. = 'vip')`
+
+fixture `minor`: expected a match, but it was rejected by
+  .age >= 18
+    .age is 12
+  .country = 'NZ'
+    .country is missing
+  ANY .tags WHERE (. = 'vip')
+
+fixture `adult`: expected a reject, but every clause passed"
+        );
+    }
+
+    #[test]
+    fn test_named_filter_test() {
+        let set = FilterSet::from_json(r#"{ "adults": ".age >= 18" }"#).unwrap();
+        let test = FilterTest::named(set.get("adults").unwrap())
+            .expect_match("adult", json!({ "age": 18 }))
+            .expect_reject("minor", json!({ "age": 17 }));
+        assert!(test.run().is_ok());
+        test.assert();
+    }
+}
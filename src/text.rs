@@ -0,0 +1,180 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Splits text into lowercase words, optionally reduced to their stems.
+///
+/// Words are runs of letters and digits; everything else separates them.
+pub(crate) fn words(text: &str, stem: bool) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word = word.to_lowercase();
+            if stem {
+                stem_word(&word)
+            } else {
+                word
+            }
+        })
+        .collect()
+}
+
+/// Returns whether every word of `query` appears in `text`, in any order.
+pub(crate) fn matches_text(text: &str, query: &str, stem: bool) -> bool {
+    let text = words(text, stem);
+    words(query, stem).iter().all(|term| text.contains(term))
+}
+
+/// The number of single-character insertions, deletions, substitutions and swaps of adjacent
+/// characters needed to turn `a` into `b`, known as the optimal string alignment distance.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows of the distance matrix: two rows back, the previous row and the current row.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = core::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// Returns whether two strings are at most `max_distance` edits apart, ignoring case.
+pub(crate) fn fuzzy_matches(a: &str, b: &str, max_distance: usize) -> bool {
+    edit_distance(&a.to_lowercase(), &b.to_lowercase()) <= max_distance
+}
+
+/// Picks the candidate closest to a misspelled name, ignoring case, `_` and `-`, so `created_at` suggests
+/// `createdAt`. Candidates more than a third of the name's length apart, or one edit for short names,
+/// are never suggested. Of equally close candidates, the first wins.
+#[cfg(feature = "std")]
+pub(crate) fn closest<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<&'c str> {
+    let normalize = |s: &str| s.replace(['_', '-'], "").to_lowercase();
+    let name = normalize(name);
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &normalize(candidate)), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Reduces an English word to a stem by removing common inflections, so `filters`, `filtered` and
+/// `filtering` are all `filter`. This is a light stemmer, not a full Porter stemmer.
+fn stem_word(word: &str) -> String {
+    // Short words are left alone, so `is` and `as` don't lose their only consonant.
+    if word.chars().count() <= 3 {
+        return word.to_string();
+    }
+    if let Some(stem) = word.strip_suffix("ies") {
+        return format!("{stem}y");
+    }
+    if let Some(stem) = word.strip_suffix("sses") {
+        return format!("{stem}ss");
+    }
+    for suffix in ["ing", "ed"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.chars().count() >= 3 && stem.chars().any(is_vowel) {
+                return undouble(stem);
+            }
+        }
+    }
+    match word.strip_suffix('s') {
+        Some(stem) if !stem.ends_with('s') && !stem.ends_with('u') => stem.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// Removes a doubled final consonant, as in `runn` from `running`, except the `ll`, `ss` and `zz`
+/// that words usually end with.
+fn undouble(stem: &str) -> String {
+    let mut chars = stem.chars().rev();
+    match (chars.next(), chars.next()) {
+        (Some(a), Some(b)) if a == b && !is_vowel(a) && !matches!(a, 'l' | 's' | 'z') => {
+            stem[..stem.len() - a.len_utf8()].to_string()
+        }
+        _ => stem.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("Fast, JSON-filtering: 100%!", false),
+            ["fast", "json", "filtering", "100"]
+        );
+        assert_eq!(
+            words("Filters filtered running queries classes is", true),
+            ["filter", "filter", "run", "query", "class", "is"]
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("john", "john"), 0);
+        assert_eq!(edit_distance("jonh", "john"), 1);
+        assert_eq!(edit_distance("jon", "john"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("ca", "abc"), 3);
+        assert!(fuzzy_matches("Jonh", "JOHN", 1));
+        assert!(!fuzzy_matches("jane", "john", 2));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_closest() {
+        let fields = ["createdAt", "updatedAt", "name"];
+        assert_eq!(closest("created_at", fields), Some("createdAt"));
+        assert_eq!(closest("nmae", fields), Some("name"));
+        assert_eq!(closest("email", fields), None);
+    }
+
+    #[test]
+    fn test_matches_text() {
+        assert!(matches_text(
+            "Fast JSON filtering in Rust",
+            "rust json",
+            false
+        ));
+        assert!(!matches_text(
+            "Fast JSON filtering in Rust",
+            "rust filter",
+            false
+        ));
+        assert!(matches_text(
+            "Fast JSON filtering in Rust",
+            "rust filter",
+            true
+        ));
+        assert!(!matches_text(
+            "Fast JSON filtering in Rust",
+            "rust go",
+            true
+        ));
+        assert!(matches_text("anything", "", false));
+    }
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+
+use serde_json::Number;
+
+use chrono::{
+    DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+};
+
+/// Parses an ISO-8601 timestamp into an instant.
+///
+/// Accepts RFC 3339 timestamps such as `2024-01-01T00:00:00Z` or `2024-01-01T02:00:00+02:00`,
+/// timestamps without an offset such as `2024-01-01T00:00:00`, and dates such as `2024-01-01`.
+/// Timestamps without an offset are taken to be in `zone`, and dates to be at midnight in `zone`.
+pub(crate) fn parse_timestamp(s: &str, zone: FixedOffset) -> Option<DateTime<FixedOffset>> {
+    // Cheap check so ordinary strings don't go through every format.
+    let bytes = s.as_bytes();
+    if bytes.len() < 10 || !bytes[..4].iter().all(u8::is_ascii_digit) || bytes[4] != b'-' {
+        return None;
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+        return Some(timestamp);
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .ok()?;
+    zone.from_local_datetime(&naive).single()
+}
+
+/// Compares two strings chronologically if both are timestamps.
+pub(crate) fn compare_timestamps(a: &str, b: &str, zone: FixedOffset) -> Option<Ordering> {
+    let a = parse_timestamp(a, zone)?.with_timezone(&Utc);
+    let b = parse_timestamp(b, zone)?.with_timezone(&Utc);
+    Some(a.cmp(&b))
+}
+
+/// Compares epoch milliseconds with a timestamp string, if the string is a timestamp.
+pub(crate) fn compare_epoch_millis(
+    millis: &Number,
+    s: &str,
+    zone: FixedOffset,
+) -> Option<Ordering> {
+    let timestamp = parse_timestamp(s, zone)?;
+    match millis.as_i64() {
+        // Compare at microsecond precision so sub-millisecond timestamps aren't rounded onto an integer.
+        Some(millis) => {
+            (i128::from(millis) * 1000).partial_cmp(&i128::from(timestamp.timestamp_micros()))
+        }
+        None => millis
+            .as_f64()?
+            .partial_cmp(&(timestamp.timestamp_micros() as f64 / 1000.0)),
+    }
+}
+
+/// Formats an instant as an RFC 3339 timestamp in UTC.
+pub(crate) fn format(instant: DateTime<Utc>) -> String {
+    instant.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Shifts a timestamp string by a number of milliseconds, keeping its offset.
+pub(crate) fn shift(s: &str, millis: i64, zone: FixedOffset) -> Option<String> {
+    let shifted = parse_timestamp(s, zone)?.checked_add_signed(Duration::milliseconds(millis))?;
+    Some(shifted.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Offset;
+
+    fn compare_timestamps(a: &str, b: &str) -> Option<Ordering> {
+        super::compare_timestamps(a, b, Utc.fix())
+    }
+
+    fn shift(s: &str, millis: i64) -> Option<String> {
+        super::shift(s, millis, Utc.fix())
+    }
+
+    #[test]
+    fn test_compare_timestamps() {
+        assert_eq!(
+            compare_timestamps("2024-01-01T00:00:00Z", "2024-01-01T01:00:00+01:00"),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare_timestamps("2024-03-05T10:00:00.5Z", "2024-03-05T10:00:00Z"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_timestamps("2024-06-01", "2024-05-31T23:59:59Z"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_timestamps("2024-06-01", "hello"), None);
+        assert_eq!(compare_timestamps("2024-13-01", "2024-01-01"), None);
+    }
+
+    #[test]
+    fn test_naive_timestamps_use_zone() {
+        let zone = FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(
+            super::compare_timestamps("2024-06-01", "2024-05-31T22:00:00Z", zone),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            super::compare_timestamps("2024-06-01T02:00:00", "2024-06-01T00:00:00Z", zone),
+            Some(Ordering::Equal)
+        );
+        // Offsets written in the timestamp win over the zone.
+        assert_eq!(
+            super::compare_timestamps("2024-06-01T00:00:00Z", "2024-06-01T00:00:00+00:00", zone),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn test_compare_epoch_millis() {
+        let zone = Utc.fix();
+        let millis = Number::from(1_704_067_200_000_i64);
+        assert_eq!(
+            compare_epoch_millis(&millis, "2024-01-01T00:00:00Z", zone),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare_epoch_millis(&millis, "2024-01-01T00:00:00.000500Z", zone),
+            Some(Ordering::Less)
+        );
+        let millis = Number::from_f64(1_704_067_200_000.5).unwrap();
+        assert_eq!(
+            compare_epoch_millis(&millis, "2024-01-01T00:00:00Z", zone),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_epoch_millis(&millis, "later", zone), None);
+    }
+
+    #[test]
+    fn test_shift() {
+        assert_eq!(
+            shift("2024-01-08T00:00:00Z", -7 * 86_400_000).as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+        assert_eq!(
+            shift("2024-01-01T23:30:00+02:00", 1_800_500).as_deref(),
+            Some("2024-01-02T00:00:00.500+02:00")
+        );
+        assert_eq!(shift("soon", 1), None);
+    }
+}
@@ -0,0 +1,248 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::batch::Operator;
+use crate::expr::{Expr, Path, Segment};
+use crate::format::Clause;
+#[cfg(feature = "chrono")]
+use crate::time;
+use crate::uuid;
+use crate::{Compound, Filter};
+
+/// An error translating filters into a database's query language, naming the first clause that
+/// can't be translated into a query selecting exactly the documents the filters pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslateError {
+    message: String,
+}
+
+impl TranslateError {
+    pub(crate) fn new(message: impl Into<String>) -> TranslateError {
+        TranslateError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for TranslateError {}
+
+/// A clause in one of the forms query languages can express.
+pub(crate) enum Translatable<'f, 'a> {
+    /// The value at a path compared with a literal. Strings, booleans and null are only compared
+    /// with `=` and `!=`.
+    Compare {
+        keys: Vec<&'f str>,
+        operator: Operator,
+        literal: &'f Value,
+    },
+    /// The array at a path containing a literal that isn't an array or an object.
+    Contains {
+        keys: Vec<&'f str>,
+        literal: &'f Value,
+    },
+    /// `ANY` or `ALL` of the elements of the array at a path passing filters.
+    Quantified {
+        all: bool,
+        keys: Vec<&'f str>,
+        filters: &'f [Filter<'a>],
+    },
+    /// A `SHOULD` clause, which always passes.
+    Always,
+}
+
+/// Reads a clause in the forms query languages can express, or fails with why it can't be.
+pub(crate) fn translatable<'f, 'a>(
+    filter: &'f Filter<'a>,
+) -> Result<Translatable<'f, 'a>, TranslateError> {
+    let unsupported = |reason: &str| {
+        TranslateError::new(format!(
+            "`{}` can't be translated: {reason}",
+            Clause(filter)
+        ))
+    };
+    if filter.should {
+        return Ok(Translatable::Always);
+    }
+    if filter.optional {
+        return Err(unsupported(
+            "clauses passing Values without their path aren't translated",
+        ));
+    }
+    if let Some(compound) = &filter.compound {
+        return match compound {
+            Compound::Quantified {
+                all,
+                array: Expr::Path(path),
+                filters,
+            } => Ok(Translatable::Quantified {
+                all: *all,
+                keys: keys(path).map_err(unsupported)?,
+                filters,
+            }),
+            Compound::Quantified { .. } => Err(unsupported("only arrays at a path are quantified")),
+            _ => Err(unsupported(
+                "only comparisons, `CONTAINS`, `ANY` and `ALL` are translated",
+            )),
+        };
+    }
+    if filter.multiplier_field.is_some() || filter.multiplier_value.is_some() {
+        return Err(unsupported("multiplied fields aren't translated"));
+    }
+    let left = match (&filter.lhs, filter.field.as_deref()) {
+        (Some(expr), _) => Operand::read(expr)
+            .ok_or_else(|| unsupported("only paths and literals are compared"))?,
+        (None, Some(field)) => Operand::Keys(vec![field]),
+        (None, None) => return Err(unsupported("the clause compares nothing")),
+    };
+    let right = match (&filter.rhs, &filter.value, filter.value_field.as_deref()) {
+        (Some(expr), ..) => Operand::read(expr)
+            .ok_or_else(|| unsupported("only paths and literals are compared"))?,
+        (None, Some(value), _) => Operand::Literal(value),
+        (None, None, Some(field)) => Operand::Keys(vec![field]),
+        (None, None, None) => return Err(unsupported("the clause compares nothing")),
+    };
+    let left = left.keys().map_err(unsupported)?;
+    let right = right.keys().map_err(unsupported)?;
+    if filter.operator == "CONTAINS" {
+        let (Operand::Keys(keys), Operand::Literal(literal)) = (left, right) else {
+            return Err(unsupported(
+                "only arrays at a path are searched for a literal",
+            ));
+        };
+        if literal.is_array() || literal.is_object() {
+            return Err(unsupported("only scalars are searched for in arrays"));
+        }
+        literal_string(literal).map_err(unsupported)?;
+        return Ok(Translatable::Contains { keys, literal });
+    }
+    let operator = match filter.operator.as_ref() {
+        "=" => Operator::Eq,
+        "!=" => Operator::Ne,
+        "<" => Operator::Lt,
+        "<=" => Operator::Le,
+        ">" => Operator::Gt,
+        ">=" => Operator::Ge,
+        operator => return Err(unsupported(&format!("`{operator}` isn't translated"))),
+    };
+    let (keys, operator, literal) = match (left, right) {
+        (Operand::Keys(keys), Operand::Literal(literal)) => (keys, operator, literal),
+        (Operand::Literal(literal), Operand::Keys(keys)) => (keys, flip(operator), literal),
+        _ => return Err(unsupported("only a path is compared with a literal")),
+    };
+    match literal {
+        Value::Number(_) => {}
+        Value::String(_) | Value::Bool(_) | Value::Null
+            if matches!(operator, Operator::Eq | Operator::Ne) => {}
+        Value::String(_) | Value::Bool(_) | Value::Null => {
+            return Err(unsupported("only numbers are ordered"));
+        }
+        _ => return Err(unsupported("arrays and objects aren't compared")),
+    }
+    literal_string(literal).map_err(unsupported)?;
+    Ok(Translatable::Compare {
+        keys,
+        operator,
+        literal,
+    })
+}
+
+/// Returns whether a key is a plain name, such as `user` or `_id`, which query languages write without
+/// quoting it.
+pub(crate) fn is_plain_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+enum Operand<'f> {
+    Path(&'f Path<'f>),
+    Keys(Vec<&'f str>),
+    Literal(&'f Value),
+}
+
+impl<'f> Operand<'f> {
+    fn read(expr: &'f Expr) -> Option<Operand<'f>> {
+        match expr {
+            Expr::Path(path) => Some(Operand::Path(path)),
+            Expr::Literal(value) => Some(Operand::Literal(value)),
+            _ => None,
+        }
+    }
+
+    /// The operand with its path, if it has one, read into keys.
+    fn keys(self) -> Result<Operand<'f>, &'static str> {
+        match self {
+            Operand::Path(path) => keys(path).map(Operand::Keys),
+            operand => Ok(operand),
+        }
+    }
+}
+
+/// The keys of a path of only keys.
+fn keys<'f>(path: &'f Path) -> Result<Vec<&'f str>, &'static str> {
+    path.segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Key {
+                name,
+                null_safe: false,
+            } => Ok(name.as_ref()),
+            Segment::Key { .. } => Err("null-safe paths aren't translated"),
+            Segment::Wildcard => Err("paths with `[*]` aren't translated"),
+        })
+        .collect()
+}
+
+/// Fails for string literals that aren't compared as text: UUIDs, which compare whatever their case,
+/// and timestamps with the `chrono` feature, which compare chronologically.
+fn literal_string(literal: &Value) -> Result<(), &'static str> {
+    let Value::String(s) = literal else {
+        return Ok(());
+    };
+    if uuid::parse_uuid(s).is_some() {
+        return Err("UUIDs aren't compared as text");
+    }
+    #[cfg(feature = "chrono")]
+    if time::parse_timestamp(s, chrono::FixedOffset::east_opt(0).unwrap()).is_some() {
+        return Err("timestamps aren't compared as text");
+    }
+    Ok(())
+}
+
+/// The operator comparing the operands the other way around, as in `.a > 1` for `1 < .a`.
+fn flip(operator: Operator) -> Operator {
+    match operator {
+        Operator::Lt => Operator::Gt,
+        Operator::Le => Operator::Ge,
+        Operator::Gt => Operator::Lt,
+        Operator::Ge => Operator::Le,
+        operator => operator,
+    }
+}
+
+/// The SQL spelling of an operator.
+pub(crate) fn sql_operator(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Eq => "=",
+        Operator::Ne => "<>",
+        Operator::Lt => "<",
+        Operator::Le => "<=",
+        Operator::Gt => ">",
+        Operator::Ge => ">=",
+    }
+}
+
+/// A string as an SQL string literal.
+pub(crate) fn sql_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
@@ -0,0 +1,202 @@
+use alloc::vec::Vec;
+
+use serde_json::{Map, Value};
+
+use crate::expr::{Path, Segment};
+
+/// A list of changes to make to a JSON Value, applied in order.
+///
+/// Built with [`Update::set`], [`Update::remove`] and [`Update::merge`], as in
+/// `Update::new().set(".status", json!("archived")).remove(".draft")`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Update {
+    operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operation {
+    Set(Path<'static>, Value),
+    Remove(Path<'static>),
+    Merge(Value),
+}
+
+impl Update {
+    /// Creates an update that changes nothing.
+    pub fn new() -> Update {
+        Update::default()
+    }
+
+    /// Sets the value at a path such as `.user.status`, creating the objects along the path and
+    /// overwriting anything already there. With a wildcard, as in `.items[*].seen`, the value is set
+    /// in every element of the array.
+    pub fn set(mut self, path: &str, value: Value) -> Update {
+        let path = Path::parse(path).into_owned();
+        self.operations.push(Operation::Set(path, value));
+        self
+    }
+
+    /// Removes the field at a path, if it is there.
+    pub fn remove(mut self, path: &str) -> Update {
+        let path = Path::parse(path).into_owned();
+        self.operations.push(Operation::Remove(path));
+        self
+    }
+
+    /// Applies a JSON merge patch (RFC 7386): the fields of the patch are set recursively, and
+    /// fields that are null in the patch are removed.
+    pub fn merge(mut self, patch: Value) -> Update {
+        self.operations.push(Operation::Merge(patch));
+        self
+    }
+
+    /// Makes the changes to a Value.
+    pub fn apply(&self, v: &mut Value) {
+        for operation in &self.operations {
+            match operation {
+                Operation::Set(path, value) => set(&path.segments, v, value),
+                Operation::Remove(path) => remove(&path.segments, v),
+                Operation::Merge(patch) => merge_patch(v, patch),
+            }
+        }
+    }
+}
+
+fn set(segments: &[Segment], target: &mut Value, value: &Value) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *target = value.clone();
+        return;
+    };
+    match segment {
+        Segment::Key { name, .. } => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(map) = target {
+                let entry = map.entry(name.as_ref()).or_insert(Value::Null);
+                set(rest, entry, value);
+            }
+        }
+        Segment::Wildcard => {
+            for element in target.as_array_mut().into_iter().flatten() {
+                set(rest, element, value);
+            }
+        }
+    }
+}
+
+fn remove(segments: &[Segment], target: &mut Value) {
+    match segments {
+        [] => {}
+        [Segment::Key { name, .. }] => {
+            if let Value::Object(map) = target {
+                map.remove(name.as_ref());
+            }
+        }
+        [Segment::Key { name, .. }, rest @ ..] => {
+            if let Some(child) = target.get_mut(name.as_ref()) {
+                remove(rest, child);
+            }
+        }
+        [Segment::Wildcard, rest @ ..] => {
+            for element in target.as_array_mut().into_iter().flatten() {
+                remove(rest, element);
+            }
+        }
+    }
+}
+
+/// Calls `f` with every value at a path. Paths without wildcards have at most one.
+pub(crate) fn for_each_at(
+    segments: &[Segment],
+    target: &mut Value,
+    f: &mut impl FnMut(&mut Value),
+) {
+    let Some((segment, rest)) = segments.split_first() else {
+        f(target);
+        return;
+    };
+    match segment {
+        Segment::Key { name, .. } => {
+            if let Some(child) = target.get_mut(name.as_ref()) {
+                for_each_at(rest, child, f);
+            }
+        }
+        Segment::Wildcard => {
+            for element in target.as_array_mut().into_iter().flatten() {
+                for_each_at(rest, element, f);
+            }
+        }
+    }
+}
+
+/// Applies a JSON merge patch as described in RFC 7386.
+pub(crate) fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge_patch(map.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_set_and_remove() {
+        let mut v = json!({ "user": "ada", "items": [{ "id": 1 }, { "id": 2, "draft": true }] });
+        Update::new()
+            .set(".user.name", json!("ada"))
+            .set(".items[*].seen", json!(true))
+            .remove(".items[*].draft")
+            .remove(".missing.field")
+            .apply(&mut v);
+        assert_eq!(
+            v,
+            json!({
+                "user": { "name": "ada" },
+                "items": [{ "id": 1, "seen": true }, { "id": 2, "seen": true }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // The example from RFC 7386.
+        let mut v = json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged",
+        });
+        let patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": { "familyName": null },
+            "tags": ["example"],
+        });
+        merge_patch(&mut v, &patch);
+        assert_eq!(
+            v,
+            json!({
+                "title": "Hello!",
+                "author": { "givenName": "John" },
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890",
+            })
+        );
+    }
+}
@@ -0,0 +1,55 @@
+/// Parses a UUID into its 128-bit value.
+///
+/// Accepts the hyphenated form `67e55044-10b1-426f-9247-bb680e5fe0c8`, the simple form without
+/// hyphens, either of them in braces, and the `urn:uuid:` form, in any case.
+pub(crate) fn parse_uuid(s: &str) -> Option<u128> {
+    let s = s.strip_prefix("urn:uuid:").unwrap_or(s);
+    let s = s
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or(s);
+    let bytes = s.as_bytes();
+    match bytes.len() {
+        32 => {}
+        36 => {
+            let hyphens_ok = [8, 13, 18, 23].iter().all(|&i| bytes[i] == b'-');
+            if !hyphens_ok {
+                return None;
+            }
+        }
+        _ => return None,
+    }
+    let mut value = 0u128;
+    for (i, &b) in bytes.iter().enumerate() {
+        if bytes.len() == 36 && matches!(i, 8 | 13 | 18 | 23) {
+            continue;
+        }
+        let digit = (b as char).to_digit(16)?;
+        value = value << 4 | u128::from(digit);
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uuid() {
+        let expected = Some(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8"), expected);
+        assert_eq!(parse_uuid("67E55044-10B1-426F-9247-BB680E5FE0C8"), expected);
+        assert_eq!(parse_uuid("67e5504410b1426f9247bb680e5fe0c8"), expected);
+        assert_eq!(
+            parse_uuid("{67e55044-10b1-426f-9247-bb680e5fe0c8}"),
+            expected
+        );
+        assert_eq!(
+            parse_uuid("urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            expected
+        );
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c"), None);
+        assert_eq!(parse_uuid("67e5504-410b1-426f-9247-bb680e5fe0c8"), None);
+        assert_eq!(parse_uuid("g7e55044-10b1-426f-9247-bb680e5fe0c8"), None);
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::expr::{Arena, Expr, Path, Segment};
+use crate::format::Clause;
+use crate::intern::Name;
+use crate::text;
+#[cfg(feature = "chrono")]
+use crate::time;
+use crate::uuid;
+use crate::{Compound, Filter};
+
+/// What filters are checked against by [`crate::validate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape<'r> {
+    /// A JSON Schema describing the Values. Only `type`, `properties`, `additionalProperties` and
+    /// `items` are used; a schema without a `type` allows any value.
+    Schema(&'r Value),
+    /// A sample of the Values. Fields are known if the sample has them, and have the types the
+    /// sample has, taking every element of an array into account.
+    Sample(&'r Value),
+}
+
+/// A problem [`crate::validate`] found with a clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// What kind of problem it is.
+    pub kind: IssueKind,
+    /// The clause with the problem, written in filter string syntax.
+    pub clause: String,
+    /// The path of the field the problem is with.
+    pub path: String,
+    /// A description of the problem.
+    pub message: String,
+    /// For an unknown field, the path of the closest known field, if one is close enough to be a
+    /// likely misspelling, such as `.createdAt` for `.created_at`.
+    pub suggestion: Option<String>,
+}
+
+/// The kinds of [`ValidationIssue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The field isn't in the schema or the sample.
+    UnknownField,
+    /// The operator can't compare values of the field's type, so the clause never passes.
+    TypeMismatch,
+    /// The field is an array, but the operator isn't one for arrays, or the operator is for arrays
+    /// but the field isn't one.
+    ArrayMisuse,
+    /// The field is an object, but the operator isn't one for objects, or the operator is for
+    /// objects but the field isn't one.
+    ObjectMisuse,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.clause, self.message)?;
+        match &self.suggestion {
+            Some(suggestion) => write!(f, " (did you mean `{suggestion}`?)"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A set of JSON types, one bit per type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Kinds(u8);
+
+impl Kinds {
+    const NULL: Kinds = Kinds(1);
+    const BOOL: Kinds = Kinds(2);
+    const NUMBER: Kinds = Kinds(4);
+    const STRING: Kinds = Kinds(8);
+    const ARRAY: Kinds = Kinds(16);
+    const OBJECT: Kinds = Kinds(32);
+    const NONE: Kinds = Kinds(0);
+    const ANY: Kinds = Kinds(63);
+    const NAMES: [&'static str; 6] = [
+        "null",
+        "a boolean",
+        "a number",
+        "a string",
+        "an array",
+        "an object",
+    ];
+
+    fn of(v: &Value) -> Kinds {
+        match v {
+            Value::Null => Kinds::NULL,
+            Value::Bool(_) => Kinds::BOOL,
+            Value::Number(_) => Kinds::NUMBER,
+            Value::String(_) => Kinds::STRING,
+            Value::Array(_) => Kinds::ARRAY,
+            Value::Object(_) => Kinds::OBJECT,
+        }
+    }
+
+    fn of_schema_type(name: &str) -> Kinds {
+        match name {
+            "null" => Kinds::NULL,
+            "boolean" => Kinds::BOOL,
+            "number" | "integer" => Kinds::NUMBER,
+            "string" => Kinds::STRING,
+            "array" => Kinds::ARRAY,
+            "object" => Kinds::OBJECT,
+            _ => Kinds::ANY,
+        }
+    }
+
+    fn union(self, other: Kinds) -> Kinds {
+        Kinds(self.0 | other.0)
+    }
+
+    fn overlaps(self, other: Kinds) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl fmt::Display for Kinds {
+    /// Writes the types as in "a number or a string".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = (0..6)
+            .filter(|i| self.0 & (1 << i) != 0)
+            .map(|i| Kinds::NAMES[i])
+            .collect();
+        f.write_str(&names.join(" or "))
+    }
+}
+
+/// The part of a schema or sample describing the values at some path.
+#[derive(Debug, Clone)]
+enum Node<'r> {
+    /// Anything is allowed, so nothing can be checked.
+    Any,
+    Schema(&'r Value),
+    Samples(Vec<&'r Value>),
+}
+
+impl<'r> Node<'r> {
+    fn kinds(&self) -> Kinds {
+        match self {
+            Node::Any => Kinds::ANY,
+            Node::Schema(schema) => match schema.get("type") {
+                Some(Value::String(name)) => Kinds::of_schema_type(name),
+                Some(Value::Array(names)) => names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(Kinds::of_schema_type)
+                    .fold(Kinds::NONE, Kinds::union),
+                _ => Kinds::ANY,
+            },
+            Node::Samples(samples) if samples.is_empty() => Kinds::ANY,
+            Node::Samples(samples) => samples
+                .iter()
+                .map(|v| Kinds::of(v))
+                .fold(Kinds::NONE, Kinds::union),
+        }
+    }
+
+    /// The node describing the values at a path. If the path has an unknown field, the error is the
+    /// path with that field replaced by the closest known one, if any is close enough.
+    fn at(&self, path: &Path) -> Result<Node<'r>, Option<String>> {
+        let mut node = self.clone();
+        for (i, segment) in path.segments.iter().enumerate() {
+            node = match segment {
+                Segment::Key { name, null_safe } => match node.key(name) {
+                    Some(next) => next,
+                    None => {
+                        let keys = node.keys();
+                        let Some(closest) = text::closest(name, keys) else {
+                            return Err(None);
+                        };
+                        let mut segments = path.segments.clone();
+                        segments[i] = Segment::Key {
+                            name: Name::Shared(closest.into()),
+                            null_safe: *null_safe,
+                        };
+                        return Err(Some(Path { segments }.to_string()));
+                    }
+                },
+                Segment::Wildcard => node.elements(),
+            };
+        }
+        Ok(node)
+    }
+
+    /// The known keys of the objects the node describes, without duplicates.
+    fn keys(&self) -> Vec<&'r str> {
+        let mut keys: Vec<&str> = match self {
+            Node::Any => Vec::new(),
+            Node::Schema(schema) => schema
+                .get("properties")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flat_map(|properties| properties.keys().map(String::as_str))
+                .collect(),
+            Node::Samples(samples) => samples
+                .iter()
+                .filter_map(|v| v.as_object())
+                .flat_map(|object| object.keys().map(String::as_str))
+                .collect(),
+        };
+        let mut seen = HashSet::new();
+        keys.retain(|key| seen.insert(*key));
+        keys
+    }
+
+    fn key(&self, name: &str) -> Option<Node<'r>> {
+        match self {
+            Node::Any => Some(Node::Any),
+            Node::Schema(schema) => {
+                if let Some(property) = schema.get("properties").and_then(|p| p.get(name)) {
+                    return Some(Node::Schema(property));
+                }
+                match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => None,
+                    Some(additional @ Value::Object(_)) => Some(Node::Schema(additional)),
+                    _ if schema.get("properties").is_some() => None,
+                    _ => Some(Node::Any),
+                }
+            }
+            Node::Samples(samples) => {
+                let objects: Vec<_> = samples.iter().filter_map(|v| v.as_object()).collect();
+                if objects.is_empty() {
+                    return Some(Node::Any);
+                }
+                let found: Vec<_> = objects.iter().filter_map(|o| o.get(name)).collect();
+                (!found.is_empty()).then_some(Node::Samples(found))
+            }
+        }
+    }
+
+    fn elements(&self) -> Node<'r> {
+        match self {
+            Node::Any => Node::Any,
+            Node::Schema(schema) => match schema.get("items") {
+                Some(items @ Value::Object(_)) => Node::Schema(items),
+                _ => Node::Any,
+            },
+            Node::Samples(samples) => Node::Samples(
+                samples
+                    .iter()
+                    .filter_map(|v| v.as_array())
+                    .flatten()
+                    .collect(),
+            ),
+        }
+    }
+}
+
+pub(crate) fn validate(filters: &[Filter], shape: Shape) -> Vec<ValidationIssue> {
+    let node = match shape {
+        Shape::Schema(schema) => Node::Schema(schema),
+        Shape::Sample(sample) => Node::Samples(vec![sample]),
+    };
+    let mut issues = Vec::new();
+    check_all(filters, &node, &mut issues);
+    issues
+}
+
+fn check_all(filters: &[Filter], node: &Node, issues: &mut Vec<ValidationIssue>) {
+    for filter in filters {
+        Checker {
+            filter,
+            node,
+            issues,
+        }
+        .check();
+    }
+}
+
+/// Checks one clause against the node of the Values it is applied to.
+struct Checker<'c, 'f, 'a, 'r> {
+    filter: &'f Filter<'a>,
+    node: &'c Node<'r>,
+    issues: &'c mut Vec<ValidationIssue>,
+}
+
+impl<'r> Checker<'_, '_, '_, 'r> {
+    fn check(&mut self) {
+        let filter = self.filter;
+        match &filter.compound {
+            Some(Compound::Quantified { array, filters, .. }) => {
+                if let Some(array) = self.operand(array, Kinds::ARRAY, "ANY and ALL") {
+                    check_all(filters, &array.elements(), self.issues);
+                }
+            }
+            Some(Compound::Fuzzy { input, target, .. }) => {
+                self.operand(input, Kinds::STRING, "FUZZY");
+                self.operand(target, Kinds::ANY, "FUZZY");
+            }
+            #[cfg(feature = "geo")]
+            Some(Compound::Within { location, .. }) => {
+                self.operand(location, Kinds::OBJECT.union(Kinds::ARRAY), "WITHIN");
+            }
+            Some(Compound::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                check_all(condition, self.node, self.issues);
+                check_all(then, self.node, self.issues);
+                check_all(otherwise, self.node, self.issues);
+            }
+            Some(Compound::Match { filters, .. }) => check_all(filters, self.node, self.issues),
+            None => {
+                let right = match (&filter.rhs, &filter.value) {
+                    (Some(Expr::Literal(value)), _) | (None, Some(value)) => Some(value),
+                    _ => None,
+                };
+                let expected = expected_kinds(&filter.operator, right);
+                let before = self.issues.len();
+                match &filter.lhs {
+                    Some(lhs) => {
+                        self.operand(lhs, expected, &filter.operator);
+                    }
+                    None => {
+                        let path = Path::parse(filter.field.as_deref().unwrap_or_default());
+                        self.path(&path, expected, &filter.operator);
+                    }
+                }
+                if let (Some(Value::String(s)), "=" | "!=") = (right, &*filter.operator) {
+                    self.unquote_hint(s, before);
+                }
+                match (&filter.rhs, &filter.value_field) {
+                    (Some(rhs), _) => {
+                        self.operand(rhs, Kinds::ANY, &filter.operator);
+                    }
+                    (None, Some(field)) => {
+                        self.path(&Path::parse(field), Kinds::ANY, &filter.operator);
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+
+    /// Checks the fields of an operand. A bare path must also have one of the expected types,
+    /// ignoring null. Returns the node of a bare path.
+    fn operand(&mut self, expr: &Expr, expected: Kinds, operator: &str) -> Option<Node<'r>> {
+        if let Expr::Path(path) = expr {
+            return self.path(path, expected, operator);
+        }
+        for_each_path(&self.filter.arena, expr, &mut |path| {
+            self.path(path, Kinds::ANY, operator);
+        });
+        None
+    }
+
+    fn path(&mut self, path: &Path, expected: Kinds, operator: &str) -> Option<Node<'r>> {
+        let node = match self.node.at(path) {
+            Ok(node) => node,
+            Err(suggestion) => {
+                let message = format!("unknown field `{path}`");
+                self.issue(IssueKind::UnknownField, path, message, suggestion);
+                return None;
+            }
+        };
+        // A path through a wildcard collects the values it finds into an array.
+        let found = if path.segments.contains(&Segment::Wildcard) {
+            Kinds::ARRAY
+        } else {
+            Kinds(node.kinds().0 & !Kinds::NULL.0)
+        };
+        if found != Kinds::NONE && !found.overlaps(expected) {
+            let kind = if expected == Kinds::ARRAY || found == Kinds::ARRAY {
+                IssueKind::ArrayMisuse
+            } else if expected == Kinds::OBJECT || found == Kinds::OBJECT {
+                IssueKind::ObjectMisuse
+            } else {
+                IssueKind::TypeMismatch
+            };
+            let message = match expected {
+                Kinds::NONE => format!("`{operator}` can never pass for `{path}` and this value"),
+                _ => format!("`{path}` is {found}, but `{operator}` expects {expected}"),
+            };
+            self.issue(kind, path, message, None);
+        }
+        Some(node)
+    }
+
+    /// Points out that a quoted number or boolean compared with a field of that type is a string, so
+    /// the issues found since `before` are likely the quotes, as in `.code = '200'`.
+    fn unquote_hint(&mut self, s: &str, before: usize) {
+        let Ok(unquoted @ (Value::Number(_) | Value::Bool(_))) = serde_json::from_str(s) else {
+            return;
+        };
+        for issue in &mut self.issues[before..] {
+            if issue.kind == IssueKind::TypeMismatch
+                && issue.message.contains(&Kinds::of(&unquoted).to_string())
+            {
+                issue.message += &format!("; write `{unquoted}` without quotes to compare with it");
+            }
+        }
+    }
+
+    fn issue(&mut self, kind: IssueKind, path: &Path, message: String, suggestion: Option<String>) {
+        self.issues.push(ValidationIssue {
+            kind,
+            clause: Clause(self.filter).to_string(),
+            path: path.to_string(),
+            message,
+            suggestion,
+        });
+    }
+}
+
+/// The types the left-hand side of a comparison must have for it to ever pass, given the literal it
+/// is compared with, if any. Operators that aren't modeled here, such as those of plugins, accept any
+/// type.
+fn expected_kinds(operator: &str, right: Option<&Value>) -> Kinds {
+    match operator {
+        "=" | "!=" => match right {
+            Some(Value::Null) | None => Kinds::ANY,
+            Some(value) => Kinds::of(value),
+        },
+        ">=" | ">" | "<=" | "<" => match right {
+            Some(Value::String(s)) if orderable(s) => Kinds::STRING,
+            Some(Value::String(_)) => Kinds::NONE,
+            Some(Value::Number(_)) => Kinds::NUMBER,
+            Some(_) => Kinds::NONE,
+            None => Kinds::NUMBER.union(Kinds::STRING),
+        },
+        "CONTAINS" | "HAS_ALL" | "HAS_ANY" | "SUBSET_OF" => Kinds::ARRAY,
+        "MATCHES_OBJECT" => Kinds::OBJECT,
+        "IN_CIDR" | "MATCHES_TEXT" | "IS UUID" => Kinds::STRING,
+        _ if operator.starts_with("SEMVER") => Kinds::STRING,
+        // `IS NOT UUID` passes every Value but UUID strings.
+        _ => Kinds::ANY,
+    }
+}
+
+/// Returns whether strings can be ordered against this one, which they can if it is a UUID or,
+/// with the `chrono` feature, a timestamp.
+pub(crate) fn orderable(s: &str) -> bool {
+    #[cfg(feature = "chrono")]
+    if time::parse_timestamp(s, chrono::FixedOffset::east_opt(0).unwrap()).is_some() {
+        return true;
+    }
+    uuid::parse_uuid(s).is_some()
+}
+
+pub(crate) fn suggest_field(v: &Value, path: &Path) -> Option<String> {
+    Node::Samples(vec![v]).at(path).err().flatten()
+}
+
+/// Calls `f` with every path an expression refers to. Paths starting at `$root` or `$parent` are
+/// skipped, as they don't read the Value being checked.
+fn for_each_path(arena: &Arena, expr: &Expr, f: &mut impl FnMut(&Path)) {
+    match expr {
+        Expr::Path(path) => f(path),
+        Expr::Anchored(..) | Expr::Literal(_) => {}
+        #[cfg(feature = "chrono")]
+        Expr::Now => {}
+        Expr::Mul(lhs, rhs) | Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            for_each_path(arena, &arena[*lhs], f);
+            for_each_path(arena, &arena[*rhs], f);
+        }
+        Expr::Shift(base, _) | Expr::Capture(base, ..) => for_each_path(arena, &arena[*base], f),
+        Expr::Call(_, exprs)
+        | Expr::Plugin(_, exprs)
+        | Expr::Coalesce(exprs)
+        | Expr::List(exprs) => {
+            for expr in &arena[*exprs] {
+                for_each_path(arena, expr, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    fn kinds(issues: Vec<ValidationIssue>) -> Vec<(IssueKind, String)> {
+        issues.into_iter().map(|i| (i.kind, i.path)).collect()
+    }
+
+    #[test]
+    fn test_validate_sample() {
+        let sample = json!({
+            "name": "Ada",
+            "age": 36,
+            "tags": ["math"],
+            "address": { "city": "London" },
+            "orders": [{ "total": 10 }],
+        });
+        let filters = parse(
+            ".name > 5 AND .nmae = 'x' AND .tags = 'math' AND .age CONTAINS 1 AND .address.city = 'London' \
+             AND ANY .orders WHERE (.total > 5 AND .status = 'paid') AND .address MATCHES_TEXT 'x' AND abs(.agee) > 1",
+        )
+        .unwrap();
+        let issues = validate(&filters, Shape::Sample(&sample));
+        assert_eq!(issues[1].suggestion.as_deref(), Some(".name"));
+        assert_eq!(issues[4].suggestion, None);
+        assert_eq!(
+            issues[6].to_string(),
+            "abs(.agee) > 1: unknown field `.agee` (did you mean `.age`?)"
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            ".name > 5: `.name` is a string, but `>` expects a number"
+        );
+        assert_eq!(
+            kinds(issues),
+            [
+                (IssueKind::TypeMismatch, ".name".to_string()),
+                (IssueKind::UnknownField, ".nmae".to_string()),
+                (IssueKind::ArrayMisuse, ".tags".to_string()),
+                (IssueKind::ArrayMisuse, ".age".to_string()),
+                (IssueKind::UnknownField, ".status".to_string()),
+                (IssueKind::ObjectMisuse, ".address".to_string()),
+                (IssueKind::UnknownField, ".agee".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_operators() {
+        let sample = json!({ "age": 36, "ip": "10.0.0.1" });
+        let issues = |filter_string: &str| {
+            kinds(validate(
+                &parse(filter_string).unwrap(),
+                Shape::Sample(&sample),
+            ))
+        };
+        // Operators the validator doesn't model, such as plugin operators, take any type.
+        for filter_string in [
+            ".age IS NOT UUID",
+            ".age NEAR 30",
+            ".age IN (36, 'x')",
+            ".age DEEP_EQ 36",
+            ".ip IN_CIDR '10.0.0.0/8'",
+        ] {
+            assert_eq!(issues(filter_string), [], "{filter_string}");
+        }
+        assert_eq!(
+            validate(&parse(".age = '36'").unwrap(), Shape::Sample(&sample))[0].to_string(),
+            ".age = '36': `.age` is a number, but `=` expects a string; write `36` without quotes to \
+             compare with it"
+        );
+        assert_eq!(issues(".ip = '10'"), []);
+        for filter_string in [
+            ".age = '36'",
+            ".age IS UUID",
+            ".age IN_CIDR '10.0.0.0/8'",
+            ".age MATCHES_TEXT 'x'",
+            ".age SEMVER>= '1.2.3'",
+        ] {
+            assert_eq!(
+                issues(filter_string),
+                [(IssueKind::TypeMismatch, ".age".to_string())],
+                "{filter_string}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "score": { "type": ["number", "null"] },
+                "items": { "type": "array", "items": { "type": "object", "properties": { "sku": { "type": "string" } } } },
+                "meta": { "type": "object" },
+            },
+        });
+        let filters = parse(
+            ".score >= 1 AND .meta.anything = 1 AND .items[*].sku HAS_ANY ('a') AND .items[*].skew HAS_ANY ('a') AND .id > 'b'",
+        )
+        .unwrap();
+        assert_eq!(
+            kinds(validate(&filters, Shape::Schema(&schema))),
+            [
+                (IssueKind::UnknownField, ".items[*].skew".to_string()),
+                (IssueKind::TypeMismatch, ".id".to_string()),
+            ]
+        );
+    }
+}
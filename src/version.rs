@@ -0,0 +1,116 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// A semantic version, compared by the precedence rules of SemVer 2.0.
+#[derive(Debug, PartialEq, Eq)]
+struct Version<'a> {
+    core: [u64; 3],
+    pre: Vec<Identifier<'a>>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier<'a> {
+    // Numeric identifiers have lower precedence than alphanumeric ones, so this order matters.
+    Numeric(u64),
+    Alphanumeric(&'a str),
+}
+
+/// Parses a version such as `1.4.0`, `v2.0.0-rc.1` or `1.4.0+build.5`.
+///
+/// A leading `v` is allowed, and missing minor or patch numbers count as zero, so `1.4` is `1.4.0`.
+/// Build metadata is ignored.
+fn parse(s: &str) -> Option<Version<'_>> {
+    let s = s.strip_prefix('v').unwrap_or(s);
+    let s = s.split_once('+').map_or(s, |(version, _build)| version);
+    let (core, pre) = match s.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (s, None),
+    };
+
+    let mut numbers = [0; 3];
+    let mut parts = core.split('.');
+    for (i, number) in numbers.iter_mut().enumerate() {
+        match parts.next() {
+            Some(part) => *number = numeric(part)?,
+            None if i > 0 => break,
+            None => return None,
+        }
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let pre = match pre {
+        Some(pre) => pre
+            .split('.')
+            .map(|id| match numeric(id) {
+                Some(n) => Some(Identifier::Numeric(n)),
+                None if !id.is_empty()
+                    && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+                {
+                    Some(Identifier::Alphanumeric(id))
+                }
+                None => None,
+            })
+            .collect::<Option<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    Some(Version { core: numbers, pre })
+}
+
+fn numeric(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Compares two version strings, or returns `None` if either is not a version.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let a = parse(a)?;
+    let b = parse(b)?;
+    Some(a.core.cmp(&b.core).then_with(|| {
+        // A pre-release has lower precedence than the release itself.
+        match (a.pre.is_empty(), b.pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.pre.cmp(&b.pre),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.4.0",
+            "1.10.0",
+            "2.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert_eq!(
+                compare_versions(pair[0], pair[1]),
+                Some(Ordering::Less),
+                "{pair:?}"
+            );
+        }
+        assert_eq!(
+            compare_versions("v1.4", "1.4.0+build.7"),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(compare_versions("1.4.0.1", "1.4.0"), None);
+        assert_eq!(compare_versions("latest", "1.4.0"), None);
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Number, Value};
+use wasmi::{Config, Engine, Func, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Val};
+use wasmi::{ExternType, ValType};
+
+use crate::plugin::{is_function_name, FunctionPlugin, PluginHost};
+
+/// An error found while loading a WASM module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmError {
+    message: String,
+}
+
+impl WasmError {
+    fn new(message: impl Into<String>) -> WasmError {
+        WasmError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+/// How much a [`WasmModule`] may compute and allocate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmLimits {
+    /// The fuel each call of a function may spend, roughly one for each instruction it runs. A call
+    /// running out of fuel fails its clause.
+    pub fuel: u64,
+    /// The largest size in bytes the module's memory may grow to.
+    pub memory: usize,
+}
+
+impl Default for WasmLimits {
+    /// A million fuel for each call and 16 MiB of memory.
+    fn default() -> WasmLimits {
+        WasmLimits {
+            fuel: 1_000_000,
+            memory: 16 << 20,
+        }
+    }
+}
+
+/// A user-supplied WASM module whose exported functions are called from filters, such as
+/// `is_prime(.n) = true`, once registered in a [`PluginHost`] with [`WasmModule::register`].
+///
+/// The module runs sandboxed: it can't import anything from the host, and each call is limited by
+/// [`WasmLimits`]. Functions take and return numbers: an `i32` or `i64` parameter takes an integer,
+/// or a boolean as 0 or 1, and an `f32` or `f64` parameter takes any number. A function returning an
+/// `i32` is a predicate, whose result is `true` unless it is 0, and other results are numbers. A
+/// call with arguments of other types, or that traps, fails its clause.
+#[derive(Clone)]
+pub struct WasmModule {
+    functions: Vec<WasmFunction>,
+}
+
+#[derive(Clone)]
+struct WasmFunction {
+    name: String,
+    func: Func,
+    params: Vec<ValType>,
+    result: ValType,
+    fuel: u64,
+    store: Arc<Mutex<Store<StoreLimits>>>,
+}
+
+impl WasmModule {
+    /// Loads a module from its binary, with the default limits.
+    pub fn new(wasm: &[u8]) -> Result<WasmModule, WasmError> {
+        WasmModule::with_limits(wasm, WasmLimits::default())
+    }
+
+    /// Loads a module from its binary, with `limits` on each call of its functions. Fails if the
+    /// binary isn't a valid module, if the module imports anything, or if one of its exported
+    /// functions has a name or a signature filters can't call.
+    pub fn with_limits(wasm: &[u8], limits: WasmLimits) -> Result<WasmModule, WasmError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| WasmError::new(e.to_string()))?;
+        if let Some(import) = module.imports().next() {
+            return Err(WasmError::new(format!(
+                "the module imports `{}.{}`, but modules can't import anything",
+                import.module(),
+                import.name()
+            )));
+        }
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new().memory_size(limits.memory).build(),
+        );
+        store.limiter(|limits| limits);
+        // The start function is limited like a call.
+        set_fuel(&mut store, limits.fuel)?;
+        let instance = Linker::new(&engine)
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|e| WasmError::new(e.to_string()))?;
+        let mut signatures = Vec::new();
+        for export in module.exports() {
+            let ExternType::Func(ty) = export.ty() else {
+                continue;
+            };
+            let name = export.name();
+            if !is_function_name(name) {
+                return Err(WasmError::new(format!(
+                    "the exported function `{name}` can't be called from filters"
+                )));
+            }
+            let (params, &[result]) = (ty.params(), ty.results()) else {
+                return Err(WasmError::new(format!(
+                    "the exported function `{name}` must return one value"
+                )));
+            };
+            if !params.iter().chain([&result]).all(is_number) {
+                return Err(WasmError::new(format!(
+                    "the exported function `{name}` must take and return numbers"
+                )));
+            }
+            signatures.push((name.to_string(), params.to_vec(), result));
+        }
+        let store = Arc::new(Mutex::new(store));
+        let functions = signatures
+            .into_iter()
+            .map(|(name, params, result)| {
+                let func = instance
+                    .get_export(&*store.lock().unwrap(), &name)
+                    .and_then(|export| export.into_func())
+                    .expect("exported function");
+                WasmFunction {
+                    name,
+                    func,
+                    params,
+                    result,
+                    fuel: limits.fuel,
+                    store: store.clone(),
+                }
+            })
+            .collect();
+        Ok(WasmModule { functions })
+    }
+
+    /// The names of the module's exported functions, in alphabetical order.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|function| function.name.as_str())
+    }
+
+    /// Adds the module's exported functions to a host, as a pack for [`PluginHost::with_pack`].
+    pub fn register(&self, host: PluginHost) -> PluginHost {
+        self.functions
+            .iter()
+            .fold(host, |host, function| host.with_function(function.clone()))
+    }
+}
+
+impl fmt::Debug for WasmModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmModule")
+            .field("functions", &self.functions().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn is_number(ty: &ValType) -> bool {
+    matches!(
+        ty,
+        ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64
+    )
+}
+
+fn set_fuel(store: &mut Store<StoreLimits>, fuel: u64) -> Result<(), WasmError> {
+    store
+        .set_fuel(fuel)
+        .map_err(|e| WasmError::new(e.to_string()))
+}
+
+impl FunctionPlugin for WasmFunction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> (usize, usize) {
+        (self.params.len(), self.params.len())
+    }
+
+    fn call(&self, args: &[Value]) -> Option<Value> {
+        let inputs = args
+            .iter()
+            .zip(&self.params)
+            .map(|(arg, ty)| argument(arg, *ty))
+            .collect::<Option<Vec<Val>>>()?;
+        let mut outputs = [Val::default_for_ty(self.result)];
+        let mut store = self.store.lock().ok()?;
+        store.set_fuel(self.fuel).ok()?;
+        self.func.call(&mut *store, &inputs, &mut outputs).ok()?;
+        match outputs[0] {
+            Val::I32(n) => Some(Value::Bool(n != 0)),
+            Val::I64(n) => Some(Value::from(n)),
+            Val::F32(n) => Number::from_f64(n.to_float().into()).map(Value::Number),
+            Val::F64(n) => Number::from_f64(n.to_float()).map(Value::Number),
+            _ => None,
+        }
+    }
+}
+
+/// The WASM value of an argument, if it has the parameter's type.
+fn argument(arg: &Value, ty: ValType) -> Option<Val> {
+    let integer = match arg {
+        Value::Bool(b) => Some(*b as i64),
+        _ => arg.as_i64(),
+    };
+    match ty {
+        ValType::I32 => Some(Val::I32(i32::try_from(integer?).ok()?)),
+        ValType::I64 => Some(Val::I64(integer?)),
+        ValType::F32 => Some(Val::F32((arg.as_f64()? as f32).into())),
+        ValType::F64 => Some(Val::F64(arg.as_f64()?.into())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_with, parse_with_plugins, ApplyOptions};
+    use serde_json::json;
+
+    const MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "is_even") (param i64) (result i32)
+            (i64.eqz (i64.rem_s (local.get 0) (i64.const 2))))
+          (func (export "half") (param f64) (result f64)
+            (f64.div (local.get 0) (f64.const 2)))
+          (func (export "spin") (param i32) (result i32)
+            (loop (br 0))
+            (i32.const 1))
+          (func (export "fail") (param i32) (result i32)
+            unreachable))
+    "#;
+
+    #[test]
+    fn test_wasm_module() {
+        let module = WasmModule::new(&wat::parse_str(MODULE).unwrap()).unwrap();
+        assert_eq!(
+            module.functions().collect::<Vec<_>>(),
+            ["fail", "half", "is_even", "spin"]
+        );
+        let host = PluginHost::new().with_pack(|host| module.register(host));
+        let options = ApplyOptions {
+            plugins: host.clone(),
+            ..Default::default()
+        };
+        let passes = |filter_string: &str, v: Value| {
+            let filters = parse_with_plugins(filter_string, &host).unwrap();
+            apply_with(&v, &filters, &options)
+        };
+        assert!(passes("is_even(.n) = true", json!({ "n": 4 })));
+        assert!(!passes("is_even(.n) = true", json!({ "n": 3 })));
+        assert!(!passes("is_even(.n) = true", json!({ "n": 2.5 })));
+        assert!(!passes("is_even(.n) = true", json!({ "n": "4" })));
+        assert!(passes("half(.n) = 1.25", json!({ "n": 2.5 })));
+        // Calls running out of fuel or trapping fail their clause, and the module stays usable.
+        assert!(!passes("spin(1) = true", json!({})));
+        assert!(!passes("fail(1) = true", json!({})));
+        assert!(passes("is_even(.n) = true", json!({ "n": 0 })));
+        assert_eq!(
+            parse_with_plugins("is_even(.a, .b) = true", &host)
+                .unwrap_err()
+                .message,
+            "wrong number of arguments to `is_even`"
+        );
+    }
+
+    #[test]
+    fn test_wasm_module_errors() {
+        let load = |source: &str| {
+            WasmModule::new(&wat::parse_str(source).unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            load(r#"(module (import "env" "log" (func (param i32))))"#),
+            "the module imports `env.log`, but modules can't import anything"
+        );
+        assert_eq!(
+            load(r#"(module (func (export "f") (param i32)))"#),
+            "the exported function `f` must return one value"
+        );
+        assert_eq!(
+            load(r#"(module (func (export "f") (param externref) (result i32) (i32.const 0)))"#),
+            "the exported function `f` must take and return numbers"
+        );
+        assert_eq!(
+            load(r#"(module (func (export "abs") (result i32) (i32.const 0)))"#),
+            "the exported function `abs` can't be called from filters"
+        );
+        let limits = WasmLimits {
+            memory: 1 << 16,
+            ..Default::default()
+        };
+        assert!(
+            WasmModule::with_limits(&wat::parse_str("(module (memory 2))").unwrap(), limits)
+                .is_err()
+        );
+        assert!(WasmModule::new(b"not wasm").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{FilterSet, FilterSetError};
+
+/// How long the files must go without changes before they are reloaded.
+const QUIET: Duration = Duration::from_millis(100);
+
+/// A [`FilterSet`] loaded from a filter file and reloaded whenever the file, or a file it includes,
+/// changes, for long-running processes whose rules change while they run.
+///
+/// Each reload compiles the whole set before swapping it in, so [`WatchedFilterSet::current`] returns
+/// either the old set or the new one, never a mix. A file that no longer loads leaves the last set in
+/// place, and the error is kept for [`WatchedFilterSet::last_error`].
+#[derive(Debug)]
+pub struct WatchedFilterSet {
+    state: Arc<State>,
+    events: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct State {
+    current: RwLock<Arc<FilterSet>>,
+    last_error: Mutex<Option<FilterSetError>>,
+}
+
+enum Message {
+    Event(notify::Result<notify::Event>),
+    Stop,
+}
+
+impl WatchedFilterSet {
+    /// Loads a filter file as [`FilterSet::load`] does, and starts watching it. Fails if the file
+    /// doesn't load or can't be watched.
+    pub fn watch(path: impl AsRef<Path>) -> Result<WatchedFilterSet, FilterSetError> {
+        let path = path.as_ref().to_path_buf();
+        let (set, files) = FilterSet::load_files(&path)?;
+        let (events, received) = mpsc::channel();
+        let sender = events.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiving thread stops before the watcher is dropped, so sending may fail then.
+            let _ = sender.send(Message::Event(event));
+        })
+        .map_err(|error| FilterSetError::new(format!("cannot watch filter files: {error}")))?;
+        let mut watched = HashSet::new();
+        watch_dirs(&mut watcher, &mut watched, &files)?;
+        let state = Arc::new(State {
+            current: RwLock::new(Arc::new(set)),
+            last_error: Mutex::new(None),
+        });
+        let reloader = Reloader {
+            path,
+            files,
+            watcher,
+            watched,
+            state: state.clone(),
+        };
+        let thread = std::thread::spawn(move || reloader.run(received));
+        Ok(WatchedFilterSet {
+            state,
+            events,
+            thread: Some(thread),
+        })
+    }
+
+    /// The filter set as last loaded. The returned set stays the same while it is used, even if the
+    /// files are reloaded in the meantime.
+    pub fn current(&self) -> Arc<FilterSet> {
+        self.state.current.read().unwrap().clone()
+    }
+
+    /// The error of the last reload, if the files changed and no longer load.
+    pub fn last_error(&self) -> Option<FilterSetError> {
+        self.state.last_error.lock().unwrap().clone()
+    }
+}
+
+impl Drop for WatchedFilterSet {
+    fn drop(&mut self) {
+        let _ = self.events.send(Message::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reloads the filter set on the events of the files it was loaded from.
+struct Reloader {
+    path: PathBuf,
+    /// The files the set was last loaded from.
+    files: HashSet<PathBuf>,
+    watcher: RecommendedWatcher,
+    /// The directories watched, as watching a directory also sees files replaced by renaming, as
+    /// editors save them.
+    watched: HashSet<PathBuf>,
+    state: Arc<State>,
+}
+
+impl Reloader {
+    fn run(mut self, received: Receiver<Message>) {
+        while let Ok(Message::Event(event)) = received.recv() {
+            let mut changed = self.changed(event);
+            // Saving a file sends several events, such as truncating it before writing it, which
+            // are handled with one reload once the files have been quiet for a moment.
+            loop {
+                match received.recv_timeout(QUIET) {
+                    Ok(Message::Event(event)) => changed |= self.changed(event),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            if changed {
+                self.reload();
+            }
+        }
+    }
+
+    fn changed(&self, event: notify::Result<notify::Event>) -> bool {
+        let Ok(event) = event else {
+            return false;
+        };
+        !event.kind.is_access()
+            && event
+                .paths
+                .iter()
+                .any(|path| self.files.contains(path) || self.files.contains(&canonical(path)))
+    }
+
+    fn reload(&mut self) {
+        let loaded = FilterSet::load_files(&self.path).and_then(|(set, files)| {
+            watch_dirs(&mut self.watcher, &mut self.watched, &files)?;
+            Ok((set, files))
+        });
+        match loaded {
+            // The error is cleared first, so the new set is never seen with the old error.
+            Ok((set, files)) => {
+                self.files = files;
+                *self.state.last_error.lock().unwrap() = None;
+                *self.state.current.write().unwrap() = Arc::new(set);
+            }
+            Err(error) => *self.state.last_error.lock().unwrap() = Some(error),
+        }
+    }
+}
+
+/// Watches the directories of the files not watched yet.
+fn watch_dirs(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut HashSet<PathBuf>,
+    files: &HashSet<PathBuf>,
+) -> Result<(), FilterSetError> {
+    for file in files {
+        let dir = file.parent().unwrap_or(Path::new("."));
+        if watched.contains(dir) {
+            continue;
+        }
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|error| {
+                FilterSetError::new(format!("cannot watch `{}`: {error}", dir.display()))
+            })?;
+        watched.insert(dir.to_path_buf());
+    }
+    Ok(())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Instant;
+
+    /// Waits up to a few seconds for a condition the watcher thread makes true.
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn test_watch() {
+        let dir = std::env::temp_dir().join(format!("filters-{}-watch", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.jsf");
+        std::fs::write(&main, "include \"common.jsf\"\nadults = @age\n").unwrap();
+        std::fs::write(dir.join("common.jsf"), "age = .age >= 18\n").unwrap();
+
+        let watched = WatchedFilterSet::watch(&main).unwrap();
+        let before = watched.current();
+        let matches = |set: &FilterSet| {
+            set.get("adults")
+                .unwrap()
+                .compiled()
+                .matches(&json!({ "age": 19 }))
+        };
+        assert!(matches(&before));
+
+        std::fs::write(dir.join("common.jsf"), "age = .age >= 21\n").unwrap();
+        assert!(eventually(|| !matches(&watched.current())));
+        assert!(matches(&before));
+
+        std::fs::write(&main, "adults = .age >=\n").unwrap();
+        assert!(eventually(|| watched.last_error().is_some()));
+        assert!(watched.current().get("adults").is_some());
+
+        std::fs::write(&main, "kids = .age < 18\n").unwrap();
+        assert!(eventually(|| watched.current().get("kids").is_some()));
+        assert_eq!(watched.last_error(), None);
+        drop(watched);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
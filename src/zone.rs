@@ -0,0 +1,196 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde_json::Value;
+
+use crate::expr::{same_path, Expr, Path, Segment};
+use crate::{ApplyOptions, Filter};
+
+/// Integers smaller than this in magnitude are exact floats.
+const EXACT: u64 = 1 << 53;
+
+/// The smallest and largest numbers at some paths of a chunk of Values, such as an NDJSON segment or
+/// an Arrow batch, used by [`crate::CompiledFilter::may_match`] to skip chunks a filter can't match
+/// any Value of.
+///
+/// A path without a range is unknown, and a path registered without numbers has none in the chunk.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZoneMap {
+    ranges: Vec<(Path<'static>, Option<(f64, f64)>)>,
+}
+
+impl ZoneMap {
+    /// Creates a zone map that knows nothing about the chunk.
+    pub fn new() -> ZoneMap {
+        ZoneMap::default()
+    }
+
+    /// Computes the ranges of the numbers at each of the paths in a chunk of Values.
+    pub fn from_values(values: &[Value], paths: &[&str]) -> ZoneMap {
+        let mut zone = ZoneMap::new();
+        for &path in paths {
+            let parsed = Path::parse(path);
+            let range = values
+                .iter()
+                .filter_map(|v| parsed.resolve(v)?.as_f64())
+                .fold(None, |range: Option<(f64, f64)>, n| match range {
+                    Some((min, max)) => Some((min.min(n), max.max(n))),
+                    None => Some((n, n)),
+                });
+            zone.set(path, range);
+        }
+        zone
+    }
+
+    /// Records that every number at a path such as `.price` in the chunk is between `min` and `max`.
+    pub fn with_range(mut self, path: &str, min: f64, max: f64) -> ZoneMap {
+        self.set(path, Some((min, max)));
+        self
+    }
+
+    /// Records that there are no numbers at a path in the chunk.
+    pub fn without_numbers(mut self, path: &str) -> ZoneMap {
+        self.set(path, None);
+        self
+    }
+
+    fn set(&mut self, path: &str, range: Option<(f64, f64)>) {
+        let path = Path::parse(path).into_owned();
+        self.ranges.retain(|(known, _)| !same_path(known, &path));
+        self.ranges.push((path, range));
+    }
+
+    /// The range of the numbers at a path: `None` if the path is unknown, and `Some(None)` if the chunk
+    /// has no numbers there.
+    fn range(&self, path: &Path) -> Option<Option<(f64, f64)>> {
+        self.ranges
+            .iter()
+            .find(|(known, _)| same_path(known, path))
+            .map(|(_, range)| *range)
+    }
+}
+
+/// Returns whether any Value of a chunk with the ranges in `zone` may pass every clause. Clauses
+/// comparing a path with a number using `=`, `<`, `<=`, `>` or `>=` can't pass when the numbers at the
+/// path are all on the wrong side, as other values never compare as equal to, or ordered against, a
+/// number. Other clauses may always pass.
+#[cfg_attr(not(feature = "chrono"), allow(unused_variables))]
+pub(crate) fn may_match(filters: &[Filter], zone: &ZoneMap, options: &ApplyOptions) -> bool {
+    // With `coerce_epoch_millis`, numbers also compare with timestamp strings.
+    #[cfg(feature = "chrono")]
+    if options.coerce_epoch_millis {
+        return true;
+    }
+    // Values without numbers at a path may still have them where the options read the path from,
+    // and comparators may order values in other ways.
+    if options.fills_missing() || !options.comparators.is_empty() {
+        return true;
+    }
+    filters.iter().all(|filter| {
+        let Some((path, operator, literal)) = range_clause(filter) else {
+            return true;
+        };
+        let Some(range) = zone.range(&path) else {
+            return true;
+        };
+        let Some((min, max)) = range else {
+            return false;
+        };
+        match operator {
+            "=" => min <= literal && literal <= max,
+            ">" => max > literal,
+            ">=" => max >= literal,
+            "<" => min < literal,
+            _ => min <= literal,
+        }
+    })
+}
+
+/// The path, operator and number of a clause comparing a path with a number, if the number is an
+/// exact float, so comparing it with the ranges of a zone map gives the same result as with the
+/// numbers they were computed from.
+fn range_clause<'f>(filter: &'f Filter) -> Option<(Path<'f>, &'f str, f64)> {
+    if filter.should
+        || filter.optional
+        || filter.compound.is_some()
+        || filter.multiplier_field.is_some()
+        || filter.multiplier_value.is_some()
+        || !matches!(filter.operator.as_ref(), "=" | "<" | "<=" | ">" | ">=")
+    {
+        return None;
+    }
+    let path = match (&filter.lhs, filter.field.as_deref()) {
+        (Some(Expr::Path(path)), _) if !path.segments.contains(&Segment::Wildcard) => path.clone(),
+        (None, Some(field)) => Path {
+            segments: vec![Segment::Key {
+                name: field.into(),
+                null_safe: false,
+            }],
+        },
+        _ => return None,
+    };
+    let number = match (&filter.rhs, &filter.value, &filter.value_field) {
+        (Some(Expr::Literal(Value::Number(n))), ..) | (None, Some(Value::Number(n)), None) => n,
+        _ => return None,
+    };
+    if number.as_i64().is_some_and(|i| i.unsigned_abs() >= EXACT)
+        || number.as_u64().is_some_and(|u| u >= EXACT)
+    {
+        return None;
+    }
+    Some((path, filter.operator.as_ref(), number.as_f64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use serde_json::json;
+
+    fn may(filter_string: &str, zone: &ZoneMap) -> bool {
+        may_match(
+            &parse(filter_string).unwrap(),
+            zone,
+            &ApplyOptions::default(),
+        )
+    }
+
+    #[test]
+    fn test_may_match() {
+        let zone = ZoneMap::new()
+            .with_range(".price", 10.0, 20.0)
+            .with_range(".user.age", 30.0, 40.0)
+            .without_numbers(".name");
+        assert!(may(".price > 15 AND .user?.age <= 30", &zone));
+        assert!(!may(".price > 20", &zone));
+        assert!(may(".price >= 20", &zone));
+        assert!(!may(".price < 10", &zone));
+        assert!(!may(".price = 25 AND .qty > 1", &zone));
+        assert!(!may(".user.age > 40.5", &zone));
+        assert!(!may(".name = 1", &zone));
+        // Other clauses and paths without a range may always pass.
+        assert!(may(".price != 25 AND .qty > 100 AND .name = 'x'", &zone));
+        assert!(may(".price > 9007199254740993", &zone));
+    }
+
+    #[test]
+    fn test_from_values() {
+        let values = [
+            json!({ "n": 3, "s": "a" }),
+            json!({ "n": -1.5 }),
+            json!({ "n": "99" }),
+        ];
+        let zone = ZoneMap::from_values(&values, &[".n", ".s"]);
+        assert_eq!(
+            zone,
+            ZoneMap::new()
+                .with_range(".n", -1.5, 3.0)
+                .without_numbers(".s")
+        );
+        for filter_string in [".n > 3", ".n < -1.5", ".s = 1"] {
+            let filters = parse(filter_string).unwrap();
+            assert!(!values.iter().any(|v| crate::apply(v, &filters)));
+            assert!(!may(filter_string, &zone));
+        }
+    }
+}